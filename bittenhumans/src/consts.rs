@@ -19,3 +19,14 @@ pub enum System {
     Decimal = 1000,
     Binary = 1024,
 }
+
+/// How units are written.
+#[derive(Debug, PartialEq, Sequence, Clone, Copy, Default)]
+pub enum UnitStyle {
+    /// "9.81 GiB" or "9.81 GB".
+    #[default]
+    Full,
+    /// "9.8G", the prefix letter only, without a space. Binary and decimal values look the
+    /// same, the "i" is dropped but the divisor still follows the [`System`].
+    Compact,
+}
@@ -2,21 +2,57 @@ pub mod consts;
 
 use consts::*;
 
+impl UnitStyle {
+    /// Decimals used by [`ByteSizeFormatter::format`].
+    pub fn default_precision(self) -> usize {
+        match self {
+            Self::Full => 2,
+            Self::Compact => 1,
+        }
+    }
+
+    fn spacing(self) -> &'static str {
+        match self {
+            Self::Full => " ",
+            Self::Compact => "",
+        }
+    }
+}
+
 pub struct ByteSizeFormatter {
+    system: System,
+    magnitude: Magnitude,
+    style: UnitStyle,
     divisor: u64,
     unit: String,
 }
 
 impl ByteSizeFormatter {
     pub fn new(system: System, magnitude: Magnitude) -> Self {
-        let infix = match system {
-            System::Binary => "i",
-            System::Decimal => "",
-        };
-        let magnitude = magnitude as usize;
         Self {
-            divisor: (system as u64).pow(magnitude as u32),
-            unit: format!("{}{infix}B", MAGNITUDE_PREFIXES[magnitude - 1]),
+            system,
+            magnitude,
+            style: UnitStyle::Full,
+            divisor: Self::compute_divisor(system, magnitude),
+            unit: Self::unit(system, magnitude, UnitStyle::Full),
+        }
+    }
+
+    /// Writes units in `style`, keeping the magnitude and divisor.
+    pub fn with_style(self, style: UnitStyle) -> Self {
+        Self {
+            style,
+            unit: Self::unit(self.system, self.magnitude, style),
+            ..self
+        }
+    }
+
+    fn unit(system: System, magnitude: Magnitude, style: UnitStyle) -> String {
+        let prefix = MAGNITUDE_PREFIXES[magnitude as usize - 1];
+        match (style, system) {
+            (UnitStyle::Compact, _) => prefix.into(),
+            (UnitStyle::Full, System::Binary) => format!("{prefix}iB"),
+            (UnitStyle::Full, System::Decimal) => format!("{prefix}B"),
         }
     }
 
@@ -24,10 +60,43 @@ impl ByteSizeFormatter {
         (system as u64).pow(magnitude as u32)
     }
 
+    /// Selects the largest magnitude for which `value` formats with a numeric part of at least 1.
+    ///
+    /// Guarantees, for every `value`:
+    /// - `fit` is monotonic, a larger value never selects a smaller magnitude.
+    /// - Unless `value` is below the first threshold (1 KiB/KB) or above the last one (1 EiB/EB),
+    ///   the numeric part of [`format`](Self::format) lies in `[1.00, base)`, even after rounding.
+    ///   Values below the first threshold format as at most "1.00 KiB".
+    /// - [`format`](Self::format) output is at most 11 characters long.
+    /// - [`parse`] reads [`format`](Self::format) output back to within half of its last
+    ///   decimal, in either [`UnitStyle`], unless rounding took it past `u64::MAX`.
     pub fn fit(value: u64, system: System) -> Self {
-        let mut last = Magnitude::Kilo;
-        for magnitude in enum_iterator::all::<Magnitude>() {
-            if (value as f64 / Self::compute_divisor(system, magnitude) as f64) < 1.0 {
+        Self::fit_between(value, system, Magnitude::Kilo, Magnitude::Exa)
+    }
+
+    /// Like [`fit`](Self::fit), but never selects a magnitude above `max`.
+    /// Values too large for `max` produce a large numeric part instead of a bigger unit.
+    pub fn fit_clamped(value: u64, system: System, max: Magnitude) -> Self {
+        Self::fit_between(value, system, Magnitude::Kilo, max)
+    }
+
+    /// Like [`fit`](Self::fit), but never selects a magnitude below `min`.
+    /// Values too small for `min` produce a numeric part below one instead of a smaller unit.
+    pub fn fit_floored(value: u64, system: System, min: Magnitude) -> Self {
+        Self::fit_between(value, system, min, Magnitude::Exa)
+    }
+
+    /// Like [`fit`](Self::fit), but only selects magnitudes from `min` to `max`, see
+    /// [`fit_floored`](Self::fit_floored) and [`fit_clamped`](Self::fit_clamped).
+    pub fn fit_between(value: u64, system: System, min: Magnitude, max: Magnitude) -> Self {
+        let mut last = min;
+        for magnitude in enum_iterator::all::<Magnitude>()
+            .skip_while(|x| *x as u8 <= min as u8)
+            .take_while(|x| *x as u8 <= max as u8)
+        {
+            // Compare the rounded value, so that e.g. 1023.999 KiB becomes 1.00 MiB
+            // instead of being printed as 1024.00 KiB.
+            if Self::round(value as f64 / Self::compute_divisor(system, magnitude) as f64) < 1.0 {
                 break;
             }
             last = magnitude;
@@ -36,6 +105,11 @@ impl ByteSizeFormatter {
         Self::new(system, last)
     }
 
+    /// Rounds like [`format`](Self::format) does.
+    fn round(value: f64) -> f64 {
+        (value * 100.0).round() / 100.0
+    }
+
     pub fn get_unit(&self) -> &str {
         &self.unit
     }
@@ -44,9 +118,126 @@ impl ByteSizeFormatter {
         &self.divisor
     }
 
+    /// Formats `value` with the style's default decimals and the unit, e.g. "1.50 KiB" or
+    /// "1.5K".
     pub fn format(&self, value: u64) -> String {
-        format!("{:.2} {}", value as f64 / self.divisor as f64, self.unit)
+        self.format_with(value, self.style.default_precision())
+    }
+
+    /// Formats `value` with `precision` decimals and the unit, e.g. "1.5 KiB".
+    pub fn format_with(&self, value: u64, precision: usize) -> String {
+        format!(
+            "{}{}{}",
+            self.format_value(value, precision),
+            self.style.spacing(),
+            self.unit
+        )
+    }
+
+    /// Formats the numeric part of `value` with `precision` decimals, e.g. "1.50".
+    pub fn format_value(&self, value: u64, precision: usize) -> String {
+        format!("{:.precision$}", value as f64 / self.divisor as f64)
+    }
+
+    /// Formats two values sharing this unit, e.g. "1.50/4.00 KiB" or "1.5/4.0K".
+    pub fn format_pair(&self, first: u64, second: u64, precision: usize) -> String {
+        format!(
+            "{}/{}{}{}",
+            self.format_value(first, precision),
+            self.format_value(second, precision),
+            self.style.spacing(),
+            self.unit
+        )
+    }
+}
+
+/// Why [`parse`] rejected its input.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// The numeric part is missing or isn't a plain non-negative decimal, e.g. "-1K" or "1e3K".
+    Number,
+    /// The unit isn't one [`ByteSizeFormatter`] writes, e.g. "1.5 XB".
+    Unit(String),
+    /// The size doesn't fit into a `u64`.
+    Overflow,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Number => f.write_str("expected a number, e.g. \"1.5\""),
+            Self::Unit(unit) => write!(f, "unknown unit \"{unit}\""),
+            Self::Overflow => f.write_str("too large"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Reads a size written by [`ByteSizeFormatter`] back into bytes, rounded to the nearest byte.
+/// Accepts both styles, with or without a space before the unit: "9.81 GiB" and "9.81 GB"
+/// name their system, compact forms like "9.8G" are read in `compact_system`. A number without
+/// a unit, or followed by "B", is a count of bytes.
+pub fn parse(text: &str, compact_system: System) -> Result<u64, ParseError> {
+    let text = text.trim();
+    let split = text
+        .find(|x: char| !x.is_ascii_digit() && x != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = (&text[..split], text[split..].trim_start());
+
+    let divisor = match unit {
+        "" | "B" => 1,
+        _ => {
+            let (prefix, system) = if let Some(prefix) = unit.strip_suffix("iB") {
+                (prefix, System::Binary)
+            } else if let Some(prefix) = unit.strip_suffix('B') {
+                (prefix, System::Decimal)
+            } else {
+                (unit, compact_system)
+            };
+            let magnitude = MAGNITUDE_PREFIXES
+                .iter()
+                .position(|x| *x == prefix)
+                .ok_or_else(|| ParseError::Unit(unit.into()))?;
+            (system as u64).pow(magnitude as u32 + 1)
+        }
+    };
+
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty() || fraction.contains('.') {
+        return Err(ParseError::Number);
+    }
+    if fraction.is_empty() {
+        // Exact for integers, which f64 can't hold beyond 2^53.
+        return whole
+            .parse::<u64>()
+            .map_err(|_| ParseError::Overflow)?
+            .checked_mul(divisor)
+            .ok_or(ParseError::Overflow);
+    }
+
+    let bytes = (number.parse::<f64>().map_err(|_| ParseError::Number)? * divisor as f64).round();
+    if bytes >= 2_f64.powi(64) {
+        return Err(ParseError::Overflow);
     }
+    Ok(bytes as u64)
+}
+
+/// Formats `value` as a full integer with its digits grouped in threes,
+/// e.g. "12 884 901 888" with a space as `thousands_separator`.
+pub fn format_exact(value: u64, thousands_separator: &str) -> String {
+    let digits = value.to_string();
+    let mut formatted =
+        String::with_capacity(digits.len() + digits.len() / 3 * thousands_separator.len());
+
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push_str(thousands_separator);
+        }
+        formatted.push(digit);
+    }
+
+    formatted
 }
 
 #[cfg(test)]
@@ -76,6 +267,216 @@ mod tests {
         assert_eq!(1_000_000_000_000_000_000_u64, *exabyte.get_divisor());
     }
 
+    #[test]
+    fn fit_clamped() {
+        let gibibyte =
+            ByteSizeFormatter::fit_clamped(1340 * 1024_u64.pow(3), System::Binary, Magnitude::Giga);
+        assert_eq!("GiB", gibibyte.get_unit());
+        assert_eq!("1340.00 GiB", gibibyte.format(1340 * 1024_u64.pow(3)));
+
+        let megabyte = ByteSizeFormatter::fit_clamped(5_000_000, System::Decimal, Magnitude::Giga);
+        assert_eq!("MB", megabyte.get_unit());
+
+        let kibibyte = ByteSizeFormatter::fit_clamped(u64::MAX, System::Binary, Magnitude::Kilo);
+        assert_eq!("KiB", kibibyte.get_unit());
+    }
+
+    #[test]
+    fn fit_floored() {
+        let mebibyte = ByteSizeFormatter::fit_floored(512, System::Binary, Magnitude::Mega);
+        assert_eq!("MiB", mebibyte.get_unit());
+        assert_eq!("0.00 MiB", mebibyte.format(512));
+
+        let terabyte =
+            ByteSizeFormatter::fit_floored(3_000_000_000_000, System::Decimal, Magnitude::Mega);
+        assert_eq!("TB", terabyte.get_unit());
+
+        let exbibyte = ByteSizeFormatter::fit_floored(1, System::Binary, Magnitude::Exa);
+        assert_eq!("EiB", exbibyte.get_unit());
+    }
+
+    /// Deterministic xorshift generator spreading values over all magnitudes.
+    fn sample_values() -> Vec<u64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut values: Vec<u64> = (0..20_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state >> (state % 64)
+            })
+            .collect();
+
+        for base in [1000_u64, 1024] {
+            for exponent in 1..=6 {
+                let threshold = base.pow(exponent);
+                values.extend([threshold - 1, threshold, threshold + 1]);
+                values.extend((1..=10).map(|x| threshold - threshold / 1000 * x / 10));
+            }
+        }
+        values.extend([0, 1, u64::MAX]);
+        values.sort_unstable();
+
+        values
+    }
+
+    fn numeric_part(formatted: &str) -> f64 {
+        formatted.split(' ').next().unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn fit_is_monotonic() {
+        for system in enum_iterator::all::<System>() {
+            let divisors: Vec<u64> = sample_values()
+                .into_iter()
+                .map(|x| *ByteSizeFormatter::fit(x, system).get_divisor())
+                .collect();
+
+            assert!(divisors.windows(2).all(|x| x[0] <= x[1]), "{system:?}");
+        }
+    }
+
+    #[test]
+    fn fit_numeric_part_in_range() {
+        for system in enum_iterator::all::<System>() {
+            let base = system as u64;
+            for value in sample_values() {
+                let formatted = ByteSizeFormatter::fit(value, system).format(value);
+                let numeric = numeric_part(&formatted);
+
+                if value < base {
+                    assert!(numeric <= 1.0, "{value} formatted as {formatted}");
+                } else if value < base.pow(6) {
+                    assert!(
+                        (1.0..base as f64).contains(&numeric),
+                        "{value} formatted as {formatted}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fit_format_length_bound() {
+        for system in enum_iterator::all::<System>() {
+            for value in sample_values() {
+                let formatted = ByteSizeFormatter::fit(value, system).format(value);
+                assert!(formatted.len() <= 11, "{value} formatted as {formatted}");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_format_round_trip() {
+        for system in enum_iterator::all::<System>() {
+            for style in enum_iterator::all::<UnitStyle>() {
+                for value in sample_values() {
+                    let formatter = ByteSizeFormatter::fit(value, system).with_style(style);
+                    let formatted = formatter.format(value);
+                    let tolerance = *formatter.get_divisor() as f64
+                        / 10_f64.powi(style.default_precision() as i32)
+                        / 2.0;
+
+                    match super::parse(&formatted, system) {
+                        Ok(parsed) => assert!(
+                            (parsed as f64 - value as f64).abs() <= tolerance,
+                            "{value} formatted as {formatted} parsed as {parsed}"
+                        ),
+                        Err(ParseError::Overflow) => assert!(
+                            value as f64 + tolerance >= 2_f64.powi(64),
+                            "{value} formatted as {formatted} overflowed"
+                        ),
+                        Err(e) => panic!("{value} formatted as {formatted}: {e}"),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fit_rounding_boundary() {
+        let almost_mebibyte = 1024 * 1024 - 1;
+        let formatter = ByteSizeFormatter::fit(almost_mebibyte, System::Binary);
+        assert_eq!("1.00 MiB", formatter.format(almost_mebibyte));
+
+        let almost_kilobyte = 999;
+        let formatter = ByteSizeFormatter::fit(almost_kilobyte, System::Decimal);
+        assert_eq!("1.00 KB", formatter.format(almost_kilobyte));
+    }
+
+    #[test]
+    fn parse() {
+        let cases = [
+            ("0", 0),
+            ("512", 512),
+            ("512 B", 512),
+            ("512B", 512),
+            ("1.50 KiB", 1536),
+            ("1.5KiB", 1536),
+            ("1.00 KB", 1000),
+            (".5 KB", 500),
+            ("2. KB", 2000),
+            ("9.81 GiB", 10_533_407_293),
+            ("9.81 GB", 9_810_000_000),
+            ("1.5K", 1536),
+            ("  1.5 K ", 1536),
+            ("16.00 EB", 16_000_000_000_000_000_000),
+            ("18446744073709551615", u64::MAX),
+            ("18446744073709551615 B", u64::MAX),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(Ok(expected), super::parse(text, System::Binary), "{text}");
+        }
+        assert_eq!(Ok(1500), super::parse("1.5K", System::Decimal));
+        assert_eq!(Ok(1536), super::parse("1.50 KiB", System::Decimal));
+
+        for text in [
+            "", " ", ".", "K", "KiB", "-1K", "+1K", "1.2.3 K", "1e3K", "1,5 K",
+        ] {
+            assert!(super::parse(text, System::Binary).is_err(), "{text}");
+        }
+        assert_eq!(Err(ParseError::Number), super::parse(".K", System::Binary));
+        for unit in ["XB", "b", "Kb", "KIB", "Ki", "KiBB", "k"] {
+            assert_eq!(
+                Err(ParseError::Unit(unit.into())),
+                super::parse(&format!("1 {unit}"), System::Binary)
+            );
+        }
+
+        for text in [
+            "18446744073709551616",
+            "99999999999999999999999 B",
+            "16E",
+            "16.00 EiB",
+            "18.45 EB",
+            "17179869184 GiB",
+        ] {
+            assert_eq!(
+                Err(ParseError::Overflow),
+                super::parse(text, System::Binary),
+                "{text}"
+            );
+        }
+        assert_eq!(
+            Ok(15 * 1024_u64.pow(6)),
+            super::parse("15E", System::Binary)
+        );
+    }
+
+    #[test]
+    fn format_exact() {
+        assert_eq!("0", super::format_exact(0, " "));
+        assert_eq!("999", super::format_exact(999, " "));
+        assert_eq!("1 000", super::format_exact(1000, " "));
+        assert_eq!("12 884 901 888", super::format_exact(12_884_901_888, " "));
+        assert_eq!(
+            "18\u{2009}446\u{2009}744\u{2009}073\u{2009}709\u{2009}551\u{2009}615",
+            super::format_exact(u64::MAX, "\u{2009}")
+        );
+        assert_eq!("1,000,000", super::format_exact(1_000_000, ","));
+        assert_eq!("100000", super::format_exact(100_000, ""));
+    }
+
     #[test]
     fn format() {
         let kib = ByteSizeFormatter::new(System::Binary, Magnitude::Kilo);
@@ -83,4 +484,55 @@ mod tests {
         let gb = ByteSizeFormatter::new(System::Decimal, Magnitude::Giga);
         assert_eq!("1.00 GB".to_string(), gb.format(1_000_000_000));
     }
+
+    #[test]
+    fn format_value_and_pair() {
+        let kib = ByteSizeFormatter::new(System::Binary, Magnitude::Kilo);
+        assert_eq!("0.5", kib.format_value(512, 1));
+        assert_eq!("2", kib.format_value(1536, 0));
+        assert_eq!("1.50/4.00 KiB", kib.format_pair(1536, 4096, 2));
+        let gb = ByteSizeFormatter::new(System::Decimal, Magnitude::Giga);
+        assert_eq!(
+            "0.250/1.000 GB",
+            gb.format_pair(250_000_000, 1_000_000_000, 3)
+        );
+    }
+
+    #[test]
+    fn compact() {
+        let compact = |value, system| {
+            ByteSizeFormatter::fit(value, system)
+                .with_style(UnitStyle::Compact)
+                .format(value)
+        };
+        let cases = [
+            (0, "0.0K", "0.0K"),
+            (512, "0.5K", "0.5K"),
+            (1000, "1.0K", "1.0K"),
+            (1536, "1.5K", "1.5K"),
+            (10_533_000_000, "9.8G", "10.5G"),
+            (16 * 1024_u64.pow(3), "16.0G", "17.2G"),
+            (999_999_999_999, "931.3G", "1.0T"),
+            (1024_u64.pow(4) - 1, "1.0T", "1.1T"),
+            (u64::MAX, "16.0E", "18.4E"),
+        ];
+        for (value, binary, decimal) in cases {
+            assert_eq!(binary, compact(value, System::Binary), "{value}");
+            assert_eq!(decimal, compact(value, System::Decimal), "{value}");
+        }
+
+        let gib =
+            ByteSizeFormatter::new(System::Binary, Magnitude::Giga).with_style(UnitStyle::Compact);
+        assert_eq!("G", gib.get_unit());
+        assert_eq!(1024_u64.pow(3), *gib.get_divisor());
+        assert_eq!("9.81G", gib.format_with(10_533_000_000, 2));
+        assert_eq!("10G", gib.format_with(10_533_000_000, 0));
+        assert_eq!(
+            "9.8/15.9G",
+            gib.format_pair(10_533_000_000, 17_072_000_000, 1)
+        );
+
+        let back = gib.with_style(UnitStyle::Full);
+        assert_eq!("9.81 GiB", back.format(10_533_000_000));
+    }
 }
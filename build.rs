@@ -0,0 +1,30 @@
+//! Embeds the git commit and the enabled features, for `stringbar --version`.
+
+use std::{env, path::Path, process::Command};
+
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|x| x.status.success())
+        .and_then(|x| String::from_utf8(x.stdout).ok())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=STRINGBAR_COMMIT={}", commit.trim());
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|x| x.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=STRINGBAR_FEATURES={}", features.join(","));
+
+    // Missing paths would rerun the script on every build, e.g. for crates.io sources.
+    for path in [".git/HEAD", ".git/refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Mixer volume read straight from the ALSA control device, so neither libasound nor amixer
+//! is needed. Only reading elements is supported.
+
+use std::{fs::File, os::fd::AsRawFd};
+
+/// Control device of the default sound card.
+const CONTROL_PATH: &str = "/dev/snd/controlC0";
+pub const DEFAULT_CONTROL: &str = "Master";
+
+const IFACE_MIXER: i32 = 2;
+const ELEM_TYPE_BOOLEAN: i32 = 1;
+const ELEM_TYPE_INTEGER: i32 = 2;
+const ELEM_ID_NAME_LEN: usize = 44;
+const LONG_SIZE: usize = std::mem::size_of::<libc::c_long>();
+
+/// `_IOWR('U', nr, T)`.
+const fn iowr<T>(nr: u64) -> u64 {
+    (3 << 30) | ((std::mem::size_of::<T>() as u64) << 16) | ((b'U' as u64) << 8) | nr
+}
+const IOCTL_ELEM_INFO: u64 = iowr::<ElemInfo>(0x11);
+const IOCTL_ELEM_READ: u64 = iowr::<ElemValue>(0x12);
+
+/// `struct snd_ctl_elem_id`.
+#[repr(C)]
+struct ElemId {
+    numid: u32,
+    iface: i32,
+    device: u32,
+    subdevice: u32,
+    name: [u8; ELEM_ID_NAME_LEN],
+    index: u32,
+}
+
+/// `struct snd_ctl_elem_info`, with the value union only exposing the integer range.
+#[repr(C)]
+struct ElemInfo {
+    id: ElemId,
+    kind: i32,
+    access: u32,
+    count: u32,
+    owner: libc::pid_t,
+    /// Minimum, maximum and step for integer elements.
+    value: [libc::c_long; 128 / LONG_SIZE],
+    reserved: [u8; 64],
+}
+
+/// `struct snd_ctl_elem_value`, with the value union only exposing integers.
+#[repr(C)]
+struct ElemValue {
+    id: ElemId,
+    indirect: u32,
+    value: [libc::c_long; 128],
+    reserved: [u8; 128],
+}
+
+impl ElemId {
+    /// Looks up a mixer element by name, the kernel fills in the rest.
+    fn mixer(name: &str) -> Option<Self> {
+        let mut id = Self {
+            numid: 0,
+            iface: IFACE_MIXER,
+            device: 0,
+            subdevice: 0,
+            name: [0; ELEM_ID_NAME_LEN],
+            index: 0,
+        };
+        // Needs a terminating NUL.
+        if name.len() >= ELEM_ID_NAME_LEN {
+            return None;
+        }
+        id.name[..name.len()].copy_from_slice(name.as_bytes());
+
+        Some(id)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Volume {
+    pub percent: f64,
+    /// Muting keeps the volume, so both are reported.
+    pub muted: bool,
+}
+
+/// Volume of the mixer `control` on the default card, e.g. "Master". `None` if there's no
+/// sound card or no such control. Controls without a playback switch are never muted.
+pub fn volume(control: &str) -> Option<Volume> {
+    let device = File::open(CONTROL_PATH).ok()?;
+    let fd = device.as_raw_fd();
+
+    let (kind, range, values) = read_element(fd, &format!("{control} Playback Volume"))?;
+    if kind != ELEM_TYPE_INTEGER {
+        return None;
+    }
+    let muted = match read_element(fd, &format!("{control} Playback Switch")) {
+        Some((ELEM_TYPE_BOOLEAN, _, switches)) => switches.iter().all(|x| *x == 0),
+        _ => false,
+    };
+
+    Some(Volume {
+        percent: percent(range, &values)?,
+        muted,
+    })
+}
+
+/// Average of all channels, relative to the element's range.
+fn percent((min, max): (libc::c_long, libc::c_long), values: &[libc::c_long]) -> Option<f64> {
+    if max <= min || values.is_empty() {
+        return None;
+    }
+    let mean = values.iter().sum::<libc::c_long>() as f64 / values.len() as f64;
+
+    Some((mean - min as f64) / (max as f64 - min as f64) * 100.0)
+}
+
+/// Type, integer range and per-channel values of the mixer element `name`.
+fn read_element(
+    fd: libc::c_int,
+    name: &str,
+) -> Option<(i32, (libc::c_long, libc::c_long), Vec<libc::c_long>)> {
+    let mut info = ElemInfo {
+        id: ElemId::mixer(name)?,
+        kind: 0,
+        access: 0,
+        count: 0,
+        owner: 0,
+        value: [0; 128 / LONG_SIZE],
+        reserved: [0; 64],
+    };
+    // SAFETY: ELEM_INFO reads and writes a `snd_ctl_elem_info`, which `info` mirrors.
+    if unsafe { libc::ioctl(fd, IOCTL_ELEM_INFO as _, &mut info as *mut ElemInfo) } != 0 {
+        return None;
+    }
+
+    let mut value = ElemValue {
+        id: ElemId::mixer(name)?,
+        indirect: 0,
+        value: [0; 128],
+        reserved: [0; 128],
+    };
+    // SAFETY: ELEM_READ reads and writes a `snd_ctl_elem_value`, which `value` mirrors.
+    if unsafe { libc::ioctl(fd, IOCTL_ELEM_READ as _, &mut value as *mut ElemValue) } != 0 {
+        return None;
+    }
+
+    let count = (info.count as usize).min(value.value.len());
+    Some((
+        info.kind,
+        (info.value[0], info.value[1]),
+        value.value[..count].to_vec(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn layout() {
+        // Sizes from <sound/asound.h>, they're encoded in the ioctl numbers.
+        assert_eq!(272, std::mem::size_of::<ElemInfo>());
+        assert_eq!(1224, std::mem::size_of::<ElemValue>());
+        assert_eq!(0xc1105511, IOCTL_ELEM_INFO);
+        assert_eq!(0xc4c85512, IOCTL_ELEM_READ);
+    }
+
+    #[test]
+    fn percentages() {
+        assert_eq!(Some(45.0), percent((0, 100), &[45, 45]));
+        assert_eq!(Some(50.0), percent((0, 87), &[87, 0]));
+        assert_eq!(Some(0.0), percent((-10, 10), &[-10]));
+        assert_eq!(None, percent((0, 0), &[0]));
+        assert_eq!(None, percent((0, 100), &[]));
+
+        assert!(ElemId::mixer(DEFAULT_CONTROL).is_some());
+        assert!(ElemId::mixer(&"x".repeat(ELEM_ID_NAME_LEN)).is_none());
+    }
+}
@@ -0,0 +1,309 @@
+use crate::text::json_string;
+
+/// One rendered entry of the section list, in config order.
+#[derive(Clone)]
+pub enum Piece {
+    /// Text, the color it's shown in and whether it's urgent, for outputs that style blocks
+    /// themselves, and the text with the section's `compact_template` if it has one.
+    Text(String, Option<String>, bool, Option<String>),
+    Separator(String),
+    /// A section that has nothing to show, leaving no trace in the bar.
+    Hidden,
+}
+
+/// Joins rendered sections into the final bar text.
+///
+/// Explicit separators are only emitted between two text pieces. Adjacent text pieces
+/// without an explicit separator between them are joined with `separator`.
+pub fn assemble(pieces: &[Piece], separator: &str) -> String {
+    let mut output = String::new();
+    let mut pending: Vec<&str> = Vec::new();
+    let mut any_text = false;
+
+    for piece in pieces {
+        match piece {
+            Piece::Separator(x) => pending.push(x),
+            Piece::Text(x, ..) => {
+                if any_text {
+                    if pending.is_empty() {
+                        output.push_str(separator);
+                    } else {
+                        pending.iter().for_each(|x| output.push_str(x));
+                    }
+                }
+                pending.clear();
+                any_text = true;
+
+                output.push_str(x);
+            }
+            Piece::Hidden => {}
+        }
+    }
+
+    output
+}
+
+/// Switches text pieces to their compact text one after another in `order`, until `fits` is
+/// happy with them. Pieces without a compact text are passed over. Whatever is still too long
+/// once all are compact is left to `fits`'s caller to cut.
+pub fn shrink(pieces: &mut [Piece], order: &[usize], fits: impl Fn(&[Piece]) -> bool) {
+    for &index in order {
+        if fits(pieces) {
+            return;
+        }
+        if let Some(Piece::Text(text, _, _, compact @ Some(_))) = pieces.get_mut(index) {
+            *text = compact.take().unwrap_or_default();
+        }
+    }
+}
+
+/// `pieces` right to left, along with the entry of `names` that belongs to each. Hidden pieces
+/// are left out first, so explicit separators stay between the same neighbours. A piece
+/// expanding into several entries, e.g. `DiskUsageEach`, is moved as a whole and keeps their
+/// order.
+pub fn reverse<T: Copy>(pieces: &[Piece], names: &[T]) -> (Vec<Piece>, Vec<T>) {
+    pieces
+        .iter()
+        .zip(names)
+        .filter(|(piece, _)| !matches!(piece, Piece::Hidden))
+        .rev()
+        .map(|(piece, name)| (piece.clone(), *name))
+        .unzip()
+}
+
+/// A status line of the i3bar protocol, with a block for every text piece that isn't blank,
+/// named by the section's entry in `names`. The bar draws its own separators between blocks,
+/// explicit separators become blocks of their own replacing them.
+pub fn blocks(pieces: &[Piece], names: &[Option<&str>]) -> String {
+    let mut blocks: Vec<Vec<(&str, String)>> = Vec::new();
+    let mut pending: Vec<&str> = Vec::new();
+
+    for (index, piece) in pieces.iter().enumerate() {
+        match piece {
+            Piece::Separator(x) => pending.push(x),
+            Piece::Text(x, color, urgent, compact) if !x.trim().is_empty() => {
+                if let (Some(previous), false) = (blocks.last_mut(), pending.is_empty()) {
+                    previous.push(("separator", "false".into()));
+                    for x in &pending {
+                        blocks.push(vec![
+                            ("full_text", json_string(x)),
+                            ("separator", "false".into()),
+                        ]);
+                    }
+                }
+                pending.clear();
+
+                let mut block = vec![("full_text", json_string(x))];
+                if let Some(name) = names.get(index).copied().flatten() {
+                    block.push(("name", json_string(name)));
+                }
+                if let Some(compact) = compact {
+                    block.push(("short_text", json_string(compact)));
+                }
+                if let Some(color) = color {
+                    block.push(("color", json_string(color)));
+                }
+                if *urgent {
+                    block.push(("urgent", "true".into()));
+                }
+                blocks.push(block);
+            }
+            Piece::Text(..) | Piece::Hidden => {}
+        }
+    }
+
+    let blocks: Vec<_> = blocks
+        .iter()
+        .map(|block| {
+            let fields: Vec<_> = block
+                .iter()
+                .map(|(key, value)| format!("\"{key}\":{value}"))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        })
+        .collect();
+    format!("[{}]", blocks.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(x: &str) -> Piece {
+        Piece::Text(x.into(), None, false, None)
+    }
+
+    fn compact(x: &str, compact: &str) -> Piece {
+        Piece::Text(x.into(), None, false, Some(compact.into()))
+    }
+
+    fn sep(x: &str) -> Piece {
+        Piece::Separator(x.into())
+    }
+
+    #[test]
+    fn global_separator() {
+        assert_eq!("", assemble(&[], " | "));
+        assert_eq!("a", assemble(&[text("a")], " | "));
+        assert_eq!(
+            "a | b | c",
+            assemble(&[text("a"), text("b"), text("c")], " | ")
+        );
+    }
+
+    #[test]
+    fn explicit_separators() {
+        assert_eq!(
+            "a · b | c ║ d",
+            assemble(
+                &[
+                    text("a"),
+                    sep(" · "),
+                    text("b"),
+                    text("c"),
+                    sep(" ║ "),
+                    text("d")
+                ],
+                " | "
+            )
+        );
+        assert_eq!(
+            "a·║b",
+            assemble(&[text("a"), sep("·"), sep("║"), text("b")], " | ")
+        );
+    }
+
+    #[test]
+    fn dangling_separators_are_dropped() {
+        assert_eq!(
+            "a | b",
+            assemble(&[sep("<"), text("a"), text("b"), sep(">")], " | ")
+        );
+        assert_eq!("", assemble(&[sep("<"), sep(">")], " | "));
+    }
+
+    #[test]
+    fn hidden_pieces() {
+        assert_eq!(
+            "a | b",
+            assemble(&[text("a"), Piece::Hidden, text("b")], " | ")
+        );
+        assert_eq!(
+            "a · b",
+            assemble(&[text("a"), sep(" · "), Piece::Hidden, text("b")], " | ")
+        );
+        assert_eq!("", assemble(&[Piece::Hidden], " | "));
+    }
+
+    #[test]
+    fn reversed() {
+        let reverse = |pieces: &[Piece]| {
+            let names: Vec<_> = (0..pieces.len()).collect();
+            reverse(pieces, &names)
+        };
+        let pieces = [
+            Piece::Hidden,
+            text("a"),
+            sep(" · "),
+            text("b"),
+            text("sda 1% sdb 2%"),
+            Piece::Hidden,
+        ];
+        let (reversed, names) = reverse(&pieces);
+        assert_eq!("sda 1% sdb 2% | b · a", assemble(&reversed, " | "));
+        assert_eq!(vec![4, 3, 2, 1], names);
+
+        // A hidden section next to an explicit separator leaves it where it was.
+        let (reversed, _) = reverse(&[
+            sep("<"),
+            text("a"),
+            Piece::Hidden,
+            sep(" · "),
+            Piece::Hidden,
+            text("b"),
+            Piece::Hidden,
+        ]);
+        assert_eq!("b · a", assemble(&reversed, " | "));
+        let (reversed, _) = reverse(&[Piece::Hidden, Piece::Hidden]);
+        assert_eq!("", assemble(&reversed, " | "));
+
+        // Blocks keep their names.
+        let (reversed, names) = reverse(&[Piece::Hidden, text("a"), text("b"), Piece::Hidden]);
+        let names: Vec<_> = names
+            .iter()
+            .map(|x| Some(["x", "y", "z", "w"][*x]))
+            .collect();
+        assert_eq!(
+            "[{\"full_text\":\"b\",\"name\":\"z\"},{\"full_text\":\"a\",\"name\":\"y\"}]",
+            blocks(&reversed, &names)
+        );
+    }
+
+    #[test]
+    fn i3bar_blocks() {
+        assert_eq!("[]", blocks(&[], &[]));
+        assert_eq!(
+            "[{\"full_text\":\"a\"},{\"full_text\":\"b \\\"c\\\"\",\"name\":\"cpu\",\"color\":\"#ff0000\"}]",
+            blocks(
+                &[
+                    text("a"),
+                    Piece::Text("b \"c\"".into(), Some("#ff0000".into()), false, None)
+                ],
+                &[None, Some("cpu")]
+            )
+        );
+        assert_eq!(
+            "[{\"full_text\":\"a\",\"urgent\":true},{\"full_text\":\"b\"}]",
+            blocks(&[Piece::Text("a".into(), None, true, None), text("b")], &[])
+        );
+        // i3bar falls back to short_text when the bar gets too long.
+        assert_eq!(
+            "[{\"full_text\":\"Mon 17 Mar 14:02\",\"short_text\":\"14:02\"}]",
+            blocks(&[compact("Mon 17 Mar 14:02", "14:02")], &[])
+        );
+        // Blank and hidden sections leave no empty blocks.
+        assert_eq!(
+            "[{\"full_text\":\"a\"},{\"full_text\":\"b\"}]",
+            blocks(
+                &[text("a"), text(""), text("  "), Piece::Hidden, text("b")],
+                &[]
+            )
+        );
+        // Explicit separators replace the bar's, dangling ones are dropped.
+        assert_eq!(
+            "[{\"full_text\":\"a\",\"separator\":false},\
+             {\"full_text\":\" · \",\"separator\":false},{\"full_text\":\"b\"}]",
+            blocks(&[sep("<"), text("a"), sep(" · "), text("b"), sep(">")], &[])
+        );
+    }
+
+    #[test]
+    fn shrinking() {
+        let pieces = || {
+            vec![
+                compact("9.8/15.9 GiB", "62%"),
+                text("up 3d"),
+                compact("Mon 17 Mar 14:02", "14:02"),
+            ]
+        };
+        let shrunk = |order: &[usize], max: usize| {
+            let mut pieces = pieces();
+            shrink(&mut pieces, order, |x| {
+                assemble(x, " | ").chars().count() <= max
+            });
+            assemble(&pieces, " | ")
+        };
+
+        // Nothing changes while it fits.
+        assert_eq!(
+            "9.8/15.9 GiB | up 3d | Mon 17 Mar 14:02",
+            shrunk(&[2, 0], 40)
+        );
+        // In the given order, only as far as needed.
+        assert_eq!("9.8/15.9 GiB | up 3d | 14:02", shrunk(&[2, 0], 30));
+        assert_eq!("62% | up 3d | Mon 17 Mar 14:02", shrunk(&[0, 2], 30));
+        assert_eq!("62% | up 3d | 14:02", shrunk(&[2, 0], 20));
+        // Still too long even fully compacted, which is left to truncation.
+        assert_eq!("62% | up 3d | 14:02", shrunk(&[2, 1, 0], 10));
+    }
+}
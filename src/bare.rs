@@ -0,0 +1,208 @@
+//! Lets config enums take struct variants without parentheses, e.g. `MemoryUsage` for
+//! `MemoryUsage()`, so giving a unit variant its first field doesn't break existing configs.
+//! Only variants whose fields all have defaults can be written bare.
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{
+    de::{
+        self, value::MapDeserializer, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+        SeqAccess, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserialize, Deserializer,
+};
+
+/// For `#[serde(deserialize_with = "bare::variants")]` on a field holding an enum.
+pub fn variants<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    deserializer.deserialize_enum("", &[], Outer(PhantomData))
+}
+
+struct Outer<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for Outer<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an enum variant")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<T, A::Error> {
+        let (name, access) = data.variant_seed(Name)?;
+        T::deserialize(Tagged { name, access })
+    }
+}
+
+/// Reads a variant name, which formats like RON don't quote like other strings.
+struct Name;
+
+impl<'de> DeserializeSeed<'de> for Name {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl Visitor<'_> for Name {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a variant name")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<String, E> {
+        Ok(v.into())
+    }
+}
+
+/// A variant whose name has already been read, handed to the enum's derived implementation.
+struct Tagged<A> {
+    name: String,
+    access: A,
+}
+
+impl<'de, A: VariantAccess<'de>> Deserializer<'de> for Tagged<A> {
+    type Error = A::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, A::Error> {
+        visitor.visit_enum(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+impl<'de, A: VariantAccess<'de>> EnumAccess<'de> for Tagged<A> {
+    type Error = A::Error;
+    type Variant = Lenient<A>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, Lenient<A>), A::Error> {
+        let name = seed.deserialize(self.name.into_deserializer())?;
+        Ok((name, Lenient(self.access)))
+    }
+}
+
+/// Passes everything through, except that a struct variant without its fields gets their
+/// defaults.
+struct Lenient<A>(A);
+
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for Lenient<A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.0.unit_variant()
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, A::Error> {
+        self.0.newtype_variant_seed(seed)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        self.0.tuple_variant(len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        let mut visitor = Some(visitor);
+        match self.0.struct_variant(fields, Once(&mut visitor)) {
+            // The visitor is only left untouched if there were no fields to read at all.
+            Err(e) => match visitor {
+                Some(visitor) => visitor
+                    .visit_map(MapDeserializer::<_, A::Error>::new(std::iter::empty::<(
+                        String,
+                        String,
+                    )>(
+                    )))
+                    .map_err(|_| e),
+                None => Err(e),
+            },
+            ok => ok,
+        }
+    }
+}
+
+/// Hands the fields to the wrapped visitor, leaving `None` behind once it has been used.
+struct Once<'a, V>(&'a mut Option<V>);
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for Once<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &*self.0 {
+            Some(visitor) => visitor.expecting(f),
+            None => f.write_str("a struct variant"),
+        }
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, map: M) -> Result<V::Value, M::Error> {
+        self.0.take().expect("visited once").visit_map(map)
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, seq: S) -> Result<V::Value, S::Error> {
+        self.0.take().expect("visited once").visit_seq(seq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    enum Shape {
+        Dot,
+        Circle {
+            #[serde(default)]
+            radius: u32,
+        },
+        Square {
+            side: u32,
+        },
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Holder {
+        #[serde(deserialize_with = "super::variants")]
+        shape: Shape,
+    }
+
+    fn parse(s: &str) -> Result<Shape, ron::error::SpannedError> {
+        ron::from_str::<Holder>(s).map(|x| x.shape)
+    }
+
+    #[test]
+    fn variants() {
+        assert_eq!(Shape::Dot, parse("(shape: Dot)").unwrap());
+        assert_eq!(
+            Shape::Circle { radius: 0 },
+            parse("(shape: Circle)").unwrap()
+        );
+        assert_eq!(
+            Shape::Circle { radius: 0 },
+            parse("(shape: Circle())").unwrap()
+        );
+        assert_eq!(
+            Shape::Circle { radius: 2 },
+            parse("(shape: Circle(radius: 2))").unwrap()
+        );
+        assert_eq!(
+            Shape::Square { side: 3 },
+            parse("(shape: Square(side: 3))").unwrap()
+        );
+        assert!(parse("(shape: Square)").is_err());
+        assert!(parse("(shape: Circle(radius: -1))").is_err());
+        assert!(parse("(shape: Hexagon)").is_err());
+    }
+}
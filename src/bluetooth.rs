@@ -0,0 +1,85 @@
+//! Bluetooth adapters and devices as BlueZ lists them, see [`Connection::bluetooth`].
+//!
+//! [`Connection::bluetooth`]: crate::dbus::Connection::bluetooth
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Status {
+    /// Whether any adapter is powered.
+    pub powered: bool,
+    /// Every device BlueZ knows about, connected or not.
+    pub devices: Vec<Device>,
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Device {
+    /// e.g. "00:1A:7D:DA:71:13".
+    pub address: String,
+    pub connected: bool,
+    /// Charge in percent, for devices reporting it.
+    pub battery: Option<u8>,
+}
+
+impl Status {
+    /// "BT off" while no adapter is powered, otherwise "BT " and the number of connected
+    /// devices. With `device_mac` only that device counts, followed by its battery level if it
+    /// has one, e.g. "BT 1 80%". Also returns the number.
+    pub fn format(&self, device_mac: Option<&str>) -> (String, usize) {
+        if !self.powered {
+            return ("BT off".into(), 0);
+        }
+
+        let mut connected = self
+            .devices
+            .iter()
+            .filter(|x| x.connected)
+            .filter(|x| device_mac.is_none_or(|mac| x.address.eq_ignore_ascii_case(mac)));
+        match device_mac {
+            Some(_) => match connected.next() {
+                Some(Device {
+                    battery: Some(battery),
+                    ..
+                }) => (format!("BT 1 {battery}%"), 1),
+                Some(_) => ("BT 1".into(), 1),
+                None => ("BT 0".into(), 0),
+            },
+            None => {
+                let count = connected.count();
+                (format!("BT {count}"), count)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting() {
+        let device = |address: &str, connected, battery| Device {
+            address: address.into(),
+            connected,
+            battery,
+        };
+        let mut status = Status {
+            powered: true,
+            devices: vec![
+                device("00:1A:7D:DA:71:13", true, Some(80)),
+                device("AC:80:0A:12:34:56", true, None),
+                device("F4:4E:FD:00:00:01", false, Some(20)),
+            ],
+        };
+
+        assert_eq!(("BT 2".into(), 2), status.format(None));
+        assert_eq!(
+            ("BT 1 80%".into(), 1),
+            status.format(Some("00:1a:7d:da:71:13"))
+        );
+        assert_eq!(("BT 1".into(), 1), status.format(Some("AC:80:0A:12:34:56")));
+        assert_eq!(("BT 0".into(), 0), status.format(Some("F4:4E:FD:00:00:01")));
+        assert_eq!(("BT 0".into(), 0), status.format(Some("00:00:00:00:00:00")));
+
+        status.powered = false;
+        assert_eq!(("BT off".into(), 0), status.format(None));
+    }
+}
@@ -0,0 +1,363 @@
+use std::time::{Duration, Instant};
+
+use crate::bar::Piece;
+
+/// Added to a section's priority for every tick it was skipped, so low priority sections
+/// still get evaluated every few ticks.
+const AGE_BOOST: u32 = 32;
+
+/// Spreads section evaluation across ticks when a tick budget is set.
+///
+/// Sections are evaluated in priority order until the budget is used up. The rest keep
+/// showing their last output and move up the order for every tick they're skipped.
+/// At least one section is evaluated per tick, so every section is reached eventually,
+/// however small the budget. Sections without previous output are always evaluated.
+///
+/// Sections with an interval of their own are only evaluated once it has passed, the others on
+/// regular ticks. Both keep showing their last output in between.
+#[derive(Default)]
+pub struct TickScheduler {
+    slots: Vec<Slot>,
+    intervals: Vec<Option<Duration>>,
+}
+
+#[derive(Default)]
+struct Slot {
+    piece: Option<Piece>,
+    /// Ticks since this section was last evaluated.
+    age: u32,
+    /// When a section with an interval of its own is evaluated next.
+    due: Option<Instant>,
+}
+
+impl TickScheduler {
+    /// A scheduler for sections with the given intervals, `None` for sections evaluated on
+    /// regular ticks.
+    pub fn new(intervals: Vec<Option<Duration>>) -> Self {
+        Self {
+            slots: Vec::new(),
+            intervals,
+        }
+    }
+
+    /// Produces this tick's pieces, in section order. `evaluate` renders a section by index and
+    /// reports how long it took. `regular` is set when the regular update is due rather than
+    /// only sections with intervals of their own. Without a budget, every section that's due is
+    /// evaluated in `order`. With one, they go by priority, raised for sections the budget
+    /// skipped before, and ties keep `order`.
+    pub fn tick(
+        &mut self,
+        order: &[usize],
+        priorities: &[u8],
+        budget: Option<Duration>,
+        now: Instant,
+        regular: bool,
+        mut evaluate: impl FnMut(usize) -> (Piece, Duration),
+    ) -> Vec<Piece> {
+        if self.slots.len() != priorities.len() {
+            self.slots = priorities.iter().map(|_| Slot::default()).collect();
+        }
+
+        let mut order = order.to_vec();
+        if budget.is_some() {
+            // Sorting is stable, so ties keep the given order.
+            order.sort_by_key(|&index| std::cmp::Reverse(self.urgency(index, priorities[index])));
+        }
+
+        let (mut spent, mut evaluated) = (Duration::ZERO, 0);
+        for index in order {
+            let interval = self.intervals.get(index).copied().flatten();
+            let slot = &mut self.slots[index];
+            let waiting = match interval {
+                Some(_) => slot.due.is_some_and(|x| now < x),
+                None => !regular,
+            };
+            if slot.piece.is_some() && waiting {
+                continue;
+            }
+            if slot.piece.is_some() && evaluated > 0 && budget.is_some_and(|x| spent >= x) {
+                slot.age = slot.age.saturating_add(1);
+                continue;
+            }
+
+            let (piece, elapsed) = evaluate(index);
+            spent += elapsed;
+            evaluated += 1;
+            slot.piece = Some(piece);
+            slot.age = 0;
+            if let Some(interval) = interval {
+                // Scheduled from the previous due time like regular ticks, so they don't drift.
+                let due = slot.due.unwrap_or(now) + interval;
+                slot.due = Some(if due <= now { now + interval } else { due });
+            }
+        }
+
+        self.slots.iter().filter_map(|x| x.piece.clone()).collect()
+    }
+
+    /// Makes a section with an interval of its own due on the next tick, after which its
+    /// schedule starts over. Others are updated on every regular tick anyway.
+    pub fn refresh(&mut self, index: usize) {
+        if let Some(slot) = self.slots.get_mut(index) {
+            slot.due = None;
+        }
+    }
+
+    /// The earliest time after `now` a section with an interval of its own is due. Sections
+    /// that are overdue because the budget ran out wait for the next tick.
+    pub fn next_due(&self, now: Instant) -> Option<Instant> {
+        self.slots
+            .iter()
+            .filter_map(|x| x.due)
+            .filter(|x| *x > now)
+            .min()
+    }
+
+    fn urgency(&self, index: usize, priority: u8) -> u32 {
+        (priority as u32).saturating_add(self.slots[index].age.saturating_mul(AGE_BOOST))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn in_order(count: usize) -> Vec<usize> {
+        (0..count).collect()
+    }
+
+    /// Runs a tick where section `i` takes `durations[i]` and renders as its index and how
+    /// many times it has been evaluated, returning the rendered texts.
+    fn tick(
+        scheduler: &mut TickScheduler,
+        counts: &mut [u32],
+        priorities: &[u8],
+        durations: &[Duration],
+        budget: Option<Duration>,
+    ) -> Vec<String> {
+        scheduler
+            .tick(
+                &in_order(priorities.len()),
+                priorities,
+                budget,
+                Instant::now(),
+                true,
+                |index| {
+                    counts[index] += 1;
+                    (
+                        Piece::Text(format!("{index}:{}", counts[index]), None, false, None),
+                        durations[index],
+                    )
+                },
+            )
+            .into_iter()
+            .map(|x| match x {
+                Piece::Text(x, ..) => x,
+                _ => panic!("expected text"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unlimited() {
+        let mut scheduler = TickScheduler::default();
+        let mut counts = [0; 3];
+        let durations = [100 * MS; 3];
+
+        for _ in 0..3 {
+            tick(&mut scheduler, &mut counts, &[0, 255, 10], &durations, None);
+        }
+        assert_eq!([3, 3, 3], counts);
+    }
+
+    #[test]
+    fn evaluation_order() {
+        let mut scheduler = TickScheduler::default();
+        let mut evaluated = Vec::new();
+        let pieces = scheduler.tick(&[2, 0, 1], &[128; 3], None, Instant::now(), true, |index| {
+            evaluated.push(index);
+            (Piece::Text(index.to_string(), None, false, None), MS)
+        });
+
+        assert_eq!(vec![2, 0, 1], evaluated);
+        // Pieces stay in section order.
+        for (index, piece) in pieces.into_iter().enumerate() {
+            match piece {
+                Piece::Text(x, ..) => assert_eq!(index.to_string(), x),
+                _ => panic!("expected text"),
+            }
+        }
+    }
+
+    #[test]
+    fn budget_defers_and_caches() {
+        let mut scheduler = TickScheduler::default();
+        let mut counts = [0; 3];
+        let priorities = [10, 255, 10];
+        let durations = [4 * MS, 1 * MS, 4 * MS];
+        let budget = Some(5 * MS);
+
+        // Nothing to show yet, so everything is evaluated regardless of the budget.
+        assert_eq!(
+            vec!["0:1", "1:1", "2:1"],
+            tick(&mut scheduler, &mut counts, &priorities, &durations, budget)
+        );
+        assert_eq!(
+            vec!["0:2", "1:2", "2:1"],
+            tick(&mut scheduler, &mut counts, &priorities, &durations, budget)
+        );
+        // Section 2 was skipped last time, so it now beats section 0.
+        assert_eq!(
+            vec!["0:2", "1:3", "2:2"],
+            tick(&mut scheduler, &mut counts, &priorities, &durations, budget)
+        );
+    }
+
+    #[test]
+    fn no_starvation() {
+        let mut scheduler = TickScheduler::default();
+        let mut counts = [0; 4];
+        let priorities = [255, 255, 0, 0];
+        // Every section blows the budget on its own.
+        let durations = [10 * MS; 4];
+
+        for _ in 0..100 {
+            tick(
+                &mut scheduler,
+                &mut counts,
+                &priorities,
+                &durations,
+                Some(MS),
+            );
+        }
+
+        assert_eq!(103, counts.iter().sum::<u32>());
+        for count in counts {
+            assert!(count >= 10, "starved: {counts:?}");
+        }
+        assert!(counts[0] > counts[2]);
+    }
+
+    #[test]
+    fn intervals() {
+        let mut scheduler = TickScheduler::new(vec![None, Some(2500 * MS), Some(500 * MS)]);
+        let mut counts = [0; 3];
+        let start = Instant::now();
+        let mut tick = |millis: u32, regular| {
+            let pieces = scheduler.tick(
+                &in_order(3),
+                &[128; 3],
+                None,
+                start + MS * millis,
+                regular,
+                |index| {
+                    counts[index] += 1;
+                    (
+                        Piece::Text(format!("{index}:{}", counts[index]), None, false, None),
+                        MS,
+                    )
+                },
+            );
+            let texts: Vec<_> = pieces
+                .into_iter()
+                .map(|x| match x {
+                    Piece::Text(x, ..) => x,
+                    _ => panic!("expected text"),
+                })
+                .collect();
+            (texts, scheduler.next_due(start + MS * millis))
+        };
+        let at = |millis: u32| Some(start + MS * millis);
+
+        // Everything is evaluated at first, then sections run when they're due.
+        assert_eq!(
+            (vec!["0:1".into(), "1:1".into(), "2:1".into()], at(500)),
+            tick(0, true)
+        );
+        assert_eq!(
+            (vec!["0:1".into(), "1:1".into(), "2:2".into()], at(1000)),
+            tick(500, false)
+        );
+        assert_eq!(
+            (vec!["0:2".into(), "1:1".into(), "2:3".into()], at(1500)),
+            tick(1000, true)
+        );
+        assert_eq!(
+            (vec!["0:3".into(), "1:1".into(), "2:3".into()], at(1500)),
+            tick(1200, true)
+        );
+        // Late ticks don't shift the schedule, missed ones aren't caught up on.
+        assert_eq!(
+            (vec!["0:3".into(), "1:2".into(), "2:4".into()], at(3100)),
+            tick(2600, false)
+        );
+        assert_eq!(
+            (vec!["0:3".into(), "1:2".into(), "2:5".into()], at(4100)),
+            tick(3600, false)
+        );
+        assert_eq!(
+            (vec!["0:3".into(), "1:2".into(), "2:5".into()], at(4100)),
+            tick(3700, false)
+        );
+    }
+
+    #[test]
+    fn refresh() {
+        let mut scheduler = TickScheduler::new(vec![Some(10_000 * MS), Some(10_000 * MS)]);
+        let mut counts = [0; 2];
+        let start = Instant::now();
+        let mut tick = |millis: u32, scheduler: &mut TickScheduler| {
+            scheduler.tick(
+                &in_order(2),
+                &[128; 2],
+                None,
+                start + MS * millis,
+                false,
+                |index| {
+                    counts[index] += 1;
+                    (Piece::Text(String::new(), None, false, None), MS)
+                },
+            );
+            counts
+        };
+
+        assert_eq!([1, 1], tick(0, &mut scheduler));
+        assert_eq!([1, 1], tick(1000, &mut scheduler));
+        // Refreshed right away, then again a full interval later.
+        scheduler.refresh(1);
+        scheduler.refresh(5);
+        assert_eq!([1, 2], tick(2000, &mut scheduler));
+        assert_eq!(
+            Some(start + MS * 10_000),
+            scheduler.next_due(start + MS * 2000)
+        );
+        assert_eq!([2, 2], tick(10_000, &mut scheduler));
+        assert_eq!([2, 3], tick(12_000, &mut scheduler));
+    }
+
+    #[test]
+    fn section_count_change() {
+        let mut scheduler = TickScheduler::default();
+        let mut counts = [0; 3];
+
+        tick(
+            &mut scheduler,
+            &mut counts,
+            &[1, 1],
+            &[MS; 3],
+            Some(10 * MS),
+        );
+        assert_eq!(
+            vec!["0:2", "1:2", "2:1"],
+            tick(
+                &mut scheduler,
+                &mut counts,
+                &[1, 1, 1],
+                &[MS; 3],
+                Some(10 * MS)
+            )
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Memory use and limit of the cgroup stringbar runs in, which is what counts inside containers
+//! where the host's totals are misleading. Only cgroup v2 is supported.
+
+use std::{fs, path::Path};
+
+/// Where the cgroup v2 hierarchy is mounted. Inside a container with its own cgroup namespace
+/// the container's cgroup is the root.
+pub const CGROUP_PATH: &str = "/sys/fs/cgroup";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Memory {
+    /// Bytes in use.
+    pub current: u64,
+    /// `None` if unlimited.
+    pub max: Option<u64>,
+}
+
+/// Reads `memory.current` and `memory.max` in `root`, `None` if either is missing or malformed,
+/// as in the root cgroup of a host.
+pub fn read_memory(root: &Path) -> Option<Memory> {
+    let read = |name| fs::read_to_string(root.join(name)).ok();
+    Some(Memory {
+        current: parse_current(&read("memory.current")?)?,
+        max: parse_max(&read("memory.max")?)?,
+    })
+}
+
+fn parse_current(content: &str) -> Option<u64> {
+    content.trim().parse().ok()
+}
+
+/// A byte count, or "max" for no limit.
+fn parse_max(content: &str) -> Option<Option<u64>> {
+    match content.trim() {
+        "max" => Some(None),
+        x => x.parse().ok().map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        assert_eq!(Some(217088000), parse_current("217088000\n"));
+        assert_eq!(None, parse_current(""));
+        assert_eq!(None, parse_current("-1\n"));
+
+        assert_eq!(Some(Some(536870912)), parse_max("536870912\n"));
+        assert_eq!(Some(None), parse_max("max\n"));
+        assert_eq!(None, parse_max("unlimited\n"));
+        assert_eq!(None, parse_max("\n"));
+    }
+
+    #[test]
+    fn memory_files() {
+        let root = std::env::temp_dir().join(format!("stringbar-cgroup-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        assert_eq!(None, read_memory(&root));
+
+        fs::write(root.join("memory.current"), "1048576\n").unwrap();
+        assert_eq!(None, read_memory(&root));
+
+        fs::write(root.join("memory.max"), "max\n").unwrap();
+        assert_eq!(
+            Some(Memory {
+                current: 1048576,
+                max: None
+            }),
+            read_memory(&root)
+        );
+
+        fs::write(root.join("memory.max"), "4194304\n").unwrap();
+        assert_eq!(Some(Some(4194304)), read_memory(&root).map(|x| x.max));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
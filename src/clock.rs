@@ -0,0 +1,83 @@
+//! Where the time comes from. The main loop reads the clock once per tick, so every section
+//! and the quiet hours check agree on the time, and tests can substitute their own.
+
+use std::time::Instant;
+
+use chrono::{DateTime, FixedOffset, Local};
+
+pub trait Clock {
+    /// Wall clock time in the local timezone, with the offset that was in effect.
+    fn now_local(&self) -> DateTime<FixedOffset>;
+    /// For measuring durations, unaffected by wall clock changes like NTP adjustments.
+    fn monotonic(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_local(&self) -> DateTime<FixedOffset> {
+        Local::now().fixed_offset()
+    }
+
+    fn monotonic(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that only moves when told to, for tests.
+#[cfg(test)]
+pub struct FakeClock {
+    local: std::cell::Cell<DateTime<FixedOffset>>,
+    monotonic: std::cell::Cell<Instant>,
+}
+
+#[cfg(test)]
+impl FakeClock {
+    /// Starts at `local`, given in RFC 3339 like "2024-03-31T01:59:59+01:00".
+    pub fn new(local: &str) -> Self {
+        Self {
+            local: DateTime::parse_from_rfc3339(local).unwrap().into(),
+            monotonic: Instant::now().into(),
+        }
+    }
+
+    pub fn advance(&self, by: std::time::Duration) {
+        self.local.set(self.local.get() + by);
+        self.monotonic.set(self.monotonic.get() + by);
+    }
+
+    /// Changes the UTC offset without moving time, like a DST switch.
+    pub fn set_offset(&self, offset_secs: i32) {
+        let offset = FixedOffset::east_opt(offset_secs).unwrap();
+        self.local.set(self.local.get().with_timezone(&offset));
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now_local(&self) -> DateTime<FixedOffset> {
+        self.local.get()
+    }
+
+    fn monotonic(&self) -> Instant {
+        self.monotonic.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn fake_clock() {
+        let clock = FakeClock::new("2024-03-31T01:59:59+01:00");
+        let start = clock.monotonic();
+
+        clock.advance(Duration::from_secs(1));
+        clock.set_offset(2 * 3600);
+        assert_eq!("2024-03-31T03:00:00+02:00", clock.now_local().to_rfc3339());
+        assert_eq!(Duration::from_secs(1), clock.monotonic() - start);
+    }
+}
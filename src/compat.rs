@@ -0,0 +1,180 @@
+//! Built-in configs approximating other status tools, for trying stringbar without writing a
+//! config first.
+//!
+//! Deviations from the originals: memory and disk show used out of total instead of a single
+//! value, and i3status' load shows all three averages instead of the 1 minute one.
+
+use std::{collections::HashMap, thread};
+
+use crate::{
+    bar,
+    clock::{Clock, SystemClock},
+    config::{Config, Decoration, Module, Section},
+    extremes::Extremes,
+    provider::{SysinfoProvider, SystemProvider},
+    rates::Rates,
+    render::{self, Context},
+    timer::Timers,
+    urgency::Urgency,
+    validity::Validity,
+};
+
+pub const MODES: [&str; 2] = ["slstatus", "i3status"];
+
+/// The built-in config for `mode`, one of [`MODES`].
+pub fn config(mode: &str) -> Option<Config> {
+    let labeled = |label: &str, module: Module| Section {
+        decoration: Decoration {
+            before: Some(label.into()),
+            ..Decoration::default()
+        },
+        ..module.into()
+    };
+    let root = || Module::DiskUsage {
+        name: "/".into(),
+        inodes: false,
+    };
+
+    let (update_interval_ms, sections) = match mode {
+        "slstatus" => (
+            1000,
+            vec![
+                labeled("load ", Module::LoadAverage),
+                labeled("mem ", Module::MemoryUsage { basis: None }),
+                labeled("/ ", root()),
+                Module::Timestamp {
+                    template: "%F %T".into(),
+                }
+                .into(),
+            ],
+        ),
+        "i3status" => (
+            5000,
+            vec![
+                labeled("/ ", root()),
+                Module::LoadAverage.into(),
+                Module::MemoryUsage { basis: None }.into(),
+                Module::Timestamp {
+                    template: "%Y-%m-%d %H:%M:%S".into(),
+                }
+                .into(),
+            ],
+        ),
+        _ => return None,
+    };
+
+    Some(Config {
+        update_interval_ms: update_interval_ms.into(),
+        sections,
+        ..Config::default()
+    })
+}
+
+/// Prints the bar for `mode` to stdout until killed. The config file is ignored.
+pub fn run(mode: &str) -> Result<(), String> {
+    let config = config(mode).ok_or_else(|| {
+        format!(
+            "unknown compat mode {mode}, expected one of: {}",
+            MODES.join(", ")
+        )
+    })?;
+
+    let mut provider = SysinfoProvider::new();
+    let mut extremes = Extremes::default();
+    let mut validity = Validity::default();
+    loop {
+        println!(
+            "{}",
+            render_line(&config, &mut provider, &mut extremes, &mut validity)
+        );
+        thread::sleep(config.update_interval_ms.0);
+    }
+}
+
+fn render_line(
+    config: &Config,
+    provider: &mut dyn SystemProvider,
+    extremes: &mut Extremes,
+    validity: &mut Validity,
+) -> String {
+    let clock = SystemClock;
+    let mut ctx = Context {
+        section: 0,
+        provider,
+        timers: &Timers::new(),
+        extremes,
+        validity,
+        rates: &mut Rates::default(),
+        values: &mut HashMap::new(),
+        readings: &mut HashMap::new(),
+        urgency: &mut Urgency::default(),
+        now: clock.monotonic(),
+        local: clock.now_local(),
+    };
+    ctx.provider.begin_tick();
+
+    let pieces: Vec<_> = config
+        .sections
+        .iter()
+        .enumerate()
+        .map(|(index, section)| {
+            ctx.section = index;
+            render::render_section(section, config, &mut ctx)
+        })
+        .collect();
+
+    bar::assemble(&pieces, &config.separator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDateTime;
+
+    use crate::provider::FakeProvider;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    #[test]
+    fn line_shape() {
+        let mut provider = FakeProvider::default();
+        provider
+            .set_memory(3 * GIB / 2, 8 * GIB)
+            .set_load_average(0.52, 0.58, 0.59)
+            .add_disk("/dev/nvme0n1p2", 200 * GIB, 150 * GIB, false)
+            .mounted_at("/");
+
+        let fixtures = [
+            (
+                "slstatus",
+                "load 0.52 0.58 0.59 | mem 1.50/8.00 GiB | / 50.00/200.00 GiB | ",
+                "%F %T",
+            ),
+            (
+                "i3status",
+                "/ 50.00/200.00 GiB | 0.52 0.58 0.59 | 1.50/8.00 GiB | ",
+                "%Y-%m-%d %H:%M:%S",
+            ),
+        ];
+
+        for (mode, prefix, date_format) in fixtures {
+            let line = render_line(
+                &config(mode).unwrap(),
+                &mut provider,
+                &mut Extremes::default(),
+                &mut Validity::default(),
+            );
+
+            let date = line
+                .strip_prefix(prefix)
+                .unwrap_or_else(|| panic!("{line}"));
+            assert!(NaiveDateTime::parse_from_str(date, date_format).is_ok());
+        }
+
+        assert!(config("dwmblocks").is_none());
+        for mode in MODES {
+            assert!(config(mode).is_some());
+        }
+    }
+}
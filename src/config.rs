@@ -0,0 +1,2069 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bittenhumans::consts::Magnitude;
+use chrono::format::{Item, StrftimeItems};
+use directories::BaseDirs;
+use ron::{extensions::Extensions, ser::PrettyConfig};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    bare,
+    disks::Spared,
+    duration::{Millis, Secs},
+    error::StringbarError,
+    expr::{self, Expr},
+    maildir, mpd, post,
+    provider::DiskInfo,
+    publicip::{self, MIN_REFRESH_INTERVAL_S},
+    schedule::TimeWindows,
+    sha256,
+    template::{self, Kind},
+    unit::Unit,
+};
+
+/// What `Temperature` sections with `warn_above` turn while it's exceeded.
+pub const TEMPERATURE_WARNING_COLOR: &str = "#ff0000";
+
+/// Lowest accepted update interval, anything below is clamped to this.
+pub const MIN_UPDATE_INTERVAL_MS: u64 = 100;
+
+/// Placeholders available in [`Section::usage_format`].
+pub const USAGE_PLACEHOLDERS: [(&str, Kind); 6] = [
+    ("used", Kind::Bytes),
+    ("total", Kind::Bytes),
+    ("free", Kind::Bytes),
+    ("percent", Kind::Decimal),
+    ("used_raw", Kind::Plain),
+    ("total_raw", Kind::Plain),
+];
+
+/// Placeholders of `compact_template` for sections other than timestamps.
+pub const COMPACT_PLACEHOLDERS: [(&str, Kind); 1] = [("value", Kind::Decimal)];
+
+#[derive(Serialize, Deserialize)]
+pub struct Config {
+    pub separator: String,
+    /// Replacement for occurrences of `separator` inside module output.
+    /// `None` leaves module output untouched.
+    #[serde(default)]
+    pub escape_separator: Option<String>,
+    pub update_interval_ms: Millis,
+    pub decimal_data_units: bool,
+    /// Digit grouping separator for exact byte counts.
+    #[serde(default = "default_thousands_separator")]
+    pub thousands_separator: String,
+    /// Drop zeros after the decimal point, e.g. "100%" instead of "100.0%".
+    #[serde(default)]
+    pub trim_trailing_zeros: bool,
+    /// Shown while frozen through the control socket. `None` blanks the bar.
+    pub frozen_text: Option<String>,
+    /// Times of day during which the bar only updates every `quiet_update_interval_ms`.
+    pub quiet_hours: Option<TimeWindows>,
+    #[serde(default = "default_quiet_update_interval_ms")]
+    pub quiet_update_interval_ms: Millis,
+    /// Output changes per second, later ones are held back and coalesced so only the latest
+    /// is shown. `None` emits every update.
+    pub max_emissions_per_sec: Option<u32>,
+    /// Hand the output every update, even if nothing changed, e.g. for a consumer that
+    /// expects a line every `update_interval_ms`.
+    #[serde(default)]
+    pub always_publish: bool,
+    /// Evaluation time per tick. Sections that don't fit are deferred to later ticks
+    /// by `priority`, showing their previous output meanwhile. `None` evaluates everything.
+    pub max_tick_budget_ms: Option<Millis>,
+    #[serde(default, deserialize_with = "bare::variants")]
+    pub output: OutputBackend,
+    /// Which display server `Auto` output picks when both `WAYLAND_DISPLAY` and `DISPLAY` are
+    /// set, e.g. under XWayland.
+    #[serde(default)]
+    pub preferred_display: DisplayServer,
+    /// Number of distinct lines kept for the control socket's history command. 0 disables it.
+    #[serde(default = "default_history_length")]
+    pub history_length: usize,
+    /// Appended to the last valid output shown in place of a value outside a section's
+    /// `valid_range`.
+    #[serde(default = "default_invalid_value_marker")]
+    pub invalid_value_marker: String,
+    /// What `MemoryUsage` sections compare against unless they set their own `basis`.
+    #[serde(default)]
+    pub memory_basis: MemoryBasis,
+    /// How config changes are noticed. Only read at startup.
+    #[serde(default)]
+    pub reload: ReloadStrategy,
+    /// Show desktop notifications for failed reloads, sections that keep failing and output
+    /// errors.
+    #[serde(default)]
+    pub error_notifications: bool,
+    /// How long after stringbar started thresholds don't make sections urgent yet, e.g. while
+    /// everything spikes right after login. Reloads don't restart it.
+    #[serde(default)]
+    pub startup_grace_secs: Secs,
+    pub sections: Vec<Section>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            separator: " | ".into(),
+            escape_separator: None,
+            update_interval_ms: 1000.into(),
+            decimal_data_units: false,
+            thousands_separator: default_thousands_separator(),
+            trim_trailing_zeros: false,
+            frozen_text: None,
+            quiet_hours: None,
+            quiet_update_interval_ms: default_quiet_update_interval_ms(),
+            max_emissions_per_sec: None,
+            always_publish: false,
+            max_tick_budget_ms: None,
+            output: OutputBackend::default(),
+            preferred_display: DisplayServer::default(),
+            history_length: default_history_length(),
+            invalid_value_marker: default_invalid_value_marker(),
+            memory_basis: MemoryBasis::default(),
+            reload: ReloadStrategy::default(),
+            error_notifications: false,
+            startup_grace_secs: Secs::default(),
+            sections: vec![
+                Section {
+                    decoration: Decoration {
+                        before: Some("dram ".into()),
+                        after: None,
+                        fg: None,
+                        bg: None,
+                    },
+                    ..Module::MemoryUsage { basis: None }.into()
+                },
+                Section {
+                    decoration: Decoration {
+                        before: Some("sda ".into()),
+                        after: None,
+                        fg: None,
+                        bg: None,
+                    },
+                    ..Module::DiskUsage {
+                        name: "/dev/sda".into(),
+                        inodes: false,
+                    }
+                    .into()
+                },
+                Section {
+                    decoration: Decoration {
+                        before: Some("total ".into()),
+                        after: None,
+                        fg: None,
+                        bg: None,
+                    },
+                    ..Module::DiskUsageTotal {
+                        include_removables: false,
+                    }
+                    .into()
+                },
+                Module::Timestamp {
+                    template: "%d/%m/%Y %H:%M".into(),
+                }
+                .into(),
+            ],
+        }
+    }
+}
+
+/// Backends showing the bar take `reverse_order`, which lists the sections right to left, e.g.
+/// to keep the first section flush right in a bar that aligns text to the right.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug)]
+pub enum OutputBackend {
+    /// Sets the root window name like xsetroot does, for dwm and similar window managers.
+    Xsetroot {
+        /// Color sections with the status2d patch's `^c#rrggbb^` sequences.
+        #[serde(default)]
+        status2d: bool,
+        /// Prefix sections with their 1-based index as a raw byte, for the statuscmd patch.
+        #[serde(default)]
+        statuscmd: bool,
+        #[serde(default)]
+        post: Option<BackendPost>,
+        /// Spawn xsetroot for every update instead of talking to the X server directly, e.g.
+        /// for a wrapper script named xsetroot. Also done when the X server can't be reached
+        /// directly, e.g. over TCP.
+        #[serde(default)]
+        spawn: bool,
+        #[serde(default)]
+        reverse_order: bool,
+        /// The properties set to the status text when talking to the X server directly, in
+        /// order. xsetroot only sets `WM_NAME` as UTF-8.
+        #[serde(default = "default_name_atoms")]
+        name_atoms: Vec<NameAtom>,
+        /// Stands in for characters outside Latin-1 in `WmNameLatin1`.
+        #[serde(default = "default_latin1_replacement")]
+        latin1_replacement: char,
+        /// Warn when the status text likely doesn't fit next to dwm's tags. Needs the direct
+        /// connection to the X server for the screen's width.
+        #[serde(default)]
+        width_check: Option<WidthCheck>,
+    },
+    /// Prints the i3bar protocol to stdout, for i3bar and swaybar, with a block for every
+    /// section. The bar draws separators between them instead of `separator`.
+    #[serde(alias = "I3Bar")]
+    I3bar {
+        #[serde(default)]
+        reverse_order: bool,
+    },
+    /// Prints each update as a line to stdout, for bars reading a command's output.
+    Stdout {
+        #[serde(default)]
+        reverse_order: bool,
+    },
+    /// Writes each update to a file or FIFO, e.g. for lemonbar or dzen2 reading a FIFO.
+    /// Replaces the file's contents unless `append` is set.
+    File {
+        path: String,
+        #[serde(default)]
+        append: bool,
+        #[serde(default)]
+        reverse_order: bool,
+    },
+    /// No display output. Sections are still evaluated for the control socket and history.
+    None,
+    /// Picks one of the others from the environment at startup, see [`crate::output::detect`].
+    #[default]
+    Auto,
+}
+
+/// Estimates the status text's width in pixels from the number of characters, to tell when dwm
+/// likely cuts it off.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct WidthCheck {
+    /// Average width of a character in the bar's font, in pixels.
+    pub glyph_width: u16,
+    /// Pixels of the screen's width the bar needs for other things, e.g. dwm's tags and
+    /// layout symbol.
+    #[serde(default)]
+    pub reserved: u16,
+}
+
+/// A root window property `Xsetroot` sets to the status text.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum NameAtom {
+    /// `WM_NAME` holding the text's UTF-8 as is, like xsetroot sets it and dwm reads it.
+    WmName,
+    /// `WM_NAME` in Latin-1 as the ICCCM has it, for consumers decoding it that way. Replaces
+    /// `WmName` if both are given.
+    WmNameLatin1,
+    /// `_NET_WM_NAME` as UTF8_STRING, as EWMH has it.
+    NetWmName,
+}
+
+pub fn default_name_atoms() -> Vec<NameAtom> {
+    vec![NameAtom::WmName, NameAtom::NetWmName]
+}
+
+fn default_latin1_replacement() -> char {
+    '?'
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum DisplayServer {
+    #[default]
+    Wayland,
+    X11,
+}
+
+impl OutputBackend {
+    /// Whether the backend shows the bar, otherwise the assembled line isn't needed.
+    pub fn wants_text(&self) -> bool {
+        match self {
+            Self::Xsetroot { .. }
+            | Self::I3bar { .. }
+            | Self::Stdout { .. }
+            | Self::File { .. }
+            | Self::Auto => true,
+            Self::None => false,
+        }
+    }
+
+    pub fn reverse_order(&self) -> bool {
+        match *self {
+            Self::Xsetroot { reverse_order, .. }
+            | Self::I3bar { reverse_order }
+            | Self::Stdout { reverse_order }
+            | Self::File { reverse_order, .. } => reverse_order,
+            Self::None | Self::Auto => false,
+        }
+    }
+
+    /// Whether status2d and statuscmd markup is enabled.
+    pub fn dwm_markup(&self) -> (bool, bool) {
+        match *self {
+            Self::Xsetroot {
+                status2d,
+                statuscmd,
+                ..
+            } => (status2d, statuscmd),
+            _ => (false, false),
+        }
+    }
+
+    /// Whether `line` is short enough that the backend's `post` doesn't cut it.
+    pub fn fits(&self, line: &str) -> bool {
+        let (status2d, statuscmd) = self.dwm_markup();
+        match self {
+            Self::Xsetroot {
+                post: Some(post), ..
+            } => post::fits(line, post, status2d, statuscmd),
+            _ => true,
+        }
+    }
+
+    /// Applies the backend's `post` to the assembled line.
+    pub fn post_process(&self, line: String) -> String {
+        let (status2d, statuscmd) = self.dwm_markup();
+        match self {
+            Self::Xsetroot {
+                post: Some(post), ..
+            } => post::apply(&line, post, status2d, statuscmd),
+            _ => line,
+        }
+    }
+}
+
+/// Changes to the assembled line, e.g. for a narrow bar or one that clips its last glyph.
+/// Applied in field order, after stripping markup.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug)]
+pub struct BackendPost {
+    /// Longer lines are cut with "…". Counts grapheme clusters, markup doesn't count.
+    pub max_length: Option<usize>,
+    pub append: Option<String>,
+    pub prepend: Option<String>,
+    /// Remove status2d colors and statuscmd markers, for reusing a config on a plain bar.
+    #[serde(default)]
+    pub strip_colors: bool,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum ReloadStrategy {
+    /// Filesystem notifications, falling back to polling if they can't be set up.
+    #[default]
+    Watch,
+    /// Checks the file for changes every `interval_secs`, for filesystems without working
+    /// notifications such as NFS.
+    Poll { interval_secs: Secs },
+    /// Never reloads.
+    None,
+}
+
+impl Config {
+    /// Rejects zero intervals, timestamp templates chrono can't format and empty valid ranges,
+    /// and clamps update intervals below [`MIN_UPDATE_INTERVAL_MS`] and public IP refresh
+    /// intervals below [`MIN_REFRESH_INTERVAL_S`].
+    ///
+    /// Reloads only swap in configs that pass, so problems surface here rather than as a panic
+    /// or garbage output while rendering.
+    pub fn validate(&mut self) -> Result<(), String> {
+        for (name, interval) in [
+            ("update_interval_ms", &mut self.update_interval_ms),
+            (
+                "quiet_update_interval_ms",
+                &mut self.quiet_update_interval_ms,
+            ),
+        ] {
+            if interval.is_zero() {
+                return Err(format!("{name} must be greater than 0"));
+            }
+
+            if *interval < MIN_UPDATE_INTERVAL_MS.into() {
+                warn!(
+                    "{name} of {interval} is below the minimum, using {MIN_UPDATE_INTERVAL_MS}ms."
+                );
+                *interval = MIN_UPDATE_INTERVAL_MS.into();
+            }
+        }
+
+        for (index, section) in self.sections.iter_mut().enumerate() {
+            if let Some(interval) = &mut section.interval_ms {
+                if interval.is_zero() {
+                    return Err(format!(
+                        "section {}: interval_ms must be greater than 0",
+                        index + 1
+                    ));
+                }
+
+                if *interval < MIN_UPDATE_INTERVAL_MS.into() {
+                    warn!("Section {} interval_ms of {interval} is below the minimum, using {MIN_UPDATE_INTERVAL_MS}ms.", index + 1);
+                    *interval = MIN_UPDATE_INTERVAL_MS.into();
+                }
+            }
+
+            if let Module::Temperature {
+                warn_above: Some(warn_above),
+                clear_below,
+                ..
+            } = section.module
+            {
+                if clear_below.is_some_and(|x| x > warn_above) {
+                    return Err(format!(
+                        "section {}: clear_below must not be above warn_above",
+                        index + 1
+                    ));
+                }
+                // Last, so it wins over the section's own thresholds.
+                section.thresholds.push(Threshold {
+                    value: warn_above.into(),
+                    direction: ThresholdDirection::Above,
+                    decoration: Decoration {
+                        fg: Some(TEMPERATURE_WARNING_COLOR.into()),
+                        ..section.decoration.clone()
+                    },
+                    urgent: true,
+                    clear_at: clear_below.map(Into::into),
+                });
+            }
+
+            if let Module::PublicIp {
+                refresh_interval_s, ..
+            } = &mut section.module
+            {
+                if *refresh_interval_s < MIN_REFRESH_INTERVAL_S.into() {
+                    warn!("PublicIp refresh_interval_s of {refresh_interval_s} is below the minimum, using {MIN_REFRESH_INTERVAL_S}s.");
+                    *refresh_interval_s = MIN_REFRESH_INTERVAL_S.into();
+                }
+            }
+        }
+
+        if self.max_emissions_per_sec == Some(0) {
+            return Err("max_emissions_per_sec must be greater than 0".into());
+        }
+
+        if let ReloadStrategy::Poll { interval_secs } = self.reload {
+            if interval_secs.is_zero() {
+                return Err("reload interval_secs must be greater than 0".into());
+            }
+        }
+
+        for (index, section) in self.sections.iter().enumerate() {
+            if section.watch && section.module.watched_path(None).is_none() {
+                return Err(format!(
+                    "section {}: watch only works for modules reading files, i.e. Maildir",
+                    index + 1
+                ));
+            }
+
+            if let Module::Temperature {
+                aggregate: TempAggregate::Named(names),
+                ..
+            } = &section.module
+            {
+                if names.is_empty() {
+                    return Err(format!(
+                        "section {}: aggregate Named needs at least one name",
+                        index + 1
+                    ));
+                }
+            }
+
+            if let Module::IpAddress {
+                max_addresses: 0, ..
+            } = section.module
+            {
+                return Err(format!(
+                    "section {}: max_addresses must be greater than 0",
+                    index + 1
+                ));
+            }
+
+            if let Module::NetworkThroughput {
+                average_over_s,
+                peak_hold_s,
+                min_unit,
+                max_unit,
+                reset_signal,
+                ..
+            } = section.module
+            {
+                let highest = libc::SIGRTMAX() - libc::SIGRTMIN();
+                if reset_signal.is_some_and(|x| i32::from(x) > highest) {
+                    return Err(format!(
+                        "section {}: reset_signal must be at most {highest}, SIGRTMAX - SIGRTMIN",
+                        index + 1
+                    ));
+                }
+                if let (Some(min), Some(max)) = (min_unit, max_unit) {
+                    if min > max {
+                        return Err(format!(
+                            "section {}: min_unit {min:?} is above max_unit {max:?}",
+                            index + 1
+                        ));
+                    }
+                }
+                for (name, window) in [
+                    ("average_over_s", average_over_s),
+                    ("peak_hold_s", peak_hold_s),
+                ] {
+                    if window.is_some_and(|x| x.is_zero()) {
+                        return Err(format!(
+                            "section {}: {name} must be greater than 0",
+                            index + 1
+                        ));
+                    }
+                }
+            }
+
+            if let Module::Timestamp { template } = &section.module {
+                if StrftimeItems::new(template).any(|x| x == Item::Error) {
+                    return Err(format!(
+                        "section {}: invalid timestamp template {template:?}",
+                        index + 1
+                    ));
+                }
+            }
+
+            if let Some(format) = &section.usage_format {
+                template::check(format, &USAGE_PLACEHOLDERS)
+                    .map_err(|e| format!("section {}: usage_format has an {e}", index + 1))?;
+            }
+
+            match (&section.compact_template, &section.module) {
+                (None, _) => {}
+                (
+                    Some(_),
+                    Module::Separator { .. }
+                    | Module::DiskUsageEach { .. }
+                    | Module::BatteryEach { .. }
+                    | Module::NetworkEach { .. },
+                ) => {
+                    return Err(format!(
+                        "section {}: {} doesn't take a compact_template",
+                        index + 1,
+                        section.module.name()
+                    ));
+                }
+                (Some(template), Module::Timestamp { .. }) => {
+                    if StrftimeItems::new(template).any(|x| x == Item::Error) {
+                        return Err(format!(
+                            "section {}: invalid compact_template {template:?}",
+                            index + 1
+                        ));
+                    }
+                }
+                (Some(template), _) => template::check(template, &COMPACT_PLACEHOLDERS)
+                    .map_err(|e| format!("section {}: compact_template has an {e}", index + 1))?,
+            }
+
+            if let Some((min, max)) = section.valid_range {
+                if min.partial_cmp(&max).is_none_or(|x| x.is_gt()) {
+                    return Err(format!(
+                        "section {}: valid_range ({min}, {max}) is empty",
+                        index + 1
+                    ));
+                }
+            }
+
+            if let Some(id) = &section.id {
+                if !expr::is_identifier(id) {
+                    return Err(format!(
+                        "section {}: id {id:?} may only contain letters, digits and underscores, and can't start with a digit",
+                        index + 1
+                    ));
+                }
+                if let Some(other) = self.sections[..index]
+                    .iter()
+                    .position(|x| x.id.as_ref() == Some(id))
+                {
+                    return Err(format!(
+                        "section {}: id {id:?} is already used by section {}",
+                        index + 1,
+                        other + 1
+                    ));
+                }
+            }
+        }
+
+        if let Err(index) = expr::evaluation_order(&self.dependencies()?) {
+            return Err(format!(
+                "section {}: expression depends on its own value",
+                index + 1
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Indices of the sections each section's [`Module::Derived`] expression reads.
+    pub fn dependencies(&self) -> Result<Vec<Vec<usize>>, String> {
+        self.sections
+            .iter()
+            .enumerate()
+            .map(|(index, section)| {
+                let Module::Derived { expression, .. } = &section.module else {
+                    return Ok(Vec::new());
+                };
+                let expression = Expr::parse(expression).map_err(|e| {
+                    format!(
+                        "section {}: invalid expression {expression:?}: {e}",
+                        index + 1
+                    )
+                })?;
+
+                expression
+                    .references()
+                    .into_iter()
+                    .map(|id| {
+                        self.sections
+                            .iter()
+                            .position(|x| x.id.as_deref() == Some(id))
+                            .ok_or_else(|| {
+                                format!("section {}: no section has the id {id:?}", index + 1)
+                            })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Every duration the config sets as it was read, e.g. "section 3 (PublicIp)
+    /// refresh_interval_s: 90s", so `stringbar check` can show what bare numbers meant.
+    pub fn durations(&self) -> Vec<String> {
+        let mut durations = vec![
+            format!("update_interval_ms: {}", self.update_interval_ms),
+            format!(
+                "quiet_update_interval_ms: {}",
+                self.quiet_update_interval_ms
+            ),
+        ];
+        if let Some(budget) = self.max_tick_budget_ms {
+            durations.push(format!("max_tick_budget_ms: {budget}"));
+        }
+        if let ReloadStrategy::Poll { interval_secs } = self.reload {
+            durations.push(format!("reload interval_secs: {interval_secs}"));
+        }
+        if !self.startup_grace_secs.is_zero() {
+            durations.push(format!("startup_grace_secs: {}", self.startup_grace_secs));
+        }
+
+        for (index, section) in self.sections.iter().enumerate() {
+            let (timeout, refresh, average, peak) = match section.module {
+                Module::Command { timeout_ms, .. } => (timeout_ms, None, None, None),
+                Module::PublicIp {
+                    refresh_interval_s, ..
+                } => (None, Some(refresh_interval_s), None, None),
+                Module::NetworkThroughput {
+                    average_over_s,
+                    peak_hold_s,
+                    ..
+                } => (None, None, average_over_s, peak_hold_s),
+                _ => (None, None, None, None),
+            };
+            let fields = [
+                ("interval_ms", section.interval_ms.map(|x| x.to_string())),
+                ("timeout_ms", timeout.map(|x| x.to_string())),
+                ("refresh_interval_s", refresh.map(|x| x.to_string())),
+                ("average_over_s", average.map(|x| x.to_string())),
+                ("peak_hold_s", peak.map(|x| x.to_string())),
+            ];
+            for (name, value) in fields {
+                if let Some(value) = value {
+                    durations.push(format!(
+                        "section {} ({}) {name}: {value}",
+                        index + 1,
+                        section.module.name()
+                    ));
+                }
+            }
+        }
+
+        durations
+    }
+
+    /// Whether any section shows inode usage.
+    pub fn wants_inodes(&self) -> bool {
+        self.sections.iter().any(|x| {
+            matches!(
+                x.module,
+                Module::DiskUsage { inodes: true, .. } | Module::DiskUsageEach { inodes: true, .. }
+            )
+        })
+    }
+
+    /// Disks the sections with `skip_when_standby` show.
+    pub fn spared_disks(&self) -> Spared {
+        let mut spared = Spared::default();
+        for section in self.sections.iter().filter(|x| x.skip_when_standby) {
+            match &section.module {
+                Module::DiskUsage { name, .. } => spared.names.push(name.clone()),
+                Module::DiskUsageTotal { .. } | Module::DiskUsageEach { .. } => spared.all = true,
+                _ => {}
+            }
+        }
+
+        spared
+    }
+
+    /// The `reset_signal` of each `NetworkThroughput` section with one, along with its interface.
+    pub fn reset_signals(&self) -> Vec<(u8, &str)> {
+        self.sections
+            .iter()
+            .filter_map(|x| match &x.module {
+                Module::NetworkThroughput {
+                    interface,
+                    reset_signal: Some(signal),
+                    ..
+                } => Some((*signal, interface.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Paths the sections with `watch` read, by section index.
+    pub fn watched_paths(&self) -> Vec<(usize, PathBuf)> {
+        let home = BaseDirs::new().map(|x| x.home_dir().to_path_buf());
+        self.sections
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.watch)
+            .filter_map(|(index, x)| Some((index, x.module.watched_path(home.as_deref())?)))
+            .collect()
+    }
+
+    /// Each section's interval of its own, see [`Section::interval_ms`].
+    pub fn section_intervals(&self) -> Vec<Option<Duration>> {
+        self.sections
+            .iter()
+            .map(|x| x.interval_ms.map(|x| x.0))
+            .collect()
+    }
+
+    /// Order to evaluate sections in, so derived sections come after the sections they read.
+    /// Config order for configs that didn't pass [`validate`](Self::validate).
+    pub fn evaluation_order(&self) -> Vec<usize> {
+        self.dependencies()
+            .ok()
+            .and_then(|x| expr::evaluation_order(&x).ok())
+            .unwrap_or_else(|| (0..self.sections.len()).collect())
+    }
+
+    /// Order in which sections switch to their `compact_template`, see `shrink_priority`.
+    pub fn shrink_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.sections.len()).collect();
+        order.sort_by_key(|x| std::cmp::Reverse(self.sections[*x].shrink_priority));
+        order
+    }
+}
+
+fn default_thousands_separator() -> String {
+    "\u{2009}".into()
+}
+
+fn default_quiet_update_interval_ms() -> Millis {
+    60_000.into()
+}
+
+fn default_history_length() -> usize {
+    100
+}
+
+fn default_public_ip_url() -> String {
+    publicip::DEFAULT_URL.into()
+}
+
+fn default_public_ip_refresh_interval_s() -> Secs {
+    3600.into()
+}
+
+fn default_mpd_host() -> String {
+    mpd::DEFAULT_HOST.into()
+}
+
+fn default_mpd_port() -> u16 {
+    mpd::DEFAULT_PORT
+}
+
+fn default_mpd_format() -> String {
+    mpd::DEFAULT_FORMAT.into()
+}
+
+fn default_max_addresses() -> usize {
+    1
+}
+
+fn default_invalid_value_marker() -> String {
+    "?".into()
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub enum Module {
+    Timestamp {
+        template: String,
+    },
+    /// `basis` overrides the global `memory_basis` for this section.
+    MemoryUsage {
+        #[serde(default)]
+        basis: Option<MemoryBasis>,
+    },
+    SwapUsage,
+    CpuUsage,
+    /// Clock frequency over all cores, e.g. "3.80 GHz" or "800 MHz".
+    CpuFrequency {
+        #[serde(default)]
+        aggregate: FreqAggregate,
+    },
+    ProcessCount,
+    /// Name of the process using the most CPU or memory and how much, e.g. "firefox 34%".
+    /// CPU usage is per core like in top, memory a share of the total.
+    TopProcess {
+        by: SortKey,
+        /// Longer names are cut off, ending in "…" to stay within `max_len` characters.
+        max_len: Option<usize>,
+        #[serde(default)]
+        exclude_kernel_threads: bool,
+    },
+    /// 1, 5 and 15 minute load averages.
+    LoadAverage,
+    /// The disk with the given device name or mount point, e.g. "/dev/sda" or "/".
+    /// With `inodes`, inode usage is shown as well.
+    DiskUsage {
+        name: String,
+        #[serde(default)]
+        inodes: bool,
+    },
+    DiskUsageTotal {
+        include_removables: bool,
+    },
+    /// Expands into one entry per matching disk, each decorated with the section's decoration.
+    /// `{name}` in the decoration is replaced by the disk's name.
+    DiskUsageEach {
+        #[serde(default)]
+        filter: DiskFilter,
+        /// Placed between entries, defaults to the global separator.
+        separator: Option<String>,
+        #[serde(default)]
+        inodes: bool,
+    },
+    Timer {
+        name: String,
+        expired_text: Option<String>,
+    },
+    /// Charge of the battery with the given name, e.g. "BAT0", or of the first one found.
+    Battery {
+        name: Option<String>,
+        /// Hide the section instead of showing "N/A" when there's no such battery.
+        #[serde(default)]
+        hide_when_missing: bool,
+        /// Follows the charge once it has reached the battery's charge threshold, e.g. "▕"
+        /// for "80%▕", so a battery kept at 80% doesn't look like it stopped charging early.
+        #[serde(default)]
+        limit_marker: Option<String>,
+        /// Show the charge in percent of the charge threshold instead of the full capacity.
+        #[serde(default)]
+        relative_to_limit: bool,
+    },
+    /// Expands into one entry per battery like [`Module::Battery`] shows it, sorted by name and
+    /// each decorated with the section's decoration. `{name}` in the decoration is replaced by
+    /// the battery's name.
+    BatteryEach {
+        /// Placed between entries, defaults to the global separator.
+        separator: Option<String>,
+    },
+    /// Temperature of the sensors whose label contains `component`, ignoring case,
+    /// e.g. "coretemp" or "Tctl", combined as `aggregate` says.
+    Temperature {
+        component: String,
+        #[serde(default)]
+        fahrenheit: bool,
+        #[serde(default)]
+        aggregate: TempAggregate,
+        /// Degrees Celsius above which the section turns red and urgent, a shorthand for a
+        /// threshold added by [`Config::validate`]. Whole degrees, like the section shows.
+        warn_above: Option<u8>,
+        /// Degrees Celsius the temperature has to drop to before the warning goes away again,
+        /// `warn_above` by default.
+        clear_below: Option<u8>,
+    },
+    /// Time since boot.
+    Uptime {
+        #[serde(default)]
+        format: UptimeFormat,
+    },
+    /// What a program prints, without the line break ending it, run on every update.
+    /// `fallback` ("ERR" by default) is shown if it fails, exits non-zero, runs longer than
+    /// `timeout_ms` or prints more than one line without `join_lines` to join them with.
+    /// Commands run on the update itself, holding up the other sections, so the timeout is
+    /// 200ms by default; slower ones need a `timeout_ms` of their own.
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        timeout_ms: Option<Millis>,
+        fallback: Option<String>,
+        #[serde(default)]
+        join_lines: Option<String>,
+    },
+    /// Brightness of a backlight, e.g. "intel_backlight", or of the first one found.
+    Brightness {
+        device: Option<String>,
+    },
+    /// Active X keyboard layout, e.g. "us".
+    KeyboardLayout,
+    /// Output volume of a mixer control on the default sound card, "Master" by default.
+    /// Shows "muted" while muted.
+    Volume {
+        control: Option<String>,
+    },
+    /// Arithmetic over the values of sections with an `id`, e.g. "mem.value + swap.value".
+    /// See [`expr`](crate::expr) for the syntax. `unit` says what the result measures, a plain
+    /// number unless given.
+    Derived {
+        expression: String,
+        #[serde(default)]
+        unit: Unit,
+    },
+    /// The machine's public address as reported by the plain text endpoint `url`, fetched with
+    /// curl in the background every `refresh_interval_s` seconds. Shows "…" until the first
+    /// fetch succeeds and the previous address while a refresh fails.
+    PublicIp {
+        #[serde(default = "default_public_ip_url")]
+        url: String,
+        #[serde(default = "default_public_ip_refresh_interval_s")]
+        refresh_interval_s: Secs,
+    },
+    /// Addresses of a network interface, e.g. "192.168.1.23". Link-local addresses are left
+    /// out unless `include_link_local` is set.
+    IpAddress {
+        interface: String,
+        #[serde(default)]
+        family: AddrFamily,
+        /// Further addresses are left out.
+        #[serde(default = "default_max_addresses")]
+        max_addresses: usize,
+        #[serde(default)]
+        include_link_local: bool,
+        /// Placed between addresses, " " by default.
+        separator: Option<String>,
+        /// Shown when the interface has no address of `family`, "no addr" by default.
+        missing_text: Option<String>,
+    },
+    /// The track an MPD server is playing, with `{artist}`, `{title}`, `{elapsed}`,
+    /// `{duration}` and `{state}` in `format` replaced. Hidden while stopped or unreachable.
+    Mpd {
+        #[serde(default = "default_mpd_host")]
+        host: String,
+        #[serde(default = "default_mpd_port")]
+        port: u16,
+        #[serde(default = "default_mpd_format")]
+        format: String,
+        /// Longer titles are cut with "…".
+        max_len: Option<usize>,
+    },
+    /// Unread mail in the Maildir at `path`, e.g. "✉ 3". Hidden while there's none.
+    Maildir {
+        path: String,
+    },
+    /// Number of connected Bluetooth devices, e.g. "BT 2", or "BT off", asked from BlueZ over
+    /// D-Bus. With `device_mac` only that device counts, followed by its battery level.
+    Bluetooth {
+        device_mac: Option<String>,
+    },
+    /// Number of failed systemd units, e.g. "✗ 2", asked over D-Bus. Hidden while none have
+    /// failed and on systems without systemd.
+    SystemdFailed,
+    /// Which output backend is failing, e.g. "file✗" while a FIFO has no reader. Hidden while
+    /// it works.
+    BackendHealth,
+    /// Download and upload rates of a network interface, e.g. "eth0". With `average_over_s`
+    /// the rates are averaged over that many seconds, with `peak_hold_s` each is followed by the
+    /// highest rate seen in that many seconds, e.g. "1.20 MiB/s (3.40 MiB/s) ↓".
+    /// `min_unit` and `max_unit` keep rates from being shown in smaller or bigger units,
+    /// e.g. "0.3 MiB/s" or "1500.0 MiB/s". With `show_totals` each rate is followed by the bytes
+    /// transferred since stringbar started, e.g. "1.2 MiB/s (4.3 GiB) ↓", which SIGRTMIN plus
+    /// `reset_signal` zeroes.
+    NetworkThroughput {
+        interface: String,
+        #[serde(default)]
+        average_over_s: Option<Secs>,
+        #[serde(default)]
+        peak_hold_s: Option<Secs>,
+        #[serde(default)]
+        min_unit: Option<UnitPrefix>,
+        #[serde(default)]
+        max_unit: Option<UnitPrefix>,
+        #[serde(default)]
+        show_totals: bool,
+        #[serde(default)]
+        reset_signal: Option<u8>,
+    },
+    /// Expands into one entry per network interface like [`Module::NetworkThroughput`] shows
+    /// it, sorted by name and each decorated with the section's decoration. `{name}` in the
+    /// decoration is replaced by the interface's name.
+    NetworkEach {
+        #[serde(default)]
+        exclude_loopback: bool,
+        /// Placed between entries, defaults to the global separator.
+        separator: Option<String>,
+    },
+    /// An explicit separator, used instead of the global one between its neighbours.
+    /// `fg` is only used by color-capable outputs.
+    Separator {
+        content: String,
+        fg: Option<String>,
+    },
+}
+
+impl Module {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Timestamp { .. } => "Timestamp",
+            Self::MemoryUsage { .. } => "MemoryUsage",
+            Self::SwapUsage => "SwapUsage",
+            Self::CpuUsage => "CpuUsage",
+            Self::CpuFrequency { .. } => "CpuFrequency",
+            Self::ProcessCount => "ProcessCount",
+            Self::TopProcess { .. } => "TopProcess",
+            Self::LoadAverage => "LoadAverage",
+            Self::DiskUsage { .. } => "DiskUsage",
+            Self::DiskUsageTotal { .. } => "DiskUsageTotal",
+            Self::DiskUsageEach { .. } => "DiskUsageEach",
+            Self::Timer { .. } => "Timer",
+            Self::PublicIp { .. } => "PublicIp",
+            Self::IpAddress { .. } => "IpAddress",
+            Self::Maildir { .. } => "Maildir",
+            Self::Bluetooth { .. } => "Bluetooth",
+            Self::SystemdFailed => "SystemdFailed",
+            Self::BackendHealth => "BackendHealth",
+            Self::Mpd { .. } => "Mpd",
+            Self::NetworkThroughput { .. } => "NetworkThroughput",
+            Self::NetworkEach { .. } => "NetworkEach",
+            Self::Battery { .. } => "Battery",
+            Self::BatteryEach { .. } => "BatteryEach",
+            Self::Temperature { .. } => "Temperature",
+            Self::Uptime { .. } => "Uptime",
+            Self::Command { .. } => "Command",
+            Self::Brightness { .. } => "Brightness",
+            Self::KeyboardLayout => "KeyboardLayout",
+            Self::Volume { .. } => "Volume",
+            Self::Derived { .. } => "Derived",
+            Self::Separator { .. } => "Separator",
+        }
+    }
+
+    /// What a section with `watch` watches for changes, `None` for modules that don't read
+    /// files. A Maildir's unread count only changes with its `new` directory.
+    pub fn watched_path(&self, home: Option<&Path>) -> Option<PathBuf> {
+        match self {
+            Self::Maildir { path } => Some(maildir::expand_home(path, home).join("new")),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Hash)]
+pub struct DiskFilter {
+    #[serde(default)]
+    pub include_removables: bool,
+    /// Only include disks whose name contains this string.
+    pub name_contains: Option<String>,
+}
+
+impl DiskFilter {
+    pub fn matches(&self, disk: &DiskInfo) -> bool {
+        (self.include_removables || !disk.is_removable)
+            && self
+                .name_contains
+                .as_ref()
+                .is_none_or(|x| disk.name.contains(x.as_str()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AddrFamily {
+    Ipv4,
+    Ipv6,
+    /// Both, in the order the kernel lists them.
+    #[default]
+    Any,
+}
+
+impl AddrFamily {
+    pub fn matches(self, address: &IpAddr) -> bool {
+        match self {
+            Self::Ipv4 => address.is_ipv4(),
+            Self::Ipv6 => address.is_ipv6(),
+            Self::Any => true,
+        }
+    }
+}
+
+/// Exceptions to the sanitising of a section's module output, for modules whose output is
+/// trusted, e.g. a command printing lemonbar markup. Every option is off unless set.
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug)]
+pub struct SanitizeOverride {
+    /// Keep control characters, e.g. ESC, instead of removing them. Output markup like
+    /// statuscmd's still removes them afterwards.
+    #[serde(default)]
+    pub allow_control: bool,
+    /// Cut the output at this many characters instead of 512.
+    pub max_length: Option<usize>,
+    /// Keep line breaks instead of turning each run of them into a space.
+    #[serde(default)]
+    pub preserve_newlines: bool,
+}
+
+/// Swaps in another decoration while a section's value is at or beyond `value`.
+#[derive(Serialize, Deserialize)]
+pub struct Threshold {
+    pub value: f64,
+    #[serde(default)]
+    pub direction: ThresholdDirection,
+    pub decoration: Decoration,
+    /// Marks the section urgent while triggered, for bars that draw attention to it. Outputs
+    /// without such a notion put "!" in front of the section instead.
+    #[serde(default)]
+    pub urgent: bool,
+    /// Keeps the threshold triggered until the value is back at or past this, e.g. 70.0 for
+    /// one at 80.0, so a value hovering around it doesn't make the section flicker.
+    pub clear_at: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum ThresholdDirection {
+    /// Triggered at or above the threshold, e.g. for CPU usage.
+    #[default]
+    Above,
+    /// Triggered at or below the threshold, e.g. for battery charge.
+    Below,
+}
+
+impl Threshold {
+    pub fn triggered_by(&self, value: f64) -> bool {
+        match self.direction {
+            ThresholdDirection::Above => value >= self.value,
+            ThresholdDirection::Below => value <= self.value,
+        }
+    }
+
+    /// Whether a threshold triggered by an earlier value still is at `value`.
+    pub fn stays_triggered_by(&self, value: f64) -> bool {
+        self.triggered_by(value)
+            || match (self.direction, self.clear_at) {
+                (_, None) => false,
+                (ThresholdDirection::Above, Some(clear_at)) => value > clear_at,
+                (ThresholdDirection::Below, Some(clear_at)) => value < clear_at,
+            }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum Alignment {
+    #[default]
+    Left,
+    Right,
+    /// Odd padding puts the extra space on the right.
+    Center,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Decoration {
+    pub before: Option<String>,
+    pub after: Option<String>,
+    /// Colors as `#rrggbb`, for color-capable outputs.
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Section {
+    #[serde(deserialize_with = "bare::variants")]
+    pub module: Module,
+    /// Name [`Module::Derived`] expressions refer to this section's value by.
+    pub id: Option<String>,
+    #[serde(default)]
+    pub decoration: Decoration,
+    /// Decimals for percentages, overriding the module's default.
+    pub decimals: Option<usize>,
+    /// How byte usage modules display their values.
+    #[serde(default)]
+    pub usage_display: UsageDisplay,
+    /// Replaces the used/total output of byte usage modules, with [`USAGE_PLACEHOLDERS`]
+    /// filled in, e.g. "{free} free".
+    pub usage_format: Option<String>,
+    /// Write byte values like "9.8/15.9G" instead of "9.80/15.90 GiB".
+    #[serde(default)]
+    pub compact_units: bool,
+    /// Keep the running minimum and maximum, available as `{min}` and `{max}` in the decoration.
+    #[serde(default)]
+    pub track_extremes: bool,
+    /// Evaluation order under `max_tick_budget_ms`, higher goes first.
+    /// Defaults to 255 for timestamps and 128 otherwise.
+    pub priority: Option<u8>,
+    /// Values outside this inclusive range are treated as bogus readings, overriding the
+    /// module's default range.
+    pub valid_range: Option<(f64, f64)>,
+    /// Leaves the section out of the bar, decoration and separator included, while its output
+    /// matches.
+    pub hide_when: Option<HideCondition>,
+    /// Updates the section on its own schedule instead of every `update_interval_ms`, showing
+    /// its previous output in between.
+    pub interval_ms: Option<Millis>,
+    /// Decorations to use instead of `decoration` depending on the section's value. The last
+    /// triggered one wins, so they're listed from mildest to most severe.
+    #[serde(default)]
+    pub thresholds: Vec<Threshold>,
+    /// Pads the module output with spaces to this many columns, so changing values don't
+    /// shift the rest of the bar. Longer output is left as it is.
+    pub min_width: Option<usize>,
+    /// Cuts the module output to this many columns, ending in "…". Together with an equal
+    /// `min_width` the section always takes up exactly that many.
+    pub max_width: Option<usize>,
+    /// Disk sections leave spinning drives asleep, showing their previous usage followed by
+    /// "(sleeping)" instead of spinning them up.
+    #[serde(default)]
+    pub skip_when_standby: bool,
+    /// Updates the section as soon as the file or directory its module reads changes, with
+    /// `reload: Watch`.
+    #[serde(default)]
+    pub watch: bool,
+    /// Where the module output goes within `min_width`.
+    #[serde(default)]
+    pub align: Alignment,
+    /// Shorter module output for when the bar is longer than the output's `post` allows, e.g.
+    /// "{value:0}%" turning memory's "9.8/15.9 GiB" into "62%". A strftime format for
+    /// timestamps, e.g. "%H:%M", [`COMPACT_PLACEHOLDERS`] filled in for the others. Sections
+    /// without a value keep their regular output.
+    pub compact_template: Option<String>,
+    /// Order in which sections switch to their `compact_template` until the bar fits, higher
+    /// goes first and ties go in config order.
+    #[serde(default)]
+    pub shrink_priority: u8,
+    /// Loosens what's removed from the module output before it's shown, see
+    /// [`sanitize`](crate::sanitize).
+    pub sanitize: Option<SanitizeOverride>,
+}
+
+impl Section {
+    /// The decoration of the threshold at `threshold`, see
+    /// [`Urgency::check`](crate::urgency::Urgency::check), or the regular one.
+    pub fn decoration(&self, threshold: Option<usize>) -> &Decoration {
+        threshold
+            .and_then(|x| self.thresholds.get(x))
+            .map_or(&self.decoration, |x| &x.decoration)
+    }
+
+    pub fn priority(&self) -> u8 {
+        self.priority.unwrap_or(match self.module {
+            Module::Timestamp { .. } => u8::MAX,
+            _ => 128,
+        })
+    }
+
+    /// The range the section's value must lie in. Percentages default to 0-100, temperatures
+    /// to 0-150°C and counts to non-negative numbers, other modules aren't checked unless
+    /// configured.
+    pub fn valid_range(&self) -> Option<(f64, f64)> {
+        self.valid_range.or(match self.module {
+            Module::MemoryUsage { .. }
+            | Module::SwapUsage
+            | Module::CpuUsage
+            | Module::DiskUsage { .. }
+            | Module::DiskUsageTotal { .. }
+            | Module::Battery { .. }
+            | Module::Brightness { .. } => Some((0.0, 100.0)),
+            Module::Temperature { .. } => Some((0.0, 150.0)),
+            Module::ProcessCount
+            | Module::LoadAverage
+            | Module::Maildir { .. }
+            | Module::Bluetooth { .. }
+            | Module::SystemdFailed
+            | Module::BackendHealth => Some((0.0, f64::INFINITY)),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum HideCondition {
+    /// The section's value is 0, e.g. swap usage while no swap is used or configured. Sections
+    /// without a value are never hidden.
+    Zero,
+    /// The module's output is empty, e.g. a command printing nothing.
+    Empty,
+}
+
+impl HideCondition {
+    pub fn hides(self, module_out: &str, value: Option<f64>) -> bool {
+        match self {
+            Self::Zero => value == Some(0.0),
+            Self::Empty => module_out.is_empty(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+pub enum UsageDisplay {
+    /// Values scaled to a fitting unit, e.g. "9.80/15.90 GiB".
+    #[default]
+    Fitted,
+    /// Exact byte counts with grouped digits, e.g. "12 884 901 888/17 179 869 184 B".
+    ExactBytes,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MemoryBasis {
+    /// Memory of the whole machine, as the kernel reports it.
+    Host,
+    /// Use and limit of stringbar's cgroup (v2), e.g. a container's. Unlimited cgroups are
+    /// shown against the host's total.
+    Cgroup,
+    /// The cgroup if its limit is below the host's total, the host otherwise.
+    #[default]
+    Auto,
+}
+
+/// How the sensors a `Temperature` section matches are combined, e.g. one per core for
+/// "coretemp".
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum TempAggregate {
+    /// The first sensor only.
+    #[default]
+    First,
+    /// The hottest one.
+    Max,
+    #[serde(alias = "Avg")]
+    Average,
+    /// The first sensor whose label also contains each of these, shown one after another with
+    /// the name in front. The section's value is the hottest of them.
+    Named(Vec<String>),
+}
+
+/// How the frequencies of all cores are combined.
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum FreqAggregate {
+    Max,
+    Min,
+    #[default]
+    Average,
+}
+
+/// A byte unit without its system, e.g. `Mega` for MiB or MB as `decimal_data_units` says.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum UnitPrefix {
+    Kilo,
+    Mega,
+    Giga,
+    Tera,
+    Peta,
+    Exa,
+}
+
+impl From<UnitPrefix> for Magnitude {
+    fn from(prefix: UnitPrefix) -> Self {
+        match prefix {
+            UnitPrefix::Kilo => Self::Kilo,
+            UnitPrefix::Mega => Self::Mega,
+            UnitPrefix::Giga => Self::Giga,
+            UnitPrefix::Tera => Self::Tera,
+            UnitPrefix::Peta => Self::Peta,
+            UnitPrefix::Exa => Self::Exa,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SortKey {
+    Cpu,
+    Memory,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum UptimeFormat {
+    /// Days, hours and minutes without leading zero components, e.g. "3d 4h 12m".
+    /// Seconds are only shown during the first minute.
+    #[default]
+    Compact,
+    /// Total hours and minutes, e.g. "76:12".
+    HoursMinutes,
+    /// Total seconds, e.g. "274320".
+    Seconds,
+}
+
+impl From<Module> for Section {
+    fn from(module: Module) -> Self {
+        Self {
+            module,
+            id: None,
+            decoration: Decoration::default(),
+            decimals: None,
+            usage_display: UsageDisplay::default(),
+            usage_format: None,
+            compact_units: false,
+            track_extremes: false,
+            priority: None,
+            valid_range: None,
+            hide_when: None,
+            interval_ms: None,
+            thresholds: Vec::new(),
+            min_width: None,
+            max_width: None,
+            skip_when_standby: false,
+            watch: false,
+            align: Alignment::default(),
+            compact_template: None,
+            shrink_priority: 0,
+            sanitize: None,
+        }
+    }
+}
+
+/// What the config was loaded from, for confirming that a deployed file was picked up.
+pub struct ConfigFileInfo {
+    pub modified: Option<SystemTime>,
+    pub sha256: String,
+}
+
+/// Loads the config file. With `create_default`, a missing file is replaced by the default config.
+pub fn load_config(
+    config_file_path: &Path,
+    create_default: bool,
+) -> Result<(Config, ConfigFileInfo), StringbarError> {
+    match std::fs::read(config_file_path) {
+        Ok(contents) => match ron::de::from_bytes::<Config>(&contents) {
+            Ok(mut x) => {
+                if let Err(e) = x.validate() {
+                    return Err(StringbarError::ConfigValidation(format!(
+                        "Invalid config file: {e}"
+                    )));
+                }
+
+                Ok((
+                    x,
+                    ConfigFileInfo {
+                        modified: std::fs::metadata(config_file_path)
+                            .and_then(|x| x.modified())
+                            .ok(),
+                        sha256: sha256::hex_digest(&contents),
+                    },
+                ))
+            }
+            Err(e) => Err(StringbarError::ConfigLoad(format!(
+                "Unable to read config file: {e}"
+            ))),
+        },
+        Err(e) => match e.kind() {
+            std::io::ErrorKind::NotFound if create_default => {
+                let new_config = Config::default();
+                let contents = match ron::ser::to_string_pretty(
+                    &new_config,
+                    PrettyConfig::new().extensions(Extensions::all()),
+                ) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        return Err(StringbarError::ConfigLoad(format!(
+                            "Unable to serialize new config file: {e}"
+                        )))
+                    }
+                };
+
+                let unwritten = || {
+                    warn!(
+                        "Not creating {} in a read-only location, using the default config.",
+                        real_path(config_file_path).display()
+                    );
+                    Ok((
+                        Config::default(),
+                        ConfigFileInfo {
+                            modified: None,
+                            sha256: sha256::hex_digest(contents.as_bytes()),
+                        },
+                    ))
+                };
+                if is_read_only(config_file_path) {
+                    return unwritten();
+                }
+
+                let mut handle = match OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(config_file_path)
+                {
+                    Ok(x) => x,
+                    // The directory couldn't be created, or the filesystem is read-only
+                    // despite its permissions.
+                    Err(e) if is_read_only_error(&e) || e.kind() == io::ErrorKind::NotFound => {
+                        return unwritten();
+                    }
+                    Err(e) => {
+                        return Err(StringbarError::Io(format!(
+                            "Unable to create new config file: {e}"
+                        )))
+                    }
+                };
+
+                if let Err(e) = handle.write_all(contents.as_bytes()) {
+                    return Err(StringbarError::Io(format!(
+                        "Unable to write to new config file: {e}"
+                    )));
+                }
+
+                info!("Wrote new config file.");
+                Ok((
+                    new_config,
+                    ConfigFileInfo {
+                        modified: handle.metadata().and_then(|x| x.modified()).ok(),
+                        sha256: sha256::hex_digest(contents.as_bytes()),
+                    },
+                ))
+            }
+            _ => Err(StringbarError::ConfigLoad(format!(
+                "Unable to open config file: {e}"
+            ))),
+        },
+    }
+}
+
+/// Whether `path`, or the closest of its ancestors that exists, lacks write permissions, as with
+/// config files linked from a read-only store. Only looks at permissions, so writes may still
+/// fail on read-only filesystems, see [`is_read_only_error`].
+pub fn is_read_only(path: &Path) -> bool {
+    path.ancestors()
+        .find_map(|x| std::fs::metadata(x).ok())
+        .is_some_and(|x| x.permissions().readonly())
+}
+
+/// Whether a write failed because its location is read-only.
+pub fn is_read_only_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+    )
+}
+
+/// `path` with symlinks resolved as far as it exists, where writes to it would actually go.
+pub fn real_path(path: &Path) -> PathBuf {
+    if let Ok(x) = path.canonicalize() {
+        return x;
+    }
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => real_path(parent).join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        let contents = r#"(
+            separator: " | ",
+            update_interval_ms: "1.5s",
+            quiet_update_interval_ms: 90000,
+            decimal_data_units: false,
+            frozen_text: None,
+            quiet_hours: None,
+            max_emissions_per_sec: None,
+            max_tick_budget_ms: None,
+            reload: Poll(interval_secs: 30),
+            sections: [
+                (module: CpuUsage, interval_ms: Some("2m")),
+                (module: PublicIp(refresh_interval_s: 90)),
+                (module: NetworkThroughput(interface: "eth0", average_over_s: Some("500ms"))),
+            ],
+        )"#;
+        let config: Config = ron::from_str(contents).unwrap();
+        assert_eq!(
+            vec![
+                "update_interval_ms: 1500ms",
+                "quiet_update_interval_ms: 90s",
+                "reload interval_secs: 30s",
+                "section 1 (CpuUsage) interval_ms: 2m",
+                "section 2 (PublicIp) refresh_interval_s: 90s",
+                "section 3 (NetworkThroughput) average_over_s: 500ms",
+            ],
+            config.durations()
+        );
+
+        assert!(ron::from_str::<Config>(&contents.replace("\"1.5s\"", "\"-1s\"")).is_err());
+    }
+
+    #[test]
+    fn bare_variants() {
+        let parse = |output: &str| {
+            let contents = format!(
+                "(separator: \" \", update_interval_ms: 1000, decimal_data_units: false, \
+                 output: {output}, sections: [(module: MemoryUsage)])"
+            );
+            ron::from_str::<Config>(&contents).unwrap().output
+        };
+        assert_eq!(
+            OutputBackend::Stdout {
+                reverse_order: false
+            },
+            parse("Stdout")
+        );
+        assert_eq!(
+            OutputBackend::I3bar {
+                reverse_order: true
+            },
+            parse("I3Bar(reverse_order: true)")
+        );
+        assert!(!parse("Xsetroot").reverse_order());
+    }
+
+    #[test]
+    fn spared_disks() {
+        let disk = |name: &str, skip_when_standby| Section {
+            skip_when_standby,
+            ..Module::DiskUsage {
+                name: name.into(),
+                inodes: false,
+            }
+            .into()
+        };
+        let mut config = Config {
+            sections: vec![
+                disk("/dev/sda", false),
+                disk("/dev/sdb", true),
+                disk("/mnt", true),
+            ],
+            ..Config::default()
+        };
+        let spared = config.spared_disks();
+        assert!(!spared.all);
+        assert_eq!(vec!["/dev/sdb", "/mnt"], spared.names);
+
+        config.sections.push(Section {
+            skip_when_standby: true,
+            ..Module::DiskUsageTotal {
+                include_removables: false,
+            }
+            .into()
+        });
+        assert!(config.spared_disks().all);
+        assert!(Config::default().spared_disks().is_empty());
+    }
+
+    #[test]
+    fn wants_inodes() {
+        let mut config = Config {
+            sections: vec![Module::DiskUsage {
+                name: "/".into(),
+                inodes: false,
+            }
+            .into()],
+            ..Config::default()
+        };
+        assert!(!config.wants_inodes());
+
+        config.sections.push(
+            Module::DiskUsageEach {
+                filter: DiskFilter::default(),
+                separator: None,
+                inodes: true,
+            }
+            .into(),
+        );
+        assert!(config.wants_inodes());
+    }
+
+    #[test]
+    fn watched_paths() {
+        let maildir = |path: &str, watch| Section {
+            watch,
+            ..Module::Maildir { path: path.into() }.into()
+        };
+        let config = Config {
+            sections: vec![
+                maildir("/var/mail/a", true),
+                Module::CpuUsage.into(),
+                maildir("/var/mail/b", false),
+                maildir("/var/mail/c", true),
+            ],
+            ..Config::default()
+        };
+        assert_eq!(
+            vec![
+                (0, PathBuf::from("/var/mail/a/new")),
+                (3, PathBuf::from("/var/mail/c/new"))
+            ],
+            config.watched_paths()
+        );
+        assert_eq!(
+            Some(PathBuf::from("/home/a/Mail/new")),
+            Module::Maildir {
+                path: "~/Mail".into()
+            }
+            .watched_path(Some(Path::new("/home/a")))
+        );
+    }
+
+    #[test]
+    fn interval_validation() {
+        let cases = [
+            (1000, 60_000, Ok((1000, 60_000))),
+            (250, 100, Ok((250, 100))),
+            (10, 99, Ok((100, 100))),
+            (0, 60_000, Err(())),
+            (1000, 0, Err(())),
+        ];
+
+        for (update, quiet, expected) in cases {
+            let mut config = Config {
+                update_interval_ms: Millis::from(update),
+                quiet_update_interval_ms: Millis::from(quiet),
+                ..Config::default()
+            };
+            let result = config.validate().map_err(|_| ());
+
+            assert_eq!(
+                expected.map(|(update, quiet)| (Millis::from(update), Millis::from(quiet))),
+                result.map(|_| (config.update_interval_ms, config.quiet_update_interval_ms))
+            );
+        }
+
+        let section = |interval_ms: Option<u64>| Config {
+            sections: vec![Section {
+                interval_ms: interval_ms.map(Millis::from),
+                ..Module::CpuUsage.into()
+            }],
+            ..Config::default()
+        };
+        for (interval, expected) in [
+            (None, Ok(None)),
+            (Some(5000), Ok(Some(5000))),
+            (Some(10), Ok(Some(100))),
+        ] {
+            let mut config = section(interval);
+            let result = config.validate().map(|_| config.sections[0].interval_ms);
+            assert_eq!(expected.map(|x: Option<u64>| x.map(Millis::from)), result);
+        }
+        assert_eq!(
+            Err("section 1: interval_ms must be greater than 0".into()),
+            section(Some(0)).validate()
+        );
+    }
+
+    #[test]
+    fn semantic_validation() {
+        let with = |section: Section| Config {
+            sections: vec![Module::CpuUsage.into(), section],
+            ..Config::default()
+        };
+        let timestamp = |template: &str| {
+            Section::from(Module::Timestamp {
+                template: template.into(),
+            })
+        };
+        let range = |range| Section {
+            valid_range: Some(range),
+            ..Module::CpuUsage.into()
+        };
+
+        let cases = [
+            (with(timestamp("%F %T")), Ok(())),
+            (
+                with(Section {
+                    watch: true,
+                    ..Module::CpuUsage.into()
+                }),
+                Err("section 2: watch only works for modules reading files, i.e. Maildir"),
+            ),
+            (
+                with(timestamp("%Q")),
+                Err("section 2: invalid timestamp template \"%Q\""),
+            ),
+            (with(range((0.0, 0.0))), Ok(())),
+            (
+                with(range((10.0, 5.0))),
+                Err("section 2: valid_range (10, 5) is empty"),
+            ),
+            (
+                with(range((f64::NAN, 5.0))),
+                Err("section 2: valid_range (NaN, 5) is empty"),
+            ),
+            (
+                with(
+                    Module::IpAddress {
+                        interface: "eth0".into(),
+                        family: AddrFamily::Any,
+                        max_addresses: 0,
+                        include_link_local: false,
+                        separator: None,
+                        missing_text: None,
+                    }
+                    .into(),
+                ),
+                Err("section 2: max_addresses must be greater than 0"),
+            ),
+            (
+                with(
+                    Module::NetworkThroughput {
+                        interface: "eth0".into(),
+                        average_over_s: Some(10.into()),
+                        peak_hold_s: Some(0.into()),
+                        min_unit: None,
+                        max_unit: None,
+                        show_totals: false,
+                        reset_signal: None,
+                    }
+                    .into(),
+                ),
+                Err("section 2: peak_hold_s must be greater than 0"),
+            ),
+            (
+                with(
+                    Module::NetworkThroughput {
+                        interface: "eth0".into(),
+                        average_over_s: None,
+                        peak_hold_s: None,
+                        min_unit: Some(UnitPrefix::Giga),
+                        max_unit: Some(UnitPrefix::Mega),
+                        show_totals: false,
+                        reset_signal: None,
+                    }
+                    .into(),
+                ),
+                Err("section 2: min_unit Giga is above max_unit Mega"),
+            ),
+            (
+                with(
+                    Module::NetworkThroughput {
+                        interface: "eth0".into(),
+                        average_over_s: None,
+                        peak_hold_s: None,
+                        min_unit: None,
+                        max_unit: None,
+                        show_totals: true,
+                        reset_signal: Some(31),
+                    }
+                    .into(),
+                ),
+                Err("section 2: reset_signal must be at most 30, SIGRTMAX - SIGRTMIN"),
+            ),
+            (
+                with(Section {
+                    usage_format: Some("{free} free of {totl}".into()),
+                    ..Module::MemoryUsage { basis: None }.into()
+                }),
+                Err("section 2: usage_format has an unknown placeholder {totl}"),
+            ),
+            (
+                with(Section {
+                    usage_format: Some("{percent:GiB.1}".into()),
+                    ..Module::MemoryUsage { basis: None }.into()
+                }),
+                Err("section 2: usage_format has an invalid placeholder {percent:GiB.1}, only byte values take a unit"),
+            ),
+            (
+                with(Section {
+                    compact_template: Some("{percent:0}%".into()),
+                    ..Module::MemoryUsage { basis: None }.into()
+                }),
+                Err("section 2: compact_template has an unknown placeholder {percent:0}"),
+            ),
+            (
+                with(Section {
+                    compact_template: Some("%H:%Q".into()),
+                    ..Module::Timestamp {
+                        template: "%a %d %b %H:%M".into(),
+                    }
+                    .into()
+                }),
+                Err("section 2: invalid compact_template \"%H:%Q\""),
+            ),
+            (
+                with(Section {
+                    compact_template: Some("{value}".into()),
+                    ..Module::DiskUsageEach {
+                        filter: DiskFilter::default(),
+                        separator: None,
+                        inodes: false,
+                    }
+                    .into()
+                }),
+                Err("section 2: DiskUsageEach doesn't take a compact_template"),
+            ),
+            (
+                Config {
+                    reload: ReloadStrategy::Poll {
+                        interval_secs: 0.into(),
+                    },
+                    ..Config::default()
+                },
+                Err("reload interval_secs must be greater than 0"),
+            ),
+            (
+                Config {
+                    max_emissions_per_sec: Some(0),
+                    ..Config::default()
+                },
+                Err("max_emissions_per_sec must be greater than 0"),
+            ),
+        ];
+
+        for (mut config, expected) in cases {
+            assert_eq!(expected.map_err(String::from), config.validate());
+        }
+    }
+
+    #[test]
+    fn derived_sections() {
+        let named = |id: &str, module: Module| Section {
+            id: Some(id.into()),
+            ..module.into()
+        };
+        let derived = |expression: &str| {
+            Section::from(Module::Derived {
+                expression: expression.into(),
+                unit: Unit::Count,
+            })
+        };
+        let config = |sections| Config {
+            sections,
+            ..Config::default()
+        };
+
+        let cases = [
+            (
+                vec![
+                    named("both", Module::Derived {
+                        expression: "mem.value + swap.value".into(),
+                        unit: Unit::Count,
+                    }),
+                    named("mem", Module::MemoryUsage { basis: None }),
+                    named("swap", Module::SwapUsage),
+                    derived("both.value / 2"),
+                ],
+                Ok(vec![1, 2, 0, 3]),
+            ),
+            (vec![derived("1 + 1")], Ok(vec![0])),
+            (
+                vec![derived("mem.value")],
+                Err("section 1: no section has the id \"mem\""),
+            ),
+            (
+                vec![named("mem", Module::MemoryUsage { basis: None }), derived("mem.value +")],
+                Err("section 2: invalid expression \"mem.value +\": unexpected end of expression"),
+            ),
+            (
+                vec![named("mem", Module::MemoryUsage { basis: None }), named("mem", Module::SwapUsage)],
+                Err("section 2: id \"mem\" is already used by section 1"),
+            ),
+            (
+                vec![named("mem usage", Module::MemoryUsage { basis: None })],
+                Err("section 1: id \"mem usage\" may only contain letters, digits and underscores, and can't start with a digit"),
+            ),
+            (
+                vec![named("a", Module::Derived {
+                    expression: "b.value".into(),
+                    unit: Unit::Count,
+                }),
+                named("b", Module::Derived {
+                    expression: "a.value * 2".into(),
+                    unit: Unit::Count,
+                })],
+                Err("section 1: expression depends on its own value"),
+            ),
+            (
+                vec![named("a", Module::Derived {
+                    expression: "a.value + 1".into(),
+                    unit: Unit::Count,
+                })],
+                Err("section 1: expression depends on its own value"),
+            ),
+        ];
+
+        for (sections, expected) in cases {
+            let mut config = config(sections);
+            let order = config.evaluation_order();
+            assert_eq!(
+                expected.clone().map(|_| ()).map_err(String::from),
+                config.validate()
+            );
+            match expected {
+                Ok(expected) => assert_eq!(expected, order),
+                Err(_) => assert_eq!((0..config.sections.len()).collect::<Vec<_>>(), order),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_candidate_is_rejected() {
+        let dir = std::env::temp_dir().join(format!("stringbar-candidate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.ron");
+
+        let valid = ron::to_string(&Config::default()).unwrap();
+        let invalid = valid.replace("%d/%m/%Y %H:%M", "%d/%m/%Y %H:%");
+        std::fs::write(&path, &valid[..valid.len() / 2]).unwrap();
+        assert!(matches!(
+            load_config(&path, false),
+            Err(StringbarError::ConfigLoad(_))
+        ));
+        std::fs::write(&path, &invalid).unwrap();
+        assert!(matches!(
+            load_config(&path, false),
+            Err(StringbarError::ConfigValidation(_))
+        ));
+        std::fs::write(&path, &valid).unwrap();
+        assert!(load_config(&path, false).is_ok());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            load_config(&path, false),
+            Err(StringbarError::ConfigLoad(_))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn read_only_locations() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let root = std::env::temp_dir().join(format!("stringbar-read-only-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let store = root.join("store");
+        let home = root.join("home");
+        fs::create_dir_all(&store).unwrap();
+        fs::create_dir_all(&home).unwrap();
+        let set_mode = |path: &Path, mode| {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+        };
+
+        // A config linked from a read-only store loads, and resolves to the store.
+        let contents = ron::to_string(&Config::default()).unwrap();
+        fs::write(store.join("config.ron"), &contents).unwrap();
+        set_mode(&store.join("config.ron"), 0o444);
+        set_mode(&store, 0o555);
+        let linked = home.join("config.ron");
+        std::os::unix::fs::symlink(store.join("config.ron"), &linked).unwrap();
+
+        assert!(is_read_only(&linked));
+        assert!(!is_read_only(&home.join("other.ron")));
+        assert_eq!(
+            store.canonicalize().unwrap().join("config.ron"),
+            real_path(&linked)
+        );
+        assert!(load_config(&linked, true).is_ok());
+
+        // A missing config in a read-only directory isn't created, the default is used instead.
+        let missing = store.join("missing/config.ron");
+        assert!(is_read_only(&missing));
+        assert!(load_config(&missing, true).is_ok());
+        assert!(!missing.exists());
+
+        assert!(is_read_only_error(&io::Error::from(
+            io::ErrorKind::ReadOnlyFilesystem
+        )));
+        assert!(!is_read_only_error(&io::Error::from(
+            io::ErrorKind::NotFound
+        )));
+
+        set_mode(&store, 0o755);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
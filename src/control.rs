@@ -0,0 +1,347 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    sync::{atomic::Ordering, mpsc::Sender, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tracing::{error, info};
+
+use crate::{
+    duration, health, history, runtime::Runtime, snapshot::SectionSnapshot, unit, PROFILING,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum Request {
+    Timer {
+        name: String,
+        action: TimerAction,
+    },
+    Profile(bool),
+    Status,
+    Freeze,
+    Thaw,
+    ResetExtremes,
+    History(Option<usize>),
+    /// The latest section values in the Prometheus text format.
+    Metrics,
+    /// Every section as a JSON snapshot, one per line.
+    Sections,
+    /// Marks a section, by id or number from 1, urgent or takes that back.
+    Urgent {
+        section: String,
+        urgent: bool,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TimerAction {
+    Start(Option<Duration>),
+    Stop,
+    Reset,
+}
+
+pub fn parse(line: &str) -> Result<Request, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words.as_slice() {
+        ["timer", action, name, rest @ ..] => {
+            let name = name.to_string();
+            let action = match (*action, rest) {
+                ("start", []) => TimerAction::Start(None),
+                ("start", [duration]) => TimerAction::Start(Some(
+                    duration::parse_compact(duration)
+                        .ok_or_else(|| format!("invalid duration: {duration}"))?,
+                )),
+                ("stop", []) => TimerAction::Stop,
+                ("reset", []) => TimerAction::Reset,
+                _ => return Err("usage: timer start|stop|reset <name> [duration]".into()),
+            };
+
+            Ok(Request::Timer { name, action })
+        }
+        ["profile", "on"] => Ok(Request::Profile(true)),
+        ["profile", "off"] => Ok(Request::Profile(false)),
+        ["status"] => Ok(Request::Status),
+        ["freeze"] => Ok(Request::Freeze),
+        ["thaw"] => Ok(Request::Thaw),
+        ["reset-extremes"] => Ok(Request::ResetExtremes),
+        ["metrics"] => Ok(Request::Metrics),
+        ["sections"] => Ok(Request::Sections),
+        ["history"] => Ok(Request::History(None)),
+        ["history", count] => Ok(Request::History(Some(
+            count
+                .parse()
+                .map_err(|_| format!("invalid count: {count}"))?,
+        ))),
+        ["urgent", section, state] => Ok(Request::Urgent {
+            section: section.to_string(),
+            urgent: match *state {
+                "on" => true,
+                "off" => false,
+                _ => return Err("usage: urgent <section> on|off".into()),
+            },
+        }),
+        [] => Err("empty request".into()),
+        [command, ..] => Err(format!("unknown command: {command}")),
+    }
+}
+
+pub fn handle(request: Request, runtime: &Mutex<Runtime>, wake: &Sender<()>) -> String {
+    match request {
+        Request::Timer { name, action } => {
+            let now = Instant::now();
+            let mut runtime = runtime.lock().unwrap();
+            let timer = runtime.timers.entry(name.clone()).or_default();
+
+            match action {
+                TimerAction::Start(countdown) => timer.start(now, countdown),
+                TimerAction::Stop => timer.stop(now),
+                TimerAction::Reset => timer.reset(),
+            }
+
+            format!(
+                "ok: {name} {}",
+                if timer.is_running() {
+                    "running"
+                } else {
+                    "stopped"
+                }
+            )
+        }
+        Request::Profile(enabled) => {
+            PROFILING.store(enabled, Ordering::Relaxed);
+            "ok".into()
+        }
+        Request::Status => {
+            let runtime = runtime.lock().unwrap();
+            let mut status = format!(
+                "frozen={} {} {}",
+                runtime.frozen,
+                runtime.reloads.summary(),
+                health::summary()
+            );
+            if let Some(remaining) = runtime.urgency.grace_remaining(Instant::now()) {
+                let remaining = remaining.as_secs_f64().ceil();
+                status.push_str(&format!(" grace: {remaining}s remaining"));
+            }
+            status
+        }
+        Request::Freeze | Request::Thaw => {
+            runtime.lock().unwrap().frozen = request == Request::Freeze;
+            let _ = wake.send(());
+            "ok".into()
+        }
+        Request::ResetExtremes => {
+            runtime.lock().unwrap().extremes.reset();
+            "ok".into()
+        }
+        Request::History(count) => history::format(count),
+        Request::Metrics => unit::metrics(&runtime.lock().unwrap().readings),
+        Request::Sections => {
+            let snapshots: Vec<_> = runtime
+                .lock()
+                .unwrap()
+                .snapshots
+                .iter()
+                .map(SectionSnapshot::json)
+                .collect();
+            snapshots.join("\n")
+        }
+        Request::Urgent { section, urgent } => {
+            runtime.lock().unwrap().urgency.force(&section, urgent);
+            let _ = wake.send(());
+            "ok".into()
+        }
+    }
+}
+
+/// Binds the control socket at `path` and serves requests on a background thread.
+///
+/// A leftover socket from a previous instance is replaced, but a live one is not.
+pub fn spawn(path: &Path, runtime: Arc<Mutex<Runtime>>, wake: Sender<()>) -> io::Result<()> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another instance is listening on the control socket",
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    info!("Listening on control socket {}", path.display());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = serve(stream, &runtime, &wake) {
+                        error!("Control socket client error: {e}");
+                    }
+                }
+                Err(e) => error!("Unable to accept control socket connection: {e}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve(stream: UnixStream, runtime: &Mutex<Runtime>, wake: &Sender<()>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let response = match parse(&line) {
+        Ok(request) => handle(request, runtime, wake),
+        Err(e) => format!("error: {e}"),
+    };
+
+    (&stream).write_all(format!("{response}\n").as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    #[test]
+    fn parse_timer() {
+        assert_eq!(
+            Ok(Request::Timer {
+                name: "pomodoro".into(),
+                action: TimerAction::Start(Some(Duration::from_secs(1500)))
+            }),
+            parse("timer start pomodoro 25m\n")
+        );
+        assert_eq!(
+            Ok(Request::Timer {
+                name: "build".into(),
+                action: TimerAction::Start(None)
+            }),
+            parse("timer start build")
+        );
+        assert_eq!(
+            Ok(Request::Timer {
+                name: "build".into(),
+                action: TimerAction::Reset
+            }),
+            parse("timer reset build")
+        );
+        assert!(parse("timer stop build 5m").is_err());
+        assert!(parse("timer start build soon").is_err());
+        assert!(parse("timer pause build").is_err());
+    }
+
+    #[test]
+    fn parse_other() {
+        assert_eq!(Ok(Request::Profile(true)), parse("profile on"));
+        assert_eq!(Ok(Request::Profile(false)), parse("profile off"));
+        assert_eq!(Ok(Request::Status), parse("status\n"));
+        assert_eq!(Ok(Request::Freeze), parse("freeze"));
+        assert_eq!(Ok(Request::Thaw), parse("thaw"));
+        assert_eq!(Ok(Request::ResetExtremes), parse("reset-extremes"));
+        assert_eq!(Ok(Request::Metrics), parse("metrics"));
+        assert_eq!(Ok(Request::Sections), parse("sections"));
+        assert_eq!(Ok(Request::History(None)), parse("history"));
+        assert_eq!(Ok(Request::History(Some(5))), parse("history 5"));
+        assert_eq!(
+            Ok(Request::Urgent {
+                section: "cpu".into(),
+                urgent: true
+            }),
+            parse("urgent cpu on")
+        );
+        assert_eq!(
+            Ok(Request::Urgent {
+                section: "2".into(),
+                urgent: false
+            }),
+            parse("urgent 2 off")
+        );
+        assert!(parse("urgent cpu").is_err());
+        assert!(parse("urgent cpu maybe").is_err());
+        assert!(parse("history some").is_err());
+        assert!(parse("profile maybe").is_err());
+        assert!(parse("").is_err());
+        assert!(parse("launch missiles").is_err());
+    }
+
+    #[test]
+    fn handle_timer() {
+        let runtime = Mutex::new(Runtime::default());
+        let (wake, _) = mpsc::channel();
+        assert_eq!(
+            "ok: a running",
+            handle(parse("timer start a").unwrap(), &runtime, &wake)
+        );
+        handle(parse("timer start b 10s").unwrap(), &runtime, &wake);
+        assert_eq!(
+            "ok: b stopped",
+            handle(parse("timer stop b").unwrap(), &runtime, &wake)
+        );
+
+        let runtime = runtime.lock().unwrap();
+        assert!(runtime.timers["a"].is_running());
+        assert!(!runtime.timers["b"].is_running());
+    }
+
+    #[test]
+    fn handle_freeze() {
+        let runtime = Mutex::new(Runtime::default());
+        let (wake, woken) = mpsc::channel();
+
+        handle(Request::Freeze, &runtime, &wake);
+        assert!(runtime.lock().unwrap().frozen);
+        assert!(handle(Request::Status, &runtime, &wake).starts_with("frozen=true "));
+        assert!(woken.try_recv().is_ok());
+
+        handle(Request::Thaw, &runtime, &wake);
+        assert!(!runtime.lock().unwrap().frozen);
+        assert!(woken.try_recv().is_ok());
+    }
+
+    #[test]
+    fn status_grace() {
+        let runtime = Mutex::new(Runtime::default());
+        let (wake, _) = mpsc::channel();
+        assert!(!handle(Request::Status, &runtime, &wake).contains("grace"));
+
+        runtime
+            .lock()
+            .unwrap()
+            .urgency
+            .set_grace(Duration::from_secs(60));
+        assert!(handle(Request::Status, &runtime, &wake).ends_with(" grace: 60s remaining"));
+    }
+
+    #[test]
+    fn rebind_after_crash() {
+        let dir = std::env::temp_dir().join(format!("stringbar-control-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("control.sock");
+
+        // A crashed instance leaves its socket file behind without anyone listening.
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let runtime = Arc::new(Mutex::new(Runtime::default()));
+        let (wake, _) = mpsc::channel();
+        spawn(&path, runtime.clone(), wake.clone()).unwrap();
+
+        // but a live one is left alone
+        assert_eq!(
+            io::ErrorKind::AddrInUse,
+            spawn(&path, runtime, wake).unwrap_err().kind()
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
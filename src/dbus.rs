@@ -0,0 +1,883 @@
+//! Just enough of the D-Bus wire protocol to ask systemd about its units and BlueZ about
+//! Bluetooth devices over the system bus and show desktop notifications over the session bus,
+//! without linking libdbus.
+
+use std::{
+    env,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::bluetooth;
+
+pub const SYSTEM_BUS_PATH: &str = "/run/dbus/system_bus_socket";
+const TIMEOUT: Duration = Duration::from_secs(1);
+/// Larger messages are treated as garbage rather than allocated, the spec's limit is 128MiB.
+const MAX_MESSAGE_LENGTH: u32 = 1 << 26;
+
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// A header field value.
+#[derive(Clone, Copy)]
+enum Value<'a> {
+    String(&'a str),
+    ObjectPath(&'a str),
+    Signature(&'a str),
+    /// Only sent by the bus, e.g. reply serials.
+    #[cfg(test)]
+    U32(u32),
+}
+
+/// Appends values in little endian wire format.
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, alignment: usize) {
+        self.data
+            .resize(self.data.len().next_multiple_of(alignment), 0);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.data.extend(value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.data.extend(value.as_bytes());
+        self.data.push(0);
+    }
+
+    fn signature(&mut self, value: &str) {
+        self.data.push(value.len() as u8);
+        self.data.extend(value.as_bytes());
+        self.data.push(0);
+    }
+
+    /// An array of strings, `as`.
+    fn strings(&mut self, values: &[&str]) {
+        self.u32(0);
+        let start = self.data.len();
+        for value in values {
+            self.string(value);
+        }
+        let length = (self.data.len() - start) as u32;
+        self.data[start - 4..start].copy_from_slice(&length.to_le_bytes());
+    }
+
+    fn value(&mut self, value: Value) {
+        match value {
+            Value::String(x) => {
+                self.signature("s");
+                self.string(x);
+            }
+            Value::ObjectPath(x) => {
+                self.signature("o");
+                self.string(x);
+            }
+            Value::Signature(x) => {
+                self.signature("g");
+                self.signature(x);
+            }
+            #[cfg(test)]
+            Value::U32(x) => {
+                self.signature("u");
+                self.u32(x);
+            }
+        }
+    }
+}
+
+/// Reads values in either byte order. Offsets are relative to the start of the message or
+/// body, which are both 8 byte aligned.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], big_endian: bool) -> Self {
+        Self {
+            data,
+            position: 0,
+            big_endian,
+        }
+    }
+
+    fn align(&mut self, alignment: usize) {
+        self.position = self.position.next_multiple_of(alignment);
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or("truncated message")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.align(4);
+        let bytes = self.bytes(4)?.try_into().unwrap();
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    fn text(&mut self, length: usize) -> Result<String, String> {
+        let text = String::from_utf8_lossy(self.bytes(length)?).into_owned();
+        self.bytes(1)?;
+        Ok(text)
+    }
+
+    /// A string or object path.
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u32()? as usize;
+        self.text(length)
+    }
+
+    fn signature(&mut self) -> Result<String, String> {
+        let length = self.u8()? as usize;
+        self.text(length)
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u32()? != 0)
+    }
+
+    /// Skips a value of a single complete type.
+    fn skip(&mut self, signature: &str) -> Result<(), String> {
+        match signature.as_bytes().first() {
+            Some(b's' | b'o') => self.string().map(drop),
+            Some(b'g') => self.signature().map(drop),
+            Some(b'v') => {
+                let signature = self.signature()?;
+                self.skip(&signature)
+            }
+            Some(b'a') => {
+                let length = self.u32()? as usize;
+                self.align(alignment(&signature[1..]));
+                self.bytes(length).map(drop)
+            }
+            Some(b'(' | b'{') => {
+                self.align(8);
+                let mut members = &signature[1..signature.len() - 1];
+                while !members.is_empty() {
+                    let (member, rest) = split_type(members)?;
+                    self.skip(member)?;
+                    members = rest;
+                }
+                Ok(())
+            }
+            Some(b'y' | b'n' | b'q' | b'b' | b'i' | b'u' | b'h' | b'x' | b't' | b'd') => {
+                let size = alignment(signature);
+                self.align(size);
+                self.bytes(size).map(drop)
+            }
+            _ => Err(format!("unsupported type {signature}")),
+        }
+    }
+
+    /// Calls `element` for every element of an array of dict entries or structs.
+    fn array(
+        &mut self,
+        mut element: impl FnMut(&mut Self) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let length = self.u32()? as usize;
+        self.align(8);
+        let end = self.position + length;
+        while self.position < end {
+            self.align(8);
+            element(self)?;
+        }
+        Ok(())
+    }
+
+    /// The number of elements of an array of `element`, which must be a struct.
+    fn count(&mut self, element: &str) -> Result<usize, String> {
+        let length = self.u32()? as usize;
+        self.align(8);
+        let end = self.position + length;
+        let mut count = 0;
+        while self.position < end {
+            self.skip(element)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Splits the first single complete type off `signature`, e.g. "a{sv}" off "a{sv}as".
+fn split_type(signature: &str) -> Result<(&str, &str), String> {
+    let length = match signature.as_bytes().first() {
+        Some(b'a') => 1 + split_type(&signature[1..])?.0.len(),
+        Some(open @ (b'(' | b'{')) => {
+            let close = if *open == b'(' { b')' } else { b'}' };
+            let mut length = 1;
+            loop {
+                match signature.as_bytes().get(length) {
+                    Some(x) if *x == close => break length + 1,
+                    Some(_) => length += split_type(&signature[length..])?.0.len(),
+                    None => return Err(format!("unbalanced signature {signature}")),
+                }
+            }
+        }
+        Some(_) => 1,
+        None => return Err("empty signature".into()),
+    };
+    Ok(signature.split_at(length))
+}
+
+/// Alignment of the type `signature` starts with, also the size of fixed size types.
+fn alignment(signature: &str) -> usize {
+    match signature.as_bytes().first() {
+        Some(b'y' | b'g' | b'v') => 1,
+        Some(b'n' | b'q') => 2,
+        Some(b'x' | b't' | b'd' | b'(' | b'{') => 8,
+        _ => 4,
+    }
+}
+
+/// A message read off the bus, with the header fields used here.
+struct Message {
+    kind: u8,
+    reply_serial: Option<u32>,
+    error_name: Option<String>,
+    signature: String,
+    big_endian: bool,
+    body: Vec<u8>,
+}
+
+impl Message {
+    fn body(&self) -> Reader<'_> {
+        Reader::new(&self.body, self.big_endian)
+    }
+}
+
+fn encode(kind: u8, serial: u32, fields: &[(u8, Value)], signature: &str, body: &[u8]) -> Vec<u8> {
+    let mut message = Writer::default();
+    message.data.extend([b'l', kind, 0, 1]);
+    message.u32(body.len() as u32);
+    message.u32(serial);
+
+    message.u32(0);
+    let start = message.data.len();
+    let signature =
+        (!signature.is_empty()).then_some((FIELD_SIGNATURE, Value::Signature(signature)));
+    for (code, value) in fields.iter().copied().chain(signature) {
+        message.align(8);
+        message.data.push(code);
+        message.value(value);
+    }
+    let length = (message.data.len() - start) as u32;
+    message.data[start - 4..start].copy_from_slice(&length.to_le_bytes());
+
+    message.align(8);
+    message.data.extend(body);
+    message.data
+}
+
+fn read_message(stream: &mut impl Read) -> Result<Message, String> {
+    let mut fixed = [0; 16];
+    stream.read_exact(&mut fixed).map_err(|e| e.to_string())?;
+    let big_endian = match fixed[0] {
+        b'l' => false,
+        b'B' => true,
+        x => return Err(format!("invalid byte order {x:#x}")),
+    };
+    let mut header = Reader::new(&fixed, big_endian);
+    header.position = 4;
+    let body_length = header.u32()?;
+    header.u32()?;
+    let fields_length = header.u32()?;
+    if body_length > MAX_MESSAGE_LENGTH || fields_length > MAX_MESSAGE_LENGTH {
+        return Err("message too long".into());
+    }
+
+    // The fields are followed by padding up to the body.
+    let padded = (16 + fields_length as usize).next_multiple_of(8) - 16;
+    let mut rest = vec![0; padded + body_length as usize];
+    stream.read_exact(&mut rest).map_err(|e| e.to_string())?;
+
+    let mut message = Message {
+        kind: fixed[1],
+        reply_serial: None,
+        error_name: None,
+        signature: String::new(),
+        big_endian,
+        body: rest.split_off(padded),
+    };
+    let mut fields = Reader::new(&rest[..fields_length as usize], big_endian);
+    while fields.position < fields.data.len() {
+        fields.align(8);
+        let code = fields.u8()?;
+        let signature = fields.signature()?;
+        match (code, signature.as_str()) {
+            (FIELD_REPLY_SERIAL, "u") => message.reply_serial = Some(fields.u32()?),
+            (FIELD_ERROR_NAME, "s") => message.error_name = Some(fields.string()?),
+            (FIELD_SIGNATURE, "g") => message.signature = fields.signature()?,
+            (_, signature) => fields.skip(signature)?,
+        }
+    }
+
+    Ok(message)
+}
+
+/// A connection to the system bus, kept open across updates.
+pub struct Connection {
+    stream: BufReader<UnixStream>,
+    serial: u32,
+}
+
+impl Connection {
+    /// Connects to the system bus, at `DBUS_SYSTEM_BUS_ADDRESS` if that's a socket path.
+    pub fn system() -> Result<Self, String> {
+        let address = env::var("DBUS_SYSTEM_BUS_ADDRESS").ok();
+        let path = address
+            .as_deref()
+            .and_then(socket_path)
+            .unwrap_or(SYSTEM_BUS_PATH);
+        Self::connect(Path::new(path))
+    }
+
+    /// Connects to the session bus, at `DBUS_SESSION_BUS_ADDRESS` if that's a socket path or
+    /// else at `$XDG_RUNTIME_DIR/bus`.
+    pub fn session() -> Result<Self, String> {
+        let address = env::var("DBUS_SESSION_BUS_ADDRESS").ok();
+        let path = match address.as_deref().and_then(socket_path) {
+            Some(x) => PathBuf::from(x),
+            None => {
+                PathBuf::from(env::var_os("XDG_RUNTIME_DIR").ok_or("no session bus")?).join("bus")
+            }
+        };
+        Self::connect(&path)
+    }
+
+    fn connect(path: &Path) -> Result<Self, String> {
+        let stream = UnixStream::connect(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        stream
+            .set_read_timeout(Some(TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        let mut connection = Self {
+            stream: BufReader::new(stream),
+            serial: 0,
+        };
+
+        // SAFETY: getuid can't fail.
+        let uid = unsafe { libc::getuid() }.to_string();
+        let uid: String = uid.bytes().map(|x| format!("{x:02x}")).collect();
+        connection.send(format!("\0AUTH EXTERNAL {uid}\r\n").as_bytes())?;
+        let mut line = String::new();
+        connection
+            .stream
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?;
+        if !line.starts_with("OK ") {
+            return Err(format!("authentication rejected: {}", line.trim()));
+        }
+        connection.send(b"BEGIN\r\n")?;
+
+        connection.call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "Hello",
+            "",
+            &[],
+        )?;
+        Ok(connection)
+    }
+
+    fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        self.stream
+            .get_mut()
+            .write_all(data)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Calls a method and waits for its reply, skipping signals sent meanwhile.
+    fn call(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        signature: &str,
+        body: &[u8],
+    ) -> Result<Message, String> {
+        self.serial += 1;
+        let fields = [
+            (FIELD_PATH, Value::ObjectPath(path)),
+            (FIELD_INTERFACE, Value::String(interface)),
+            (FIELD_MEMBER, Value::String(member)),
+            (FIELD_DESTINATION, Value::String(destination)),
+        ];
+        self.send(&encode(METHOD_CALL, self.serial, &fields, signature, body))?;
+
+        loop {
+            let reply = read_message(&mut self.stream)?;
+            if reply.reply_serial != Some(self.serial) {
+                continue;
+            }
+            return match reply.kind {
+                METHOD_RETURN => Ok(reply),
+                ERROR => {
+                    let name = reply.error_name.as_deref().unwrap_or("unknown error");
+                    Err(match reply.signature.starts_with('s') {
+                        true => format!("{name}: {}", reply.body().string()?),
+                        false => name.into(),
+                    })
+                }
+                _ => continue,
+            };
+        }
+    }
+
+    /// The number of systemd units in the failed state.
+    pub fn failed_units(&mut self) -> Result<usize, String> {
+        let mut states = Writer::default();
+        states.strings(&["failed"]);
+        let reply = self.call(
+            "org.freedesktop.systemd1",
+            "/org/freedesktop/systemd1",
+            "org.freedesktop.systemd1.Manager",
+            "ListUnitsFiltered",
+            "as",
+            &states.data,
+        )?;
+
+        const UNIT: &str = "(ssssssouso)";
+        if reply.signature != format!("a{UNIT}") {
+            return Err(format!("unexpected reply signature {}", reply.signature));
+        }
+        reply.body().count(UNIT)
+    }
+
+    /// Bluetooth adapters and devices, from BlueZ's object tree.
+    pub fn bluetooth(&mut self) -> Result<bluetooth::Status, String> {
+        let reply = self.call(
+            "org.bluez",
+            "/",
+            "org.freedesktop.DBus.ObjectManager",
+            "GetManagedObjects",
+            "",
+            &[],
+        )?;
+
+        if reply.signature != MANAGED_OBJECTS_SIGNATURE {
+            return Err(format!("unexpected reply signature {}", reply.signature));
+        }
+        read_bluez_objects(&mut reply.body())
+    }
+
+    /// Shows a desktop notification with the notification daemon's default timeout.
+    pub fn notify(&mut self, summary: &str, body: &str) -> Result<(), String> {
+        self.call(
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+            "Notify",
+            NOTIFY_SIGNATURE,
+            &notify_args(summary, body),
+        )
+        .map(drop)
+    }
+}
+
+const NOTIFY_SIGNATURE: &str = "susssasa{sv}i";
+
+/// App name, replaced id, icon, summary, body, actions, hints and timeout.
+fn notify_args(summary: &str, body: &str) -> Vec<u8> {
+    let mut args = Writer::default();
+    args.string("stringbar");
+    args.u32(0);
+    args.string("");
+    args.string(summary);
+    args.string(body);
+    args.strings(&[]);
+    // No hints, an empty array is still padded to its elements' alignment.
+    args.u32(0);
+    args.align(8);
+    args.u32(-1_i32 as u32);
+    args.data
+}
+
+const MANAGED_OBJECTS_SIGNATURE: &str = "a{oa{sa{sv}}}";
+
+/// Picks adapters, devices and their batteries out of objects by path, then by interface and
+/// property name. Everything else is skipped.
+fn read_bluez_objects(body: &mut Reader) -> Result<bluetooth::Status, String> {
+    let mut status = bluetooth::Status::default();
+    body.array(|object| {
+        object.string()?;
+        let mut device: Option<bluetooth::Device> = None;
+        let mut battery = None;
+        object.array(|interface| {
+            let name = interface.string()?;
+            if name == "org.bluez.Device1" {
+                device.get_or_insert_default();
+            }
+            interface.array(|property| {
+                let property_name = property.string()?;
+                let signature = property.signature()?;
+                match (name.as_str(), property_name.as_str(), signature.as_str()) {
+                    ("org.bluez.Adapter1", "Powered", "b") => status.powered |= property.bool()?,
+                    ("org.bluez.Device1", "Address", "s") => {
+                        device.get_or_insert_default().address = property.string()?
+                    }
+                    ("org.bluez.Device1", "Connected", "b") => {
+                        device.get_or_insert_default().connected = property.bool()?
+                    }
+                    ("org.bluez.Battery1", "Percentage", "y") => battery = Some(property.u8()?),
+                    (_, _, signature) => property.skip(signature)?,
+                }
+                Ok(())
+            })
+        })?;
+        if let Some(device) = device {
+            status.devices.push(bluetooth::Device { battery, ..device });
+        }
+        Ok(())
+    })?;
+
+    Ok(status)
+}
+
+/// The socket path of a `unix:path=...` bus address, the first one if there are several.
+fn socket_path(address: &str) -> Option<&str> {
+    address
+        .split(';')
+        .filter_map(|x| x.strip_prefix("unix:"))
+        .flat_map(|x| x.split(','))
+        .find_map(|x| x.strip_prefix("path="))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::net::UnixListener, thread};
+
+    use super::*;
+
+    #[test]
+    fn addresses() {
+        let cases = [
+            (
+                "unix:path=/var/run/dbus/system_bus_socket",
+                Some("/var/run/dbus/system_bus_socket"),
+            ),
+            (
+                "unix:abstract=/tmp/dbus-x;unix:guid=1234,path=/run/bus",
+                Some("/run/bus"),
+            ),
+            ("tcp:host=localhost,port=1234", None),
+            ("", None),
+        ];
+        for (address, expected) in cases {
+            assert_eq!(expected, socket_path(address), "{address}");
+        }
+    }
+
+    #[test]
+    fn notification() {
+        let args = notify_args("stringbar: config not reloaded", "section 2: …");
+        let mut args = Reader::new(&args, false);
+        assert_eq!("stringbar", args.string().unwrap());
+        assert_eq!(0, args.u32().unwrap());
+        assert_eq!("", args.string().unwrap());
+        assert_eq!("stringbar: config not reloaded", args.string().unwrap());
+        assert_eq!("section 2: …", args.string().unwrap());
+        // No actions and no hints.
+        assert_eq!(0, args.u32().unwrap());
+        assert_eq!(Ok(0), args.count("(sv)"));
+        assert_eq!(u32::MAX, args.u32().unwrap());
+        assert_eq!(args.data.len(), args.position);
+    }
+
+    #[test]
+    fn messages() {
+        let mut body = Writer::default();
+        body.strings(&["failed", "dead"]);
+        let message = encode(
+            METHOD_CALL,
+            7,
+            &[(FIELD_MEMBER, Value::String("ListUnitsFiltered"))],
+            "as",
+            &body.data,
+        );
+        // The body starts 8 byte aligned.
+        assert_eq!(0, (message.len() - body.data.len()) % 8);
+
+        let read = read_message(&mut message.as_slice()).unwrap();
+        assert_eq!(METHOD_CALL, read.kind);
+        assert_eq!("as", read.signature);
+        let mut body = read.body();
+        assert_eq!(21, body.u32().unwrap());
+        assert_eq!("failed", body.string().unwrap());
+        assert_eq!("dead", body.string().unwrap());
+
+        assert!(read_message(&mut &message[..message.len() - 1]).is_err());
+        assert!(read_message(&mut &b"x\x01\0\x01"[..]).is_err());
+    }
+
+    #[test]
+    fn signatures() {
+        assert_eq!(Ok(("a{sv}", "as")), split_type("a{sv}as"));
+        assert_eq!(Ok(("(s(ua{qv}))", "")), split_type("(s(ua{qv}))"));
+        assert_eq!(Ok(("y", "b")), split_type("yb"));
+        assert!(split_type("a{sv").is_err());
+        assert!(split_type("").is_err());
+    }
+
+    /// Writes an array of dict entries or structs, `entries` writes them.
+    fn array(body: &mut Writer, entries: impl FnOnce(&mut Writer)) {
+        body.u32(0);
+        let length_at = body.data.len() - 4;
+        body.align(8);
+        let start = body.data.len();
+        entries(body);
+        let length = (body.data.len() - start) as u32;
+        body.data[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    /// Writes the properties of an interface.
+    type Properties<'a> = &'a dyn Fn(&mut Writer);
+
+    /// Writes an object with its interfaces.
+    fn object(body: &mut Writer, path: &str, interfaces: &[(&str, Properties)]) {
+        body.align(8);
+        body.string(path);
+        array(body, |body| {
+            for (name, properties) in interfaces {
+                body.align(8);
+                body.string(name);
+                array(body, properties);
+            }
+        });
+    }
+
+    fn property(body: &mut Writer, name: &str, signature: &str, value: impl FnOnce(&mut Writer)) {
+        body.align(8);
+        body.string(name);
+        body.signature(signature);
+        value(body);
+    }
+
+    #[test]
+    fn bluez_objects() {
+        let mut body = Writer::default();
+        array(&mut body, |body| {
+            object(body, "/org/bluez", &[("org.bluez.AgentManager1", &|_| {})]);
+            object(
+                body,
+                "/org/bluez/hci0",
+                &[
+                    ("org.freedesktop.DBus.Introspectable", &|_| {}),
+                    ("org.bluez.Adapter1", &|body| {
+                        property(body, "Address", "s", |x| x.string("00:11:22:33:44:55"));
+                        property(body, "Class", "u", |x| x.u32(0x7c010c));
+                        property(body, "Powered", "b", |x| x.u32(1));
+                        property(body, "UUIDs", "as", |x| {
+                            x.strings(&["0000110e-0000-1000-8000-00805f9b34fb"])
+                        });
+                    }),
+                ],
+            );
+            object(
+                body,
+                "/org/bluez/hci0/dev_00_1A_7D_DA_71_13",
+                &[
+                    ("org.bluez.Device1", &|body| {
+                        property(body, "Address", "s", |x| x.string("00:1A:7D:DA:71:13"));
+                        property(body, "RSSI", "n", |x| {
+                            x.align(2);
+                            x.data.extend((-60_i16).to_le_bytes());
+                        });
+                        property(body, "ManufacturerData", "a{qv}", |x| {
+                            array(x, |x| {
+                                x.data.extend(76_u16.to_le_bytes());
+                                x.signature("ay");
+                                x.u32(3);
+                                x.data.extend([1, 2, 3]);
+                            })
+                        });
+                        property(body, "Connected", "b", |x| x.u32(1));
+                    }),
+                    ("org.bluez.Battery1", &|body| {
+                        property(body, "Percentage", "y", |x| x.data.push(80));
+                    }),
+                ],
+            );
+            object(
+                body,
+                "/org/bluez/hci0/dev_F4_4E_FD_00_00_01",
+                &[("org.bluez.Device1", &|body| {
+                    property(body, "Connected", "b", |x| x.u32(0));
+                    property(body, "Address", "s", |x| x.string("F4:4E:FD:00:00:01"));
+                })],
+            );
+        });
+
+        assert_eq!(
+            Ok(bluetooth::Status {
+                powered: true,
+                devices: vec![
+                    bluetooth::Device {
+                        address: "00:1A:7D:DA:71:13".into(),
+                        connected: true,
+                        battery: Some(80),
+                    },
+                    bluetooth::Device {
+                        address: "F4:4E:FD:00:00:01".into(),
+                        connected: false,
+                        battery: None,
+                    },
+                ],
+            }),
+            read_bluez_objects(&mut Reader::new(&body.data, false))
+        );
+        assert!(read_bluez_objects(&mut Reader::new(&body.data[..40], false)).is_err());
+    }
+
+    /// A reply to `ListUnitsFiltered` listing `units` failed units.
+    fn unit_list(reply_serial: u32, units: usize) -> Vec<u8> {
+        let mut body = Writer::default();
+        body.u32(0);
+        body.align(8);
+        let start = body.data.len();
+        for unit in 0..units {
+            body.align(8);
+            let name = format!("unit{unit}.service");
+            for text in [name.as_str(), "", "loaded", "failed", "failed", ""] {
+                body.string(text);
+            }
+            body.string("/org/freedesktop/systemd1/unit");
+            body.u32(0);
+            body.string("");
+            body.string("/");
+        }
+        let length = (body.data.len() - start) as u32;
+        body.data[0..4].copy_from_slice(&length.to_le_bytes());
+
+        encode(
+            METHOD_RETURN,
+            100 + reply_serial,
+            &[(FIELD_REPLY_SERIAL, Value::U32(reply_serial))],
+            "a(ssssssouso)",
+            &body.data,
+        )
+    }
+
+    /// Serves the handshake, `Hello` and then answers calls from `replies` in order. Calls are
+    /// numbered from 1.
+    fn fake_bus(path: &Path, replies: Vec<fn(u32) -> Vec<u8>>) -> thread::JoinHandle<()> {
+        let listener = UnixListener::bind(path).unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut line = Vec::new();
+            stream.read_until(b'\n', &mut line).unwrap();
+            assert!(line.starts_with(b"\0AUTH EXTERNAL "));
+            stream
+                .get_mut()
+                .write_all(b"OK 0123456789abcdef\r\n")
+                .unwrap();
+            line.clear();
+            stream.read_until(b'\n', &mut line).unwrap();
+            assert_eq!(b"BEGIN\r\n", line.as_slice());
+
+            let hello = |serial| {
+                let mut body = Writer::default();
+                body.string(":1.42");
+                encode(
+                    METHOD_RETURN,
+                    1,
+                    &[(FIELD_REPLY_SERIAL, Value::U32(serial))],
+                    "s",
+                    &body.data,
+                )
+            };
+            let replies = [hello as fn(u32) -> Vec<u8>].into_iter().chain(replies);
+            for (serial, reply) in (1..).zip(replies) {
+                let Ok(call) = read_message(&mut stream) else {
+                    return;
+                };
+                assert_eq!(METHOD_CALL, call.kind);
+                stream.get_mut().write_all(&reply(serial)).unwrap();
+            }
+        })
+    }
+
+    #[test]
+    fn failed_units() {
+        let dir = env::temp_dir().join(format!("stringbar-dbus-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bus");
+        let _ = fs::remove_file(&path);
+
+        let server = fake_bus(
+            &path,
+            vec![
+                |serial| unit_list(serial, 2),
+                |serial| {
+                    // A signal arriving before the reply is skipped.
+                    let mut messages = encode(4, 50, &[], "", &[]);
+                    messages.extend(unit_list(serial, 0));
+                    messages
+                },
+                |serial| {
+                    let mut body = Writer::default();
+                    body.string("Access denied");
+                    encode(
+                        ERROR,
+                        60,
+                        &[
+                            (FIELD_REPLY_SERIAL, Value::U32(serial)),
+                            (
+                                FIELD_ERROR_NAME,
+                                Value::String("org.freedesktop.DBus.Error.AccessDenied"),
+                            ),
+                        ],
+                        "s",
+                        &body.data,
+                    )
+                },
+            ],
+        );
+
+        let mut connection = Connection::connect(&path).unwrap();
+        assert_eq!(Ok(2), connection.failed_units());
+        assert_eq!(Ok(0), connection.failed_units());
+        assert_eq!(
+            Err("org.freedesktop.DBus.Error.AccessDenied: Access denied".into()),
+            connection.failed_units()
+        );
+        drop(connection);
+        server.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(Connection::connect(&dir.join("missing")).is_err());
+    }
+}
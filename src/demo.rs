@@ -0,0 +1,511 @@
+//! `stringbar --demo`: the real bar driven by made-up system data, for screenshots and for
+//! trying configs without showing the machine's own disks and sensors.
+//!
+//! Values follow smooth curves over update ticks plus a little seeded jitter, so the same seed
+//! produces the same sequence. Command sections still run their commands.
+
+use std::{
+    f64::consts::TAU,
+    net::IpAddr,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    alsa, bluetooth, cgroup,
+    disks::DiskSnapshot,
+    mpd,
+    provider::{
+        BatteryInfo, ChargeState, ComponentInfo, DiskInfo, NetworkInterface, ProcessInfo,
+        SystemProvider,
+    },
+};
+
+pub const USAGE: &str = "usage: stringbar --demo [--seed <number>]";
+
+const GIB: u64 = 1024 * 1024 * 1024;
+/// Three days and a bit.
+const BASE_UPTIME: u64 = 3 * 86_400 + 4 * 3600 + 12 * 60;
+const HEADPHONES: &str = "00:1A:7D:DA:71:13";
+
+/// The seed if `args` ask for demo mode, a random one unless given with `--seed`.
+pub fn seed(args: &[String]) -> Result<Option<u64>, String> {
+    match args {
+        [flag, rest @ ..] if flag == "--demo" => match rest {
+            [] => Ok(Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|x| x.subsec_nanos() as u64 ^ x.as_secs())
+                    .unwrap_or_default(),
+            )),
+            [flag, seed] if flag == "--seed" => seed
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid seed: {seed}\n{USAGE}")),
+            _ => Err(USAGE.into()),
+        },
+        _ => Ok(None),
+    }
+}
+
+pub struct DemoProvider {
+    seed: u64,
+    tick: u64,
+    disks: Vec<DiskInfo>,
+    disk_snapshot: DiskSnapshot,
+    components: Vec<ComponentInfo>,
+    processes: Vec<ProcessInfo>,
+}
+
+impl DemoProvider {
+    pub fn new(seed: u64) -> Self {
+        let disk = |name: &str, mount_point: &str, total: u64, removable| DiskInfo {
+            name: name.into(),
+            mount_point: mount_point.into(),
+            file_system: "ext4".into(),
+            total_space: total,
+            available_space: total,
+            is_removable: removable,
+            inodes: None,
+            spared: false,
+        };
+        let mut provider = Self {
+            seed,
+            tick: 0,
+            disks: vec![
+                disk("/dev/nvme0n1p2", "/", 120 * GIB, false),
+                disk("/dev/nvme0n1p3", "/home", 800 * GIB, false),
+                disk("/dev/sda1", "/run/media/demo/backup", 2000 * GIB, true),
+            ],
+            disk_snapshot: DiskSnapshot::default(),
+            components: vec![
+                ComponentInfo {
+                    label: "coretemp Package id 0".into(),
+                    temperature: 0.0,
+                },
+                ComponentInfo {
+                    label: "nvme Composite".into(),
+                    temperature: 0.0,
+                },
+            ],
+            processes: ["firefox", "Xorg", "alacritty", "cargo", "kworker/u16:2"]
+                .into_iter()
+                .map(|name| ProcessInfo {
+                    name: name.into(),
+                    cpu_usage: 0.0,
+                    memory: 0,
+                    kernel_thread: name.starts_with("kworker"),
+                })
+                .collect(),
+        };
+        provider.update();
+        provider
+    }
+
+    /// A value in [0, 1) that only depends on the seed, the tick and `channel`.
+    fn jitter(&self, channel: u64) -> f64 {
+        // splitmix64
+        let mut x = self
+            .seed
+            .wrapping_add(self.tick.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+            .wrapping_add(channel.wrapping_mul(0xbf58_476d_1ce4_e5b9));
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^= x >> 31;
+
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A sine wave between 0 and 1 with the given period in ticks, shifted by the seed.
+    fn wave(&self, period: f64, channel: u64) -> f64 {
+        let phase = (self.seed.wrapping_add(channel) % 1000) as f64 / 1000.0;
+        ((self.tick as f64 / period + phase) * TAU).sin() / 2.0 + 0.5
+    }
+
+    /// Refreshes the values that change over time.
+    fn update(&mut self) {
+        // The disks slowly fill up, the backup drive in steps.
+        let filled = [
+            0.55 + self.tick as f64 * 0.000_02,
+            0.70 + self.tick as f64 * 0.000_005,
+            0.40 + (self.tick / 600) as f64 * 0.01,
+        ];
+        for (disk, filled) in self.disks.iter_mut().zip(filled) {
+            let used = (disk.total_space as f64 * filled.min(0.98)) as u64;
+            disk.available_space = disk.total_space - used;
+            let inodes = disk.total_space / (64 * 1024);
+            disk.inodes = Some((inodes / 3, inodes));
+        }
+        self.disk_snapshot = DiskSnapshot::new(self.disks.clone());
+
+        let cpu = self.cpu_usage_now() as f32;
+        let temperatures = [
+            42.0 + cpu * 0.4 + self.jitter(10) as f32,
+            38.0 + self.wave(300.0, 11) as f32 * 6.0,
+        ];
+        for (component, temperature) in self.components.iter_mut().zip(temperatures) {
+            component.temperature = temperature;
+        }
+
+        // The browser and occasional builds take turns being the busiest.
+        let usage = [
+            (4.0 + 30.0 * self.wave(90.0, 12), 2.1),
+            (2.0 + 3.0 * self.jitter(13), 0.3),
+            (1.0 + 2.0 * self.jitter(14), 0.1),
+            (120.0 * self.wave(200.0, 15).powi(4), 0.8),
+            (12.0 * self.jitter(16).powi(6), 0.0),
+        ];
+        for (process, (cpu, memory)) in self.processes.iter_mut().zip(usage) {
+            process.cpu_usage = cpu as f32;
+            process.memory = (memory * GIB as f64) as u64;
+        }
+    }
+
+    fn cpu_usage_now(&self) -> f64 {
+        (8.0 + 30.0 * self.wave(60.0, 1) + 6.0 * self.jitter(1)).min(100.0)
+    }
+}
+
+impl SystemProvider for DemoProvider {
+    fn begin_tick(&mut self) {
+        self.tick += 1;
+        self.update();
+    }
+
+    fn memory(&mut self) -> (u64, u64) {
+        let used = 5.5 + 2.5 * self.wave(240.0, 2) + 0.2 * self.jitter(2);
+        ((used * GIB as f64) as u64, 16 * GIB)
+    }
+
+    /// Like on a host, where the root cgroup has no limits.
+    fn cgroup_memory(&mut self) -> Option<cgroup::Memory> {
+        None
+    }
+
+    fn swap(&mut self) -> (u64, u64) {
+        let used = 0.25 + 0.1 * self.wave(900.0, 3);
+        ((used * GIB as f64) as u64, 4 * GIB)
+    }
+
+    fn cpu_usage(&mut self) -> f32 {
+        self.cpu_usage_now() as f32
+    }
+
+    /// Eight cores boosting with the load, each a little differently.
+    fn cpu_frequencies(&mut self) -> Vec<u64> {
+        let boost = self.cpu_usage_now() / 100.0;
+        (0..8)
+            .map(|core| (800.0 + 3600.0 * boost * (1.0 - core as f64 * 0.05)) as u64)
+            .collect()
+    }
+
+    fn process_count(&mut self) -> usize {
+        280 + (20.0 * self.wave(120.0, 4)) as usize + (3.0 * self.jitter(4)) as usize
+    }
+
+    fn processes(&mut self) -> &[ProcessInfo] {
+        &self.processes
+    }
+
+    fn load_average(&mut self) -> (f64, f64, f64) {
+        let load = self.cpu_usage_now() / 100.0 * 8.0;
+        (load, load * 0.9 + 0.1, load * 0.8 + 0.2)
+    }
+
+    fn disks(&mut self) -> &DiskSnapshot {
+        &self.disk_snapshot
+    }
+
+    fn network_rates(&mut self, _interface: &str) -> Option<(f64, f64)> {
+        let received = 1.2e6 * self.wave(30.0, 5).powi(3) + 40e3 * self.jitter(5);
+        let transmitted = 180e3 * self.wave(45.0, 6).powi(2) + 10e3 * self.jitter(6);
+        Some((received, transmitted))
+    }
+
+    fn network_interfaces(&mut self) -> Vec<NetworkInterface> {
+        [("lo", true), ("wlan0", false)]
+            .map(|(name, loopback)| NetworkInterface {
+                name: name.into(),
+                loopback,
+            })
+            .into()
+    }
+
+    fn network_totals(&mut self, _interface: &str) -> Option<[u64; 2]> {
+        // About the average of the rates above for every tick so far.
+        Some([3 * GIB + self.tick * 600_000, GIB / 5 + self.tick * 90_000])
+    }
+
+    fn interface_addresses(&mut self, _interface: &str) -> Option<Vec<IpAddr>> {
+        Some(
+            ["192.168.1.23", "2001:db8::5", "fe80::a00:27ff:fe4e:66a1"]
+                .into_iter()
+                .map(|x| x.parse().unwrap())
+                .collect(),
+        )
+    }
+
+    fn batteries(&mut self) -> Vec<BatteryInfo> {
+        // Drains by a percent every minute at one update per second, then charges again.
+        let cycle = self.tick % 12_000;
+        let (capacity, state) = match cycle < 6000 {
+            true => (95 - cycle / 100, ChargeState::Discharging),
+            false => (35 + (cycle - 6000) / 100, ChargeState::Charging),
+        };
+        vec![BatteryInfo {
+            name: "BAT0".into(),
+            capacity: capacity.min(100) as u8,
+            state,
+            end_threshold: None,
+        }]
+    }
+
+    fn components(&mut self) -> &[ComponentInfo] {
+        &self.components
+    }
+
+    /// Advances a second per update, like everything else it only depends on the tick.
+    fn uptime(&mut self) -> u64 {
+        BASE_UPTIME + self.tick
+    }
+
+    fn brightness(&mut self, _device: Option<&str>) -> Option<(u64, u64)> {
+        Some((7680, 19200))
+    }
+
+    fn keyboard_layout(&mut self) -> Result<String, String> {
+        Ok("us".into())
+    }
+
+    fn volume(&mut self, _control: &str) -> Option<alsa::Volume> {
+        Some(alsa::Volume {
+            percent: 45.0,
+            muted: false,
+        })
+    }
+
+    /// From the range reserved for documentation.
+    fn public_ip(&mut self, _url: &str, _refresh_interval: Duration) -> Option<String> {
+        Some("203.0.113.42".into())
+    }
+
+    /// Mail trickles in and is read every quarter of an hour.
+    fn unread_mail(&mut self, _maildir: &Path) -> Result<usize, String> {
+        Ok((self.tick % 900 / 300) as usize)
+    }
+
+    /// A unit fails for a minute every ten.
+    fn failed_units(&mut self) -> Result<usize, String> {
+        Ok(usize::from(self.tick % 600 >= 540))
+    }
+
+    /// Headphones that lose a percent of charge every five minutes, and a keyboard.
+    fn bluetooth(&mut self) -> Result<bluetooth::Status, String> {
+        let device = |address: &str, battery| bluetooth::Device {
+            address: address.into(),
+            connected: true,
+            battery,
+        };
+        Ok(bluetooth::Status {
+            powered: true,
+            devices: vec![
+                device(HEADPHONES, Some(100 - (self.tick / 300 % 100) as u8)),
+                device("AC:80:0A:12:34:56", None),
+            ],
+        })
+    }
+
+    /// A four minute track on repeat.
+    fn mpd(&mut self, _host: &str, _port: u16) -> Result<mpd::Status, String> {
+        Ok(mpd::Status {
+            state: mpd::State::Play,
+            artist: Some("The Demos".into()),
+            title: Some("Made Up Song".into()),
+            elapsed: Some(Duration::from_secs(self.tick % 240)),
+            duration: Some(Duration::from_secs(240)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        bar,
+        clock::{Clock, SystemClock},
+        config::{
+            AddrFamily, Config, DiskFilter, FreqAggregate, Module, SortKey, TempAggregate,
+            UptimeFormat,
+        },
+        extremes::Extremes,
+        publicip,
+        rates::Rates,
+        render::{self, Context},
+        timer::Timers,
+        urgency::Urgency,
+        validity::Validity,
+    };
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn arguments() {
+        assert_eq!(Ok(None), seed(&args(&[])));
+        assert_eq!(Ok(None), seed(&args(&["install"])));
+        assert_eq!(Ok(Some(42)), seed(&args(&["--demo", "--seed", "42"])));
+        assert!(seed(&args(&["--demo"])).is_ok_and(|x| x.is_some()));
+        assert_eq!(
+            Err(format!("invalid seed: soon\n{USAGE}")),
+            seed(&args(&["--demo", "--seed", "soon"]))
+        );
+        assert_eq!(Err(USAGE.into()), seed(&args(&["--demo", "42"])));
+    }
+
+    /// Renders `ticks` lines of a bar using every module the demo provider feeds.
+    fn render(seed: u64, ticks: usize) -> Vec<String> {
+        let config = Config {
+            sections: [
+                Module::CpuUsage,
+                Module::CpuFrequency {
+                    aggregate: FreqAggregate::Max,
+                },
+                Module::MemoryUsage { basis: None },
+                Module::SwapUsage,
+                Module::ProcessCount,
+                Module::TopProcess {
+                    by: SortKey::Cpu,
+                    max_len: Some(8),
+                    exclude_kernel_threads: false,
+                },
+                Module::TopProcess {
+                    by: SortKey::Memory,
+                    max_len: None,
+                    exclude_kernel_threads: true,
+                },
+                Module::LoadAverage,
+                Module::DiskUsage {
+                    name: "/".into(),
+                    inodes: true,
+                },
+                Module::DiskUsageTotal {
+                    include_removables: true,
+                },
+                Module::DiskUsageEach {
+                    filter: DiskFilter::default(),
+                    separator: None,
+                    inodes: false,
+                },
+                Module::NetworkThroughput {
+                    interface: "wlan0".into(),
+                    average_over_s: None,
+                    peak_hold_s: None,
+                    min_unit: None,
+                    max_unit: None,
+                    show_totals: false,
+                    reset_signal: None,
+                },
+                Module::IpAddress {
+                    interface: "wlan0".into(),
+                    family: AddrFamily::Any,
+                    max_addresses: 2,
+                    include_link_local: false,
+                    separator: None,
+                    missing_text: None,
+                },
+                Module::Battery {
+                    name: None,
+                    hide_when_missing: false,
+                    limit_marker: None,
+                    relative_to_limit: false,
+                },
+                Module::Temperature {
+                    component: "coretemp".into(),
+                    fahrenheit: false,
+                    aggregate: TempAggregate::First,
+                    warn_above: None,
+                    clear_below: None,
+                },
+                Module::Uptime {
+                    format: UptimeFormat::Compact,
+                },
+                Module::Brightness { device: None },
+                Module::KeyboardLayout,
+                Module::Volume { control: None },
+                Module::PublicIp {
+                    url: publicip::DEFAULT_URL.into(),
+                    refresh_interval_s: 3600.into(),
+                },
+                Module::Maildir {
+                    path: "~/Mail/inbox".into(),
+                },
+                Module::Bluetooth { device_mac: None },
+                Module::Bluetooth {
+                    device_mac: Some(HEADPHONES.into()),
+                },
+                Module::SystemdFailed,
+                Module::Mpd {
+                    host: mpd::DEFAULT_HOST.into(),
+                    port: mpd::DEFAULT_PORT,
+                    format: "{title} {elapsed}/{duration}".into(),
+                    max_len: Some(10),
+                },
+            ]
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+            ..Config::default()
+        };
+
+        let mut provider = DemoProvider::new(seed);
+        let (mut extremes, mut validity) = (Extremes::default(), Validity::default());
+        (0..ticks)
+            .map(|_| {
+                let mut ctx = Context {
+                    section: 0,
+                    provider: &mut provider,
+                    timers: &Timers::new(),
+                    extremes: &mut extremes,
+                    validity: &mut validity,
+                    rates: &mut Rates::default(),
+                    values: &mut HashMap::new(),
+                    readings: &mut HashMap::new(),
+                    urgency: &mut Urgency::default(),
+                    now: SystemClock.monotonic(),
+                    local: SystemClock.now_local(),
+                };
+                ctx.provider.begin_tick();
+                let pieces: Vec<_> = config
+                    .sections
+                    .iter()
+                    .enumerate()
+                    .map(|(index, section)| {
+                        ctx.section = index;
+                        render::render_section(section, &config, &mut ctx)
+                    })
+                    .collect();
+                bar::assemble(&pieces, &config.separator)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reproducible() {
+        let lines = render(7, 50);
+        assert_eq!(lines, render(7, 50));
+        assert_ne!(lines, render(8, 50));
+        // Values change between ticks.
+        assert_ne!(lines[0], lines[1]);
+
+        for line in lines {
+            assert!(!line.contains("N/A"), "{line}");
+            assert!(
+                !line.contains(&Config::default().invalid_value_marker),
+                "{line}"
+            );
+        }
+    }
+}
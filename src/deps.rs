@@ -0,0 +1,329 @@
+//! Checks for external programs the config relies on, so a missing one is reported
+//! once at startup instead of failing on every update.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsStr,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{Config, Module, OutputBackend},
+    error::StringbarError,
+};
+
+const PROBE_TIMEOUT: Duration = Duration::from_millis(250);
+
+pub trait Probe: Send + Sync {
+    fn has_binary(&self, name: &str) -> bool;
+}
+
+/// Looks for executables in `$PATH`.
+pub struct PathProbe;
+
+impl Probe for PathProbe {
+    fn has_binary(&self, name: &str) -> bool {
+        env::var_os("PATH")
+            .is_some_and(|path| env::split_paths(&path).any(|dir| is_executable(&dir.join(name))))
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|x| x.is_file() && x.permissions().mode() & 0o111 != 0)
+}
+
+/// External binaries used by `config`, mapped to what uses them.
+pub fn required_binaries(config: &Config) -> BTreeMap<String, Vec<String>> {
+    let mut required: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    match config.output {
+        OutputBackend::Xsetroot { spawn: true, .. } => {
+            required.insert("xsetroot".into(), vec!["the bar output".into()]);
+        }
+        OutputBackend::Xsetroot { .. }
+        | OutputBackend::I3bar { .. }
+        | OutputBackend::Stdout { .. }
+        | OutputBackend::File { .. }
+        | OutputBackend::None
+        | OutputBackend::Auto => {}
+    }
+
+    for (index, section) in config.sections.iter().enumerate() {
+        let binary = match &section.module {
+            Module::Command { command, .. } => command.as_str(),
+            Module::PublicIp { .. } => "curl",
+            _ => continue,
+        };
+        required.entry(binary.into()).or_default().push(format!(
+            "section {} ({})",
+            index + 1,
+            section.module.name()
+        ));
+    }
+
+    required
+}
+
+/// Fails if the output backend can't work at all, unlike [`check`], which only reports what
+/// sections will be missing. `display` is the value of `$DISPLAY`, `connect` tries connecting
+/// to it for setting the root window name without xsetroot.
+pub fn check_output(
+    output: &OutputBackend,
+    display: Option<&OsStr>,
+    probe: &dyn Probe,
+    connect: fn() -> Result<(), String>,
+) -> Result<(), StringbarError> {
+    match output {
+        OutputBackend::Xsetroot { spawn, .. } => {
+            if display.is_none_or(|x| x.is_empty()) {
+                return Err(StringbarError::DisplayUnavailable(
+                    "DISPLAY is not set, there's no X display to show the bar on".into(),
+                ));
+            }
+            let unreachable = match spawn {
+                true => None,
+                false => connect().err(),
+            };
+            if (*spawn || unreachable.is_some()) && !probe.has_binary("xsetroot") {
+                return Err(StringbarError::OutputBackendInit(match unreachable {
+                    Some(e) => format!(
+                        "Unable to connect to the X server ({e}) and `xsetroot` was not found in PATH"
+                    ),
+                    None => "The bar output uses `xsetroot` but it was not found in PATH".into(),
+                }));
+            }
+            Ok(())
+        }
+        OutputBackend::I3bar { .. }
+        | OutputBackend::Stdout { .. }
+        | OutputBackend::File { .. }
+        | OutputBackend::None
+        | OutputBackend::Auto => Ok(()),
+    }
+}
+
+/// Probes all binaries in parallel and returns a single message describing the missing ones.
+///
+/// Probes that don't answer within a short timeout count as present,
+/// so a slow filesystem can't delay startup or cause false alarms.
+pub fn check(config: &Config, probe: &'static dyn Probe) -> Option<String> {
+    let required = required_binaries(config);
+    let (tx, rx) = mpsc::channel();
+
+    for binary in required.keys() {
+        let tx = tx.clone();
+        let binary = binary.clone();
+        thread::spawn(move || {
+            let found = probe.has_binary(&binary);
+            let _ = tx.send((binary, found));
+        });
+    }
+    drop(tx);
+
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    let mut missing = Vec::new();
+    while let Ok((binary, found)) =
+        rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+    {
+        if !found {
+            missing.push(format!(
+                "{} uses `{binary}` but it was not found in PATH",
+                required[&binary].join(", ")
+            ));
+        }
+    }
+    missing.sort();
+
+    if missing.is_empty() {
+        None
+    } else {
+        Some(format!("Missing dependencies: {}", missing.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::default_name_atoms;
+
+    struct FakeProbe(Vec<&'static str>, Duration);
+
+    fn fake(present: Vec<&'static str>, delay: Duration) -> &'static FakeProbe {
+        Box::leak(Box::new(FakeProbe(present, delay)))
+    }
+
+    impl Probe for FakeProbe {
+        fn has_binary(&self, name: &str) -> bool {
+            thread::sleep(self.1);
+            self.0.contains(&name)
+        }
+    }
+
+    #[test]
+    fn reports_missing() {
+        let config = |spawn| Config {
+            output: OutputBackend::Xsetroot {
+                status2d: false,
+                statuscmd: false,
+                post: None,
+                spawn,
+                reverse_order: false,
+                name_atoms: default_name_atoms(),
+                latin1_replacement: '?',
+                width_check: None,
+            },
+            ..Config::default()
+        };
+        assert_eq!(
+            None,
+            check(&config(true), fake(vec!["xsetroot"], Duration::ZERO))
+        );
+        assert_eq!(
+            Some(
+                "Missing dependencies: the bar output uses `xsetroot` but it was not found in PATH"
+                    .into()
+            ),
+            check(&config(true), fake(vec![], Duration::ZERO))
+        );
+        // Only needed if the X server can't be reached directly, which check_output handles.
+        assert_eq!(None, check(&config(false), fake(vec![], Duration::ZERO)));
+    }
+
+    #[test]
+    fn output() {
+        let xsetroot = |spawn| OutputBackend::Xsetroot {
+            status2d: false,
+            statuscmd: false,
+            post: None,
+            spawn,
+            reverse_order: false,
+            name_atoms: default_name_atoms(),
+            latin1_replacement: '?',
+            width_check: None,
+        };
+        let display = Some(OsStr::new(":0"));
+        let present = fake(vec!["xsetroot"], Duration::ZERO);
+        let missing = fake(vec![], Duration::ZERO);
+        let connects = || Ok(());
+        let refused = || Err("connection refused".into());
+
+        for spawn in [false, true] {
+            assert_eq!(
+                Ok(()),
+                check_output(&xsetroot(spawn), display, present, refused)
+            );
+            assert!(matches!(
+                check_output(&xsetroot(spawn), None, present, connects),
+                Err(StringbarError::DisplayUnavailable(_))
+            ));
+            assert!(matches!(
+                check_output(&xsetroot(spawn), Some(OsStr::new("")), present, connects),
+                Err(StringbarError::DisplayUnavailable(_))
+            ));
+        }
+        assert_eq!(
+            Ok(()),
+            check_output(&xsetroot(false), display, missing, connects)
+        );
+        assert_eq!(
+            Err(StringbarError::OutputBackendInit(
+                "Unable to connect to the X server (connection refused) and `xsetroot` was not \
+                 found in PATH"
+                    .into()
+            )),
+            check_output(&xsetroot(false), display, missing, refused)
+        );
+        assert_eq!(
+            Err(StringbarError::OutputBackendInit(
+                "The bar output uses `xsetroot` but it was not found in PATH".into()
+            )),
+            check_output(&xsetroot(true), display, missing, connects)
+        );
+        assert_eq!(
+            Ok(()),
+            check_output(&OutputBackend::None, None, missing, refused)
+        );
+        assert_eq!(
+            Ok(()),
+            check_output(
+                &OutputBackend::I3bar {
+                    reverse_order: false
+                },
+                None,
+                missing,
+                refused
+            )
+        );
+    }
+
+    #[test]
+    fn commands() {
+        let command = |name: &str| {
+            Module::Command {
+                command: name.into(),
+                args: Vec::new(),
+                timeout_ms: None,
+                fallback: None,
+                join_lines: None,
+            }
+            .into()
+        };
+        let config = Config {
+            output: OutputBackend::None,
+            sections: vec![
+                command("weather"),
+                Module::CpuUsage.into(),
+                command("weather"),
+                command("/opt/bin/mail-count"),
+                Module::PublicIp {
+                    url: "https://icanhazip.com".into(),
+                    refresh_interval_s: 3600.into(),
+                }
+                .into(),
+            ],
+            ..Config::default()
+        };
+
+        assert_eq!(
+            Some(
+                "Missing dependencies: section 1 (Command), section 3 (Command) uses `weather` but it was not found in PATH"
+                    .into()
+            ),
+            check(
+                &config,
+                fake(vec!["/opt/bin/mail-count", "curl"], Duration::ZERO)
+            )
+        );
+    }
+
+    #[test]
+    fn headless() {
+        let config = Config {
+            output: OutputBackend::None,
+            ..Config::default()
+        };
+        assert!(required_binaries(&config).is_empty());
+        assert_eq!(None, check(&config, fake(vec![], Duration::ZERO)));
+    }
+
+    #[test]
+    fn slow_probes_count_as_present() {
+        let started = Instant::now();
+        assert_eq!(
+            None,
+            check(&Config::default(), fake(vec![], PROBE_TIMEOUT * 4))
+        );
+        assert!(started.elapsed() < PROBE_TIMEOUT * 2);
+    }
+
+    #[test]
+    fn path_probe() {
+        assert!(PathProbe.has_binary("sh"));
+        assert!(!PathProbe.has_binary("stringbar-definitely-not-installed"));
+    }
+}
@@ -0,0 +1,476 @@
+use serde::Serialize;
+
+use crate::config::{Config, Section};
+
+/// Describes what changed between two configs, one entry per change, e.g.
+/// `update_interval_ms 1000→500` or `section 3 added (Timer)`. Empty if nothing did.
+///
+/// Sections are matched by their module first, so inserting a section doesn't report every
+/// following one as changed. Unmatched sections at the same position count as modified.
+pub fn diff(old: &Config, new: &Config) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    field(&mut changes, "separator", &old.separator, &new.separator);
+    field(
+        &mut changes,
+        "escape_separator",
+        &old.escape_separator,
+        &new.escape_separator,
+    );
+    field(
+        &mut changes,
+        "update_interval_ms",
+        &old.update_interval_ms,
+        &new.update_interval_ms,
+    );
+    field(
+        &mut changes,
+        "decimal_data_units",
+        &old.decimal_data_units,
+        &new.decimal_data_units,
+    );
+    field(
+        &mut changes,
+        "thousands_separator",
+        &old.thousands_separator,
+        &new.thousands_separator,
+    );
+    field(
+        &mut changes,
+        "trim_trailing_zeros",
+        &old.trim_trailing_zeros,
+        &new.trim_trailing_zeros,
+    );
+    field(
+        &mut changes,
+        "frozen_text",
+        &old.frozen_text,
+        &new.frozen_text,
+    );
+    field(
+        &mut changes,
+        "quiet_hours",
+        &old.quiet_hours,
+        &new.quiet_hours,
+    );
+    field(
+        &mut changes,
+        "quiet_update_interval_ms",
+        &old.quiet_update_interval_ms,
+        &new.quiet_update_interval_ms,
+    );
+    field(
+        &mut changes,
+        "max_emissions_per_sec",
+        &old.max_emissions_per_sec,
+        &new.max_emissions_per_sec,
+    );
+    field(
+        &mut changes,
+        "always_publish",
+        &old.always_publish,
+        &new.always_publish,
+    );
+    field(
+        &mut changes,
+        "max_tick_budget_ms",
+        &old.max_tick_budget_ms,
+        &new.max_tick_budget_ms,
+    );
+    field(&mut changes, "output", &old.output, &new.output);
+    field(
+        &mut changes,
+        "preferred_display",
+        &old.preferred_display,
+        &new.preferred_display,
+    );
+    field(
+        &mut changes,
+        "history_length",
+        &old.history_length,
+        &new.history_length,
+    );
+    field(
+        &mut changes,
+        "invalid_value_marker",
+        &old.invalid_value_marker,
+        &new.invalid_value_marker,
+    );
+    field(
+        &mut changes,
+        "memory_basis",
+        &old.memory_basis,
+        &new.memory_basis,
+    );
+    field(&mut changes, "reload", &old.reload, &new.reload);
+    field(
+        &mut changes,
+        "error_notifications",
+        &old.error_notifications,
+        &new.error_notifications,
+    );
+    field(
+        &mut changes,
+        "startup_grace_secs",
+        &old.startup_grace_secs,
+        &new.startup_grace_secs,
+    );
+
+    sections(&mut changes, &old.sections, &new.sections);
+
+    changes
+}
+
+fn sections(changes: &mut Vec<String>, old: &[Section], new: &[Section]) {
+    // Index of the old section each new one is matched with.
+    let mut matches: Vec<Option<usize>> = vec![None; new.len()];
+    let mut taken = vec![false; old.len()];
+
+    // Same module at the same position, then the same module anywhere, then whatever
+    // is left at the same position.
+    for (index, section) in new.iter().enumerate() {
+        if old.get(index).is_some_and(|x| x.module == section.module) {
+            matches[index] = Some(index);
+            taken[index] = true;
+        }
+    }
+    for (index, section) in new.iter().enumerate() {
+        if matches[index].is_none() {
+            matches[index] = (0..old.len()).find(|&x| !taken[x] && old[x].module == section.module);
+            if let Some(x) = matches[index] {
+                taken[x] = true;
+            }
+        }
+    }
+    for index in 0..new.len().min(old.len()) {
+        if matches[index].is_none() && !taken[index] {
+            matches[index] = Some(index);
+            taken[index] = true;
+        }
+    }
+
+    for (index, section) in old.iter().enumerate() {
+        if !taken[index] {
+            changes.push(format!(
+                "section {} removed ({})",
+                index + 1,
+                section.module.name()
+            ));
+        }
+    }
+
+    let moved = moved(&matches);
+    for (index, section) in new.iter().enumerate() {
+        let Some(old_index) = matches[index] else {
+            changes.push(format!(
+                "section {} added ({})",
+                index + 1,
+                section.module.name()
+            ));
+            continue;
+        };
+        let old_section = &old[old_index];
+
+        if moved[index] {
+            changes.push(format!(
+                "section {} moved to {} ({})",
+                old_index + 1,
+                index + 1,
+                section.module.name()
+            ));
+        }
+
+        let prefix = format!("section {}", index + 1);
+        if old_section.module != section.module {
+            if old_section.module.name() == section.module.name() {
+                field(
+                    changes,
+                    &format!("{prefix} module"),
+                    &old_section.module,
+                    &section.module,
+                );
+            } else {
+                changes.push(format!(
+                    "{prefix} module changed {}→{}",
+                    old_section.module.name(),
+                    section.module.name()
+                ));
+            }
+        }
+
+        let (old, new) = (&old_section.decoration, &section.decoration);
+        field(
+            changes,
+            &format!("{prefix} before"),
+            &old.before,
+            &new.before,
+        );
+        field(changes, &format!("{prefix} after"), &old.after, &new.after);
+        field(changes, &format!("{prefix} fg"), &old.fg, &new.fg);
+        field(changes, &format!("{prefix} bg"), &old.bg, &new.bg);
+
+        let (old, new) = (old_section, section);
+        field(changes, &format!("{prefix} id"), &old.id, &new.id);
+        field(
+            changes,
+            &format!("{prefix} decimals"),
+            &old.decimals,
+            &new.decimals,
+        );
+        field(
+            changes,
+            &format!("{prefix} usage_display"),
+            &old.usage_display,
+            &new.usage_display,
+        );
+        field(
+            changes,
+            &format!("{prefix} usage_format"),
+            &old.usage_format,
+            &new.usage_format,
+        );
+        field(
+            changes,
+            &format!("{prefix} compact_units"),
+            &old.compact_units,
+            &new.compact_units,
+        );
+        field(
+            changes,
+            &format!("{prefix} track_extremes"),
+            &old.track_extremes,
+            &new.track_extremes,
+        );
+        field(
+            changes,
+            &format!("{prefix} priority"),
+            &old.priority,
+            &new.priority,
+        );
+        field(
+            changes,
+            &format!("{prefix} valid_range"),
+            &old.valid_range,
+            &new.valid_range,
+        );
+        field(
+            changes,
+            &format!("{prefix} hide_when"),
+            &old.hide_when,
+            &new.hide_when,
+        );
+        field(
+            changes,
+            &format!("{prefix} min_width"),
+            &old.min_width,
+            &new.min_width,
+        );
+        field(
+            changes,
+            &format!("{prefix} max_width"),
+            &old.max_width,
+            &new.max_width,
+        );
+        field(
+            changes,
+            &format!("{prefix} skip_when_standby"),
+            &old.skip_when_standby,
+            &new.skip_when_standby,
+        );
+        field(changes, &format!("{prefix} watch"), &old.watch, &new.watch);
+        field(changes, &format!("{prefix} align"), &old.align, &new.align);
+        field(
+            changes,
+            &format!("{prefix} compact_template"),
+            &old.compact_template,
+            &new.compact_template,
+        );
+        field(
+            changes,
+            &format!("{prefix} shrink_priority"),
+            &old.shrink_priority,
+            &new.shrink_priority,
+        );
+        field(
+            changes,
+            &format!("{prefix} thresholds"),
+            &old.thresholds,
+            &new.thresholds,
+        );
+        field(
+            changes,
+            &format!("{prefix} interval_ms"),
+            &old.interval_ms,
+            &new.interval_ms,
+        );
+    }
+}
+
+/// Marks the matched sections whose order relative to the others changed, keeping the largest
+/// set of sections that are still in their old order in place.
+fn moved(matches: &[Option<usize>]) -> Vec<bool> {
+    let n = matches.len();
+    // Longest increasing run of old indices ending at each position, and its predecessor.
+    let mut length = vec![0usize; n];
+    let mut previous: Vec<Option<usize>> = vec![None; n];
+
+    for i in 0..n {
+        let Some(old_i) = matches[i] else { continue };
+        length[i] = 1;
+        for j in 0..i {
+            if matches[j].is_some_and(|old_j| old_j < old_i) && length[j] + 1 > length[i] {
+                length[i] = length[j] + 1;
+                previous[i] = Some(j);
+            }
+        }
+    }
+
+    let mut moved: Vec<bool> = matches.iter().map(Option::is_some).collect();
+    let mut current = (0..n).filter(|&x| length[x] > 0).max_by_key(|&x| length[x]);
+    while let Some(i) = current {
+        moved[i] = false;
+        current = previous[i];
+    }
+
+    moved
+}
+
+fn field<T: Serialize>(changes: &mut Vec<String>, name: &str, old: &T, new: &T) {
+    let (old, new) = (
+        ron::to_string(old).unwrap_or_default(),
+        ron::to_string(new).unwrap_or_default(),
+    );
+
+    if old != new {
+        changes.push(format!("{name} {old}→{new}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::Module;
+
+    fn config(sections: Vec<Section>) -> Config {
+        Config {
+            sections,
+            ..Config::default()
+        }
+    }
+
+    fn disk(name: &str) -> Section {
+        Module::DiskUsage {
+            name: name.into(),
+            inodes: false,
+        }
+        .into()
+    }
+
+    #[test]
+    fn unchanged() {
+        assert!(diff(&Config::default(), &Config::default()).is_empty());
+    }
+
+    #[test]
+    fn scalars() {
+        let old = Config::default();
+        let new = Config {
+            update_interval_ms: 500.into(),
+            separator: " · ".into(),
+            quiet_hours: Some("22:00-07:00".to_string().try_into().unwrap()),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            vec![
+                "separator \" | \"→\" · \"",
+                "update_interval_ms 1000→500",
+                "quiet_hours None→Some(\"22:00-07:00\")",
+            ],
+            diff(&old, &new)
+        );
+    }
+
+    #[test]
+    fn sections_added_and_removed() {
+        let old = config(vec![
+            Module::CpuUsage.into(),
+            Module::MemoryUsage { basis: None }.into(),
+        ]);
+        let new = config(vec![
+            Module::ProcessCount.into(),
+            Module::CpuUsage.into(),
+            Module::MemoryUsage { basis: None }.into(),
+        ]);
+
+        assert_eq!(vec!["section 1 added (ProcessCount)"], diff(&old, &new));
+        assert_eq!(vec!["section 1 removed (ProcessCount)"], diff(&new, &old));
+    }
+
+    #[test]
+    fn sections_reordered() {
+        let old = config(vec![
+            Module::CpuUsage.into(),
+            Module::MemoryUsage { basis: None }.into(),
+            Module::SwapUsage.into(),
+        ]);
+        let new = config(vec![
+            Module::SwapUsage.into(),
+            Module::CpuUsage.into(),
+            Module::MemoryUsage { basis: None }.into(),
+        ]);
+
+        assert_eq!(vec!["section 3 moved to 1 (SwapUsage)"], diff(&old, &new));
+    }
+
+    #[test]
+    fn sections_modified() {
+        let old = config(vec![
+            Module::CpuUsage.into(),
+            disk("/dev/sda"),
+            Module::DiskUsageTotal {
+                include_removables: false,
+            }
+            .into(),
+            Module::MemoryUsage { basis: None }.into(),
+        ]);
+        let new = config(vec![
+            Section {
+                decimals: Some(2),
+                ..Module::CpuUsage.into()
+            },
+            disk("/dev/sdb"),
+            Module::DiskUsageTotal {
+                include_removables: true,
+            }
+            .into(),
+            Module::SwapUsage.into(),
+        ]);
+
+        assert_eq!(
+            vec![
+                "section 1 decimals None→Some(2)",
+                "section 2 module DiskUsage(name:\"/dev/sda\",inodes:false)→DiskUsage(name:\"/dev/sdb\",inodes:false)",
+                "section 3 module DiskUsageTotal(include_removables:false)→DiskUsageTotal(include_removables:true)",
+                "section 4 module changed MemoryUsage→SwapUsage",
+            ],
+            diff(&old, &new)
+        );
+
+        // a section that moved away isn't mistaken for a modified one
+        let new = config(vec![
+            Module::CpuUsage.into(),
+            disk("/dev/sdb"),
+            disk("/dev/sda"),
+        ]);
+        assert_eq!(
+            vec![
+                "section 3 removed (DiskUsageTotal)",
+                "section 4 removed (MemoryUsage)",
+                "section 2 added (DiskUsage)",
+            ],
+            diff(&old, &new)
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! The mounted disks as every disk module sees them during one update.
+
+use std::{fs, path::Path};
+
+use crate::provider::DiskInfo;
+
+/// Where Linux exposes block devices, partitions included.
+pub const SYS_CLASS_BLOCK_PATH: &str = "/sys/class/block";
+
+/// Filesystems without data of their own that sysinfo still lists.
+const PSEUDO_FILE_SYSTEMS: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "configfs",
+    "debugfs",
+    "efivarfs",
+    "fuse.gvfsd-fuse",
+    "fuse.portal",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "ramfs",
+    "securityfs",
+    "tracefs",
+];
+
+/// Space and inode counts of all disks read at once, so every disk section in an update shows
+/// the same numbers.
+#[derive(Default)]
+pub struct DiskSnapshot {
+    /// Every mount of a real filesystem, in mount order.
+    mounts: Vec<DiskInfo>,
+}
+
+impl DiskSnapshot {
+    /// Leaves out pseudo filesystems.
+    pub fn new(disks: impl IntoIterator<Item = DiskInfo>) -> Self {
+        Self {
+            mounts: disks
+                .into_iter()
+                .filter(|x| !PSEUDO_FILE_SYSTEMS.contains(&x.file_system.as_str()))
+                .collect(),
+        }
+    }
+
+    /// Every mount, including further mounts of the same device, e.g. btrfs subvolumes.
+    pub fn mounts(&self) -> &[DiskInfo] {
+        &self.mounts
+    }
+
+    /// One mount per device, the first one listed, so totals don't count a device twice.
+    pub fn devices(&self) -> impl Iterator<Item = &DiskInfo> {
+        self.mounts
+            .iter()
+            .enumerate()
+            .filter(|(index, disk)| !self.mounts[..*index].iter().any(|x| x.name == disk.name))
+            .map(|(_, disk)| disk)
+    }
+
+    /// The disk with the device name, e.g. "/dev/sda1", or mount point `name`.
+    pub fn find(&self, name: &str) -> Option<&DiskInfo> {
+        self.mounts
+            .iter()
+            .find(|x| x.name == name || x.mount_point == name)
+    }
+}
+
+/// The drive a disk is on, as sysfs tells without sending the drive any commands.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct DriveState {
+    /// Spinning rather than solid state.
+    pub rotational: bool,
+    /// Runtime power management suspended the drive, e.g. spun down.
+    pub standby: bool,
+}
+
+impl DriveState {
+    /// The drive holding `device`, e.g. "/dev/sdb1", looked up in `class_block`, usually
+    /// [`SYS_CLASS_BLOCK_PATH`]. `None` for anything that isn't a block device, e.g. tmpfs.
+    pub fn read(class_block: &Path, device: &str) -> Option<Self> {
+        // E.g. /dev/mapper/x links to /dev/dm-0.
+        let device = fs::canonicalize(device).unwrap_or(device.into());
+        let mut dir = fs::canonicalize(class_block.join(device.file_name()?)).ok()?;
+        // Partitions are listed inside their drive.
+        if dir.join("partition").exists() {
+            dir.pop();
+        }
+
+        let read = |file: &str| fs::read_to_string(dir.join(file)).unwrap_or_default();
+        Some(Self {
+            rotational: read("queue/rotational").trim() == "1",
+            standby: read("device/power/runtime_status").trim() == "suspended",
+        })
+    }
+
+    /// Whether querying the disk would spin the drive up.
+    pub fn asleep(self) -> bool {
+        self.rotational && self.standby
+    }
+}
+
+/// Disks not to query while their drive is asleep, from sections with `skip_when_standby`.
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct Spared {
+    /// Every disk, for sections showing more than one.
+    pub all: bool,
+    /// Device names or mount points.
+    pub names: Vec<String>,
+}
+
+impl Spared {
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.names.is_empty()
+    }
+
+    pub fn contains(&self, name: &str, mount_point: &str) -> bool {
+        self.all || self.names.iter().any(|x| x == name || x == mount_point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disk(name: &str, mount_point: &str, file_system: &str, total: u64) -> DiskInfo {
+        DiskInfo {
+            name: name.into(),
+            mount_point: mount_point.into(),
+            file_system: file_system.into(),
+            total_space: total,
+            available_space: total / 2,
+            is_removable: false,
+            inodes: None,
+            spared: false,
+        }
+    }
+
+    #[test]
+    fn drive_state() {
+        let root = std::env::temp_dir().join(format!("stringbar-block-{}", std::process::id()));
+        let drive = |name: &str, rotational: &str, status: Option<&str>| {
+            let dir = root.join("devices").join(name);
+            fs::create_dir_all(dir.join("queue")).unwrap();
+            fs::write(dir.join("queue/rotational"), rotational).unwrap();
+            if let Some(status) = status {
+                fs::create_dir_all(dir.join("device/power")).unwrap();
+                fs::write(dir.join("device/power/runtime_status"), status).unwrap();
+            }
+            fs::create_dir_all(root.join("class")).unwrap();
+            std::os::unix::fs::symlink(&dir, root.join("class").join(name)).unwrap();
+        };
+        let partition = |drive: &str, name: &str| {
+            let dir = root.join("devices").join(drive).join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("partition"), "1\n").unwrap();
+            std::os::unix::fs::symlink(&dir, root.join("class").join(name)).unwrap();
+        };
+        drive("sda", "1\n", Some("suspended\n"));
+        partition("sda", "sda1");
+        drive("sdb", "1\n", Some("active\n"));
+        drive("nvme0n1", "0\n", Some("suspended\n"));
+        partition("nvme0n1", "nvme0n1p2");
+        drive("dm-0", "0\n", None);
+
+        let state = |device| DriveState::read(&root.join("class"), device);
+        let (asleep, awake) = (
+            DriveState {
+                rotational: true,
+                standby: true,
+            },
+            DriveState {
+                rotational: true,
+                standby: false,
+            },
+        );
+        assert_eq!(Some(asleep), state("/dev/sda1"));
+        assert_eq!(Some(asleep), state("/dev/sda"));
+        assert_eq!(Some(awake), state("/dev/sdb"));
+        assert!(state("/dev/nvme0n1p2").is_some_and(|x| x.standby && !x.asleep()));
+        assert_eq!(Some(DriveState::default()), state("/dev/dm-0"));
+        assert_eq!(None, state("tmpfs"));
+        assert_eq!(None, state("/dev/sdz1"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn snapshot() {
+        let snapshot = DiskSnapshot::new([
+            disk("/dev/nvme0n1p2", "/", "btrfs", 100),
+            disk("/dev/nvme0n1p2", "/home", "btrfs", 100),
+            disk("systemd-1", "/boot", "autofs", 0),
+            disk("/dev/nvme0n1p1", "/boot", "vfat", 10),
+            disk("portal", "/run/user/1000/doc", "fuse.portal", 0),
+            disk(
+                "overlay",
+                "/var/lib/docker/overlay2/x/merged",
+                "overlay",
+                100,
+            ),
+            disk("/dev/sdb1", "/mnt", "ext4", 0),
+        ]);
+
+        let mounts: Vec<_> = snapshot.mounts().iter().map(|x| &x.mount_point).collect();
+        assert_eq!(
+            vec![
+                "/",
+                "/home",
+                "/boot",
+                "/var/lib/docker/overlay2/x/merged",
+                "/mnt"
+            ],
+            mounts
+        );
+        let devices: Vec<_> = snapshot.devices().map(|x| &x.mount_point).collect();
+        assert_eq!(
+            vec!["/", "/boot", "/var/lib/docker/overlay2/x/merged", "/mnt"],
+            devices
+        );
+
+        assert_eq!("/home", snapshot.find("/home").unwrap().mount_point);
+        assert_eq!("/", snapshot.find("/dev/nvme0n1p2").unwrap().mount_point);
+        assert_eq!("vfat", snapshot.find("/boot").unwrap().file_system);
+        assert!(snapshot.find("/run/user/1000/doc").is_none());
+    }
+}
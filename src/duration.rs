@@ -0,0 +1,364 @@
+use std::{fmt, time::Duration};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// A duration in the config, either a string like "500ms", "1.5s", "2m" or "1h", or a bare
+/// number in the unit of `UNIT_MS` milliseconds the field was given in before strings were
+/// accepted. Written back as a bare number where that's exact, so older versions still read it.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct ConfigDuration<const UNIT_MS: u64>(pub Duration);
+
+/// For `*_ms` fields.
+pub type Millis = ConfigDuration<1>;
+/// For `*_s` and `*_secs` fields.
+pub type Secs = ConfigDuration<1000>;
+
+impl<const UNIT_MS: u64> ConfigDuration<UNIT_MS> {
+    pub fn is_zero(self) -> bool {
+        self.0.is_zero()
+    }
+}
+
+/// A number of the field's unit.
+impl<const UNIT_MS: u64> From<u64> for ConfigDuration<UNIT_MS> {
+    fn from(units: u64) -> Self {
+        Self(Duration::from_millis(units.saturating_mul(UNIT_MS)))
+    }
+}
+
+/// Shortest exact form, e.g. "90s", "2m" or "250ms".
+impl<const UNIT_MS: u64> fmt::Display for ConfigDuration<UNIT_MS> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.0.subsec_nanos().is_multiple_of(1_000_000) {
+            return write!(f, "{}s", self.0.as_secs_f64());
+        }
+
+        let ms = self.0.as_millis();
+        match ms {
+            0 => write!(f, "0s"),
+            _ if ms.is_multiple_of(3_600_000) => write!(f, "{}h", ms / 3_600_000),
+            _ if ms.is_multiple_of(60_000) => write!(f, "{}m", ms / 60_000),
+            _ if ms.is_multiple_of(1000) => write!(f, "{}s", ms / 1000),
+            _ => write!(f, "{ms}ms"),
+        }
+    }
+}
+
+impl<const UNIT_MS: u64> Serialize for ConfigDuration<UNIT_MS> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let unit = Duration::from_millis(UNIT_MS).as_nanos();
+        match self.0.as_nanos() % unit {
+            0 => serializer.serialize_u64((self.0.as_nanos() / unit) as u64),
+            _ => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de, const UNIT_MS: u64> Deserialize<'de> for ConfigDuration<UNIT_MS> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor<const UNIT_MS: u64>;
+
+        impl<const UNIT_MS: u64> de::Visitor<'_> for Visitor<UNIT_MS> {
+            type Value = ConfigDuration<UNIT_MS>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a duration like \"500ms\", \"1.5s\", \"2m\" or \"1h\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, units: u64) -> Result<Self::Value, E> {
+                Ok(units.into())
+            }
+
+            fn visit_i64<E: de::Error>(self, units: i64) -> Result<Self::Value, E> {
+                self.visit_f64(units as f64)
+            }
+
+            fn visit_f64<E: de::Error>(self, units: f64) -> Result<Self::Value, E> {
+                if units < 0.0 {
+                    return Err(E::custom(format!("duration {units} is negative")));
+                }
+                from_units(units, UNIT_MS)
+                    .map(ConfigDuration)
+                    .ok_or_else(|| E::custom(format!("duration {units} is out of range")))
+            }
+
+            fn visit_str<E: de::Error>(self, input: &str) -> Result<Self::Value, E> {
+                parse_config(input, UNIT_MS)
+                    .map(ConfigDuration)
+                    .map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Parses a number with an optional unit of "ms", "s", "m" or "h", e.g. "1.5s". Numbers
+/// without a unit are in units of `unit_ms` milliseconds.
+pub fn parse_config(input: &str, unit_ms: u64) -> Result<Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|x: char| !x.is_ascii_digit() && x != '.')
+        .unwrap_or(input.len());
+    let (number, unit) = (&input[..split], input[split..].trim_start());
+
+    let unit_ms = match unit {
+        "" => unit_ms,
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ if number.is_empty() && input.starts_with('-') => {
+            return Err(format!("duration {input:?} is negative"))
+        }
+        _ => {
+            return Err(format!(
+                "invalid duration {input:?}, expected a number followed by ms, s, m or h"
+            ))
+        }
+    };
+    let number: f64 = number.parse().map_err(|_| {
+        format!("invalid duration {input:?}, expected a number followed by ms, s, m or h")
+    })?;
+
+    from_units(number, unit_ms).ok_or_else(|| format!("duration {input:?} is out of range"))
+}
+
+fn from_units(units: f64, unit_ms: u64) -> Option<Duration> {
+    Duration::try_from_secs_f64(units * unit_ms as f64 / 1000.0).ok()
+}
+
+/// Formats a duration as e.g. "1h 2m 3s", omitting leading zero components.
+pub fn format_compact(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+    if hours > 0 {
+        format!("{hours}h {minutes}m {seconds}s")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+/// Formats a long duration as e.g. "3d 4h 12m", omitting leading zero components.
+/// Durations under a minute are shown in seconds.
+pub fn format_days(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, minutes) = (secs / 86_400, secs / 3600 % 24, secs / 60 % 60);
+
+    if days > 0 {
+        format!("{days}d {hours}h {minutes}m")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// Formats a duration as total hours and minutes, e.g. "76:12".
+pub fn format_hours_minutes(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 3600, secs / 60 % 60)
+}
+
+/// Parses a duration like "90", "90s", "25m" or "1h30m". Bare numbers are seconds.
+pub fn parse_compact(input: &str) -> Option<Duration> {
+    if let Ok(secs) = input.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let mut total = 0_u64;
+    let mut number = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let multiplier = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        total = total.checked_add(number.parse::<u64>().ok()?.checked_mul(multiplier)?)?;
+        number.clear();
+    }
+
+    if !number.is_empty() || input.is_empty() {
+        return None;
+    }
+
+    Some(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format() {
+        assert_eq!("0s", format_compact(Duration::ZERO));
+        assert_eq!("59s", format_compact(Duration::from_millis(59_999)));
+        assert_eq!("1m 0s", format_compact(Duration::from_secs(60)));
+        assert_eq!("1h 0m 5s", format_compact(Duration::from_secs(3605)));
+        assert_eq!("100h 0m 0s", format_compact(Duration::from_secs(360_000)));
+    }
+
+    #[test]
+    fn days() {
+        let cases = [
+            (0, "0s"),
+            (59, "59s"),
+            (60, "1m"),
+            (3599, "59m"),
+            (3600, "1h 0m"),
+            (4 * 3600 + 12 * 60 + 59, "4h 12m"),
+            (86_399, "23h 59m"),
+            (86_400, "1d 0h 0m"),
+            (86_400 + 60, "1d 0h 1m"),
+            (3 * 86_400 + 4 * 3600 + 12 * 60, "3d 4h 12m"),
+            (400 * 86_400, "400d 0h 0m"),
+        ];
+
+        for (secs, expected) in cases {
+            assert_eq!(expected, format_days(Duration::from_secs(secs)), "{secs}");
+        }
+    }
+
+    #[test]
+    fn hours_minutes() {
+        let cases = [
+            (0, "0:00"),
+            (59, "0:00"),
+            (60, "0:01"),
+            (3600 + 5 * 60, "1:05"),
+            (86_399, "23:59"),
+            (86_400, "24:00"),
+            (76 * 3600 + 12 * 60, "76:12"),
+        ];
+
+        for (secs, expected) in cases {
+            assert_eq!(
+                expected,
+                format_hours_minutes(Duration::from_secs(secs)),
+                "{secs}"
+            );
+        }
+    }
+
+    #[test]
+    fn config_durations() {
+        let ms = Duration::from_millis;
+        let cases = [
+            ("500ms", 1, Ok(ms(500))),
+            ("1s", 1, Ok(ms(1000))),
+            ("1.5s", 1, Ok(ms(1500))),
+            ("2m", 1, Ok(ms(120_000))),
+            ("1h", 1, Ok(ms(3_600_000))),
+            ("0.25h", 1000, Ok(ms(900_000))),
+            (" 10 s ", 1, Ok(ms(10_000))),
+            (".5s", 1, Ok(ms(500))),
+            ("0s", 1, Ok(Duration::ZERO)),
+            // Without a unit, the field's.
+            ("90", 1, Ok(ms(90))),
+            ("90", 1000, Ok(ms(90_000))),
+            ("1.5", 1000, Ok(ms(1500))),
+            ("-5s", 1, Err("duration \"-5s\" is negative")),
+            ("-5", 1000, Err("duration \"-5\" is negative")),
+            (
+                "",
+                1,
+                Err("invalid duration \"\", expected a number followed by ms, s, m or h"),
+            ),
+            (
+                "s",
+                1,
+                Err("invalid duration \"s\", expected a number followed by ms, s, m or h"),
+            ),
+            (
+                "5 seconds",
+                1,
+                Err("invalid duration \"5 seconds\", expected a number followed by ms, s, m or h"),
+            ),
+            (
+                "1h30m",
+                1,
+                Err("invalid duration \"1h30m\", expected a number followed by ms, s, m or h"),
+            ),
+            (
+                "1.2.3s",
+                1,
+                Err("invalid duration \"1.2.3s\", expected a number followed by ms, s, m or h"),
+            ),
+            (
+                "1e3ms",
+                1,
+                Err("invalid duration \"1e3ms\", expected a number followed by ms, s, m or h"),
+            ),
+            (
+                "99999999999999999999h",
+                1,
+                Err("duration \"99999999999999999999h\" is out of range"),
+            ),
+        ];
+
+        for (input, unit_ms, expected) in cases {
+            assert_eq!(
+                expected.map_err(String::from),
+                parse_config(input, unit_ms),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn config_duration_serde() {
+        let millis = |x: &str| ron::from_str::<Millis>(x).map(|x| x.0);
+        let secs = |x: &str| ron::from_str::<Secs>(x).map(|x| x.0);
+        assert_eq!(Ok(Duration::from_millis(90)), millis("90"));
+        assert_eq!(Ok(Duration::from_secs(90)), secs("90"));
+        assert_eq!(Ok(Duration::from_millis(1500)), secs("1.5"));
+        assert_eq!(Ok(Duration::from_millis(1500)), millis("\"1.5s\""));
+        assert!(millis("-1").is_err());
+        assert!(millis("\"fast\"").is_err());
+
+        // Bare numbers where exact.
+        assert_eq!("1500", ron::to_string(&Millis::from(1500)).unwrap());
+        assert_eq!("3600", ron::to_string(&Secs::from(3600)).unwrap());
+        assert_eq!(
+            "\"1500ms\"",
+            ron::to_string(&ConfigDuration::<1000>(Duration::from_millis(1500))).unwrap()
+        );
+
+        let display = [
+            (Duration::ZERO, "0s"),
+            (Duration::from_millis(250), "250ms"),
+            (Duration::from_millis(1500), "1500ms"),
+            (Duration::from_secs(90), "90s"),
+            (Duration::from_secs(120), "2m"),
+            (Duration::from_secs(7200), "2h"),
+            (Duration::from_micros(1500), "0.0015s"),
+        ];
+        for (duration, expected) in display {
+            assert_eq!(expected, ConfigDuration::<1>(duration).to_string());
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Some(Duration::from_secs(90)), parse_compact("90"));
+        assert_eq!(Some(Duration::from_secs(90)), parse_compact("90s"));
+        assert_eq!(Some(Duration::from_secs(1500)), parse_compact("25m"));
+        assert_eq!(Some(Duration::from_secs(5400)), parse_compact("1h30m"));
+        assert_eq!(None, parse_compact(""));
+        assert_eq!(None, parse_compact("m"));
+        assert_eq!(None, parse_compact("10x"));
+        assert_eq!(None, parse_compact("1h30"));
+        assert_eq!(None, parse_compact("-5s"));
+    }
+}
@@ -0,0 +1,150 @@
+/// Stands in for `^` in module output, which status2d would read as the start of a command.
+const CARET_REPLACEMENT: char = 'ˆ';
+/// statuscmd only understands markers up to this index.
+const MAX_STATUSCMD_INDEX: usize = 0x0f;
+
+/// Removes anything from module output that the enabled patches would interpret as markup.
+pub fn scrub(text: &str, status2d: bool, statuscmd: bool) -> String {
+    text.chars()
+        .filter(|x| !(statuscmd && (*x as u32) < 0x20))
+        .map(|x| match x {
+            '^' if status2d => CARET_REPLACEMENT,
+            x => x,
+        })
+        .collect()
+}
+
+/// Wraps a rendered section in status2d colors and a statuscmd marker for its 1-based `index`.
+/// Sections past the 15th don't get a marker.
+pub fn wrap(
+    text: &str,
+    index: usize,
+    fg: Option<&str>,
+    bg: Option<&str>,
+    status2d: bool,
+    statuscmd: bool,
+) -> String {
+    let mut output = String::new();
+
+    if statuscmd && (1..=MAX_STATUSCMD_INDEX).contains(&index) {
+        output.push(index as u8 as char);
+    }
+
+    let colored = status2d && (fg.is_some() || bg.is_some());
+    if status2d {
+        if let Some(fg) = fg {
+            output.push_str(&format!("^c{fg}^"));
+        }
+        if let Some(bg) = bg {
+            output.push_str(&format!("^b{bg}^"));
+        }
+    }
+
+    output.push_str(text);
+
+    if colored {
+        output.push_str("^d^");
+    }
+
+    output
+}
+
+/// Splits a line into text and the markup [`wrap`] adds for the enabled patches, in order, as
+/// `(is_markup, part)` pairs.
+pub fn segments(line: &str, status2d: bool, statuscmd: bool) -> Vec<(bool, &str)> {
+    let mut segments = Vec::new();
+    let (mut position, mut text_start) = (0, 0);
+
+    while let Some(c) = line[position..].chars().next() {
+        let markup_end = match c {
+            _ if statuscmd && (c as u32) < 0x20 => Some(position + 1),
+            '^' if status2d => line[position + 1..].find('^').map(|x| position + x + 2),
+            _ => None,
+        };
+        match markup_end {
+            Some(end) => {
+                if text_start < position {
+                    segments.push((false, &line[text_start..position]));
+                }
+                segments.push((true, &line[position..end]));
+                (position, text_start) = (end, end);
+            }
+            None => position += c.len_utf8(),
+        }
+    }
+    if text_start < line.len() {
+        segments.push((false, &line[text_start..]));
+    }
+
+    segments
+}
+
+/// Removes the markup [`wrap`] adds for the enabled patches.
+pub fn strip(line: &str, status2d: bool, statuscmd: bool) -> String {
+    segments(line, status2d, statuscmd)
+        .into_iter()
+        .filter(|(markup, _)| !markup)
+        .map(|(_, text)| text)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrub_markup() {
+        let text = "a^c#ff0000^\x03b\tc";
+
+        assert_eq!(text, scrub(text, false, false));
+        assert_eq!("aˆc#ff0000ˆ\x03b\tc", scrub(text, true, false));
+        assert_eq!("a^c#ff0000^bc", scrub(text, false, true));
+        assert_eq!("aˆc#ff0000ˆbc", scrub(text, true, true));
+    }
+
+    #[test]
+    fn markup_segments() {
+        let line = "\x01^c#ff0000^cpu 5%^d^ | \x02mem^";
+
+        assert_eq!(vec![(false, line)], segments(line, false, false));
+        assert_eq!(
+            vec![
+                (true, "\x01"),
+                (true, "^c#ff0000^"),
+                (false, "cpu 5%"),
+                (true, "^d^"),
+                (false, " | "),
+                (true, "\x02"),
+                (false, "mem^"),
+            ],
+            segments(line, true, true)
+        );
+        assert_eq!("cpu 5% | mem^", strip(line, true, true));
+        assert_eq!("\x01cpu 5% | \x02mem^", strip(line, true, false));
+        assert_eq!("^c#ff0000^cpu 5%^d^ | mem^", strip(line, false, true));
+    }
+
+    #[test]
+    fn wrap_sections() {
+        let cases = [
+            (1, None, None, false, false, "text"),
+            (1, Some("#ff0000"), None, false, true, "\x01text"),
+            (2, Some("#ff0000"), None, true, false, "^c#ff0000^text^d^"),
+            (
+                3,
+                Some("#ff0000"),
+                Some("#000000"),
+                true,
+                true,
+                "\x03^c#ff0000^^b#000000^text^d^",
+            ),
+            (4, None, None, true, true, "\x04text"),
+            (15, None, None, false, true, "\x0ftext"),
+            (16, None, None, false, true, "text"),
+        ];
+
+        for (index, fg, bg, status2d, statuscmd, expected) in cases {
+            assert_eq!(expected, wrap("text", index, fg, bg, status2d, statuscmd));
+        }
+    }
+}
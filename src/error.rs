@@ -0,0 +1,68 @@
+//! Errors that stop stringbar during startup, each with its own exit code so supervisors can
+//! tell them apart. Errors after startup are logged and handled where they happen.
+
+use std::fmt;
+
+#[derive(PartialEq, Debug)]
+pub enum StringbarError {
+    /// The config file couldn't be read or parsed.
+    ConfigLoad(String),
+    /// The config file parsed but has invalid values.
+    ConfigValidation(String),
+    /// The output backend can't be used, e.g. xsetroot isn't installed.
+    OutputBackendInit(String),
+    /// Another instance is running.
+    InstanceLock(String),
+    /// There's no X display to show the bar on.
+    DisplayUnavailable(String),
+    Io(String),
+}
+
+impl StringbarError {
+    /// 2 for config errors, 3 without a display, 4 while another instance runs, 5 if the output
+    /// backend can't be used and 1 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::ConfigLoad(_) | Self::ConfigValidation(_) => 2,
+            Self::DisplayUnavailable(_) => 3,
+            Self::InstanceLock(_) => 4,
+            Self::OutputBackendInit(_) => 5,
+            Self::Io(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for StringbarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ConfigLoad(x)
+            | Self::ConfigValidation(x)
+            | Self::OutputBackendInit(x)
+            | Self::InstanceLock(x)
+            | Self::DisplayUnavailable(x)
+            | Self::Io(x) => f.write_str(x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let errors = [
+            StringbarError::ConfigLoad(String::new()),
+            StringbarError::DisplayUnavailable(String::new()),
+            StringbarError::InstanceLock(String::new()),
+            StringbarError::OutputBackendInit(String::new()),
+            StringbarError::Io(String::new()),
+        ];
+        let codes: Vec<_> = errors.iter().map(|x| x.exit_code()).collect();
+        assert_eq!(vec![2, 3, 4, 5, 1], codes);
+        assert_eq!(
+            2,
+            StringbarError::ConfigValidation(String::new()).exit_code()
+        );
+    }
+}
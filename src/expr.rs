@@ -0,0 +1,429 @@
+//! Arithmetic over other sections' values, for [`Module::Derived`](crate::config::Module::Derived).
+//!
+//! Expressions support `+`, `-`, `*`, `/`, parentheses, numeric literals like `2.0e12` and
+//! section values written as `<id>.value`, e.g. `(mem.value + swap.value) / 2`.
+
+use std::fmt;
+
+/// How deeply parentheses, negations and operators may nest, so that parsing, evaluating and
+/// dropping an expression can't overflow the stack.
+const MAX_DEPTH: usize = 100;
+
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// Value of the section with this id.
+    Value(String),
+    Negate(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Op {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Dot,
+    Op(Op),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Number(x) => write!(f, "{x}"),
+            Self::Ident(x) => write!(f, "{x}"),
+            Self::Dot => write!(f, "."),
+            Self::Op(Op::Add) => write!(f, "+"),
+            Self::Op(Op::Subtract) => write!(f, "-"),
+            Self::Op(Op::Multiply) => write!(f, "*"),
+            Self::Op(Op::Divide) => write!(f, "/"),
+            Self::Open => write!(f, "("),
+            Self::Close => write!(f, ")"),
+        }
+    }
+}
+
+/// Whether `id` can be referenced from an expression.
+pub fn is_identifier(id: &str) -> bool {
+    id.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '+' => Token::Op(Op::Add),
+            '-' => Token::Op(Op::Subtract),
+            '*' => Token::Op(Op::Multiply),
+            '/' => Token::Op(Op::Divide),
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '.' => Token::Dot,
+            '0'..='9' => {
+                let mut end = start + 1;
+                let mut previous = c;
+                while let Some(&(index, c)) = chars.peek() {
+                    let exponent_sign = matches!(c, '+' | '-') && matches!(previous, 'e' | 'E');
+                    if !(c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E') || exponent_sign) {
+                        break;
+                    }
+                    chars.next();
+                    end = index + 1;
+                    previous = c;
+                }
+                let literal = &input[start..end];
+                Token::Number(
+                    literal
+                        .parse()
+                        .map_err(|_| format!("invalid number {literal}"))?,
+                )
+            }
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = start + 1;
+                while let Some(&(index, c)) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    chars.next();
+                    end = index + 1;
+                }
+                Token::Ident(input[start..end].into())
+            }
+            _ => return Err(format!("unexpected character {c:?}")),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// How deep in the expression tree the parser is, see [`MAX_DEPTH`].
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    /// Consumes the next token if it's one of `ops`.
+    fn op(&mut self, ops: [Op; 2]) -> Option<Op> {
+        match self.tokens.get(self.position) {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                self.position += 1;
+                Some(*op)
+            }
+            _ => None,
+        }
+    }
+
+    /// Goes one level deeper into the expression tree.
+    fn descend(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!("nested more than {MAX_DEPTH} levels deep"));
+        }
+        Ok(())
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let depth = self.depth;
+        let mut expr = self.product()?;
+        // Each operator puts what came before one level further down.
+        while let Some(op) = self.op([Op::Add, Op::Subtract]) {
+            self.descend()?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.product()?));
+        }
+        self.depth = depth;
+
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let depth = self.depth;
+        let mut expr = self.unary()?;
+        while let Some(op) = self.op([Op::Multiply, Op::Divide]) {
+            self.descend()?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+        self.depth = depth;
+
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.descend()?;
+        let expr = self.operand();
+        self.depth -= 1;
+        expr
+    }
+
+    fn operand(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Op(Op::Subtract)) => Ok(Expr::Negate(Box::new(self.unary()?))),
+            Some(Token::Number(x)) => Ok(Expr::Number(*x)),
+            Some(Token::Ident(id)) => {
+                let id = id.clone();
+                if self.next() == Some(&Token::Dot)
+                    && self.next() == Some(&Token::Ident("value".into()))
+                {
+                    Ok(Expr::Value(id))
+                } else {
+                    Err(format!("expected {id}.value"))
+                }
+            }
+            Some(Token::Open) => {
+                let expr = self.sum()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    Some(token) => Err(format!("expected ) but found {token}")),
+                    None => Err("missing )".into()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {token}")),
+            None => Err("unexpected end of expression".into()),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+            depth: 0,
+        };
+        let expr = parser.sum()?;
+        match parser.next() {
+            Some(token) => Err(format!("unexpected {token}")),
+            None => Ok(expr),
+        }
+    }
+
+    /// Ids of the sections whose values this expression reads.
+    pub fn references(&self) -> Vec<&str> {
+        match self {
+            Self::Number(_) => Vec::new(),
+            Self::Value(id) => vec![id],
+            Self::Negate(x) => x.references(),
+            Self::Binary(_, a, b) => {
+                let mut references = a.references();
+                references.extend(b.references());
+                references
+            }
+        }
+    }
+
+    /// Evaluates the expression with section values from `value`. `None` if one of them is
+    /// missing or the result isn't a finite number, e.g. after dividing by zero.
+    pub fn eval(&self, value: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+        self.eval_inner(value).filter(|x| x.is_finite())
+    }
+
+    fn eval_inner(&self, value: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+        Some(match self {
+            Self::Number(x) => *x,
+            Self::Value(id) => value(id)?,
+            Self::Negate(x) => -x.eval_inner(value)?,
+            Self::Binary(op, a, b) => {
+                let (a, b) = (a.eval_inner(value)?, b.eval_inner(value)?);
+                match op {
+                    Op::Add => a + b,
+                    Op::Subtract => a - b,
+                    Op::Multiply => a * b,
+                    Op::Divide => a / b,
+                }
+            }
+        })
+    }
+}
+
+/// Orders nodes so each comes after the ones it depends on, keeping the original order where
+/// possible. `dependencies[i]` lists the nodes node `i` depends on.
+/// Fails with a node on a cycle if there is one.
+pub fn evaluation_order(dependencies: &[Vec<usize>]) -> Result<Vec<usize>, usize> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        New,
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        node: usize,
+        dependencies: &[Vec<usize>],
+        states: &mut [State],
+        order: &mut Vec<usize>,
+    ) -> Result<(), usize> {
+        match states[node] {
+            State::Done => return Ok(()),
+            State::Visiting => return Err(node),
+            State::New => {}
+        }
+
+        states[node] = State::Visiting;
+        for &dependency in &dependencies[node] {
+            visit(dependency, dependencies, states, order)?;
+        }
+        states[node] = State::Done;
+        order.push(node);
+
+        Ok(())
+    }
+
+    let mut states = vec![State::New; dependencies.len()];
+    let mut order = Vec::with_capacity(dependencies.len());
+    for node in 0..dependencies.len() {
+        visit(node, dependencies, &mut states, &mut order)?;
+    }
+
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(input: &str) -> Option<f64> {
+        let values = |id: &str| match id {
+            "mem" => Some(40.0),
+            "swap" => Some(10.0),
+            "disk_data" => Some(1.0e12),
+            _ => None,
+        };
+        Expr::parse(input).unwrap().eval(&values)
+    }
+
+    #[test]
+    fn arithmetic() {
+        let cases = [
+            ("1", 1.0),
+            ("1 + 2 * 3", 7.0),
+            ("(1 + 2) * 3", 9.0),
+            ("10 - 4 - 3", 3.0),
+            ("8 / 4 / 2", 1.0),
+            ("-2 * -3", 6.0),
+            ("--1", 1.0),
+            ("-(1 + 1)", -2.0),
+            ("2.5e1", 25.0),
+            ("1e-2 * 100", 1.0),
+            ("1E+3", 1000.0),
+            ("  ( ( 7 ) )  ", 7.0),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(Some(expected), eval(input), "{input}");
+        }
+    }
+
+    #[test]
+    fn section_values() {
+        assert_eq!(Some(50.0), eval("mem.value + swap.value"));
+        assert_eq!(Some(25.0), eval("mem.value/swap.value*2 + 17"));
+        assert_eq!(Some(50.0), eval("disk_data.value / 2.0e12 * 100"));
+        assert_eq!(None, eval("mem.value + unknown.value"));
+        assert_eq!(None, eval("mem.value / 0"));
+        assert_eq!(None, eval("0 / 0"));
+
+        assert_eq!(
+            vec!["mem", "swap", "mem"],
+            Expr::parse("mem.value * (swap.value - -mem.value)")
+                .unwrap()
+                .references()
+        );
+        assert!(Expr::parse("4 * 2").unwrap().references().is_empty());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let cases = [
+            ("", "unexpected end of expression"),
+            ("1 +", "unexpected end of expression"),
+            ("(1 + 2", "missing )"),
+            ("(1 2)", "expected ) but found 2"),
+            ("1 2", "unexpected 2"),
+            (")", "unexpected )"),
+            ("* 2", "unexpected *"),
+            ("mem", "expected mem.value"),
+            ("mem.max", "expected mem.value"),
+            ("mem value", "expected mem.value"),
+            ("1.2.3", "invalid number 1.2.3"),
+            ("1e", "invalid number 1e"),
+            ("2 ^ 3", "unexpected character '^'"),
+            ("mem.value % 2", "unexpected character '%'"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(Err(expected.to_string()), Expr::parse(input), "{input}");
+        }
+    }
+
+    #[test]
+    fn nesting() {
+        let too_deep = Err(format!("nested more than {MAX_DEPTH} levels deep"));
+        let parens = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(too_deep, Expr::parse(&parens(100_000)));
+        assert_eq!(too_deep, Expr::parse(&format!("{}1", "-".repeat(100_000))));
+        assert_eq!(
+            too_deep,
+            Expr::parse(&format!("{}1", "1 + ".repeat(100_000)))
+        );
+        assert_eq!(
+            too_deep,
+            Expr::parse(&format!("{}1", "2 * ".repeat(100_000)))
+        );
+
+        assert_eq!(Some(1.0), eval(&parens(MAX_DEPTH / 2)));
+        assert_eq!(Some(40.0), eval(&format!("{}1", "1 + ".repeat(39))));
+    }
+
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("mem"));
+        assert!(is_identifier("disk_data2"));
+        assert!(is_identifier("_x"));
+        assert!(!is_identifier(""));
+        assert!(!is_identifier("2disk"));
+        assert!(!is_identifier("disk-data"));
+        assert!(!is_identifier("mem.value"));
+    }
+
+    #[test]
+    fn order() {
+        assert_eq!(Ok(vec![]), evaluation_order(&[]));
+        assert_eq!(
+            Ok(vec![0, 1, 2]),
+            evaluation_order(&[vec![], vec![], vec![]])
+        );
+        // 0 reads 2, which reads 1.
+        assert_eq!(
+            Ok(vec![1, 2, 0, 3]),
+            evaluation_order(&[vec![2], vec![], vec![1], vec![0, 1]])
+        );
+        // Shared dependencies are only listed once.
+        assert_eq!(
+            Ok(vec![2, 0, 1]),
+            evaluation_order(&[vec![2], vec![2, 0], vec![]])
+        );
+
+        assert_eq!(Err(0), evaluation_order(&[vec![0]]));
+        assert_eq!(Err(0), evaluation_order(&[vec![1], vec![2], vec![0]]));
+        assert_eq!(Err(1), evaluation_order(&[vec![], vec![2], vec![1]]));
+    }
+}
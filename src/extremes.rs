@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use crate::config::Module;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Range {
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Running minimum and maximum of section values since start or the last reset.
+///
+/// Keyed by module rather than section index, so tracking survives sections being
+/// added or reordered on reload. Identical modules share their extremes.
+#[derive(Default)]
+pub struct Extremes(HashMap<Module, Range>);
+
+impl Extremes {
+    /// Records a value and returns the updated range. NaN is ignored.
+    pub fn record(&mut self, module: &Module, value: f64) -> Option<Range> {
+        if value.is_nan() {
+            return self.get(module);
+        }
+
+        let range = self.0.entry(module.clone()).or_insert(Range {
+            min: value,
+            max: value,
+        });
+        range.min = range.min.min(value);
+        range.max = range.max.max(value);
+
+        Some(*range)
+    }
+
+    pub fn get(&self, module: &Module) -> Option<Range> {
+        self.0.get(module).copied()
+    }
+
+    pub fn reset(&mut self) {
+        self.0.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_reset() {
+        let mut extremes = Extremes::default();
+        let cpu = Module::CpuUsage;
+
+        assert_eq!(None, extremes.get(&cpu));
+        assert_eq!(None, extremes.record(&cpu, f64::NAN));
+
+        for value in [20.0, 5.0, f64::NAN, 80.0, 40.0] {
+            extremes.record(&cpu, value);
+        }
+        assert_eq!(
+            Some(Range {
+                min: 5.0,
+                max: 80.0
+            }),
+            extremes.get(&cpu)
+        );
+        assert_eq!(None, extremes.get(&Module::MemoryUsage { basis: None }));
+
+        extremes.reset();
+        assert_eq!(None, extremes.get(&cpu));
+        assert_eq!(
+            Some(Range {
+                min: 10.0,
+                max: 10.0
+            }),
+            extremes.record(&cpu, 10.0)
+        );
+    }
+}
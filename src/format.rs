@@ -0,0 +1,386 @@
+use bittenhumans::{
+    consts::{Magnitude, System, UnitStyle},
+    ByteSizeFormatter,
+};
+
+/// How byte values are scaled and printed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct UnitPolicy {
+    pub system: System,
+    /// Always use this magnitude instead of fitting one to the total.
+    pub fixed_magnitude: Option<Magnitude>,
+    /// Bounds for fitted magnitudes, values too large or small for them get a large or
+    /// small numeric part instead.
+    pub min_magnitude: Option<Magnitude>,
+    pub max_magnitude: Option<Magnitude>,
+    /// Decimals of the numeric parts.
+    pub precision: usize,
+    pub style: UnitStyle,
+}
+
+impl UnitPolicy {
+    /// Fitted units with two decimals, decimal (KB, MB, ...) or binary (KiB, MiB, ...).
+    pub fn new(decimal_data_units: bool) -> Self {
+        Self {
+            system: if decimal_data_units {
+                System::Decimal
+            } else {
+                System::Binary
+            },
+            fixed_magnitude: None,
+            min_magnitude: None,
+            max_magnitude: None,
+            precision: 2,
+            style: UnitStyle::Full,
+        }
+    }
+
+    fn formatter(&self, fit_to: u64) -> ByteSizeFormatter {
+        match self.fixed_magnitude {
+            Some(magnitude) => ByteSizeFormatter::new(self.system, magnitude),
+            None => match (self.min_magnitude, self.max_magnitude) {
+                (None, None) => ByteSizeFormatter::fit(fit_to, self.system),
+                (Some(min), None) => ByteSizeFormatter::fit_floored(fit_to, self.system, min),
+                (None, Some(max)) => ByteSizeFormatter::fit_clamped(fit_to, self.system, max),
+                (Some(min), Some(max)) => {
+                    ByteSizeFormatter::fit_between(fit_to, self.system, min, max)
+                }
+            },
+        }
+        .with_style(self.style)
+    }
+}
+
+/// Formats used out of total in a shared unit, e.g. "9.80/15.90 GiB" or "9.8/15.9G".
+/// Unless fixed, the unit is the one fitting `total`.
+pub fn format_byte_usage(used: u64, total: u64, policy: &UnitPolicy) -> String {
+    policy
+        .formatter(total)
+        .format_pair(used, total, policy.precision)
+}
+
+/// Formats a byte count in the unit fitting it, e.g. "3.10 GiB" or "3.1G".
+pub fn format_bytes(bytes: u64, policy: &UnitPolicy) -> String {
+    policy.formatter(bytes).format_with(bytes, policy.precision)
+}
+
+/// Formats a transfer rate in the unit fitting it, e.g. "1.2 MiB/s" or "1.2M/s".
+/// Rates below one unit of the smallest magnitude still use it, e.g. "0.3 KiB/s".
+pub fn format_byte_rate(bytes_per_second: f64, policy: &UnitPolicy) -> String {
+    let bytes = bytes_per_second.max(0.0).round() as u64;
+
+    format!("{}/s", format_bytes(bytes, policy))
+}
+
+/// Formats a percentage clamped to [0, 100] with the given number of decimals.
+///
+/// With `trim_trailing_zeros`, zeros after the decimal point are dropped ("100%" instead of "100.0%").
+pub fn format_percent(value: f64, decimals: usize, trim_trailing_zeros: bool) -> String {
+    let value = if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, 100.0)
+    };
+
+    let mut formatted = format_number(value, decimals, trim_trailing_zeros);
+    formatted.push('%');
+
+    formatted
+}
+
+/// Formats a clock frequency given in MHz as whole MHz below 1 GHz, e.g. "800 MHz", and in
+/// GHz with the given number of decimals from there, e.g. "3.80 GHz".
+pub fn format_frequency(mhz: f64, decimals: usize, trim_trailing_zeros: bool) -> String {
+    let mhz = mhz.max(0.0);
+    if mhz.round() < 1000.0 {
+        return format!("{mhz:.0} MHz");
+    }
+
+    // Rounded in MHz, 3795 MHz is 3.795 GHz exactly but not as a float.
+    let ghz = (mhz / 10f64.powi(3 - decimals as i32)).round() / 10f64.powi(decimals as i32);
+    format!("{} GHz", format_number(ghz, decimals, trim_trailing_zeros))
+}
+
+/// Formats a number with the given number of decimals, optionally dropping zeros after the
+/// decimal point.
+pub fn format_number(value: f64, decimals: usize, trim_trailing_zeros: bool) -> String {
+    let mut formatted = format!("{value:.decimals$}");
+    if trim_trailing_zeros && formatted.contains('.') {
+        formatted.truncate(formatted.trim_end_matches('0').trim_end_matches('.').len());
+    }
+
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIB: u64 = 1024;
+    const MIB: u64 = 1024 * KIB;
+    const GIB: u64 = 1024 * MIB;
+
+    #[test]
+    fn byte_usage() {
+        let binary = UnitPolicy::new(false);
+        let decimal = UnitPolicy::new(true);
+
+        let cases = [
+            // used == total
+            (16 * GIB, 16 * GIB, binary, "16.00/16.00 GiB"),
+            (8_000_000_000, 8_000_000_000, decimal, "8.00/8.00 GB"),
+            // used == 0
+            (0, 16 * GIB, binary, "0.00/16.00 GiB"),
+            (0, 500_000_000, decimal, "0.00/500.00 MB"),
+            // total == 0
+            (0, 0, binary, "0.00/0.00 KiB"),
+            (0, 0, decimal, "0.00/0.00 KB"),
+            // used > total
+            (3 * GIB, 2 * GIB, binary, "3.00/2.00 GiB"),
+            (1_500_000, 1_000_000, decimal, "1.50/1.00 MB"),
+            // straddling a magnitude boundary
+            (MIB - 1, MIB - 1, binary, "1.00/1.00 MiB"),
+            (MIB - 6 * KIB, MIB - 6 * KIB, binary, "1018.00/1018.00 KiB"),
+            (994_000, 994_000, decimal, "994.00/994.00 KB"),
+            (999_000, 999_000, decimal, "1.00/1.00 MB"),
+            (512 * KIB, GIB, binary, "0.00/1.00 GiB"),
+        ];
+
+        for (used, total, policy, expected) in cases {
+            assert_eq!(expected, format_byte_usage(used, total, &policy));
+        }
+    }
+
+    #[test]
+    fn byte_usage_policy() {
+        let policy = UnitPolicy {
+            system: System::Binary,
+            fixed_magnitude: Some(Magnitude::Mega),
+            min_magnitude: None,
+            max_magnitude: None,
+            precision: 1,
+            style: UnitStyle::Full,
+        };
+        assert_eq!(
+            "512.0/16384.0 MiB",
+            format_byte_usage(512 * MIB, 16 * GIB, &policy)
+        );
+
+        let policy = UnitPolicy {
+            precision: 0,
+            ..UnitPolicy::new(true)
+        };
+        assert_eq!(
+            "2/4 GB",
+            format_byte_usage(1_500_000_000, 4_000_000_000, &policy)
+        );
+    }
+
+    #[test]
+    fn bytes() {
+        let compact = UnitPolicy {
+            precision: 1,
+            style: UnitStyle::Compact,
+            ..UnitPolicy::new(false)
+        };
+
+        assert_eq!(
+            "3.10 GiB",
+            format_bytes(3 * GIB + 100 * MIB, &UnitPolicy::new(false))
+        );
+        assert_eq!("3.1G", format_bytes(3 * GIB + 100 * MIB, &compact));
+        assert_eq!("0.00 KiB", format_bytes(0, &UnitPolicy::new(false)));
+    }
+
+    #[test]
+    fn byte_rate() {
+        let rate = |precision| UnitPolicy {
+            precision,
+            ..UnitPolicy::new(false)
+        };
+
+        let cases = [
+            (0.0, rate(1), "0.0 KiB/s"),
+            (-5.0, rate(1), "0.0 KiB/s"),
+            (300.0, rate(1), "0.3 KiB/s"),
+            (340.0 * KIB as f64, rate(0), "340 KiB/s"),
+            (1.2 * MIB as f64, rate(1), "1.2 MiB/s"),
+            (1_200_000.0, UnitPolicy::new(true), "1.20 MB/s"),
+        ];
+
+        for (bytes_per_second, policy, expected) in cases {
+            assert_eq!(expected, format_byte_rate(bytes_per_second, &policy));
+        }
+    }
+
+    #[test]
+    fn byte_rate_bounds() {
+        let bounded = |min, max| UnitPolicy {
+            precision: 1,
+            min_magnitude: min,
+            max_magnitude: max,
+            ..UnitPolicy::new(false)
+        };
+        let (mega, giga) = (Some(Magnitude::Mega), Some(Magnitude::Giga));
+
+        let cases = [
+            (300.0 * KIB as f64, bounded(mega, None), "0.3 MiB/s"),
+            (3.0 * GIB as f64, bounded(mega, None), "3.0 GiB/s"),
+            (3.0 * GIB as f64, bounded(None, mega), "3072.0 MiB/s"),
+            (300.0, bounded(None, mega), "0.3 KiB/s"),
+            (300.0 * KIB as f64, bounded(mega, giga), "0.3 MiB/s"),
+            (
+                3.0 * 1024.0 * GIB as f64,
+                bounded(mega, giga),
+                "3072.0 GiB/s",
+            ),
+            (1.5 * MIB as f64, bounded(mega, giga), "1.5 MiB/s"),
+        ];
+        for (bytes_per_second, policy, expected) in cases {
+            assert_eq!(expected, format_byte_rate(bytes_per_second, &policy));
+        }
+    }
+
+    #[test]
+    fn compact() {
+        let compact = |decimal, precision| UnitPolicy {
+            precision,
+            style: UnitStyle::Compact,
+            ..UnitPolicy::new(decimal)
+        };
+
+        assert_eq!(
+            "9.8/16.0G",
+            format_byte_usage(10_533_000_000, 16 * GIB, &compact(false, 1))
+        );
+        assert_eq!(
+            "10.5/17.2G",
+            format_byte_usage(10_533_000_000, 16 * GIB, &compact(true, 1))
+        );
+        assert_eq!(
+            "0.00/1.00G",
+            format_byte_usage(512 * KIB, GIB, &compact(false, 2))
+        );
+        assert_eq!(
+            "512/16384M",
+            format_byte_usage(
+                512 * MIB,
+                16 * GIB,
+                &UnitPolicy {
+                    fixed_magnitude: Some(Magnitude::Mega),
+                    ..compact(false, 0)
+                }
+            )
+        );
+        assert_eq!("0.3K/s", format_byte_rate(300.0, &compact(false, 1)));
+        assert_eq!(
+            "1.2M/s",
+            format_byte_rate(1.2 * MIB as f64, &compact(false, 1))
+        );
+        assert_eq!("1M/s", format_byte_rate(1_200_000.0, &compact(true, 0)));
+    }
+
+    /// The string splitting implementation this replaced, which the default policy must match.
+    fn split_format(used: u64, total: u64, si_units: bool) -> String {
+        let formatter = ByteSizeFormatter::fit(
+            total,
+            if si_units {
+                System::Decimal
+            } else {
+                System::Binary
+            },
+        );
+
+        format!(
+            "{}/{}",
+            formatter.format(used).split(" ").collect::<Vec<_>>()[0],
+            formatter.format(total)
+        )
+    }
+
+    #[test]
+    fn byte_usage_characterization() {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut values = vec![0, 1, 999, 1000, 1023, 1024, u64::MAX];
+        for _ in 0..2000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            // Spread the samples over all magnitudes.
+            values.push(state >> (state % 64));
+        }
+
+        for pair in values.windows(2) {
+            let (used, total) = (pair[0], pair[1]);
+            for si_units in [false, true] {
+                assert_eq!(
+                    split_format(used, total, si_units),
+                    format_byte_usage(used, total, &UnitPolicy::new(si_units)),
+                    "{used}/{total}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn percent_boundaries() {
+        let cases = [
+            (0.0, 1, false, "0.0%"),
+            (0.049, 1, false, "0.0%"),
+            (99.95, 1, false, "100.0%"),
+            (100.0, 1, false, "100.0%"),
+            (0.0, 0, false, "0%"),
+            (0.049, 0, false, "0%"),
+            (99.95, 0, false, "100%"),
+            (100.0, 0, false, "100%"),
+            (0.0, 1, true, "0%"),
+            (0.049, 2, true, "0.05%"),
+            (99.95, 1, true, "100%"),
+            (100.0, 2, true, "100%"),
+            (7.50, 2, true, "7.5%"),
+            (10.0, 0, true, "10%"),
+        ];
+
+        for (value, decimals, trim, expected) in cases {
+            assert_eq!(
+                expected,
+                format_percent(value, decimals, trim),
+                "{value} with {decimals} decimals"
+            );
+        }
+    }
+
+    #[test]
+    fn percent_clamps() {
+        assert_eq!("100.0%", format_percent(400.0, 1, false));
+        assert_eq!("0.0%", format_percent(-3.0, 1, false));
+        assert_eq!("0%", format_percent(f64::NAN, 0, false));
+    }
+
+    #[test]
+    fn frequency() {
+        let cases = [
+            (800.0, 2, false, "800 MHz"),
+            (999.4, 2, false, "999 MHz"),
+            // Rounding up to 1000 MHz switches to GHz.
+            (999.5, 2, false, "1.00 GHz"),
+            (1000.0, 2, false, "1.00 GHz"),
+            (3795.0, 2, false, "3.80 GHz"),
+            (3794.9, 2, false, "3.79 GHz"),
+            (3800.0, 1, false, "3.8 GHz"),
+            (3849.0, 1, false, "3.8 GHz"),
+            (3850.0, 1, false, "3.9 GHz"),
+            (3000.0, 2, true, "3 GHz"),
+            (4712.0, 3, false, "4.712 GHz"),
+            (-5.0, 2, false, "0 MHz"),
+        ];
+
+        for (mhz, decimals, trim, expected) in cases {
+            assert_eq!(
+                expected,
+                format_frequency(mhz, decimals, trim),
+                "{mhz} MHz with {decimals} decimals"
+            );
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Whether the output backend is getting the bar through, for the status command and
+//! `BackendHealth` sections. A FIFO without a reader or a gone X server otherwise only shows
+//! up in the log.
+//!
+//! Kept outside of [`Runtime`](crate::runtime::Runtime) like the history, so recording the
+//! outcome of a write never waits on the render loop.
+
+use std::sync::{LazyLock, Mutex};
+
+use chrono::{DateTime, Local};
+
+static HEALTH: LazyLock<Mutex<Health>> = LazyLock::new(Default::default);
+
+#[derive(Default)]
+pub struct Health {
+    /// Lowercase name of the backend, e.g. "file".
+    backend: Option<String>,
+    last_success: Option<DateTime<Local>>,
+    /// Writes that failed since the last one that didn't.
+    failures: u32,
+    /// Whether the backend lost its connection and reconnects with the next write.
+    reconnecting: bool,
+    /// Lines a backend writing on a thread of its own replaced before writing them.
+    dropped: u64,
+    /// Whether the last line likely didn't fit the bar.
+    truncated: bool,
+}
+
+impl Health {
+    /// Starts over for another backend, e.g. after a reload changed it.
+    pub fn set_backend(&mut self, backend: &str) {
+        *self = Self {
+            backend: Some(backend.to_lowercase()),
+            ..Self::default()
+        };
+    }
+
+    /// Records the outcome of a write.
+    pub fn record(
+        &mut self,
+        result: &Result<(), String>,
+        reconnecting: bool,
+        now: DateTime<Local>,
+    ) {
+        match result {
+            Ok(()) => {
+                self.last_success = Some(now);
+                self.failures = 0;
+            }
+            Err(_) => self.failures = self.failures.saturating_add(1),
+        }
+        self.reconnecting = reconnecting;
+    }
+
+    pub fn set_dropped(&mut self, dropped: u64) {
+        self.dropped = dropped;
+    }
+
+    pub fn set_truncated(&mut self, truncated: bool) {
+        self.truncated = truncated;
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// E.g. "file✗" while the last write failed or the backend is reconnecting, `None` while
+    /// it's fine.
+    pub fn indicator(&self) -> Option<String> {
+        let backend = self.backend.as_deref()?;
+        (self.failures > 0 || self.reconnecting).then(|| format!("{backend}✗"))
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "output={} output_last_ok={} output_failures={} output_reconnecting={} \
+             output_dropped={} truncated={}",
+            self.backend.as_deref().unwrap_or("none"),
+            self.last_success
+                .map(|x| x.to_rfc3339())
+                .unwrap_or("never".into()),
+            self.failures,
+            self.reconnecting,
+            self.dropped,
+            self.truncated,
+        )
+    }
+}
+
+pub fn set_backend(backend: &str) {
+    HEALTH.lock().unwrap().set_backend(backend);
+}
+
+pub fn record(result: &Result<(), String>, reconnecting: bool) {
+    HEALTH
+        .lock()
+        .unwrap()
+        .record(result, reconnecting, Local::now());
+}
+
+pub fn set_dropped(dropped: u64) {
+    HEALTH.lock().unwrap().set_dropped(dropped);
+}
+
+pub fn set_truncated(truncated: bool) {
+    HEALTH.lock().unwrap().set_truncated(truncated);
+}
+
+/// The indicator and the consecutive failures, see [`Health::indicator`].
+pub fn status() -> (Option<String>, u32) {
+    let health = HEALTH.lock().unwrap();
+    (health.indicator(), health.failures())
+}
+
+pub fn summary() -> String {
+    HEALTH.lock().unwrap().summary()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::OutputBackend, output};
+
+    #[test]
+    fn file_backend() {
+        let dir = std::env::temp_dir().join(format!("stringbar-health-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let backend = OutputBackend::File {
+            path: dir.join("bar").to_string_lossy().into_owned(),
+            append: false,
+            reverse_order: false,
+        };
+        let mut sink = output::open(&backend).unwrap();
+
+        let mut health = Health::default();
+        assert_eq!(None, health.indicator());
+        health.set_backend(output::name(&backend));
+        assert!(health
+            .summary()
+            .starts_with("output=file output_last_ok=never output_failures=0"));
+
+        // Writing fails until the directory exists.
+        for failures in 1..=2 {
+            health.record(&sink.publish("a"), sink.reconnecting(), Local::now());
+            assert_eq!(Some("file✗".into()), health.indicator());
+            assert_eq!(failures, health.failures());
+        }
+
+        std::fs::create_dir(&dir).unwrap();
+        health.record(&sink.publish("b"), sink.reconnecting(), Local::now());
+        assert_eq!(None, health.indicator());
+        assert!(health.summary().contains(" output_failures=0 "));
+        assert!(!health.summary().contains("output_last_ok=never"));
+
+        health.record(&Ok(()), true, Local::now());
+        assert_eq!(Some("file✗".into()), health.indicator());
+
+        health.set_dropped(3);
+        health.set_truncated(true);
+        assert!(health
+            .summary()
+            .ends_with(" output_dropped=3 truncated=true"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
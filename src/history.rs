@@ -0,0 +1,200 @@
+//! The last lines the bar showed, for finding out what flashed by.
+//!
+//! Kept outside of [`Runtime`](crate::runtime::Runtime) so the panic hook can still read it
+//! when the render loop panics while holding the runtime lock.
+
+use std::{
+    collections::VecDeque,
+    panic::Location,
+    sync::{LazyLock, Mutex, TryLockError},
+};
+
+use chrono::{DateTime, Local};
+use tracing::error;
+
+/// Longer lines are cut to this many bytes, so the buffer's size stays bounded.
+const MAX_LINE_LENGTH: usize = 1024;
+
+static HISTORY: LazyLock<Mutex<History>> = LazyLock::new(Default::default);
+
+#[derive(Default)]
+pub struct History {
+    entries: VecDeque<(DateTime<Local>, String)>,
+    capacity: usize,
+}
+
+impl History {
+    /// Changes how many lines are kept, dropping the oldest ones if needed. 0 disables the history.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Records a line unless it's the same as the previous one.
+    pub fn push(&mut self, time: DateTime<Local>, line: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let line = truncate(line);
+        if self.entries.back().is_some_and(|x| x.1 == line) {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((time, line));
+    }
+
+    /// The last `count` lines, oldest first, one per line prefixed by when they were first shown.
+    pub fn format(&self, count: usize) -> String {
+        self.entries
+            .iter()
+            .skip(self.entries.len().saturating_sub(count))
+            .map(|(time, line)| format!("{} {line}", time.to_rfc3339()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn truncate(line: &str) -> String {
+    if line.len() <= MAX_LINE_LENGTH {
+        return line.to_string();
+    }
+
+    let mut end = MAX_LINE_LENGTH;
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}…", &line[..end])
+}
+
+pub fn set_capacity(capacity: usize) {
+    HISTORY.lock().unwrap().set_capacity(capacity);
+}
+
+pub fn push(line: &str) {
+    HISTORY.lock().unwrap().push(Local::now(), line);
+}
+
+/// The last `count` lines, or all of them.
+pub fn format(count: Option<usize>) -> String {
+    HISTORY.lock().unwrap().format(count.unwrap_or(usize::MAX))
+}
+
+/// Makes panics log the history before the default panic message.
+pub fn dump_on_panic() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        dump(&HISTORY, info.location(), |x| error!("{x}"));
+        default(info);
+    }));
+}
+
+fn dump(history: &Mutex<History>, location: Option<&Location>, log: impl Fn(String)) {
+    let history = match history.try_lock() {
+        Ok(x) => x,
+        Err(TryLockError::Poisoned(x)) => x.into_inner(),
+        // Panicked while recording a line.
+        Err(TryLockError::WouldBlock) => {
+            log("Panicked, bar history is unavailable.".into());
+            return;
+        }
+    };
+
+    log(format!(
+        "Panicked at {}, recent bar output:\n{}",
+        location
+            .map(ToString::to_string)
+            .unwrap_or("unknown location".into()),
+        history.format(usize::MAX)
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    use chrono::TimeZone;
+
+    fn at(second: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2024, 5, 1, 12, 0, second)
+            .single()
+            .unwrap()
+    }
+
+    #[test]
+    fn ring_buffer() {
+        let mut history = History::default();
+        history.push(at(0), "dropped, history is disabled");
+        assert_eq!("", history.format(10));
+
+        history.set_capacity(3);
+        for (second, line) in ["a", "b", "b", "c", "d"].into_iter().enumerate() {
+            history.push(at(second as u32), line);
+        }
+
+        assert_eq!(
+            format!(
+                "{} b\n{} c\n{} d",
+                at(1).to_rfc3339(),
+                at(3).to_rfc3339(),
+                at(4).to_rfc3339()
+            ),
+            history.format(10)
+        );
+        assert_eq!(format!("{} d", at(4).to_rfc3339()), history.format(1));
+
+        history.set_capacity(1);
+        assert_eq!(format!("{} d", at(4).to_rfc3339()), history.format(10));
+        history.set_capacity(0);
+        assert_eq!("", history.format(10));
+    }
+
+    #[test]
+    fn long_lines() {
+        let mut history = History::default();
+        history.set_capacity(1);
+
+        // The cut falls inside the multi-byte character.
+        let line = format!("{}ä", "a".repeat(MAX_LINE_LENGTH - 1));
+        history.push(at(0), &line);
+
+        let expected = format!("{}…", "a".repeat(MAX_LINE_LENGTH - 1));
+        assert_eq!(
+            format!("{} {expected}", at(0).to_rfc3339()),
+            history.format(1)
+        );
+    }
+
+    #[test]
+    fn panic_dump() {
+        let history = Mutex::new(History::default());
+        history.lock().unwrap().set_capacity(2);
+        history.lock().unwrap().push(at(0), "before the panic");
+
+        let logged = RefCell::new(Vec::new());
+        dump(&history, Some(Location::caller()), |x| {
+            logged.borrow_mut().push(x)
+        });
+        dump(&history, None, |x| logged.borrow_mut().push(x));
+
+        // The panic happened while the history was locked.
+        let guard = history.lock().unwrap();
+        dump(&history, None, |x| logged.borrow_mut().push(x));
+        drop(guard);
+
+        let logged = logged.into_inner();
+        assert!(logged[0].starts_with("Panicked at src/history.rs"));
+        assert!(logged[0].ends_with(&format!("{} before the panic", at(0).to_rfc3339())));
+        assert!(logged[1].starts_with("Panicked at unknown location,"));
+        assert_eq!("Panicked, bar history is unavailable.", logged[2]);
+    }
+}
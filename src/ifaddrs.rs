@@ -0,0 +1,89 @@
+//! Addresses assigned to network interfaces, which sysinfo doesn't expose.
+
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ptr,
+};
+
+/// Every interface with its IPv4 and IPv6 addresses in the order the kernel lists them.
+/// Interfaces without addresses are included with an empty list.
+pub fn interface_addresses() -> io::Result<HashMap<String, Vec<IpAddr>>> {
+    let mut list: *mut libc::ifaddrs = ptr::null_mut();
+    // SAFETY: `list` is only read after getifaddrs filled it in.
+    if unsafe { libc::getifaddrs(&mut list) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut interfaces: HashMap<String, Vec<IpAddr>> = HashMap::new();
+    let mut entry = list;
+    // SAFETY: every entry and its name are valid until freeifaddrs, the address points to the
+    // sockaddr type matching its family.
+    unsafe {
+        while let Some(ifaddr) = entry.as_ref() {
+            entry = ifaddr.ifa_next;
+            let name = CStr::from_ptr(ifaddr.ifa_name).to_string_lossy();
+            let addresses = interfaces.entry(name.into_owned()).or_default();
+
+            let Some(addr) = ifaddr.ifa_addr.as_ref() else {
+                continue;
+            };
+            match addr.sa_family as libc::c_int {
+                libc::AF_INET => {
+                    let addr = &*ifaddr.ifa_addr.cast::<libc::sockaddr_in>();
+                    addresses.push(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)).into());
+                }
+                libc::AF_INET6 => {
+                    let addr = &*ifaddr.ifa_addr.cast::<libc::sockaddr_in6>();
+                    addresses.push(Ipv6Addr::from(addr.sin6_addr.s6_addr).into());
+                }
+                _ => {}
+            }
+        }
+        libc::freeifaddrs(list);
+    }
+
+    Ok(interfaces)
+}
+
+/// Addresses only valid on the local link, fe80::/10 and 169.254.0.0/16.
+pub fn is_link_local(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V4(x) => x.is_link_local(),
+        IpAddr::V6(x) => x.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback() {
+        let interfaces = interface_addresses().unwrap();
+        assert!(interfaces["lo"].contains(&IpAddr::V4(Ipv4Addr::LOCALHOST)));
+    }
+
+    #[test]
+    fn link_local() {
+        let cases = [
+            ("fe80::1", true),
+            ("fe80::a00:27ff:fe4e:66a1", true),
+            ("febf::1", true),
+            ("fec0::1", false),
+            ("2001:db8::5", false),
+            ("::1", false),
+            ("169.254.10.3", true),
+            ("192.168.1.23", false),
+        ];
+        for (address, expected) in cases {
+            assert_eq!(
+                expected,
+                is_link_local(&address.parse().unwrap()),
+                "{address}"
+            );
+        }
+    }
+}
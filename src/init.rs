@@ -0,0 +1,345 @@
+//! `stringbar init`, which writes a starter config for the disks, battery and sensors found on
+//! this machine.
+
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use ron::{extensions::Extensions, ser::PrettyConfig};
+
+use crate::{
+    config::{self, Config, Decoration, Module, Section, TempAggregate},
+    install,
+    provider::{SysinfoProvider, SystemProvider},
+};
+
+const USAGE: &str = "usage: stringbar init [--defaults] [--force]";
+
+const HEADER: &str = "// Starter config written by stringbar init, see the README for every option.
+// Changes are picked up while stringbar is running.
+";
+
+/// What was found on this machine.
+#[derive(Default)]
+pub struct Probe {
+    /// Mount points of non-removable disks.
+    pub mount_points: Vec<String>,
+    pub batteries: Vec<String>,
+    /// Labels of temperature sensors.
+    pub sensors: Vec<String>,
+}
+
+impl Probe {
+    pub fn detect(provider: &mut dyn SystemProvider) -> Self {
+        let mut mount_points: Vec<String> = provider
+            .disks()
+            .mounts()
+            .iter()
+            .filter(|x| !x.is_removable)
+            .map(|x| x.mount_point.clone())
+            .collect();
+        mount_points.sort();
+        mount_points.dedup();
+
+        Self {
+            mount_points,
+            batteries: provider.batteries().into_iter().map(|x| x.name).collect(),
+            sensors: provider
+                .components()
+                .iter()
+                .map(|x| x.label.clone())
+                .collect(),
+        }
+    }
+}
+
+/// Handles `stringbar init <args>`, writing to `path`. Returns a message for the user on failure.
+pub fn run(args: &[String], path: &Path) -> Result<(), String> {
+    let has = |flag: &str| args.iter().any(|x| x == flag);
+    if let Some(unknown) = args
+        .iter()
+        .find(|x| !["--defaults", "--force"].contains(&x.as_str()))
+    {
+        return Err(format!("unknown argument {unknown}\n{USAGE}"));
+    }
+    let read_only = || {
+        format!(
+            "{} is read-only, not writing a config there",
+            config::real_path(path).display()
+        )
+    };
+    if config::is_read_only(path) {
+        return Err(read_only());
+    }
+    if path.exists() && !has("--force") {
+        return Err(format!(
+            "{} already exists, pass --force to overwrite it",
+            path.display()
+        ));
+    }
+
+    let probe = Probe::detect(&mut SysinfoProvider::new());
+    let config = if has("--defaults") {
+        build(&probe, |_| true)
+    } else {
+        let stdin = io::stdin();
+        build(&probe, |question| ask(question, &mut stdin.lock()))
+    };
+
+    let contents = render(&config)?;
+    install::write_file(path, &contents, has("--force")).map_err(|e| {
+        if config::is_read_only_error(&e) {
+            read_only()
+        } else {
+            format!("Unable to write {}: {e}", path.display())
+        }
+    })?;
+
+    println!("Wrote {}.", path.display());
+    Ok(())
+}
+
+/// Assembles a config from what was found, calling `ask` with a yes/no question for every
+/// optional part.
+pub fn build(probe: &Probe, mut ask: impl FnMut(&str) -> bool) -> Config {
+    let labeled = |label: &str, module: Module| Section {
+        decoration: Decoration {
+            before: Some(label.into()),
+            ..Decoration::default()
+        },
+        ..module.into()
+    };
+
+    let mut sections = vec![
+        labeled("cpu ", Module::CpuUsage),
+        labeled("dram ", Module::MemoryUsage { basis: None }),
+    ];
+
+    if let Some(sensor) = probe.sensors.first() {
+        if ask(&format!("Show the temperature of {sensor}?")) {
+            sections.push(labeled(
+                "temp ",
+                Module::Temperature {
+                    component: sensor.clone(),
+                    fahrenheit: false,
+                    aggregate: TempAggregate::First,
+                    warn_above: None,
+                    clear_below: None,
+                },
+            ));
+        }
+    }
+
+    for mount_point in &probe.mount_points {
+        if ask(&format!("Show disk usage of {mount_point}?")) {
+            sections.push(labeled(
+                &format!("{mount_point} "),
+                Module::DiskUsage {
+                    name: mount_point.clone(),
+                    inodes: false,
+                },
+            ));
+        }
+    }
+
+    if !probe.batteries.is_empty() && ask("Show the battery level?") {
+        sections.push(labeled(
+            "bat ",
+            Module::Battery {
+                name: None,
+                hide_when_missing: true,
+                limit_marker: None,
+                relative_to_limit: false,
+            },
+        ));
+    }
+
+    let template = if ask("Use a 24-hour clock?") {
+        "%d/%m/%Y %H:%M"
+    } else {
+        "%d/%m/%Y %I:%M %p"
+    };
+    sections.push(
+        Module::Timestamp {
+            template: template.into(),
+        }
+        .into(),
+    );
+
+    Config {
+        sections,
+        ..Config::default()
+    }
+}
+
+/// The config file contents for `config`, with a comment on top.
+pub fn render(config: &Config) -> Result<String, String> {
+    let body =
+        ron::ser::to_string_pretty(config, PrettyConfig::new().extensions(Extensions::all()))
+            .map_err(|e| format!("Unable to serialize config: {e}"))?;
+
+    Ok(format!("{HEADER}{body}\n"))
+}
+
+/// Asks a yes/no question on the terminal until it gets an answer. Empty answers and end of
+/// input mean yes.
+fn ask(question: &str, input: &mut impl BufRead) -> bool {
+    loop {
+        print!("{question} [Y/n] ");
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+        if input.read_line(&mut line).unwrap_or(0) == 0 {
+            println!();
+            return true;
+        }
+
+        match line.trim().to_lowercase().as_str() {
+            "" | "y" | "yes" => return true,
+            "n" | "no" => return false,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::provider::{ChargeState, FakeProvider};
+
+    fn probe() -> Probe {
+        let mut provider = FakeProvider::default();
+        provider
+            .add_disk("/dev/nvme0n1p2", 100, 50, false)
+            .mounted_at("/")
+            .add_disk("/dev/nvme0n1p3", 100, 50, false)
+            .mounted_at("/home")
+            .add_disk("/dev/sdc1", 100, 50, true)
+            .mounted_at("/media/usb")
+            .add_battery("BAT0", 80, ChargeState::Discharging)
+            .add_component("coretemp Package id 0", 50.0);
+
+        Probe::detect(&mut provider)
+    }
+
+    fn modules(config: &Config) -> Vec<&'static str> {
+        config.sections.iter().map(|x| x.module.name()).collect()
+    }
+
+    #[test]
+    fn scripted_answers() {
+        let probe = probe();
+        assert_eq!(vec!["/", "/home"], probe.mount_points);
+
+        let mut questions = Vec::new();
+        let mut answers = [false, true, false, true, false].into_iter();
+        let config = build(&probe, |question| {
+            questions.push(question.to_string());
+            answers.next().unwrap()
+        });
+
+        assert_eq!(
+            vec![
+                "Show the temperature of coretemp Package id 0?",
+                "Show disk usage of /?",
+                "Show disk usage of /home?",
+                "Show the battery level?",
+                "Use a 24-hour clock?",
+            ],
+            questions
+        );
+        assert_eq!(
+            vec![
+                "CpuUsage",
+                "MemoryUsage",
+                "DiskUsage",
+                "Battery",
+                "Timestamp"
+            ],
+            modules(&config)
+        );
+        assert!(matches!(
+            &config.sections[4].module,
+            Module::Timestamp { template } if template == "%d/%m/%Y %I:%M %p"
+        ));
+    }
+
+    #[test]
+    fn defaults() {
+        let config = build(&probe(), |_| true);
+        assert_eq!(
+            vec![
+                "CpuUsage",
+                "MemoryUsage",
+                "Temperature",
+                "DiskUsage",
+                "DiskUsage",
+                "Battery",
+                "Timestamp"
+            ],
+            modules(&config)
+        );
+
+        // Nothing detected, only the clock is asked about.
+        let mut asked = 0;
+        let config = build(&Probe::default(), |_| {
+            asked += 1;
+            true
+        });
+        assert_eq!(1, asked);
+        assert_eq!(
+            vec!["CpuUsage", "MemoryUsage", "Timestamp"],
+            modules(&config)
+        );
+    }
+
+    #[test]
+    fn rendered_config_loads() {
+        let contents = render(&build(&probe(), |_| true)).unwrap();
+        assert!(contents.starts_with("// "));
+
+        let mut config: Config = ron::from_str(&contents).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(7, config.sections.len());
+    }
+
+    #[test]
+    fn read_only_target() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let root =
+            std::env::temp_dir().join(format!("stringbar-init-read-only-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let store = root.join("store");
+        fs::create_dir_all(&store).unwrap();
+        fs::write(store.join("config.ron"), "()").unwrap();
+        fs::set_permissions(store.join("config.ron"), fs::Permissions::from_mode(0o444)).unwrap();
+        fs::set_permissions(&store, fs::Permissions::from_mode(0o555)).unwrap();
+        let linked = root.join("config.ron");
+        std::os::unix::fs::symlink(store.join("config.ron"), &linked).unwrap();
+
+        let error = run(&["--defaults".into(), "--force".into()], &linked).unwrap_err();
+        assert_eq!(
+            format!(
+                "{} is read-only, not writing a config there",
+                store.canonicalize().unwrap().join("config.ron").display()
+            ),
+            error
+        );
+
+        fs::set_permissions(&store, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn terminal_answers() {
+        let mut input = "maybe\nN\n\ny\n".as_bytes();
+        assert!(!ask("?", &mut input));
+        assert!(ask("?", &mut input));
+        assert!(ask("?", &mut input));
+        // End of input.
+        assert!(ask("?", &mut input));
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+};
+
+use directories::BaseDirs;
+
+const USAGE: &str = "usage: stringbar install --systemd-user [--print] [--force] | --xinitrc";
+
+/// Handles `stringbar install <args>`, returning a message for the user on failure.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let has = |flag: &str| args.iter().any(|x| x == flag);
+    if let Some(unknown) = args
+        .iter()
+        .find(|x| !["--systemd-user", "--print", "--force", "--xinitrc"].contains(&x.as_str()))
+    {
+        return Err(format!("unknown argument {unknown}\n{USAGE}"));
+    }
+
+    let binary = std::env::current_exe()
+        .and_then(|x| x.canonicalize())
+        .map_err(|e| format!("Unable to resolve the stringbar binary: {e}"))?;
+    if !binary.is_file() {
+        return Err(format!("{} is not a file", binary.display()));
+    }
+
+    if has("--xinitrc") {
+        print!("{}", xinitrc_snippet(&binary));
+        return Ok(());
+    }
+
+    if !has("--systemd-user") {
+        return Err(USAGE.into());
+    }
+
+    let unit = systemd_unit(&binary);
+    if has("--print") {
+        print!("{unit}");
+        return Ok(());
+    }
+
+    let path = BaseDirs::new()
+        .ok_or("Unable to get config directory.")?
+        .config_dir()
+        .join("systemd/user/stringbar.service");
+    write_file(&path, &unit, has("--force")).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => format!(
+            "{} already exists, pass --force to overwrite it",
+            path.display()
+        ),
+        _ => format!("Unable to write {}: {e}", path.display()),
+    })?;
+
+    println!(
+        "Wrote {}. Enable it with: systemctl --user enable --now stringbar.service",
+        path.display()
+    );
+    Ok(())
+}
+
+pub fn systemd_unit(binary: &Path) -> String {
+    format!(
+        "[Unit]
+Description=A dwm-style status bar
+PartOf=graphical-session.target
+After=graphical-session.target
+
+[Service]
+# The Xsetroot output needs DISPLAY and XAUTHORITY, import them with:
+# systemctl --user import-environment DISPLAY XAUTHORITY
+ExecStart={}
+Restart=on-failure
+
+[Install]
+WantedBy=graphical-session.target
+",
+        systemd_quote(binary)
+    )
+}
+
+pub fn xinitrc_snippet(binary: &Path) -> String {
+    format!("{} &\n", shell_quote(binary))
+}
+
+/// Writes `contents` to `path`, creating its parent directories. Fails with
+/// [`AlreadyExists`](io::ErrorKind::AlreadyExists) if the file exists, unless `force` is set.
+pub fn write_file(path: &Path, contents: &str, force: bool) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+fn systemd_quote(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if path.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        path.into_owned()
+    }
+}
+
+fn shell_quote(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if path
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "/._-+".contains(c))
+    {
+        path.into_owned()
+    } else {
+        format!("'{}'", path.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_contents() {
+        let unit = systemd_unit(Path::new("/usr/local/bin/stringbar"));
+        assert!(unit.contains("\nExecStart=/usr/local/bin/stringbar\n"));
+        assert!(unit.contains("\nRestart=on-failure\n"));
+        assert!(unit.contains("\nWantedBy=graphical-session.target\n"));
+        assert!(!unit.contains("WatchdogSec"));
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(
+            "\"/opt/my bin/stringbar\"",
+            systemd_quote(Path::new("/opt/my bin/stringbar"))
+        );
+        assert_eq!(
+            "'/opt/it'\\''s/stringbar' &\n",
+            xinitrc_snippet(Path::new("/opt/it's/stringbar"))
+        );
+        assert_eq!(
+            "/usr/bin/stringbar &\n",
+            xinitrc_snippet(Path::new("/usr/bin/stringbar"))
+        );
+    }
+
+    #[test]
+    fn refuses_to_overwrite() {
+        let dir = std::env::temp_dir().join(format!("stringbar-install-{}", std::process::id()));
+        let path = dir.join("systemd/user/stringbar.service");
+
+        write_file(&path, "first", false).unwrap();
+        assert_eq!(
+            io::ErrorKind::AlreadyExists,
+            write_file(&path, "second", false).unwrap_err().kind()
+        );
+        assert_eq!("first", std::fs::read_to_string(&path).unwrap());
+
+        write_file(&path, "third", true).unwrap();
+        assert_eq!("third", std::fs::read_to_string(&path).unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+//! Unread mail in a Maildir, which is every message its MDA delivered to `new` that no mail
+//! client has moved to `cur` yet.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Replaces a leading `~` with `home`, e.g. "~/Mail/work" with "/home/user/Mail/work".
+pub fn expand_home(path: &str, home: Option<&Path>) -> PathBuf {
+    match (path.strip_prefix('~'), home) {
+        (Some(""), Some(home)) => home.to_path_buf(),
+        (Some(rest), Some(home)) if rest.starts_with('/') => home.join(&rest[1..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Number of messages in the `new` directory of `maildir`. Dotfiles, like the temporary files
+/// some tools leave behind, don't count.
+pub fn count_new(maildir: &Path) -> Result<usize, String> {
+    let new = maildir.join("new");
+    let entries = fs::read_dir(&new).map_err(|e| format!("{}: {e}", new.display()))?;
+
+    Ok(entries
+        .flatten()
+        .filter(|x| !x.file_name().to_string_lossy().starts_with('.'))
+        .count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn home() {
+        let home = Some(Path::new("/home/user"));
+        assert_eq!(
+            PathBuf::from("/home/user/Mail/work"),
+            expand_home("~/Mail/work", home)
+        );
+        assert_eq!(PathBuf::from("/home/user"), expand_home("~", home));
+        assert_eq!(
+            PathBuf::from("/var/mail/x"),
+            expand_home("/var/mail/x", home)
+        );
+        // Other users' homes aren't looked up.
+        assert_eq!(
+            PathBuf::from("~other/Mail"),
+            expand_home("~other/Mail", home)
+        );
+        assert_eq!(PathBuf::from("~/Mail"), expand_home("~/Mail", None));
+    }
+
+    #[test]
+    fn counting() {
+        let root = std::env::temp_dir().join(format!("stringbar-maildir-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["new", "cur", "tmp"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        assert_eq!(Ok(0), count_new(&root));
+
+        fs::write(root.join("new/1700000000.M1P2.host"), "").unwrap();
+        fs::write(root.join("new/1700000001.M2P2.host"), "").unwrap();
+        fs::write(root.join("new/.uidvalidity"), "").unwrap();
+        fs::write(root.join("cur/1600000000.M1P1.host:2,S"), "").unwrap();
+        assert_eq!(Ok(2), count_new(&root));
+
+        fs::remove_dir_all(&root).unwrap();
+        let error = count_new(&root).unwrap_err();
+        assert!(error.starts_with(&format!("{}: ", root.join("new").display())));
+    }
+}
@@ -1,306 +1,753 @@
 use std::{
-    fs::OpenOptions,
-    io::{BufReader, BufWriter},
-    path::Path,
-    process::Command,
-    sync::{Arc, Mutex},
+    collections::HashMap,
+    io,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use bittenhumans::ByteSizeFormatter;
-use chrono::Local;
 use directories::ProjectDirs;
-use notify::{RecommendedWatcher, Watcher};
-use ron::{extensions::Extensions, ser::PrettyConfig};
-use serde::{Deserialize, Serialize};
-use sysinfo::{CpuRefreshKind, Disk, Disks, MemoryRefreshKind, ProcessRefreshKind, System};
-use tracing::{error, info};
-
-#[derive(Serialize, Deserialize)]
-struct Config {
-    separator: String,
-    update_interval_ms: u64,
-    decimal_data_units: bool,
-    sections: Vec<Section>,
+use tracing::{error, info, warn};
+
+mod alsa;
+mod bar;
+mod bare;
+mod bluetooth;
+mod budget;
+mod cgroup;
+mod clock;
+mod compat;
+mod config;
+mod control;
+mod dbus;
+mod demo;
+mod deps;
+mod diff;
+mod disks;
+mod duration;
+mod dwm;
+mod error;
+mod expr;
+mod extremes;
+mod format;
+mod health;
+mod history;
+mod ifaddrs;
+mod init;
+mod install;
+mod maildir;
+mod mpd;
+mod notify;
+mod oneshot;
+mod output;
+mod post;
+mod provider;
+mod publicip;
+mod rates;
+mod render;
+mod runtime;
+mod sanitize;
+mod schedule;
+mod sha256;
+mod snapshot;
+mod spawn;
+mod statvfs;
+mod template;
+mod text;
+mod throttle;
+mod timer;
+mod tui;
+mod unit;
+mod urgency;
+mod validity;
+mod version;
+mod warn;
+mod watch;
+mod x11;
+mod xkb;
+
+use bar::Piece;
+use budget::TickScheduler;
+use clock::{Clock, SystemClock};
+use config::{load_config, Config, OutputBackend, ReloadStrategy};
+use error::StringbarError;
+use output::Output;
+use provider::{SysinfoProvider, SystemProvider};
+use render::Context;
+use runtime::Runtime;
+use throttle::Throttle;
+
+/// Toggled by SIGUSR2. When set, every section gets its evaluation time appended.
+/// Deliberately not part of [`Config`](config::Config), so it can't be enabled from the config file.
+static PROFILING: AtomicBool = AtomicBool::new(false);
+
+/// Set once SIGTERM or SIGINT arrives, the main loop exits at its next wake-up.
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// Offsets from SIGRTMIN of the `reset_signal`s that arrived since the last tick, as bits.
+static RESET_SIGNALS: AtomicU32 = AtomicU32::new(0);
+
+/// How long exiting waits for the output to take the last line.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(1);
+
+extern "C" fn toggle_profiling(_: libc::c_int) {
+    PROFILING.fetch_xor(true, Ordering::Relaxed);
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            separator: " | ".into(),
-            update_interval_ms: 1000,
-            decimal_data_units: false,
-            sections: vec![
-                Section {
-                    decoration: Decoration {
-                        before: Some("dram ".into()),
-                        after: None,
-                    },
-                    module: Module::MemoryUsage,
-                },
-                Section {
-                    decoration: Decoration {
-                        before: Some("sda ".into()),
-                        after: None,
-                    },
-                    module: Module::DiskUsage {
-                        name: "/dev/sda".into(),
-                    },
-                },
-                Section {
-                    decoration: Decoration {
-                        before: Some("total ".into()),
-                        after: None,
-                    },
-                    module: Module::DiskUsageTotal {
-                        include_removables: false,
-                    },
-                },
-                Section {
-                    decoration: Decoration {
-                        before: None,
-                        after: None,
-                    },
-                    module: Module::Timestamp {
-                        template: "%d/%m/%Y %H:%M".into(),
-                    },
-                },
-            ],
-        }
-    }
+extern "C" fn reset_totals(signal: libc::c_int) {
+    RESET_SIGNALS.fetch_or(1 << (signal - libc::SIGRTMIN()), Ordering::Relaxed);
 }
 
-#[derive(Serialize, Deserialize)]
-enum Module {
-    Timestamp { template: String },
-    MemoryUsage,
-    SwapUsage,
-    CpuUsage,
-    ProcessCount,
-    DiskUsage { name: String },
-    DiskUsageTotal { include_removables: bool },
-}
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // Prompts and the stdout backends read stdout.
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    if args.first().is_some_and(|x| x == "install") {
+        if let Err(e) = install::run(&args[1..]) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.first().is_some_and(|x| x == "--version") {
+        if let Err(e) = version::run(&args[1..]) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.first().is_some_and(|x| x == "schema") {
+        println!("{}", snapshot::schema());
+        return;
+    }
+    if args.first().is_some_and(|x| x == "--compat") {
+        let result = match args.get(1) {
+            Some(mode) => compat::run(mode),
+            None => Err(format!(
+                "usage: stringbar --compat <{}>",
+                compat::MODES.join("|")
+            )),
+        };
+        if let Err(e) = result {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
 
-#[derive(Serialize, Deserialize)]
-struct Decoration {
-    before: Option<String>,
-    after: Option<String>,
-}
+    let output_flag = match output::take_flag(&mut args) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    // Runs the bar like without it, only showing it in the terminal.
+    let tui = args.first().is_some_and(|x| x == "tui");
+    if tui {
+        args.remove(0);
+    }
+    let demo_seed = match demo::seed(&args) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
 
-#[derive(Serialize, Deserialize)]
-struct Section {
-    module: Module,
-    decoration: Decoration,
-}
+    let dirs = ProjectDirs::from("", "", "stringbar")
+        .unwrap_or_else(|| fail(StringbarError::Io("Unable to get config directory.".into())));
 
-fn load_config(config_file_path: &Path) -> Option<Config> {
-    match OpenOptions::new().read(true).open(config_file_path) {
-        Ok(config_file) => match ron::de::from_reader(BufReader::new(config_file)) {
-            Ok(x) => Some(x),
-            Err(e) => {
-                error!("Unable to read config file: {e}");
-                None
-            }
-        },
-        Err(e) => match e.kind() {
-            std::io::ErrorKind::NotFound => {
-                let new_config = Config::default();
-                let handle = match OpenOptions::new()
-                    .write(true)
-                    .create_new(true)
-                    .open(config_file_path)
-                {
-                    Ok(x) => x,
-                    Err(e) => {
-                        error!("Unable to create new config file: {e}");
-                        return None;
-                    }
-                };
+    if let Err(e) = std::fs::create_dir_all(dirs.config_dir()) {
+        // Still fine if a config is provided some other way, loading reports what's missing.
+        if !config::is_read_only_error(&e) {
+            fail(StringbarError::Io(format!(
+                "Unable to create config directory: {e}"
+            )));
+        }
+    }
 
-                if let Err(e) = ron::ser::to_writer_pretty(
-                    BufWriter::new(handle),
-                    &new_config,
-                    PrettyConfig::new().extensions(Extensions::all()),
-                ) {
-                    error!("Unable to write to new config file: {e}");
-                    return None;
+    let config_file_path = dirs.config_dir().join("config.ron");
+    if args.first().is_some_and(|x| x == "render") {
+        if let Err(e) = oneshot::run(&args[1..], &config_file_path) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.first().is_some_and(|x| x == "check") {
+        match load_config(&config_file_path, false) {
+            Ok((mut config, _)) => {
+                output::Choice::new(
+                    output_flag,
+                    output::Env::current(),
+                    config.preferred_display,
+                )
+                .apply(&mut config.output);
+                if let Some(missing) = deps::check(&config, &deps::PathProbe) {
+                    eprintln!("{missing}");
                 }
-
-                info!("Wrote new config file.");
-                Some(new_config)
+                println!("Durations:");
+                for duration in config.durations() {
+                    println!("  {duration}");
+                }
+                println!("{} is valid.", config_file_path.display());
             }
-            _ => {
-                error!("Unable to open config file: {e}");
-                None
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(e.exit_code());
             }
-        },
+        }
+        return;
     }
-}
-
-fn main() {
-    tracing_subscriber::fmt().init();
-
-    let dirs = match ProjectDirs::from("", "", "stringbar") {
-        Some(x) => x,
-        None => {
-            error!("Unable to get config directory.");
-            return;
+    if args.first().is_some_and(|x| x == "init") {
+        if let Err(e) = init::run(&args[1..], &config_file_path) {
+            eprintln!("{e}");
+            std::process::exit(1);
         }
-    };
-
-    if let Err(e) = std::fs::create_dir_all(dirs.config_dir()) {
-        error!("Unable to create config directory: {e}");
         return;
     }
 
-    let config_file_path = dirs.config_dir().join("config.ron");
-    let config = Arc::new(Mutex::new(
-        load_config(&config_file_path).expect("Initial config load failed, exiting."),
-    ));
+    // Lets the control socket and config reloads interrupt the sleep between updates.
+    let (wake_tx, wake_rx) = mpsc::channel();
+    // Before any other threads are started, they inherit the blocked signals.
+    spawn_signal_waiter(wake_tx.clone());
 
-    let mut watcher;
-    {
-        let config = config.clone();
-        let config_file_path = config_file_path.clone();
+    let (mut config, file_info) = load_config(&config_file_path, true).unwrap_or_else(|e| fail(e));
+    let output_choice = output::Choice::new(
+        output_flag,
+        output::Env::current(),
+        config.preferred_display,
+    );
+    info!("{}", output_choice.apply(&mut config.output));
+    // The preview only shows the line, it doesn't need the output to be there.
+    if let (false, Err(e)) = (
+        tui,
+        deps::check_output(
+            &config.output,
+            std::env::var_os("DISPLAY").as_deref(),
+            &deps::PathProbe,
+            || x11::Connection::open().map(|_| ()),
+        ),
+    ) {
+        fail(e);
+    }
+    if let Some(missing) = deps::check(&config, &deps::PathProbe) {
+        warn!("{missing}");
+    }
+    history::set_capacity(config.history_length);
+    history::dump_on_panic();
+    notify::set_enabled(config.error_notifications);
+    let config = Arc::new(Mutex::new(config));
 
-        watcher = match RecommendedWatcher::new(
-            move |result: Result<notify::Event, notify::Error>| {
-                let event = result.unwrap();
+    let runtime = Arc::new(Mutex::new(Runtime::default()));
+    runtime.lock().unwrap().reloads.loaded(file_info, false);
 
-                if event.kind.is_modify() {
-                    info!("Config file has changed, reloading...");
-                    if let Some(new_config) = load_config(&config_file_path) {
-                        *config.lock().unwrap() = new_config;
+    {
+        let reload_strategy = config.lock().unwrap().reload;
+        let path = config_file_path.clone();
+        let wake = wake_tx.clone();
+
+        let reload = Arc::new({
+            let config = config.clone();
+            let runtime = runtime.clone();
+            move || {
+                info!("Config file has changed, reloading...");
+                match load_config(&path, false) {
+                    Ok((mut new_config, file_info)) => {
+                        output_choice.apply(&mut new_config.output);
+                        if let Some(missing) = deps::check(&new_config, &deps::PathProbe) {
+                            warn!("{missing}");
+                        }
+                        let mut config = config.lock().unwrap();
+                        let changes = diff::diff(&config, &new_config);
+                        history::set_capacity(new_config.history_length);
+                        notify::set_enabled(new_config.error_notifications);
+                        *config = new_config;
+                        warn::reset();
+                        // Still holding the config, so the render loop never sees the new
+                        // sections before the reload is counted. Locking the runtime first
+                        // would deadlock with the render loop, see `Runtime`.
+                        let mut runtime = runtime.lock().unwrap();
+                        runtime.reloads.loaded(file_info, true);
+                        runtime.reloads.last_changes = changes;
+                        info!("Config reloaded. {}", runtime.reloads.summary());
+                        // Sections with long intervals of their own show changes right away.
+                        let _ = wake.send(());
+                    }
+                    Err(e) => {
+                        error!("{e}");
+                        notify::error("stringbar: config reload failed", &e.to_string());
+                        runtime.lock().unwrap().reloads.failed += 1;
                     }
                 }
-            },
-            notify::Config::default(),
-        ) {
-            Ok(x) => x,
-            Err(e) => {
-                error!("Unable to build RecommendedWatcher: {e}");
-                return;
             }
+        });
+        let poll = |interval| {
+            let reload = reload.clone();
+            watch::spawn_polling(config_file_path.clone(), interval, move || reload())
+        };
+
+        match reload_strategy {
+            ReloadStrategy::Watch => {
+                let watched = {
+                    let reload = reload.clone();
+                    let config = config.clone();
+                    let runtime = runtime.clone();
+                    let wake = wake_tx.clone();
+                    watch::spawn(
+                        config_file_path.clone(),
+                        move || config.lock().unwrap().watched_paths(),
+                        move |trigger| match trigger {
+                            watch::Trigger::Config => reload(),
+                            watch::Trigger::Section(index) => {
+                                runtime.lock().unwrap().refresh.insert(index);
+                                let _ = wake.send(());
+                            }
+                        },
+                    )
+                };
+                if let Err(e) = watched {
+                    warn!(
+                        "Unable to watch config ({e}), checking it every {}s instead.",
+                        watch::FALLBACK_POLL_INTERVAL.as_secs()
+                    );
+                    poll(watch::FALLBACK_POLL_INTERVAL);
+                }
+            }
+            ReloadStrategy::Poll { interval_secs } => poll(interval_secs.0),
+            ReloadStrategy::None => info!("Config reloading is disabled."),
         }
     }
 
-    if let Err(e) = watcher.watch(&config_file_path, notify::RecursiveMode::NonRecursive) {
-        error!("Unable to start watching config: {e}");
-    };
+    // SAFETY: the handler only touches an atomic, which is async-signal-safe.
+    if unsafe {
+        libc::signal(
+            libc::SIGUSR2,
+            toggle_profiling as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        )
+    } == libc::SIG_ERR
+    {
+        error!("Unable to install SIGUSR2 handler, section profiling is unavailable.");
+    }
+    install_reset_signals(&config.lock().unwrap());
+
+    let mut tui = tui.then(|| {
+        tui::Tui::start(wake_tx.clone()).unwrap_or_else(|e| {
+            fail(StringbarError::Io(format!(
+                "Unable to set up the terminal: {e}"
+            )))
+        })
+    });
+
+    match dirs.runtime_dir() {
+        // The control socket belongs to the bar that's running alongside the preview.
+        Some(_) if tui.is_some() => info!("Previewing, the control socket is unavailable."),
+        Some(runtime_dir) => {
+            match std::fs::create_dir_all(runtime_dir).and_then(|_| {
+                control::spawn(&runtime_dir.join("control.sock"), runtime.clone(), wake_tx)
+            }) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                    fail(StringbarError::InstanceLock(format!("Not starting: {e}.")))
+                }
+                Err(e) => error!("Unable to start control socket: {e}"),
+            }
+        }
+        None => error!("Unable to get runtime directory, control socket is unavailable."),
+    }
 
-    let mut system = System::new();
-    let mut disks = Disks::new();
+    let mut provider: Box<dyn SystemProvider> = match demo_seed {
+        Some(seed) => {
+            info!("Demo mode, showing made-up data. Repeat with --seed {seed}.");
+            Box::new(demo::DemoProvider::new(seed))
+        }
+        None => Box::new(SysinfoProvider::new()),
+    };
+    provider.spare_disks(config.lock().unwrap().spared_disks());
+    provider.want_inodes(config.lock().unwrap().wants_inodes());
+    let mut scheduler = TickScheduler::new(config.lock().unwrap().section_intervals());
+    let clock = SystemClock;
+    let mut throttle = Throttle::new(config.lock().unwrap().max_emissions_per_sec);
+    let mut order = config.lock().unwrap().evaluation_order();
+    let mut reloads = 0;
+    let mut profiling = false;
+    let mut frozen = false;
+    let mut quiet = false;
+    let mut next_tick = Instant::now();
+    let mut last_output = String::new();
+    // When each section was last evaluated, for the snapshots.
+    let mut evaluated = HashMap::new();
+    let mut backend = config.lock().unwrap().output.clone();
+    let previewing = tui.is_some();
+    let open = |backend: &OutputBackend| match previewing {
+        true => None,
+        false => output::open(backend),
+    };
+    let mut sink = open(&backend);
+    health::set_backend(output::name(&backend));
 
     loop {
-        let mut output = String::new();
-        let config = config.lock().unwrap();
-        let interval = config.update_interval_ms;
-        let mut disks_refreshed = false;
-
-        for section in &config.sections {
-            let module_out = match &section.module {
-                Module::Timestamp { template } => Local::now().format(template).to_string(),
-                Module::MemoryUsage => {
-                    system.refresh_memory_specifics(MemoryRefreshKind::new().with_ram());
-                    format_byte_usage(
-                        system.used_memory(),
-                        system.total_memory(),
-                        config.decimal_data_units,
-                    )
+        for key in tui.as_ref().map(tui::Tui::keys).unwrap_or_default() {
+            match key {
+                tui::Key::Quit => SHUTDOWN.store(true, Ordering::Relaxed),
+                tui::Key::Refresh => {
+                    let sections = config.lock().unwrap().sections.len();
+                    runtime.lock().unwrap().refresh.extend(0..sections);
                 }
-                Module::SwapUsage => {
-                    system.refresh_memory_specifics(MemoryRefreshKind::new().with_swap());
-                    format_byte_usage(
-                        system.used_swap(),
-                        system.total_swap(),
-                        config.decimal_data_units,
-                    )
-                }
-                Module::CpuUsage => {
-                    system.refresh_cpu_specifics(CpuRefreshKind::new().with_cpu_usage());
-
-                    format!("{:.2}%", system.global_cpu_info().cpu_usage())
-                }
-                Module::ProcessCount => {
-                    system.refresh_processes_specifics(ProcessRefreshKind::new());
-                    format!("{}", system.processes().len())
-                }
-                Module::DiskUsage { name } => {
-                    if !disks_refreshed {
-                        disks.refresh_list();
-                        disks_refreshed = true;
+                tui::Key::ToggleTiming => {
+                    if let Some(tui) = &mut tui {
+                        tui.toggle_timing();
                     }
+                }
+            }
+        }
+        if SHUTDOWN.load(Ordering::Relaxed) {
+            if let Some(text) = throttle.flush() {
+                emit(&mut sink, &text);
+            }
+            if let Some(sink) = &mut sink {
+                sink.finish(FINISH_TIMEOUT);
+            }
+            info!("Exiting.");
+            return;
+        }
 
-                    if let Some(disk) = disks.iter().find(|x| x.name().to_string_lossy().eq(name)) {
-                        let used = disk.total_space() - disk.available_space();
-
-                        format_byte_usage(used, disk.total_space(), config.decimal_data_units)
-                    } else {
-                        "N/A".into()
+        // Read once, so all sections and the quiet hours check agree on the time.
+        let (now, local) = (clock.monotonic(), clock.now_local());
+        let config = config.lock().unwrap();
+        if backend != config.output {
+            backend.clone_from(&config.output);
+            sink = open(&backend);
+            health::set_backend(output::name(&backend));
+            // The new backend hasn't shown anything yet.
+            last_output.clear();
+        }
+        let mut interval = config.update_interval_ms.0;
+        // Otherwise only sections with intervals of their own are due.
+        let regular = now >= next_tick;
+
+        if frozen != runtime.lock().unwrap().frozen {
+            frozen = !frozen;
+            if frozen {
+                info!("Frozen, pausing evaluation.");
+                if config.output.wants_text() {
+                    let text = status_line(
+                        &config,
+                        &[Piece::Text(
+                            config.frozen_text.clone().unwrap_or_default(),
+                            None,
+                            false,
+                            None,
+                        )],
+                    );
+                    last_output.clone_from(&text);
+                    if let Some(text) = throttle.offer(text, Instant::now()) {
+                        emit(&mut sink, &text);
                     }
                 }
-                Module::DiskUsageTotal { include_removables } => {
-                    if !disks_refreshed {
-                        disks.refresh_list();
-                        disks_refreshed = true;
-                    }
+            } else {
+                info!("Thawed, resuming evaluation.");
+            }
+        }
 
-                    let mut total = 0;
-                    let mut used = 0;
+        if frozen {
+            drop(config);
+            wait(
+                &wake_rx,
+                &mut throttle,
+                &mut sink,
+                Instant::now() + interval,
+            );
+            next_tick = Instant::now();
+            continue;
+        }
 
-                    let mut filtered_disks: Vec<&Disk> = disks.iter().collect();
-                    if !include_removables {
-                        filtered_disks = disks.iter().filter(|x| !x.is_removable()).collect();
-                    }
+        if let Some(quiet_hours) = &config.quiet_hours {
+            let time = local.time();
+            if quiet != quiet_hours.contains(time) {
+                quiet = !quiet;
+                info!(
+                    "{} quiet hours.",
+                    if quiet { "Entering" } else { "Leaving" }
+                );
+            }
 
-                    for disk in filtered_disks {
-                        total += disk.total_space();
-                        used += disk.total_space() - disk.available_space();
-                    }
+            if quiet {
+                interval = config.quiet_update_interval_ms.0;
+            }
+            // Wake up right at the next boundary so the switch happens on time.
+            if let Some(boundary) = quiet_hours.until_next_boundary(time) {
+                interval = interval.min(boundary);
+            }
+        } else {
+            quiet = false;
+        }
+
+        if profiling != PROFILING.load(Ordering::Relaxed) {
+            profiling = !profiling;
+            info!(
+                "Section profiling {}.",
+                if profiling { "enabled" } else { "disabled" }
+            );
+        }
 
-                    format_byte_usage(used, total, config.decimal_data_units)
+        let pieces = {
+            let mut runtime = runtime.lock().unwrap();
+            let runtime = &mut *runtime;
+            // Cached output belongs to the old section list.
+            if reloads != runtime.reloads.successful {
+                reloads = runtime.reloads.successful;
+                scheduler = TickScheduler::new(config.section_intervals());
+                provider.spare_disks(config.spared_disks());
+                provider.want_inodes(config.wants_inodes());
+                order = config.evaluation_order();
+                throttle.set_rate(config.max_emissions_per_sec);
+                install_reset_signals(&config);
+                runtime.values.clear();
+                runtime.readings.clear();
+                runtime.urgency.reset_thresholds();
+                evaluated.clear();
+                if let Some(tui) = &mut tui {
+                    tui.reset();
                 }
+            }
+            runtime.urgency.set_grace(config.startup_grace_secs.0);
+            for index in std::mem::take(&mut runtime.refresh) {
+                scheduler.refresh(index);
+            }
+            let reset = RESET_SIGNALS.swap(0, Ordering::Relaxed);
+            for (signal, interface) in config.reset_signals() {
+                if reset & 1 << signal != 0 {
+                    runtime.rates.reset_totals(interface);
+                    info!("Zeroed the session totals of {interface}.");
+                }
+            }
+
+            let mut ctx = Context {
+                section: 0,
+                provider: &mut *provider,
+                timers: &runtime.timers,
+                extremes: &mut runtime.extremes,
+                validity: &mut runtime.validity,
+                rates: &mut runtime.rates,
+                values: &mut runtime.values,
+                readings: &mut runtime.readings,
+                urgency: &mut runtime.urgency,
+                now,
+                local,
             };
+            ctx.provider.begin_tick();
+
+            let priorities: Vec<u8> = config.sections.iter().map(|x| x.priority()).collect();
+            let budget = config.max_tick_budget_ms.map(|x| x.0);
+            let pieces = scheduler.tick(&order, &priorities, budget, now, regular, |index| {
+                ctx.section = index;
+                let started = Instant::now();
+                let mut piece = render::render_section(&config.sections[index], &config, &mut ctx);
+                let elapsed = started.elapsed();
+                notify::section_done(index);
+                evaluated.insert(index, (now, local));
+                if let Some(tui) = &mut tui {
+                    tui.evaluated(index, elapsed);
+                }
+
+                if let (true, Piece::Text(output, ..)) = (profiling, &mut piece) {
+                    output.push_str(&format!(" ⟨{:.1}ms⟩", elapsed.as_secs_f64() * 1000.0));
+                }
 
-            if !output.is_empty() {
-                output.push_str(&config.separator);
+                (piece, elapsed)
+            });
+            runtime.snapshots =
+                snapshot::take(&config, &pieces, &runtime.readings, &evaluated, now);
+            pieces
+        };
+
+        if config.output.wants_text() {
+            let output = status_line(&config, &pieces);
+
+            // Most updates change nothing, e.g. a clock showing minutes. Failed updates are
+            // retried, so e.g. a FIFO that gets a reader is caught up.
+            let heartbeat = config.always_publish || health::status().1 > 0;
+            if output != last_output || (regular && heartbeat) {
+                last_output.clone_from(&output);
+                if let Some(text) = throttle.offer(output, Instant::now()) {
+                    emit(&mut sink, &text);
+                }
             }
+        }
+        if let Some(tui) = &tui {
+            let line = match config.output {
+                // i3bar's blocks are JSON, shown the way other outputs would.
+                OutputBackend::I3bar { .. } => bar::assemble(&pieces, &config.separator),
+                _ => status_line(&config, &pieces),
+            };
+            tui.draw(&line, &runtime.lock().unwrap(), local);
+        }
+        drop(config);
 
-            if let Some(x) = &section.decoration.before {
-                output.push_str(x);
+        // Scheduled from the previous tick rather than from now, so evaluation time doesn't
+        // add up and sub-second clocks don't skip.
+        let now = Instant::now();
+        if regular {
+            next_tick += interval;
+            if next_tick <= now {
+                next_tick = now + interval;
             }
+        }
+        let until = scheduler
+            .next_due(now)
+            .map_or(next_tick, |x| x.min(next_tick));
+        if wait(&wake_rx, &mut throttle, &mut sink, until) {
+            next_tick = Instant::now();
+        }
+    }
+}
 
-            output.push_str(&module_out);
+/// Sleeps until `until`, emitting output held back by `throttle` once it's due.
+/// Returns `true` if woken early through `wake`.
+fn wait(
+    wake: &Receiver<()>,
+    throttle: &mut Throttle,
+    sink: &mut Option<Box<dyn Output>>,
+    until: Instant,
+) -> bool {
+    loop {
+        let deadline = throttle.deadline().map_or(until, |x| x.min(until));
+        if wake
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .is_ok()
+        {
+            return true;
+        }
 
-            if let Some(x) = &section.decoration.after {
-                output.push_str(x);
-            }
+        if let Some(text) = throttle.due(Instant::now()) {
+            emit(sink, &text);
         }
+        if Instant::now() >= until {
+            return false;
+        }
+    }
+}
 
-        if let Err(e) = Command::new("xsetroot").arg("-name").arg(output).output() {
-            error!("Unable to set root window name: {e}");
+/// Blocks SIGTERM and SIGINT and waits for them on a thread of their own, which sets
+/// [`SHUTDOWN`] and wakes the main loop so it can flush held back output before exiting.
+/// Threads started afterwards inherit the blocked signals.
+fn spawn_signal_waiter(wake: Sender<()>) {
+    let mut signals = MaybeUninit::<libc::sigset_t>::uninit();
+    // SAFETY: `signals` is initialized by sigemptyset before anything else reads it.
+    let signals = unsafe {
+        libc::sigemptyset(signals.as_mut_ptr());
+        libc::sigaddset(signals.as_mut_ptr(), libc::SIGTERM);
+        libc::sigaddset(signals.as_mut_ptr(), libc::SIGINT);
+        if libc::pthread_sigmask(libc::SIG_BLOCK, signals.as_ptr(), std::ptr::null_mut()) != 0 {
+            error!("Unable to block SIGTERM and SIGINT, held back output is lost on exit.");
+            return;
+        }
+        signals.assume_init()
+    };
+
+    thread::spawn(move || {
+        let mut signal = 0;
+        // SAFETY: `signals` is an initialized set and `signal` outlives the call.
+        if unsafe { libc::sigwait(&signals, &mut signal) } == 0 {
+            SHUTDOWN.store(true, Ordering::Relaxed);
+            let _ = wake.send(());
+        }
+    });
+}
+
+/// Installs the handler for the `reset_signal`s of `config`. Those a reload drops keep it, their
+/// default action would end stringbar.
+fn install_reset_signals(config: &Config) {
+    for (signal, _) in config.reset_signals() {
+        let signal = libc::SIGRTMIN() + i32::from(signal);
+        // SAFETY: the handler only touches an atomic, which is async-signal-safe, and reads
+        // SIGRTMIN, which glibc keeps in a variable set up before main.
+        if unsafe {
+            libc::signal(
+                signal,
+                reset_totals as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            )
+        } == libc::SIG_ERR
+        {
+            error!("Unable to install a handler for signal {signal}, it can't reset totals.");
         }
-        drop(config);
-        thread::sleep(Duration::from_millis(interval));
     }
 }
 
-fn format_byte_usage(used: u64, total: u64, si_units: bool) -> String {
-    type System = bittenhumans::consts::System;
+/// Logs an error that stops stringbar during startup and exits with its code.
+fn fail(error: StringbarError) -> ! {
+    error!("{error}");
+    std::process::exit(error.exit_code());
+}
 
-    let formatter = ByteSizeFormatter::fit(
-        total,
-        if si_units {
-            System::Decimal
-        } else {
-            System::Binary
-        },
-    );
+/// What the output shows for `pieces`: a line of i3bar protocol blocks, one per section, or
+/// the pieces joined with `separator`.
+fn status_line(config: &Config, pieces: &[Piece]) -> String {
+    let shrunk;
+    let pieces = match config.output {
+        // i3bar shortens blocks to their short_text itself.
+        OutputBackend::I3bar { .. } => pieces,
+        _ => {
+            shrunk = shrink(config, pieces);
+            &shrunk
+        }
+    };
+    let names: Vec<_> = config.sections.iter().map(|x| x.id.as_deref()).collect();
+    let reversed;
+    let (pieces, names) = if config.output.reverse_order() {
+        reversed = bar::reverse(pieces, &names);
+        (&reversed.0[..], &reversed.1[..])
+    } else {
+        (pieces, &names[..])
+    };
+
+    match config.output {
+        OutputBackend::I3bar { .. } => bar::blocks(pieces, names),
+        _ => config
+            .output
+            .post_process(bar::assemble(pieces, &config.separator)),
+    }
+}
 
-    format!(
-        "{}/{}",
-        formatter.format(used).split(" ").collect::<Vec<_>>()[0],
-        formatter.format(total)
-    )
+/// `pieces` with as many sections switched to their `compact_template` as it takes for the
+/// line to fit the output's `post`, which cuts it if that's not enough.
+fn shrink(config: &Config, pieces: &[Piece]) -> Vec<Piece> {
+    let order = config.shrink_order();
+    let reverse = config.output.reverse_order();
+    let mut pieces = pieces.to_vec();
+    bar::shrink(&mut pieces, &order, |pieces| {
+        let line = match reverse {
+            true => bar::assemble(
+                &bar::reverse(pieces, &vec![(); pieces.len()]).0,
+                &config.separator,
+            ),
+            false => bar::assemble(pieces, &config.separator),
+        };
+        config.output.fits(&line)
+    });
+    pieces
+}
+
+fn emit(sink: &mut Option<Box<dyn Output>>, text: &str) {
+    if let Some(sink) = sink {
+        let result = sink.publish(text);
+        health::record(&result, sink.reconnecting());
+        health::set_dropped(sink.dropped());
+        health::set_truncated(sink.truncated());
+        if let Err(e) = result {
+            error!("{e}");
+            notify::error("stringbar: output failed", &e);
+        }
+    }
+    history::push(text);
 }
@@ -0,0 +1,400 @@
+//! The track an MPD server is playing, over its plain text protocol.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use crate::text;
+
+pub const DEFAULT_HOST: &str = "localhost";
+pub const DEFAULT_PORT: u16 = 6600;
+pub const DEFAULT_FORMAT: &str = "{artist} - {title}";
+/// MPD usually runs on the same machine, anything slower would hold up the bar.
+const TIMEOUT: Duration = Duration::from_millis(500);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Left over at the ends when a placeholder is empty, e.g. the artist of a radio stream.
+const DANGLING: &[char] = &[' ', '-', '–', '—', '|', '·', ':', '/'];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum State {
+    Play,
+    Pause,
+    Stop,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Status {
+    pub state: State,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub elapsed: Option<Duration>,
+    pub duration: Option<Duration>,
+}
+
+impl Status {
+    /// Builds the status from the replies to `status` and `currentsong`.
+    fn parse(status: &[(String, String)], song: &[(String, String)]) -> Result<Self, String> {
+        let get = |pairs: &[(String, String)], key: &str| {
+            pairs
+                .iter()
+                .find(|(x, _)| x == key)
+                .map(|(_, value)| value.clone())
+        };
+        let seconds = |value: Option<String>| {
+            value
+                .and_then(|x| x.parse::<f64>().ok())
+                .filter(|x| x.is_finite() && *x >= 0.0)
+                .map(Duration::from_secs_f64)
+        };
+
+        let state = match get(status, "state").as_deref() {
+            Some("play") => State::Play,
+            Some("pause") => State::Pause,
+            Some("stop") => State::Stop,
+            x => return Err(format!("unknown state {x:?}")),
+        };
+        Ok(Self {
+            state,
+            artist: get(song, "Artist"),
+            title: get(song, "Title"),
+            elapsed: seconds(get(status, "elapsed")),
+            duration: seconds(get(status, "duration").or_else(|| get(song, "Time"))),
+        })
+    }
+
+    /// Replaces `{artist}`, `{title}`, `{elapsed}`, `{duration}` and `{state}` in `format`.
+    /// Missing tags are left empty and separators they leave at either end are trimmed, so
+    /// "{artist} - {title}" shows just the title of a stream without an artist. Titles longer
+    /// than `max_len` characters are cut with "…".
+    pub fn format(&self, format: &str, max_len: Option<usize>) -> String {
+        let title = match (&self.title, max_len) {
+            (Some(title), Some(max_len)) => text::truncate(title, max_len),
+            (title, _) => title.clone().unwrap_or_default(),
+        };
+        let state = match self.state {
+            State::Play => "playing",
+            State::Pause => "paused",
+            State::Stop => "stopped",
+        };
+
+        format
+            .replace("{artist}", self.artist.as_deref().unwrap_or(""))
+            .replace("{title}", &title)
+            .replace(
+                "{elapsed}",
+                &self.elapsed.map(format_time).unwrap_or_default(),
+            )
+            .replace(
+                "{duration}",
+                &self.duration.map(format_time).unwrap_or_default(),
+            )
+            .replace("{state}", state)
+            .trim_matches(DANGLING)
+            .into()
+    }
+}
+
+/// Minutes and seconds like players show them, e.g. "3:07" or "1:02:03".
+fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{hours}:{:02}:{:02}", seconds / 60 % 60, seconds % 60),
+    }
+}
+
+/// A connection to one server, kept open across updates.
+pub struct Client {
+    host: String,
+    port: u16,
+    stream: Option<BufReader<TcpStream>>,
+    /// After a failure, no connection is attempted before this.
+    retry_at: Option<Instant>,
+    backoff: Duration,
+    last_error: String,
+}
+
+impl Client {
+    pub fn new(host: &str, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            stream: None,
+            retry_at: None,
+            backoff: MIN_BACKOFF,
+            last_error: String::new(),
+        }
+    }
+
+    /// The server's current status. Connects if needed, waiting twice as long after every
+    /// failed attempt, up to a minute. Errors while waiting repeat the last one.
+    pub fn status(&mut self, now: Instant) -> Result<Status, String> {
+        if self.stream.is_none() {
+            if self.retry_at.is_some_and(|x| now < x) {
+                return Err(self.last_error.clone());
+            }
+            match self.connect() {
+                Ok(stream) => self.stream = Some(stream),
+                Err(e) => return Err(self.failed(now, e)),
+            }
+        }
+
+        let stream = self.stream.as_mut().unwrap();
+        match command(stream, "status").and_then(|status| {
+            let song = command(stream, "currentsong")?;
+            Status::parse(&status, &song)
+        }) {
+            Ok(status) => {
+                self.retry_at = None;
+                self.backoff = MIN_BACKOFF;
+                Ok(status)
+            }
+            Err(e) => Err(self.failed(now, e)),
+        }
+    }
+
+    fn connect(&self) -> Result<BufReader<TcpStream>, String> {
+        let address = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|e| format!("{}: {e}", self.host))?
+            .next()
+            .ok_or_else(|| format!("{}: no address", self.host))?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT).map_err(|e| e.to_string())?;
+        stream
+            .set_read_timeout(Some(TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+
+        let mut stream = BufReader::new(stream);
+        let mut greeting = String::new();
+        stream.read_line(&mut greeting).map_err(|e| e.to_string())?;
+        if !greeting.starts_with("OK MPD ") {
+            return Err(format!("not an MPD server: {:?}", greeting.trim()));
+        }
+        Ok(stream)
+    }
+
+    /// Drops the connection and schedules the next attempt.
+    fn failed(&mut self, now: Instant, error: String) -> String {
+        self.stream = None;
+        self.retry_at = Some(now + self.backoff);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        self.last_error = format!("{}:{}: {error}", self.host, self.port);
+        self.last_error.clone()
+    }
+}
+
+/// Sends `command` and reads the `key: value` lines of the reply.
+fn command(
+    stream: &mut BufReader<TcpStream>,
+    command: &str,
+) -> Result<Vec<(String, String)>, String> {
+    stream
+        .get_mut()
+        .write_all(format!("{command}\n").as_bytes())
+        .map_err(|e| e.to_string())?;
+
+    let mut pairs = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if stream.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("connection closed".into());
+        }
+        let line = line.trim_end_matches('\n');
+        if line == "OK" {
+            return Ok(pairs);
+        }
+        if let Some(error) = line.strip_prefix("ACK ") {
+            return Err(error.into());
+        }
+        if let Some((key, value)) = line.split_once(": ") {
+            pairs.push((key.into(), value.into()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    fn status(artist: Option<&str>, title: Option<&str>) -> Status {
+        Status {
+            state: State::Play,
+            artist: artist.map(Into::into),
+            title: title.map(Into::into),
+            elapsed: Some(Duration::from_secs(187)),
+            duration: Some(Duration::from_secs(3723)),
+        }
+    }
+
+    #[test]
+    fn placeholders() {
+        let song = status(Some("Daft Punk"), Some("Harder, Better, Faster, Stronger"));
+        let stream = Status {
+            duration: None,
+            ..status(None, Some("Radio Paradise - Main Mix"))
+        };
+        let untagged = Status {
+            state: State::Pause,
+            elapsed: None,
+            ..status(None, None)
+        };
+
+        let cases = [
+            (
+                &song,
+                DEFAULT_FORMAT,
+                None,
+                "Daft Punk - Harder, Better, Faster, Stronger",
+            ),
+            (
+                &song,
+                "{title} [{elapsed}/{duration}]",
+                Some(10),
+                "Harder, B… [3:07/1:02:03]",
+            ),
+            (&song, "{state}: {artist}", None, "playing: Daft Punk"),
+            // No artist on a stream.
+            (&stream, DEFAULT_FORMAT, None, "Radio Paradise - Main Mix"),
+            (
+                &stream,
+                "{title} {elapsed}/{duration}",
+                None,
+                "Radio Paradise - Main Mix 3:07",
+            ),
+            (&untagged, DEFAULT_FORMAT, None, ""),
+            (&untagged, "{state} {artist}", None, "paused"),
+        ];
+        for (status, format, max_len, expected) in cases {
+            assert_eq!(expected, status.format(format, max_len), "{format}");
+        }
+    }
+
+    #[test]
+    fn times() {
+        let cases = [
+            (0, "0:00"),
+            (59, "0:59"),
+            (187, "3:07"),
+            (3600, "1:00:00"),
+            (3723, "1:02:03"),
+        ];
+        for (seconds, expected) in cases {
+            assert_eq!(expected, format_time(Duration::from_secs(seconds)));
+        }
+    }
+
+    fn pairs(text: &str) -> Vec<(String, String)> {
+        text.lines()
+            .filter_map(|x| x.split_once(": "))
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect()
+    }
+
+    #[test]
+    fn parsing() {
+        let status = pairs("volume: 50\nstate: play\nelapsed: 12.345\nduration: 245.123");
+        let song = pairs("file: a.flac\nArtist: Someone\nTitle: Something\nTime: 245");
+        assert_eq!(
+            Ok(Status {
+                state: State::Play,
+                artist: Some("Someone".into()),
+                title: Some("Something".into()),
+                elapsed: Some(Duration::from_secs_f64(12.345)),
+                duration: Some(Duration::from_secs_f64(245.123)),
+            }),
+            Status::parse(&status, &song)
+        );
+
+        // Older servers only report the song's length in whole seconds.
+        let status = pairs("state: pause\nelapsed: 1.0");
+        assert_eq!(
+            Some(Duration::from_secs(245)),
+            Status::parse(&status, &song).unwrap().duration
+        );
+
+        assert_eq!(
+            Ok(State::Stop),
+            Status::parse(&pairs("state: stop"), &[]).map(|x| x.state)
+        );
+        assert_eq!(Err("unknown state None".into()), Status::parse(&[], &[]));
+    }
+
+    /// Accepts `connections` connections, answering each with `replies` and then closing it.
+    fn fake_server(connections: usize, replies: &'static [&'static str]) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for _ in 0..connections {
+                let (stream, _) = listener.accept().unwrap();
+                let mut stream = BufReader::new(stream);
+                stream.get_mut().write_all(b"OK MPD 0.23.5\n").unwrap();
+                for reply in replies {
+                    let mut command = String::new();
+                    if stream.read_line(&mut command).unwrap() == 0 {
+                        break;
+                    }
+                    stream.get_mut().write_all(reply.as_bytes()).unwrap();
+                }
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn reconnecting() {
+        let port = fake_server(
+            2,
+            &[
+                "state: play\nelapsed: 3.5\nOK\n",
+                "Artist: Someone\nTitle: Something\nOK\n",
+                "state: play\nelapsed: 4.5\nOK\n",
+                "Title: Something\nOK\n",
+            ],
+        );
+        let mut client = Client::new("127.0.0.1", port);
+        let start = Instant::now();
+
+        // Both updates use the same connection.
+        let status = client.status(start).unwrap();
+        assert_eq!(Some("Someone".into()), status.artist);
+        assert_eq!(None, client.status(start).unwrap().artist);
+
+        // The server closes it, the next attempt waits a second, then twice as long.
+        let error = client.status(start).unwrap_err();
+        assert_eq!(error, client.status(start).unwrap_err());
+        assert!(client.status(start + MIN_BACKOFF).is_ok());
+        assert!(client.status(start + MIN_BACKOFF).is_ok());
+        assert!(client.status(start + MIN_BACKOFF).is_err());
+        assert!(client.status(start + MIN_BACKOFF * 2).is_err());
+        assert_eq!(MIN_BACKOFF * 4, client.backoff);
+    }
+
+    #[test]
+    fn errors() {
+        let port = fake_server(1, &["ACK [5@0] {status} unknown command\n"]);
+        let mut client = Client::new("127.0.0.1", port);
+        assert_eq!(
+            Err(format!(
+                "127.0.0.1:{port}: [5@0] {{status}} unknown command"
+            )),
+            client.status(Instant::now())
+        );
+
+        // Nothing listens on the port once the listener is dropped.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        assert!(Client::new("127.0.0.1", port)
+            .status(Instant::now())
+            .is_err());
+    }
+}
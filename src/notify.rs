@@ -0,0 +1,281 @@
+//! Desktop notifications for errors that would otherwise only show up in the log, enabled with
+//! `error_notifications`.
+//!
+//! Notifications are handed to a thread of its own that talks to the notification daemon, so a
+//! slow or missing daemon never holds up updates. Without one, the thread logs once at debug
+//! level and drops everything after.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{mpsc, LazyLock, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+use crate::{dbus, text, warn::RateLimiter};
+
+/// The same error is shown at most this often.
+const PERIOD: Duration = Duration::from_secs(600);
+/// A section counts as failing once its warning has come up on every update for this long.
+const PERSISTENT_AFTER: Duration = Duration::from_secs(60);
+/// Notification popups only have room for a few lines.
+const MAX_BODY_LEN: usize = 200;
+
+static NOTIFIER: LazyLock<Mutex<Notifier>> =
+    LazyLock::new(|| Mutex::new(Notifier::new(background_sender())));
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+}
+
+pub struct Notifier {
+    enabled: bool,
+    limiter: RateLimiter,
+    send: Box<dyn FnMut(Notification) + Send>,
+    /// When each section warning still coming up was first seen.
+    failing_since: HashMap<String, Instant>,
+    /// Section warnings seen while rendering sections that aren't done yet.
+    seen: HashSet<String>,
+}
+
+impl Notifier {
+    fn new(send: impl FnMut(Notification) + Send + 'static) -> Self {
+        Self {
+            enabled: false,
+            limiter: RateLimiter::default(),
+            send: Box::new(send),
+            failing_since: HashMap::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Sends a notification unless the same one went out within the last ten minutes.
+    fn error(&mut self, summary: &str, body: &str, now: Instant) {
+        if !self.enabled {
+            return;
+        }
+
+        let body = text::truncate(body.trim(), MAX_BODY_LEN);
+        if self
+            .limiter
+            .should_emit(&format!("{summary}\n{body}"), Some(PERIOD), now)
+        {
+            (self.send)(Notification {
+                summary: summary.into(),
+                body,
+            });
+        }
+    }
+
+    /// Forgets the warnings of the section at `index` that didn't come up while it was
+    /// rendered. Sections that weren't rendered, e.g. because of their `interval_ms`, keep
+    /// theirs.
+    fn section_done(&mut self, index: usize) {
+        let prefix = format!("{index}:");
+        let seen = &mut self.seen;
+        self.failing_since
+            .retain(|key, _| !key.starts_with(&prefix) || seen.contains(key));
+        seen.retain(|key| !key.starts_with(&prefix));
+    }
+
+    fn section_warning(&mut self, key: &str, message: impl FnOnce() -> String, now: Instant) {
+        if !self.enabled {
+            return;
+        }
+
+        self.seen.insert(key.into());
+        let since = *self.failing_since.entry(key.into()).or_insert(now);
+        if now.saturating_duration_since(since) >= PERSISTENT_AFTER {
+            self.error("stringbar: section failing", &message(), now);
+        }
+    }
+}
+
+/// Starts the sending thread with the first notification.
+fn background_sender() -> impl FnMut(Notification) + Send {
+    let mut sender: Option<mpsc::Sender<Notification>> = None;
+    move |notification| {
+        let sender = sender.get_or_insert_with(|| {
+            let (tx, rx) = mpsc::channel::<Notification>();
+            thread::spawn(move || {
+                let mut connection = None;
+                for Notification { summary, body } in rx {
+                    let sent = match &mut connection {
+                        Some(x) => Ok(x),
+                        None => dbus::Connection::session().map(|x| connection.insert(x)),
+                    }
+                    .and_then(|x| x.notify(&summary, &body));
+                    if let Err(e) = sent {
+                        debug!("Unable to show desktop notifications, not trying again: {e}");
+                        return;
+                    }
+                }
+            });
+            tx
+        });
+        // Fails once the thread gave up.
+        let _ = sender.send(notification);
+    }
+}
+
+pub fn set_enabled(enabled: bool) {
+    NOTIFIER.lock().unwrap().enabled = enabled;
+}
+
+/// Shows `body` as a desktop notification, at most once every ten minutes for the same text.
+pub fn error(summary: &str, body: &str) {
+    NOTIFIER
+        .lock()
+        .unwrap()
+        .error(summary, body, Instant::now());
+}
+
+/// Called after rendering the section at `index`.
+pub fn section_done(index: usize) {
+    NOTIFIER.lock().unwrap().section_done(index);
+}
+
+/// Called by the warning macros. Keys starting with a section index mark that section as
+/// failing, once that has lasted a minute `message` is shown.
+pub fn section_warning(key: &str, message: impl FnOnce() -> String) {
+    if key
+        .split(':')
+        .next()
+        .is_some_and(|x| x.parse::<usize>().is_ok())
+    {
+        NOTIFIER
+            .lock()
+            .unwrap()
+            .section_warning(key, message, Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notifier() -> (Notifier, mpsc::Receiver<Notification>) {
+        let (tx, rx) = mpsc::channel();
+        let mut notifier = Notifier::new(move |x| tx.send(x).unwrap());
+        notifier.enabled = true;
+        (notifier, rx)
+    }
+
+    #[test]
+    fn rate_limiting() {
+        let (mut notifier, sent) = notifier();
+        let start = Instant::now();
+        let minute = Duration::from_secs(60);
+
+        notifier.error("config", "expected ')'", start);
+        notifier.error("config", "expected ')'", start + minute);
+        // A different error goes out right away.
+        notifier.error("config", "unknown variant Foo", start + minute);
+        notifier.error("output", "expected ')'", start + minute);
+        notifier.error(
+            "config",
+            "expected ')'",
+            start + PERIOD - Duration::from_secs(1),
+        );
+        notifier.error("config", "expected ')'", start + PERIOD);
+
+        let bodies: Vec<_> = sent
+            .try_iter()
+            .map(|x| format!("{}: {}", x.summary, x.body))
+            .collect();
+        assert_eq!(
+            vec![
+                "config: expected ')'",
+                "config: unknown variant Foo",
+                "output: expected ')'",
+                "config: expected ')'",
+            ],
+            bodies
+        );
+    }
+
+    #[test]
+    fn disabled() {
+        let (mut notifier, sent) = notifier();
+        notifier.enabled = false;
+        let now = Instant::now();
+        notifier.error("config", "expected ')'", now);
+        notifier.section_warning("0:disk", || "disk / not found".into(), now);
+        notifier.section_warning("0:disk", || "disk / not found".into(), now + PERIOD);
+        assert_eq!(0, sent.try_iter().count());
+    }
+
+    #[test]
+    fn truncation() {
+        let (mut notifier, sent) = notifier();
+        notifier.error(
+            "output",
+            &format!("  {}\n", "x".repeat(500)),
+            Instant::now(),
+        );
+        let body = sent.try_recv().unwrap().body;
+        assert_eq!(MAX_BODY_LEN, body.chars().count());
+        assert!(body.ends_with("x…"));
+    }
+
+    #[test]
+    fn persistent_section_failures() {
+        let (mut notifier, sent) = notifier();
+        let start = Instant::now();
+        let update = |notifier: &mut Notifier, seconds, warning: bool| {
+            if warning {
+                notifier.section_warning(
+                    "3:disk:/mnt",
+                    || "Section 4: disk /mnt not found".into(),
+                    start + Duration::from_secs(seconds),
+                );
+            }
+            notifier.section_done(3);
+        };
+
+        for second in 0..60 {
+            update(&mut notifier, second, true);
+        }
+        assert_eq!(0, sent.try_iter().count());
+
+        // Recovered for an update, the minute starts over.
+        update(&mut notifier, 60, false);
+        for second in 61..121 {
+            update(&mut notifier, second, true);
+        }
+        assert_eq!(0, sent.try_iter().count());
+        update(&mut notifier, 121, true);
+        update(&mut notifier, 122, true);
+        assert_eq!(
+            vec![Notification {
+                summary: "stringbar: section failing".into(),
+                body: "Section 4: disk /mnt not found".into(),
+            }],
+            sent.try_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn interval_section_failures() {
+        let (mut notifier, sent) = notifier();
+        let start = Instant::now();
+        let warning = || "Section 1: unable to read Maildir".to_string();
+
+        // The section is rendered every 10s while others are every second.
+        for second in 0..=60 {
+            if second % 10 == 0 {
+                notifier.section_warning("0:maildir", warning, start + Duration::from_secs(second));
+                notifier.section_done(0);
+            }
+            notifier.section_done(1);
+        }
+        assert_eq!(
+            vec![warning()],
+            sent.try_iter().map(|x| x.body).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! `stringbar render`: prints some sections of the config once and exits, e.g. for a shell
+//! prompt. Meant to be fast, so sections that need two samples a while apart or run programs
+//! are skipped unless asked for.
+
+use std::{collections::HashMap, path::Path, thread};
+
+use sysinfo::MINIMUM_CPU_UPDATE_INTERVAL;
+
+use crate::{
+    bar,
+    clock::{Clock, SystemClock},
+    config::{load_config, Config, Module, SortKey},
+    extremes::Extremes,
+    provider::{SysinfoProvider, SystemProvider},
+    rates::Rates,
+    render::{self, Context},
+    timer::Timers,
+    urgency::Urgency,
+    validity::Validity,
+};
+
+pub const USAGE: &str =
+    "usage: stringbar render [--sections <id>,...] [--separator <text>] [--fast]";
+
+#[derive(Default, PartialEq, Debug)]
+pub struct Options {
+    /// Ids of the sections to show, all of them if `None`.
+    pub sections: Option<Vec<String>>,
+    /// Overrides the config's separator.
+    pub separator: Option<String>,
+    /// Accept the first CPU sample instead of waiting for a second one.
+    pub fast: bool,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--sections" => {
+                    let ids = args.next().ok_or(USAGE)?;
+                    options.sections = Some(ids.split(',').map(|x| x.trim().into()).collect());
+                }
+                "--separator" => options.separator = Some(args.next().ok_or(USAGE)?.clone()),
+                "--fast" => options.fast = true,
+                _ => return Err(format!("unknown argument {arg}\n{USAGE}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+pub fn run(args: &[String], config_file_path: &Path) -> Result<(), String> {
+    let options = Options::parse(args)?;
+    let (config, _) = load_config(config_file_path, false).map_err(|e| e.to_string())?;
+
+    let mut provider = SysinfoProvider::new();
+    provider.want_inodes(config.wants_inodes());
+    println!("{}", render(&config, &mut provider, &options)?);
+
+    Ok(())
+}
+
+/// Whether a section is left out unless named. Network rates need two samples, commands can
+/// take up to their timeout and the public IP is fetched in the background.
+fn slow(module: &Module) -> bool {
+    matches!(
+        module,
+        Module::NetworkThroughput { .. } | Module::Command { .. } | Module::PublicIp { .. }
+    )
+}
+
+/// Indices of the sections to show, in the order they were named or else in config order.
+fn selection(config: &Config, options: &Options) -> Result<Vec<usize>, String> {
+    let Some(ids) = &options.sections else {
+        return Ok((0..config.sections.len())
+            .filter(|x| !slow(&config.sections[*x].module))
+            .collect());
+    };
+
+    let mut selected = Vec::new();
+    for id in ids {
+        let index = config
+            .sections
+            .iter()
+            .position(|x| x.id.as_ref() == Some(id))
+            .ok_or_else(|| format!("no section has the id {id:?}"))?;
+        if let Module::NetworkThroughput { .. } = config.sections[index].module {
+            return Err(format!(
+                "section {id} shows network rates, which need more than one sample"
+            ));
+        }
+        if !selected.contains(&index) {
+            selected.push(index);
+        }
+    }
+
+    Ok(selected)
+}
+
+/// Renders the selected sections, along with the ones their expressions read.
+pub fn render(
+    config: &Config,
+    provider: &mut dyn SystemProvider,
+    options: &Options,
+) -> Result<String, String> {
+    let selected = selection(config, options)?;
+    let dependencies = config.dependencies()?;
+    let mut needed = vec![false; config.sections.len()];
+    let mut pending = selected.clone();
+    while let Some(index) = pending.pop() {
+        if !needed[index] {
+            needed[index] = true;
+            pending.extend(&dependencies[index]);
+        }
+    }
+
+    let cpu = needed.iter().zip(&config.sections).any(|(needed, x)| {
+        *needed
+            && matches!(
+                x.module,
+                Module::CpuUsage
+                    | Module::TopProcess {
+                        by: SortKey::Cpu,
+                        ..
+                    }
+            )
+    });
+    if cpu && !options.fast {
+        // CPU usage is the difference between two samples.
+        provider.cpu_usage();
+        provider.processes();
+        thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+    }
+
+    let clock = SystemClock;
+    let mut ctx = Context {
+        section: 0,
+        provider,
+        timers: &Timers::new(),
+        extremes: &mut Extremes::default(),
+        validity: &mut Validity::default(),
+        rates: &mut Rates::default(),
+        values: &mut HashMap::new(),
+        readings: &mut HashMap::new(),
+        urgency: &mut Urgency::default(),
+        now: clock.monotonic(),
+        local: clock.now_local(),
+    };
+    ctx.provider.begin_tick();
+
+    let mut pieces = HashMap::new();
+    for index in config.evaluation_order() {
+        if needed[index] {
+            ctx.section = index;
+            pieces.insert(
+                index,
+                render::render_section(&config.sections[index], config, &mut ctx),
+            );
+        }
+    }
+    let pieces: Vec<_> = selected.iter().filter_map(|x| pieces.remove(x)).collect();
+
+    Ok(bar::assemble(
+        &pieces,
+        options.separator.as_ref().unwrap_or(&config.separator),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::{config::Section, provider::FakeProvider, unit::Unit};
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn arguments() {
+        assert_eq!(Ok(Options::default()), Options::parse(&[]));
+        assert_eq!(
+            Ok(Options {
+                sections: Some(vec!["mem".into(), "cpu".into()]),
+                separator: Some(" ".into()),
+                fast: true,
+            }),
+            Options::parse(&args(&[
+                "--sections",
+                "mem, cpu",
+                "--separator",
+                " ",
+                "--fast"
+            ]))
+        );
+        assert_eq!(Err(USAGE.into()), Options::parse(&args(&["--sections"])));
+        assert_eq!(
+            Err(format!("unknown argument --slow\n{USAGE}")),
+            Options::parse(&args(&["--slow"]))
+        );
+    }
+
+    fn config() -> Config {
+        let named = |id: &str, module: Module| Section {
+            id: Some(id.into()),
+            ..module.into()
+        };
+        Config {
+            separator: " | ".into(),
+            sections: vec![
+                named("mem", Module::MemoryUsage { basis: None }),
+                named("cpu", Module::CpuUsage),
+                named(
+                    "net",
+                    Module::NetworkThroughput {
+                        interface: "eth0".into(),
+                        average_over_s: None,
+                        peak_hold_s: None,
+                        min_unit: None,
+                        max_unit: None,
+                        show_totals: false,
+                        reset_signal: None,
+                    },
+                ),
+                named(
+                    "hello",
+                    Module::Command {
+                        command: "echo".into(),
+                        args: vec!["hello".into()],
+                        timeout_ms: None,
+                        fallback: None,
+                        join_lines: None,
+                    },
+                ),
+                named(
+                    "mem_and_cpu",
+                    Module::Derived {
+                        expression: "mem.value + cpu.value".into(),
+                        unit: Unit::Count,
+                    },
+                ),
+                Module::ProcessCount.into(),
+            ],
+            ..Config::default()
+        }
+    }
+
+    fn provider() -> FakeProvider {
+        let mut provider = FakeProvider::default();
+        provider
+            .set_memory(GIB, 4 * GIB)
+            .set_cpu_usage(12.5)
+            .set_process_count(210)
+            .set_network_rates("eth0", 1000.0, 1000.0);
+        provider
+    }
+
+    #[test]
+    fn sections() {
+        let render = |options: &[&str]| {
+            let options = Options::parse(&args(options)).unwrap();
+            render(&config(), &mut provider(), &options)
+        };
+
+        assert_eq!(
+            Ok("1.00/4.00 GiB | 12.5% | 37.50 | 210".into()),
+            render(&[])
+        );
+        assert_eq!(
+            Ok("12.5% 1.00/4.00 GiB".into()),
+            render(&["--sections", "cpu,mem,cpu", "--separator", " "])
+        );
+        // Without its dependencies being shown.
+        assert_eq!(
+            Ok("37.50".into()),
+            render(&["--sections", "mem_and_cpu", "--fast"])
+        );
+        assert_eq!(Ok("hello".into()), render(&["--sections", "hello"]));
+
+        assert_eq!(
+            Err("section net shows network rates, which need more than one sample".into()),
+            render(&["--sections", "net"])
+        );
+        assert_eq!(
+            Err("no section has the id \"disk\"".into()),
+            render(&["--sections", "mem,disk"])
+        );
+    }
+
+    #[test]
+    fn fast_enough_for_a_prompt() {
+        let options = Options::parse(&args(&["--sections", "mem"])).unwrap();
+        let start = Instant::now();
+        assert_eq!(
+            Ok("1.00/4.00 GiB".into()),
+            render(&config(), &mut provider(), &options)
+        );
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,800 @@
+//! Choosing the output backend at startup, and the backends showing the assembled line.
+
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    mem,
+    os::unix::fs::{FileTypeExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use directories::BaseDirs;
+use tracing::{info, warn};
+
+use crate::{
+    config::{default_name_atoms, DisplayServer, OutputBackend, WidthCheck},
+    dwm, maildir, spawn, text, x11,
+};
+
+/// xsetroot normally returns within milliseconds, a hung X server shouldn't stall the bar for long.
+const SET_ROOT_NAME_TIMEOUT: Duration = Duration::from_secs(2);
+/// Share of the room a cut off status text has to shrink to, in percent.
+const FIT_AGAIN_PERCENT: u64 = 95;
+
+const USAGE: &str = "usage: stringbar [--output <auto|xsetroot|i3bar|stdout|none>]";
+
+/// Whether the variables detection looks at are set, empty counts as unset.
+#[derive(Clone, Copy)]
+pub struct Env {
+    pub wayland_display: bool,
+    pub swaysock: bool,
+    pub display: bool,
+}
+
+impl Env {
+    pub fn current() -> Self {
+        let set = |name| env::var_os(name).is_some_and(|x| !x.is_empty());
+        Self {
+            wayland_display: set("WAYLAND_DISPLAY"),
+            swaysock: set("SWAYSOCK"),
+            display: set("DISPLAY"),
+        }
+    }
+}
+
+/// The backend `Auto` stands for in `env`, and why. `preferred` settles sessions with both a
+/// Wayland and an X display.
+pub fn detect(env: Env, preferred: DisplayServer) -> (OutputBackend, &'static str) {
+    let xsetroot = OutputBackend::Xsetroot {
+        status2d: false,
+        statuscmd: false,
+        post: None,
+        spawn: false,
+        reverse_order: false,
+        name_atoms: default_name_atoms(),
+        latin1_replacement: '?',
+        width_check: None,
+    };
+    let wayland = env.wayland_display && (!env.display || preferred == DisplayServer::Wayland);
+
+    match (wayland, env.swaysock, env.display) {
+        (true, true, _) => (
+            OutputBackend::I3bar {
+                reverse_order: false,
+            },
+            "WAYLAND_DISPLAY and SWAYSOCK are set, swaybar reads the i3bar protocol from stdout",
+        ),
+        (true, false, _) => (
+            OutputBackend::Stdout {
+                reverse_order: false,
+            },
+            "WAYLAND_DISPLAY is set but not SWAYSOCK, printing lines for the compositor's bar",
+        ),
+        (false, _, true) if env.wayland_display => (
+            xsetroot,
+            "WAYLAND_DISPLAY and DISPLAY are set and preferred_display is X11",
+        ),
+        (false, _, true) => (xsetroot, "DISPLAY is set"),
+        (false, _, false) => (
+            OutputBackend::Stdout {
+                reverse_order: false,
+            },
+            "neither WAYLAND_DISPLAY nor DISPLAY is set",
+        ),
+    }
+}
+
+/// Takes `--output <backend>` out of `args`.
+pub fn take_flag(args: &mut Vec<String>) -> Result<Option<OutputBackend>, String> {
+    let Some(index) = args.iter().position(|x| x == "--output") else {
+        return Ok(None);
+    };
+    let Some(name) = args.get(index + 1) else {
+        return Err(USAGE.into());
+    };
+
+    let output = match name.as_str() {
+        "auto" => OutputBackend::Auto,
+        "xsetroot" => OutputBackend::Xsetroot {
+            status2d: false,
+            statuscmd: false,
+            post: None,
+            spawn: false,
+            reverse_order: false,
+            name_atoms: default_name_atoms(),
+            latin1_replacement: '?',
+            width_check: None,
+        },
+        "i3bar" => OutputBackend::I3bar {
+            reverse_order: false,
+        },
+        "stdout" => OutputBackend::Stdout {
+            reverse_order: false,
+        },
+        "none" => OutputBackend::None,
+        _ => return Err(format!("invalid output: {name}\n{USAGE}")),
+    };
+    args.drain(index..index + 2);
+
+    Ok(Some(output))
+}
+
+/// How the backend in use is chosen. Detection only happens once, so reloads resolve `Auto`
+/// the same way startup did.
+#[derive(Clone)]
+pub struct Choice {
+    flag: Option<OutputBackend>,
+    detected: (OutputBackend, &'static str),
+}
+
+impl Choice {
+    pub fn new(flag: Option<OutputBackend>, env: Env, preferred: DisplayServer) -> Self {
+        Self {
+            flag,
+            detected: detect(env, preferred),
+        }
+    }
+
+    /// Replaces `output` from the config with the backend to use and describes the choice.
+    pub fn apply(&self, output: &mut OutputBackend) -> String {
+        let mut why = "set in the config".to_string();
+        if let Some(flag) = &self.flag {
+            // The config's options for the same backend, e.g. status2d, still apply.
+            if mem::discriminant(flag) != mem::discriminant(output) {
+                *output = flag.clone();
+            }
+            why = "set by --output".into();
+        }
+        if *output == OutputBackend::Auto {
+            let (detected, reason) = &self.detected;
+            *output = detected.clone();
+            why = format!("detected since {reason}");
+        }
+
+        format!("Output: {}, {why}.", name(output))
+    }
+}
+
+pub fn name(output: &OutputBackend) -> &'static str {
+    match output {
+        OutputBackend::Xsetroot { .. } => "Xsetroot",
+        OutputBackend::I3bar { .. } => "I3bar",
+        OutputBackend::Stdout { .. } => "Stdout",
+        OutputBackend::File { .. } => "File",
+        OutputBackend::None => "None",
+        OutputBackend::Auto => "Auto",
+    }
+}
+
+/// Somewhere to show the assembled line.
+pub trait Output {
+    /// Shows `line`, replacing the previous one. Returns a message for the log on failure.
+    fn publish(&mut self, line: &str) -> Result<(), String>;
+
+    /// Whether the connection was lost and the next line reconnects.
+    fn reconnecting(&self) -> bool {
+        false
+    }
+
+    /// Lines replaced by newer ones before they could be written.
+    fn dropped(&self) -> u64 {
+        0
+    }
+
+    /// Whether the last line likely didn't fit the bar.
+    fn truncated(&self) -> bool {
+        false
+    }
+
+    /// Waits up to `timeout` for lines still being written, e.g. before exiting.
+    fn finish(&mut self, _timeout: Duration) {}
+}
+
+/// The [`Output`] for `backend`, `None` for backends that don't show anything. `Auto` has to
+/// be resolved through [`Choice`] first.
+pub fn open(backend: &OutputBackend) -> Option<Box<dyn Output>> {
+    match backend {
+        OutputBackend::Xsetroot {
+            status2d,
+            statuscmd,
+            spawn,
+            name_atoms,
+            latin1_replacement,
+            width_check,
+            ..
+        } => Some(Box::new(Xsetroot {
+            link: if *spawn {
+                Link::Spawn
+            } else {
+                Link::Unconnected
+            },
+            names: x11::Names {
+                atoms: name_atoms.clone(),
+                latin1_replacement: *latin1_replacement,
+            },
+            fit: width_check.clone().map(|check| Fit {
+                check,
+                markup: (*status2d, *statuscmd),
+                truncated: false,
+            }),
+        })),
+        OutputBackend::I3bar { .. } => Some(Box::new(Background::spawn(I3bar { started: false }))),
+        OutputBackend::Stdout { .. } => Some(Box::new(Background::spawn(Stdout))),
+        OutputBackend::File { path, append, .. } => {
+            let home = BaseDirs::new().map(|x| x.home_dir().to_path_buf());
+            Some(Box::new(File {
+                path: maildir::expand_home(path, home.as_deref()),
+                append: *append,
+            }))
+        }
+        OutputBackend::None | OutputBackend::Auto => None,
+    }
+}
+
+/// Sets the root window name, over a connection to the X server kept open between lines or
+/// through xsetroot.
+struct Xsetroot {
+    link: Link,
+    /// What the connection sets, xsetroot only knows `WM_NAME`.
+    names: x11::Names,
+    fit: Option<Fit>,
+}
+
+enum Link {
+    /// Before the first line.
+    Unconnected,
+    /// `None` after the connection failed, e.g. because the X server restarted. The next line
+    /// reconnects.
+    Native(Option<x11::Connection>),
+    Spawn,
+}
+
+impl Output for Xsetroot {
+    fn publish(&mut self, line: &str) -> Result<(), String> {
+        if let Link::Unconnected = self.link {
+            self.link = match x11::Connection::open() {
+                Ok(connection) => Link::Native(Some(connection)),
+                Err(e) => {
+                    info!("Unable to connect to the X server ({e}), spawning xsetroot instead.");
+                    Link::Spawn
+                }
+            };
+        }
+
+        match &mut self.link {
+            Link::Native(connection) => {
+                if connection.is_none() {
+                    *connection = Some(x11::Connection::open()?);
+                }
+                let open = connection.as_mut().unwrap();
+                let result = open.set_root_name(line, &self.names);
+                match (&result, &mut self.fit) {
+                    (Ok(()), Some(fit)) => fit.update(line, open.width()),
+                    (Ok(()), None) => {}
+                    (Err(_), _) => *connection = None,
+                }
+                result
+            }
+            Link::Spawn | Link::Unconnected => spawn::run(
+                Command::new("xsetroot").arg("-name").arg(line),
+                SET_ROOT_NAME_TIMEOUT,
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        }
+        .map_err(|e| format!("Unable to set root window name: {e}"))
+    }
+
+    fn reconnecting(&self) -> bool {
+        matches!(self.link, Link::Native(None))
+    }
+
+    fn truncated(&self) -> bool {
+        self.fit.as_ref().is_some_and(|x| x.truncated)
+    }
+}
+
+/// Whether dwm likely cuts off the status text, from the screen's width and an average glyph
+/// width. Once cut, the text has to shrink to [`FIT_AGAIN_PERCENT`] of the room before it
+/// counts as fitting again, so a line hovering around the limit doesn't flap.
+struct Fit {
+    check: WidthCheck,
+    /// status2d and statuscmd, whose markup takes up no room.
+    markup: (bool, bool),
+    truncated: bool,
+}
+
+impl Fit {
+    fn update(&mut self, line: &str, screen_width: u16) {
+        let (status2d, statuscmd) = self.markup;
+        let columns: usize = dwm::segments(line, status2d, statuscmd)
+            .into_iter()
+            .filter(|(markup, _)| !markup)
+            .map(|(_, text)| text::width(text))
+            .sum();
+        let needed = columns as u64 * self.check.glyph_width as u64;
+        let room = screen_width.saturating_sub(self.check.reserved) as u64;
+
+        if !self.truncated && needed > room {
+            warn!(
+                "The status text is about {needed}px wide but only about {room}px fit, dwm likely \
+                 cuts it off. max_width on sections or post's max_length keep it shorter."
+            );
+            self.truncated = true;
+        } else if self.truncated && needed * 100 <= room * FIT_AGAIN_PERCENT {
+            info!("The status text fits again, about {needed}px of {room}px.");
+            self.truncated = false;
+        }
+    }
+}
+
+/// Runs another output on a thread of its own, so a consumer that stops reading, e.g. a
+/// paused i3bar or a pager, can't hold up updates, control commands and reloads. Only the
+/// newest line waits to be written: it replaces one that's still waiting, which is counted as
+/// dropped. Failures are reported with the line after the one that failed.
+struct Background {
+    shared: Arc<(Mutex<Handoff>, Condvar)>,
+}
+
+#[derive(Default)]
+struct Handoff {
+    pending: Option<String>,
+    writing: bool,
+    /// Of the last line written.
+    error: Option<String>,
+    reconnecting: bool,
+    dropped: u64,
+    /// Set once the output is dropped, the thread exits after writing what's pending.
+    closed: bool,
+}
+
+impl Background {
+    fn spawn(mut inner: impl Output + Send + 'static) -> Self {
+        let shared = Arc::new((Mutex::new(Handoff::default()), Condvar::new()));
+        let writer = shared.clone();
+        thread::spawn(move || {
+            let (handoff, changed) = &*writer;
+            let mut state = handoff.lock().unwrap();
+            loop {
+                let Some(line) = state.pending.take() else {
+                    if state.closed {
+                        return;
+                    }
+                    state = changed.wait(state).unwrap();
+                    continue;
+                };
+                state.writing = true;
+                drop(state);
+
+                let result = inner.publish(&line);
+
+                state = handoff.lock().unwrap();
+                state.writing = false;
+                state.error = result.err();
+                state.reconnecting = inner.reconnecting();
+                changed.notify_all();
+            }
+        });
+
+        Self { shared }
+    }
+}
+
+impl Output for Background {
+    fn publish(&mut self, line: &str) -> Result<(), String> {
+        let (handoff, changed) = &*self.shared;
+        let mut state = handoff.lock().unwrap();
+        if state.pending.replace(line.into()).is_some() {
+            state.dropped += 1;
+        }
+        changed.notify_all();
+
+        state.error.clone().map_or(Ok(()), Err)
+    }
+
+    fn reconnecting(&self) -> bool {
+        self.shared.0.lock().unwrap().reconnecting
+    }
+
+    fn dropped(&self) -> u64 {
+        self.shared.0.lock().unwrap().dropped
+    }
+
+    fn finish(&mut self, timeout: Duration) {
+        let (handoff, changed) = &*self.shared;
+        let deadline = Instant::now() + timeout;
+        let mut state = handoff.lock().unwrap();
+        while state.pending.is_some() || state.writing {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return;
+            }
+            state = changed.wait_timeout(state, left).unwrap().0;
+        }
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        let (handoff, changed) = &*self.shared;
+        handoff.lock().unwrap().closed = true;
+        changed.notify_all();
+    }
+}
+
+/// The i3bar protocol, status lines of blocks from [`crate::bar::blocks`] after the header.
+struct I3bar {
+    started: bool,
+}
+
+impl Output for I3bar {
+    fn publish(&mut self, line: &str) -> Result<(), String> {
+        let mut output = String::new();
+        if !self.started {
+            output.push_str("{\"version\":1}\n[\n");
+        }
+        output.push_str(line);
+        output.push(',');
+
+        write_stdout(&output)?;
+        self.started = true;
+        Ok(())
+    }
+}
+
+struct Stdout;
+
+impl Output for Stdout {
+    fn publish(&mut self, line: &str) -> Result<(), String> {
+        write_stdout(line)
+    }
+}
+
+/// Writes `line` and a newline to stdout and flushes it. Exits once stdout is closed, nothing
+/// reads the bar anymore then, e.g. because swaybar exited.
+fn write_stdout(line: &str) -> Result<(), String> {
+    let mut stdout = io::stdout().lock();
+    match writeln!(stdout, "{line}").and_then(|_| stdout.flush()) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+            info!("Stdout was closed, exiting.");
+            std::process::exit(0);
+        }
+        Err(e) => Err(format!("Unable to write to stdout: {e}")),
+    }
+}
+
+/// Writes each line to a file, replacing its contents or appended to them, or to a FIFO.
+/// Opened for every line, so the file can be replaced or the FIFO get a new reader.
+struct File {
+    path: PathBuf,
+    append: bool,
+}
+
+impl Output for File {
+    fn publish(&mut self, line: &str) -> Result<(), String> {
+        let line = format!("{line}\n");
+        let fifo = fs::metadata(&self.path).is_ok_and(|x| x.file_type().is_fifo());
+        match fifo {
+            true => write_fifo(&self.path, line.as_bytes()),
+            false => OpenOptions::new()
+                .write(true)
+                .create(true)
+                .append(self.append)
+                .truncate(!self.append)
+                .open(&self.path)
+                .and_then(|mut file| file.write_all(line.as_bytes())),
+        }
+        .map_err(|e| format!("Unable to write to {}: {e}", self.path.display()))
+    }
+}
+
+/// Writes `line` to a FIFO without blocking, so a FIFO without a reader or with one that fell
+/// behind fails the write instead of stalling the bar. A single write puts lines of up to
+/// `PIPE_BUF` (4 KiB) bytes into the FIFO whole or not at all, so the reader never sees part
+/// of one.
+fn write_fifo(path: &Path, line: &[u8]) -> io::Result<()> {
+    let mut fifo = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+    match fifo.write(line) {
+        Ok(written) if written == line.len() => Ok(()),
+        Ok(written) => Err(io::Error::other(format!(
+            "only {written} of {} bytes fit",
+            line.len()
+        ))),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            Err(io::Error::other("the reader is behind, skipped the line"))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detection() {
+        let env = |wayland_display, swaysock, display| Env {
+            wayland_display,
+            swaysock,
+            display,
+        };
+        let xsetroot = OutputBackend::Xsetroot {
+            status2d: false,
+            statuscmd: false,
+            post: None,
+            spawn: false,
+            reverse_order: false,
+            name_atoms: default_name_atoms(),
+            latin1_replacement: '?',
+            width_check: None,
+        };
+        let cases = [
+            (
+                env(true, true, false),
+                &OutputBackend::I3bar {
+                    reverse_order: false,
+                },
+                &OutputBackend::I3bar {
+                    reverse_order: false,
+                },
+            ),
+            (
+                env(true, false, false),
+                &OutputBackend::Stdout {
+                    reverse_order: false,
+                },
+                &OutputBackend::Stdout {
+                    reverse_order: false,
+                },
+            ),
+            (env(false, false, true), &xsetroot, &xsetroot),
+            (env(false, true, true), &xsetroot, &xsetroot),
+            (
+                env(false, false, false),
+                &OutputBackend::Stdout {
+                    reverse_order: false,
+                },
+                &OutputBackend::Stdout {
+                    reverse_order: false,
+                },
+            ),
+            // Both displays, e.g. XWayland.
+            (
+                env(true, true, true),
+                &OutputBackend::I3bar {
+                    reverse_order: false,
+                },
+                &xsetroot,
+            ),
+            (
+                env(true, false, true),
+                &OutputBackend::Stdout {
+                    reverse_order: false,
+                },
+                &xsetroot,
+            ),
+        ];
+        for (env, wayland, x11) in cases {
+            assert_eq!(*wayland, detect(env, DisplayServer::Wayland).0);
+            assert_eq!(*x11, detect(env, DisplayServer::X11).0);
+        }
+
+        assert_eq!(
+            "WAYLAND_DISPLAY and DISPLAY are set and preferred_display is X11",
+            detect(env(true, true, true), DisplayServer::X11).1
+        );
+    }
+
+    #[test]
+    fn flag() {
+        let args = |x: &[&str]| -> Vec<String> { x.iter().map(|x| x.to_string()).collect() };
+
+        let mut plain = args(&["--demo"]);
+        assert_eq!(Ok(None), take_flag(&mut plain));
+        assert_eq!(args(&["--demo"]), plain);
+
+        let mut given = args(&["--output", "i3bar", "--demo"]);
+        assert_eq!(
+            Ok(Some(OutputBackend::I3bar {
+                reverse_order: false
+            })),
+            take_flag(&mut given)
+        );
+        assert_eq!(args(&["--demo"]), given);
+
+        assert!(take_flag(&mut args(&["--output"])).is_err());
+        assert!(take_flag(&mut args(&["--output", "wayland"])).is_err());
+    }
+
+    #[test]
+    fn file() {
+        let dir = env::temp_dir().join(format!("stringbar-output-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bar");
+        let backend = |append| OutputBackend::File {
+            path: path.to_string_lossy().into_owned(),
+            append,
+            reverse_order: false,
+        };
+
+        let mut replacing = open(&backend(false)).unwrap();
+        replacing.publish("first").unwrap();
+        replacing.publish("second").unwrap();
+        assert_eq!("second\n", std::fs::read_to_string(&path).unwrap());
+
+        let mut appending = open(&backend(true)).unwrap();
+        appending.publish("third").unwrap();
+        assert_eq!("second\nthird\n", std::fs::read_to_string(&path).unwrap());
+
+        // A FIFO nobody reads fails right away instead of blocking.
+        let fifo = dir.join("fifo");
+        let c_path = std::ffi::CString::new(fifo.to_string_lossy().as_bytes()).unwrap();
+        // SAFETY: `c_path` is a valid NUL terminated string.
+        assert_eq!(0, unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) });
+        let mut fifo_output = open(&OutputBackend::File {
+            path: fifo.to_string_lossy().into_owned(),
+            append: false,
+            reverse_order: false,
+        })
+        .unwrap();
+        assert!(fifo_output.publish("line").is_err());
+
+        // A reader that doesn't keep up gets whole lines, the rest are skipped.
+        let mut reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&fifo)
+            .unwrap();
+        let line = "x".repeat(999);
+        let results: Vec<_> = (0..100).map(|_| fifo_output.publish(&line)).collect();
+        assert!(results.iter().any(|x| x.is_ok()));
+        assert_eq!(
+            Some(&Err(format!(
+                "Unable to write to {}: the reader is behind, skipped the line",
+                fifo.display()
+            ))),
+            results.last()
+        );
+        let mut read = String::new();
+        io::Read::read_to_string(&mut reader, &mut read).unwrap_or_default();
+        let written = results.iter().filter(|x| x.is_ok()).count();
+        assert_eq!(format!("{line}\n").repeat(written), read);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Writes lines to a socket, blocking while it's full.
+    struct Socket(std::os::unix::net::UnixStream);
+
+    impl Output for Socket {
+        fn publish(&mut self, line: &str) -> Result<(), String> {
+            writeln!(self.0, "{line}").map_err(|e| e.to_string())
+        }
+    }
+
+    #[test]
+    fn background() {
+        let (writer, reader) = std::os::unix::net::UnixStream::pair().unwrap();
+        let mut output = Background::spawn(Socket(writer));
+
+        // Nothing reads the socket, but publishing keeps returning right away.
+        let line = "x".repeat(64 * 1024);
+        let start = Instant::now();
+        for index in 0..50 {
+            assert_eq!(Ok(()), output.publish(&format!("{index} {line}")));
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(output.dropped() > 0);
+
+        // Once read, the newest line comes through last.
+        let reading = thread::spawn(move || io::read_to_string(reader).unwrap());
+        output.finish(Duration::from_secs(5));
+        let dropped = output.dropped();
+        drop(output);
+        let read = reading.join().unwrap();
+        assert!(read.ends_with(&format!("49 {line}\n")));
+        assert_eq!(50, read.lines().count() as u64 + dropped);
+    }
+
+    #[test]
+    fn fit() {
+        let mut fit = Fit {
+            check: WidthCheck {
+                glyph_width: 10,
+                reserved: 200,
+            },
+            markup: (true, true),
+            truncated: false,
+        };
+        let mut update = |line: &str| {
+            fit.update(line, 1200);
+            fit.truncated
+        };
+
+        // 1000px of room for 100 columns.
+        assert!(!update(&"x".repeat(100)));
+        assert!(update(&"x".repeat(101)));
+        // Hysteresis, it has to get down to 95 columns.
+        assert!(update(&"x".repeat(96)));
+        assert!(!update(&"x".repeat(95)));
+
+        // Markup takes up no room, wide characters two columns.
+        let marked = format!("\x01^c#ff0000^{}^d^", "x".repeat(100));
+        assert!(!update(&marked));
+        assert!(update(&format!("{}夜", "x".repeat(99))));
+    }
+
+    #[test]
+    fn choice() {
+        let status2d = OutputBackend::Xsetroot {
+            status2d: true,
+            statuscmd: false,
+            post: None,
+            spawn: false,
+            reverse_order: false,
+            name_atoms: default_name_atoms(),
+            latin1_replacement: '?',
+            width_check: None,
+        };
+        let sway = Env {
+            wayland_display: true,
+            swaysock: true,
+            display: false,
+        };
+        let choose = |flag, mut output| {
+            let why = Choice::new(flag, sway, DisplayServer::Wayland).apply(&mut output);
+            (output, why)
+        };
+
+        assert_eq!(
+            (
+                OutputBackend::I3bar {
+                    reverse_order: false
+                },
+                "Output: I3bar, detected since WAYLAND_DISPLAY and SWAYSOCK are set, swaybar \
+                 reads the i3bar protocol from stdout."
+                    .into()
+            ),
+            choose(None, OutputBackend::Auto)
+        );
+        assert_eq!(
+            (
+                status2d.clone(),
+                "Output: Xsetroot, set in the config.".into()
+            ),
+            choose(None, status2d.clone())
+        );
+        assert_eq!(
+            (
+                OutputBackend::Stdout {
+                    reverse_order: false
+                },
+                "Output: Stdout, set by --output.".into()
+            ),
+            choose(
+                Some(OutputBackend::Stdout {
+                    reverse_order: false
+                }),
+                status2d.clone()
+            )
+        );
+        // Keeps status2d from the config.
+        let xsetroot = take_flag(&mut vec!["--output".into(), "xsetroot".into()]).unwrap();
+        assert_eq!(status2d, choose(xsetroot, status2d.clone()).0);
+        assert_eq!(
+            OutputBackend::I3bar {
+                reverse_order: false
+            },
+            choose(Some(OutputBackend::Auto), status2d.clone()).0
+        );
+    }
+}
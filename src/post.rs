@@ -0,0 +1,197 @@
+//! Final touches to the assembled line before an output backend shows it, configured with the
+//! backend's `post`.
+
+use crate::{config::BackendPost, dwm, text};
+
+/// Strips markup if asked to, then prepends, truncates and appends, in that order. `status2d`
+/// and `statuscmd` tell which markup the line may contain.
+pub fn apply(line: &str, post: &BackendPost, status2d: bool, statuscmd: bool) -> String {
+    let (mut line, status2d, statuscmd) = match post.strip_colors {
+        true => (dwm::strip(line, status2d, statuscmd), false, false),
+        false => (line.to_string(), status2d, statuscmd),
+    };
+
+    if let Some(prepend) = &post.prepend {
+        line.insert_str(0, prepend);
+    }
+    if let Some(max_length) = post.max_length {
+        line = truncate(&line, max_length, status2d, statuscmd);
+    }
+    if let Some(append) = &post.append {
+        line.push_str(append);
+    }
+
+    line
+}
+
+/// Whether [`apply`] leaves `line` as long as it is rather than cutting it to `max_length`.
+pub fn fits(line: &str, post: &BackendPost, status2d: bool, statuscmd: bool) -> bool {
+    let Some(max_length) = post.max_length else {
+        return true;
+    };
+    let uncut = BackendPost {
+        max_length: None,
+        append: None,
+        ..post.clone()
+    };
+    let markup = !post.strip_colors;
+    let line = apply(line, &uncut, status2d, statuscmd);
+    length(&line, status2d && markup, statuscmd && markup) <= max_length
+}
+
+/// Grapheme clusters of `line` outside of markup.
+fn length(line: &str, status2d: bool, statuscmd: bool) -> usize {
+    dwm::segments(line, status2d, statuscmd)
+        .iter()
+        .filter(|(markup, _)| !markup)
+        .map(|(_, text)| text::graphemes(text).len())
+        .sum()
+}
+
+/// Shortens `line` to at most `max_length` grapheme clusters, ending in "…" if anything was
+/// cut. Markup doesn't count towards the length and is kept up to the cut, status2d colors are
+/// reset after it.
+fn truncate(line: &str, max_length: usize, status2d: bool, statuscmd: bool) -> String {
+    if length(line, status2d, statuscmd) <= max_length {
+        return line.into();
+    }
+    if max_length == 0 {
+        return String::new();
+    }
+
+    let mut output = String::new();
+    let mut remaining = max_length - 1;
+    for (markup, part) in dwm::segments(line, status2d, statuscmd) {
+        if markup {
+            output.push_str(part);
+            continue;
+        }
+
+        let clusters = text::graphemes(part);
+        if clusters.len() > remaining {
+            output.extend(clusters[..remaining].iter().copied());
+            break;
+        }
+        remaining -= clusters.len();
+        output.push_str(part);
+    }
+    output.push('…');
+    if status2d {
+        output.push_str("^d^");
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post() -> BackendPost {
+        BackendPost::default()
+    }
+
+    #[test]
+    fn transforms() {
+        let line = "cpu 5% | mem 40%";
+
+        assert_eq!(line, apply(line, &post(), false, false));
+        let prepend = BackendPost {
+            prepend: Some("[".into()),
+            ..post()
+        };
+        assert_eq!("[cpu 5% | mem 40%", apply(line, &prepend, false, false));
+        let append = BackendPost {
+            append: Some(" ".into()),
+            ..post()
+        };
+        assert_eq!("cpu 5% | mem 40% ", apply(line, &append, false, false));
+        let truncated = BackendPost {
+            max_length: Some(8),
+            ..post()
+        };
+        assert_eq!("cpu 5% …", apply(line, &truncated, false, false));
+        let stripped = BackendPost {
+            strip_colors: true,
+            ..post()
+        };
+        assert_eq!(
+            line,
+            apply(
+                "\x01^c#ff0000^cpu 5%^d^ | \x02mem 40%",
+                &stripped,
+                true,
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn fitting() {
+        let post = |max_length, strip_colors| BackendPost {
+            max_length,
+            prepend: Some("> ".into()),
+            append: Some(" <".into()),
+            strip_colors,
+        };
+        let line = "^c#ff0000^cpu 5%^d^";
+
+        assert!(fits(line, &post(None, false), true, false));
+        // Counted like apply counts it, with the prepended text but without the appended text
+        // and markup.
+        assert!(fits(line, &post(Some(8), false), true, false));
+        assert!(!fits(line, &post(Some(7), false), true, false));
+        assert!(fits(line, &post(Some(8), true), true, false));
+        assert!(!fits(line, &post(Some(7), true), true, false));
+        assert_eq!("> cpu 5% <", apply(line, &post(Some(8), true), true, false));
+    }
+
+    #[test]
+    fn order() {
+        let all = BackendPost {
+            max_length: Some(6),
+            prepend: Some("> ".into()),
+            append: Some(" <".into()),
+            strip_colors: true,
+        };
+        // The prepended text counts towards the length, the appended text doesn't.
+        assert_eq!("> cpu… <", apply("^c#ff0000^cpu 5%^d^", &all, true, false));
+        assert_eq!(
+            "> ^c#ff0000^cpu…^d^ <",
+            apply(
+                "^c#ff0000^cpu 5%^d^",
+                &BackendPost {
+                    strip_colors: false,
+                    ..all
+                },
+                true,
+                false
+            )
+        );
+    }
+
+    #[test]
+    fn truncation() {
+        assert_eq!("short", truncate("short", 5, false, false));
+        assert_eq!("", truncate("short", 0, false, false));
+        assert_eq!("…", truncate("short", 1, false, false));
+        // Clusters aren't split and count once.
+        assert_eq!(
+            "e\u{301}e\u{301}…",
+            truncate("e\u{301}e\u{301}e\u{301}e\u{301}", 3, false, false)
+        );
+        assert_eq!(
+            "👩\u{200D}💻…",
+            truncate("👩\u{200D}💻👩\u{200D}💻👩\u{200D}💻", 2, false, false)
+        );
+        // Markup is neither counted nor cut.
+        assert_eq!(
+            "\x01^c#ff0000^cpu^d^\x02^c#00ff00^…^d^",
+            truncate("\x01^c#ff0000^cpu^d^\x02^c#00ff00^mem^d^", 4, true, true)
+        );
+        assert_eq!(
+            "\x01^c#ff0000^cpu^d^",
+            truncate("\x01^c#ff0000^cpu^d^", 3, true, true)
+        );
+    }
+}
@@ -0,0 +1,1221 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    net::IpAddr,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    alsa, bluetooth, cgroup, dbus,
+    disks::{self, DiskSnapshot, DriveState, Spared},
+    ifaddrs, maildir, mpd,
+    publicip::PublicIp,
+    statvfs, xkb,
+};
+
+use sysinfo::{
+    Components, CpuRefreshKind, Disks, MemoryRefreshKind, Networks, ProcessRefreshKind, System,
+    ThreadKind, UpdateKind, MINIMUM_CPU_UPDATE_INTERVAL,
+};
+
+#[derive(Clone)]
+pub struct DiskInfo {
+    pub name: String,
+    pub mount_point: String,
+    /// E.g. "ext4".
+    pub file_system: String,
+    pub total_space: u64,
+    pub available_space: u64,
+    pub is_removable: bool,
+    /// Used and total inodes, `None` if the filesystem has no inode limit.
+    pub inodes: Option<(u64, u64)>,
+    /// The counts are from before the drive went to sleep, it wasn't queried to keep it asleep.
+    pub spared: bool,
+}
+
+pub struct ComponentInfo {
+    pub label: String,
+    /// In degrees Celsius.
+    pub temperature: f32,
+}
+
+pub struct NetworkInterface {
+    pub name: String,
+    pub loopback: bool,
+}
+
+pub struct ProcessInfo {
+    pub name: String,
+    /// In percent of one core, like top shows it.
+    pub cpu_usage: f32,
+    /// Resident memory in bytes.
+    pub memory: u64,
+    /// Kernel threads have no command line.
+    pub kernel_thread: bool,
+}
+
+/// How long interface addresses are reused, they rarely change.
+const ADDRESS_CACHE_DURATION: Duration = Duration::from_secs(5);
+/// How long battery charge thresholds are reused, they only change when set by hand or by a
+/// vendor tool.
+const END_THRESHOLD_CACHE_DURATION: Duration = Duration::from_secs(60);
+
+/// Where Linux exposes batteries, among other power supplies.
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+/// Where Linux exposes display backlights.
+const BACKLIGHT_PATH: &str = "/sys/class/backlight";
+
+/// Where Linux exposes network interfaces.
+const NET_PATH: &str = "/sys/class/net";
+/// `IFF_LOOPBACK` in an interface's flags.
+const LOOPBACK_FLAG: u32 = 0x8;
+
+#[derive(Clone)]
+pub struct BatteryInfo {
+    pub name: String,
+    /// Charge in percent.
+    pub capacity: u8,
+    pub state: ChargeState,
+    /// Charge in percent the battery stops charging at, e.g. 80 on laptops set to spare it.
+    /// `None` without a threshold or one at 100%.
+    pub end_threshold: Option<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChargeState {
+    Charging,
+    Discharging,
+    /// Full, not charging while plugged in, or unknown.
+    Idle,
+}
+
+/// Source of the system data modules display.
+///
+/// Every call returns fresh data, except for the [`disks`](SystemProvider::disks) snapshot,
+/// [`network_rates`](SystemProvider::network_rates),
+/// [`components`](SystemProvider::components) and the process list behind
+/// [`process_count`](SystemProvider::process_count) and
+/// [`processes`](SystemProvider::processes), which are refreshed at most once between
+/// calls to [`begin_tick`](SystemProvider::begin_tick),
+/// and [`cpu_usage`](SystemProvider::cpu_usage), which may be reused while it's too recent
+/// to sample again.
+pub trait SystemProvider {
+    /// Called at the start of every update.
+    fn begin_tick(&mut self);
+    /// Used and total memory in bytes.
+    fn memory(&mut self) -> (u64, u64);
+    /// Memory use and limit of stringbar's cgroup, `None` without cgroup v2 limits.
+    fn cgroup_memory(&mut self) -> Option<cgroup::Memory>;
+    /// Used and total swap in bytes.
+    fn swap(&mut self) -> (u64, u64);
+    /// Global CPU usage in percent.
+    fn cpu_usage(&mut self) -> f32;
+    /// Frequency of every core in MHz, 0 for cores that don't report one.
+    fn cpu_frequencies(&mut self) -> Vec<u64>;
+    fn process_count(&mut self) -> usize;
+    /// Running processes, without their individual threads. CPU usage reads 0 on the first
+    /// refresh.
+    fn processes(&mut self) -> &[ProcessInfo];
+    /// 1, 5 and 15 minute load averages.
+    fn load_average(&mut self) -> (f64, f64, f64);
+    /// Read on first use in an update and shared by all disk sections until the next one.
+    fn disks(&mut self) -> &DiskSnapshot;
+    /// Disks to leave alone while their drive is asleep, they keep their previous counts.
+    /// Providers without real disks ignore this.
+    fn spare_disks(&mut self, _spared: Spared) {}
+    /// Whether disks come with their inode counts, which takes another query per disk.
+    /// Providers without real disks ignore this.
+    fn want_inodes(&mut self, _wanted: bool) {}
+    /// Received and transmitted bytes per second on `interface` since the previous refresh,
+    /// `None` if there's no such interface. The first refresh reports 0.
+    fn network_rates(&mut self, interface: &str) -> Option<(f64, f64)>;
+    /// Network interfaces sorted by name, including ones that appeared since the last update.
+    fn network_interfaces(&mut self) -> Vec<NetworkInterface>;
+    /// Bytes received and transmitted on `interface` as counted by the system, e.g. since the
+    /// interface came up, read along with the rates.
+    fn network_totals(&mut self, interface: &str) -> Option<[u64; 2]>;
+    /// IPv4 and IPv6 addresses of `interface`, `None` if there's no such interface.
+    /// May be up to a few seconds old.
+    fn interface_addresses(&mut self, interface: &str) -> Option<Vec<IpAddr>>;
+    /// All batteries, sorted by name.
+    fn batteries(&mut self) -> Vec<BatteryInfo>;
+    /// Temperature sensors.
+    fn components(&mut self) -> &[ComponentInfo];
+    /// Seconds since boot.
+    fn uptime(&mut self) -> u64;
+    /// Current and maximum brightness of the backlight `device`, or of the first one found.
+    /// `None` if there's no such backlight.
+    fn brightness(&mut self, device: Option<&str>) -> Option<(u64, u64)>;
+    /// Active keyboard layout, e.g. "us", or why it couldn't be read.
+    fn keyboard_layout(&mut self) -> Result<String, String>;
+    /// Volume of a mixer control on the default sound card, `None` without one.
+    fn volume(&mut self, control: &str) -> Option<alsa::Volume>;
+    /// The public address the plain text endpoint `url` reports, refreshed in the background
+    /// every `refresh_interval`. `None` until the first fetch succeeds.
+    fn public_ip(&mut self, url: &str, refresh_interval: Duration) -> Option<String>;
+    /// Number of unread messages in `maildir`, or why it couldn't be read.
+    fn unread_mail(&mut self, maildir: &Path) -> Result<usize, String>;
+    /// Number of failed systemd units, or why systemd couldn't be asked.
+    fn failed_units(&mut self) -> Result<usize, String>;
+    /// Bluetooth adapters and devices, or why BlueZ couldn't be asked.
+    fn bluetooth(&mut self) -> Result<bluetooth::Status, String>;
+    /// What the MPD server at `host` and `port` is playing, read at most once per update.
+    fn mpd(&mut self, host: &str, port: u16) -> Result<mpd::Status, String>;
+}
+
+pub struct SysinfoProvider {
+    system: System,
+    disks: Disks,
+    disk_snapshot: Option<DiskSnapshot>,
+    spared_disks: Spared,
+    /// Contents of /proc/self/mounts when the disk list was last read. While it's the same
+    /// disks are queried one by one, so sleeping ones can be skipped.
+    mounts: Option<String>,
+    /// Inode counts by mount point, for disks that weren't queried.
+    disk_inodes: HashMap<String, Option<(u64, u64)>>,
+    /// Whether any section shows inodes, they're left out otherwise.
+    inodes_wanted: bool,
+    /// Sampling CPU usage more often than [`MINIMUM_CPU_UPDATE_INTERVAL`] gives wrong numbers,
+    /// so faster update intervals keep showing the last sample.
+    cpu_refreshed: Option<Instant>,
+    /// Set once a section asks for CPU frequencies, which are read along with usage from then.
+    cpu_frequencies_wanted: bool,
+    networks: Networks,
+    network_rates: HashMap<String, (f64, f64)>,
+    networks_refreshed: bool,
+    process_info: Vec<ProcessInfo>,
+    processes_refreshed: bool,
+    /// When the network counters were last read, rates are computed against the actual time
+    /// passed since then rather than the update interval.
+    networks_sampled: Option<Instant>,
+    /// Addresses by interface and when they were read.
+    addresses: Option<(Instant, HashMap<String, Vec<IpAddr>>)>,
+    end_thresholds: EndThresholds,
+    /// Only listed on first use, so machines without temperature sections never scan sensors.
+    components: Option<Components>,
+    component_info: Vec<ComponentInfo>,
+    components_refreshed: bool,
+    /// Connected on first use and kept open, reconnected after an error.
+    keyboard: xkb::Layout,
+    public_ip: PublicIp,
+    /// Connected on first use and kept open, dropped after an error to reconnect on the next
+    /// update.
+    system_bus: Option<dbus::Connection>,
+    failed_units: Option<Result<usize, String>>,
+    bluetooth: Option<Result<bluetooth::Status, String>>,
+    /// By host and port, connections stay open across updates.
+    mpd_clients: HashMap<(String, u16), mpd::Client>,
+    mpd_statuses: HashMap<(String, u16), Result<mpd::Status, String>>,
+}
+
+impl SysinfoProvider {
+    pub fn new() -> Self {
+        Self {
+            system: System::new(),
+            disks: Disks::new(),
+            disk_snapshot: None,
+            spared_disks: Spared::default(),
+            mounts: None,
+            disk_inodes: HashMap::new(),
+            inodes_wanted: false,
+            cpu_refreshed: None,
+            cpu_frequencies_wanted: false,
+            networks: Networks::new(),
+            network_rates: HashMap::new(),
+            networks_refreshed: false,
+            process_info: Vec::new(),
+            processes_refreshed: false,
+            networks_sampled: None,
+            addresses: None,
+            end_thresholds: EndThresholds::default(),
+            components: None,
+            component_info: Vec::new(),
+            components_refreshed: false,
+            keyboard: xkb::Layout::new(),
+            public_ip: PublicIp::new(),
+            system_bus: None,
+            failed_units: None,
+            bluetooth: None,
+            mpd_clients: HashMap::new(),
+            mpd_statuses: HashMap::new(),
+        }
+    }
+}
+
+impl SysinfoProvider {
+    /// Reads the network counters once per update, shared by the network modules.
+    fn refresh_networks(&mut self) {
+        if !self.networks_refreshed {
+            // Also picks up interfaces that appeared since, their first delta is 0.
+            self.networks.refresh_list();
+            let now = Instant::now();
+            let elapsed = self
+                .networks_sampled
+                .map(|x| now.duration_since(x).as_secs_f64())
+                .unwrap_or_default();
+            let rate = |bytes: u64| {
+                if elapsed > 0.0 {
+                    bytes as f64 / elapsed
+                } else {
+                    0.0
+                }
+            };
+
+            self.network_rates = self
+                .networks
+                .iter()
+                .map(|(name, data)| {
+                    (
+                        name.clone(),
+                        (rate(data.received()), rate(data.transmitted())),
+                    )
+                })
+                .collect();
+            self.networks_sampled = Some(now);
+            self.networks_refreshed = true;
+        }
+    }
+
+    /// Samples CPU usage, and frequencies if wanted, shared by the CPU modules.
+    fn refresh_cpu(&mut self) {
+        if self
+            .cpu_refreshed
+            .is_some_and(|x| x.elapsed() < MINIMUM_CPU_UPDATE_INTERVAL)
+        {
+            return;
+        }
+
+        let kind = CpuRefreshKind::new().with_cpu_usage();
+        self.system
+            .refresh_cpu_specifics(match self.cpu_frequencies_wanted {
+                true => kind.with_frequency(),
+                false => kind,
+            });
+        self.cpu_refreshed = Some(Instant::now());
+    }
+
+    /// Reads the process list once per update, shared by the process modules.
+    fn refresh_processes(&mut self) {
+        if self.processes_refreshed {
+            return;
+        }
+
+        // Command lines never change, so they're only read for new processes.
+        self.system.refresh_processes_specifics(
+            ProcessRefreshKind::new()
+                .with_cpu()
+                .with_memory()
+                .with_cmd(UpdateKind::OnlyIfNotSet),
+        );
+        self.process_info = self
+            .system
+            .processes()
+            .values()
+            .filter(|x| x.thread_kind() != Some(ThreadKind::Userland))
+            .map(|x| ProcessInfo {
+                name: x.name().into(),
+                cpu_usage: x.cpu_usage(),
+                memory: x.memory(),
+                kernel_thread: x.cmd().is_empty(),
+            })
+            .collect();
+        self.processes_refreshed = true;
+    }
+}
+
+impl SystemProvider for SysinfoProvider {
+    fn begin_tick(&mut self) {
+        self.disk_snapshot = None;
+        self.networks_refreshed = false;
+        self.processes_refreshed = false;
+        self.components_refreshed = false;
+        self.failed_units = None;
+        self.bluetooth = None;
+        self.mpd_statuses.clear();
+    }
+
+    fn memory(&mut self) -> (u64, u64) {
+        self.system
+            .refresh_memory_specifics(MemoryRefreshKind::new().with_ram());
+        (self.system.used_memory(), self.system.total_memory())
+    }
+
+    fn cgroup_memory(&mut self) -> Option<cgroup::Memory> {
+        cgroup::read_memory(Path::new(cgroup::CGROUP_PATH))
+    }
+
+    fn swap(&mut self) -> (u64, u64) {
+        self.system
+            .refresh_memory_specifics(MemoryRefreshKind::new().with_swap());
+        (self.system.used_swap(), self.system.total_swap())
+    }
+
+    fn cpu_usage(&mut self) -> f32 {
+        self.refresh_cpu();
+        self.system.global_cpu_info().cpu_usage()
+    }
+
+    fn cpu_frequencies(&mut self) -> Vec<u64> {
+        if !self.cpu_frequencies_wanted {
+            self.cpu_frequencies_wanted = true;
+            self.system
+                .refresh_cpu_specifics(CpuRefreshKind::new().with_frequency());
+        }
+        self.refresh_cpu();
+        self.system.cpus().iter().map(|x| x.frequency()).collect()
+    }
+
+    fn process_count(&mut self) -> usize {
+        self.refresh_processes();
+        self.system.processes().len()
+    }
+
+    fn processes(&mut self) -> &[ProcessInfo] {
+        self.refresh_processes();
+        &self.process_info
+    }
+
+    fn load_average(&mut self) -> (f64, f64, f64) {
+        let load = System::load_average();
+        (load.one, load.five, load.fifteen)
+    }
+
+    fn disks(&mut self) -> &DiskSnapshot {
+        self.disk_snapshot.get_or_insert_with(|| {
+            let class_block = Path::new(disks::SYS_CLASS_BLOCK_PATH);
+            let asleep = |name: &str, mount_point: &str| {
+                self.spared_disks.contains(name, mount_point)
+                    && DriveState::read(class_block, name).is_some_and(|x| x.asleep())
+            };
+
+            // Listing disks queries all of them, only done when something was (un)mounted.
+            let mounts = fs::read_to_string("/proc/self/mounts").ok();
+            let mut skipped = HashSet::new();
+            if self.spared_disks.is_empty() || mounts.is_none() || mounts != self.mounts {
+                self.disks.refresh_list();
+                self.mounts = mounts;
+            } else {
+                for disk in self.disks.list_mut() {
+                    let mount_point = disk.mount_point().to_string_lossy().into_owned();
+                    if asleep(&disk.name().to_string_lossy(), &mount_point) {
+                        skipped.insert(mount_point);
+                    } else {
+                        disk.refresh();
+                    }
+                }
+            }
+
+            DiskSnapshot::new(self.disks.iter().map(|x| {
+                let name = x.name().to_string_lossy().into_owned();
+                let mount_point = x.mount_point().to_string_lossy().into_owned();
+                let spared = skipped.contains(&mount_point);
+                let inodes = match (spared, self.disk_inodes.get(&mount_point)) {
+                    _ if !self.inodes_wanted => None,
+                    (true, Some(inodes)) => *inodes,
+                    _ => statvfs::inodes(x.mount_point()),
+                };
+                self.disk_inodes.insert(mount_point.clone(), inodes);
+
+                DiskInfo {
+                    name,
+                    mount_point,
+                    file_system: x.file_system().to_string_lossy().into_owned(),
+                    total_space: x.total_space(),
+                    available_space: x.available_space(),
+                    is_removable: x.is_removable(),
+                    inodes,
+                    spared,
+                }
+            }))
+        })
+    }
+
+    fn spare_disks(&mut self, spared: Spared) {
+        self.spared_disks = spared;
+    }
+
+    fn want_inodes(&mut self, wanted: bool) {
+        self.inodes_wanted = wanted;
+    }
+
+    fn network_rates(&mut self, interface: &str) -> Option<(f64, f64)> {
+        self.refresh_networks();
+        self.network_rates.get(interface).copied()
+    }
+
+    fn network_interfaces(&mut self) -> Vec<NetworkInterface> {
+        self.refresh_networks();
+        let mut interfaces: Vec<_> = self
+            .network_rates
+            .keys()
+            .map(|name| NetworkInterface {
+                name: name.clone(),
+                loopback: is_loopback(name),
+            })
+            .collect();
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        interfaces
+    }
+
+    fn network_totals(&mut self, interface: &str) -> Option<[u64; 2]> {
+        self.network_rates(interface)?;
+        self.networks
+            .get(interface)
+            .map(|x| [x.total_received(), x.total_transmitted()])
+    }
+
+    fn interface_addresses(&mut self, interface: &str) -> Option<Vec<IpAddr>> {
+        let fresh = self
+            .addresses
+            .as_ref()
+            .is_some_and(|(read, _)| read.elapsed() < ADDRESS_CACHE_DURATION);
+        if !fresh {
+            let addresses = ifaddrs::interface_addresses().unwrap_or_default();
+            self.addresses = Some((Instant::now(), addresses));
+        }
+
+        self.addresses.as_ref()?.1.get(interface).cloned()
+    }
+
+    fn batteries(&mut self) -> Vec<BatteryInfo> {
+        let root = Path::new(POWER_SUPPLY_PATH);
+        let mut batteries = read_batteries(root);
+        self.end_thresholds
+            .apply(root, &mut batteries, Instant::now());
+        batteries
+    }
+
+    fn components(&mut self) -> &[ComponentInfo] {
+        if !self.components_refreshed {
+            let components = match &mut self.components {
+                Some(x) => {
+                    x.refresh();
+                    x
+                }
+                None => self
+                    .components
+                    .insert(Components::new_with_refreshed_list()),
+            };
+            self.component_info = components
+                .iter()
+                .map(|x| ComponentInfo {
+                    label: x.label().to_string(),
+                    temperature: x.temperature(),
+                })
+                .collect();
+            self.components_refreshed = true;
+        }
+
+        &self.component_info
+    }
+
+    fn uptime(&mut self) -> u64 {
+        System::uptime()
+    }
+
+    fn brightness(&mut self, device: Option<&str>) -> Option<(u64, u64)> {
+        read_backlight(Path::new(BACKLIGHT_PATH), device)
+    }
+
+    fn keyboard_layout(&mut self) -> Result<String, String> {
+        self.keyboard.read(Instant::now())
+    }
+
+    fn volume(&mut self, control: &str) -> Option<alsa::Volume> {
+        alsa::volume(control)
+    }
+
+    fn public_ip(&mut self, url: &str, refresh_interval: Duration) -> Option<String> {
+        self.public_ip
+            .address(url, refresh_interval, Instant::now())
+    }
+
+    fn unread_mail(&mut self, maildir: &Path) -> Result<usize, String> {
+        maildir::count_new(maildir)
+    }
+
+    fn failed_units(&mut self) -> Result<usize, String> {
+        if let Some(failed) = &self.failed_units {
+            return failed.clone();
+        }
+
+        let failed = match &mut self.system_bus {
+            Some(x) => Ok(x),
+            None => dbus::Connection::system().map(|x| self.system_bus.insert(x)),
+        }
+        .and_then(|x| x.failed_units());
+        if failed.is_err() {
+            self.system_bus = None;
+        }
+
+        self.failed_units.insert(failed).clone()
+    }
+
+    fn bluetooth(&mut self) -> Result<bluetooth::Status, String> {
+        if let Some(status) = &self.bluetooth {
+            return status.clone();
+        }
+
+        let status = match &mut self.system_bus {
+            Some(x) => Ok(x),
+            None => dbus::Connection::system().map(|x| self.system_bus.insert(x)),
+        }
+        .and_then(|x| x.bluetooth());
+        if status.is_err() {
+            self.system_bus = None;
+        }
+
+        self.bluetooth.insert(status).clone()
+    }
+
+    fn mpd(&mut self, host: &str, port: u16) -> Result<mpd::Status, String> {
+        let key = (host.to_string(), port);
+        if let Some(status) = self.mpd_statuses.get(&key) {
+            return status.clone();
+        }
+
+        let status = self
+            .mpd_clients
+            .entry(key.clone())
+            .or_insert_with(|| mpd::Client::new(host, port))
+            .status(Instant::now());
+        self.mpd_statuses.entry(key).or_insert(status).clone()
+    }
+}
+
+/// Whether `interface` is a loopback interface, from its flags in sysfs. Interfaces whose
+/// flags can't be read aren't.
+fn is_loopback(interface: &str) -> bool {
+    std::fs::read_to_string(Path::new(NET_PATH).join(interface).join("flags"))
+        .ok()
+        .and_then(|x| u32::from_str_radix(x.trim().trim_start_matches("0x"), 16).ok())
+        .is_some_and(|x| x & LOOPBACK_FLAG != 0)
+}
+
+/// Reads the batteries among the power supplies in `root`, skipping ones without a charge level.
+fn read_batteries(root: &Path) -> Vec<BatteryInfo> {
+    let read =
+        |path: &Path, name: &str| fs::read_to_string(path.join(name)).map(|x| x.trim().to_string());
+
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+
+    let mut batteries: Vec<BatteryInfo> = entries
+        .flatten()
+        .map(|x| x.path())
+        .filter(|x| read(x, "type").is_ok_and(|x| x == "Battery"))
+        .filter_map(|path| {
+            Some(BatteryInfo {
+                name: path.file_name()?.to_string_lossy().into_owned(),
+                capacity: read(&path, "capacity").ok()?.parse::<u8>().ok()?.min(100),
+                state: match read(&path, "status").as_deref() {
+                    Ok("Charging") => ChargeState::Charging,
+                    Ok("Discharging") => ChargeState::Discharging,
+                    _ => ChargeState::Idle,
+                },
+                end_threshold: None,
+            })
+        })
+        .collect();
+    batteries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    batteries
+}
+
+/// Charge thresholds by battery, read again once they're [`END_THRESHOLD_CACHE_DURATION`]
+/// old.
+#[derive(Default)]
+struct EndThresholds {
+    read: Option<Instant>,
+    thresholds: HashMap<String, u8>,
+}
+
+impl EndThresholds {
+    /// Fills in the thresholds of `batteries`, which are in `root`.
+    fn apply(&mut self, root: &Path, batteries: &mut [BatteryInfo], now: Instant) {
+        if self
+            .read
+            .is_none_or(|x| now.duration_since(x) >= END_THRESHOLD_CACHE_DURATION)
+        {
+            self.thresholds = batteries
+                .iter()
+                .filter_map(|x| Some((x.name.clone(), read_end_threshold(&root.join(&x.name))?)))
+                .collect();
+            self.read = Some(now);
+        }
+
+        for battery in batteries {
+            battery.end_threshold = self.thresholds.get(&battery.name).copied();
+        }
+    }
+}
+
+/// The threshold of the battery at `path`, under the name current kernels use or the one
+/// older ThinkPad drivers do. Batteries without one are the norm, so nothing is logged.
+fn read_end_threshold(path: &Path) -> Option<u8> {
+    ["charge_control_end_threshold", "charge_stop_threshold"]
+        .iter()
+        .find_map(|name| fs::read_to_string(path.join(name)).ok())?
+        .trim()
+        .parse::<u8>()
+        .ok()
+        .filter(|x| (1..100).contains(x))
+}
+
+/// Reads the backlight `device` in `root`, or the first one by name. Backlights without a
+/// maximum brightness are skipped.
+fn read_backlight(root: &Path, device: Option<&str>) -> Option<(u64, u64)> {
+    let read = |path: &Path, name: &str| {
+        fs::read_to_string(path.join(name))
+            .ok()
+            .and_then(|x| x.trim().parse::<u64>().ok())
+    };
+    let brightness = |path: &Path| match (read(path, "brightness"), read(path, "max_brightness")) {
+        (Some(brightness), Some(max)) if max > 0 => Some((brightness.min(max), max)),
+        _ => None,
+    };
+
+    if let Some(device) = device {
+        return brightness(&root.join(device));
+    }
+
+    let mut paths: Vec<_> = fs::read_dir(root)
+        .ok()?
+        .flatten()
+        .map(|x| x.path())
+        .collect();
+    paths.sort();
+    paths.iter().find_map(|x| brightness(x))
+}
+
+/// Provider returning whatever values were set, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeProvider {
+    pub memory: (u64, u64),
+    pub cgroup_memory: Option<cgroup::Memory>,
+    pub swap: (u64, u64),
+    pub cpu_usage: f32,
+    pub cpu_frequencies: Vec<u64>,
+    pub process_count: usize,
+    pub processes: Vec<ProcessInfo>,
+    pub load_average: (f64, f64, f64),
+    pub disks: Vec<DiskInfo>,
+    pub network_rates: HashMap<String, (f64, f64)>,
+    pub network_totals: HashMap<String, [u64; 2]>,
+    /// Interfaces with rates that are loopback interfaces.
+    pub loopback: HashSet<String>,
+    pub addresses: HashMap<String, Vec<IpAddr>>,
+    pub batteries: Vec<(String, u8, ChargeState)>,
+    pub end_thresholds: HashMap<String, u8>,
+    pub components: Vec<ComponentInfo>,
+    pub uptime: u64,
+    disk_snapshot: DiskSnapshot,
+    pub volumes: HashMap<String, alsa::Volume>,
+    pub backlights: Vec<(String, u64, u64)>,
+    pub keyboard_layout: Option<String>,
+    pub public_ips: HashMap<String, String>,
+    /// Unread mail by Maildir, others act like missing directories.
+    pub maildirs: HashMap<std::path::PathBuf, usize>,
+    /// `None` acts like a system without systemd.
+    pub failed_units: Option<usize>,
+    /// `None` acts like BlueZ not running.
+    pub bluetooth: Option<bluetooth::Status>,
+    /// `None` acts like an unreachable server.
+    pub mpd: Option<mpd::Status>,
+}
+
+#[cfg(test)]
+impl FakeProvider {
+    pub fn set_memory(&mut self, used: u64, total: u64) -> &mut Self {
+        self.memory = (used, total);
+        self
+    }
+
+    pub fn set_cgroup_memory(&mut self, current: u64, max: Option<u64>) -> &mut Self {
+        self.cgroup_memory = Some(cgroup::Memory { current, max });
+        self
+    }
+
+    pub fn set_swap(&mut self, used: u64, total: u64) -> &mut Self {
+        self.swap = (used, total);
+        self
+    }
+
+    pub fn set_cpu_usage(&mut self, usage: f32) -> &mut Self {
+        self.cpu_usage = usage;
+        self
+    }
+
+    pub fn set_cpu_frequencies(&mut self, mhz: &[u64]) -> &mut Self {
+        self.cpu_frequencies = mhz.to_vec();
+        self
+    }
+
+    pub fn set_process_count(&mut self, count: usize) -> &mut Self {
+        self.process_count = count;
+        self
+    }
+
+    pub fn add_process(
+        &mut self,
+        name: &str,
+        cpu_usage: f32,
+        memory: u64,
+        kernel_thread: bool,
+    ) -> &mut Self {
+        self.processes.push(ProcessInfo {
+            name: name.into(),
+            cpu_usage,
+            memory,
+            kernel_thread,
+        });
+        self
+    }
+
+    pub fn set_load_average(&mut self, one: f64, five: f64, fifteen: f64) -> &mut Self {
+        self.load_average = (one, five, fifteen);
+        self
+    }
+
+    pub fn add_disk(
+        &mut self,
+        name: &str,
+        total: u64,
+        available: u64,
+        removable: bool,
+    ) -> &mut Self {
+        self.disks.push(DiskInfo {
+            name: name.into(),
+            mount_point: String::new(),
+            file_system: "ext4".into(),
+            total_space: total,
+            available_space: available,
+            is_removable: removable,
+            inodes: None,
+            spared: false,
+        });
+        self
+    }
+
+    pub fn set_network_rates(
+        &mut self,
+        interface: &str,
+        received: f64,
+        transmitted: f64,
+    ) -> &mut Self {
+        self.network_rates
+            .insert(interface.into(), (received, transmitted));
+        self
+    }
+
+    pub fn set_network_totals(&mut self, interface: &str, totals: [u64; 2]) -> &mut Self {
+        self.network_totals.insert(interface.into(), totals);
+        self
+    }
+
+    /// Adds an address to `interface`, e.g. "192.168.1.23". An empty `address` only adds the
+    /// interface.
+    pub fn add_address(&mut self, interface: &str, address: &str) -> &mut Self {
+        let addresses = self.addresses.entry(interface.into()).or_default();
+        if !address.is_empty() {
+            addresses.push(address.parse().unwrap());
+        }
+        self
+    }
+
+    pub fn add_battery(&mut self, name: &str, capacity: u8, state: ChargeState) -> &mut Self {
+        self.batteries.push((name.into(), capacity, state));
+        self
+    }
+
+    pub fn set_end_threshold(&mut self, battery: &str, threshold: u8) -> &mut Self {
+        self.end_thresholds.insert(battery.into(), threshold);
+        self
+    }
+
+    pub fn add_component(&mut self, label: &str, temperature: f32) -> &mut Self {
+        self.components.push(ComponentInfo {
+            label: label.into(),
+            temperature,
+        });
+        self
+    }
+
+    pub fn set_uptime(&mut self, secs: u64) -> &mut Self {
+        self.uptime = secs;
+        self
+    }
+
+    pub fn add_backlight(&mut self, device: &str, brightness: u64, max: u64) -> &mut Self {
+        self.backlights.push((device.into(), brightness, max));
+        self
+    }
+
+    pub fn set_keyboard_layout(&mut self, layout: &str) -> &mut Self {
+        self.keyboard_layout = Some(layout.into());
+        self
+    }
+
+    pub fn set_public_ip(&mut self, url: &str, address: &str) -> &mut Self {
+        self.public_ips.insert(url.into(), address.into());
+        self
+    }
+
+    pub fn set_unread_mail(&mut self, maildir: &str, count: usize) -> &mut Self {
+        self.maildirs.insert(maildir.into(), count);
+        self
+    }
+
+    pub fn set_failed_units(&mut self, count: usize) -> &mut Self {
+        self.failed_units = Some(count);
+        self
+    }
+
+    pub fn set_bluetooth(&mut self, status: bluetooth::Status) -> &mut Self {
+        self.bluetooth = Some(status);
+        self
+    }
+
+    pub fn set_mpd(&mut self, status: mpd::Status) -> &mut Self {
+        self.mpd = Some(status);
+        self
+    }
+
+    pub fn set_volume(&mut self, control: &str, percent: f64, muted: bool) -> &mut Self {
+        self.volumes
+            .insert(control.into(), alsa::Volume { percent, muted });
+        self
+    }
+
+    /// Sets the mount point of the last added disk.
+    pub fn mounted_at(&mut self, mount_point: &str) -> &mut Self {
+        if let Some(disk) = self.disks.last_mut() {
+            disk.mount_point = mount_point.into();
+        }
+        self
+    }
+
+    /// Sets the inode usage of the last added disk.
+    pub fn with_inodes(&mut self, used: u64, total: u64) -> &mut Self {
+        if let Some(disk) = self.disks.last_mut() {
+            disk.inodes = Some((used, total));
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+impl SystemProvider for FakeProvider {
+    fn begin_tick(&mut self) {}
+
+    fn memory(&mut self) -> (u64, u64) {
+        self.memory
+    }
+
+    fn cgroup_memory(&mut self) -> Option<cgroup::Memory> {
+        self.cgroup_memory
+    }
+
+    fn swap(&mut self) -> (u64, u64) {
+        self.swap
+    }
+
+    fn cpu_usage(&mut self) -> f32 {
+        self.cpu_usage
+    }
+
+    fn cpu_frequencies(&mut self) -> Vec<u64> {
+        self.cpu_frequencies.clone()
+    }
+
+    fn process_count(&mut self) -> usize {
+        self.process_count
+    }
+
+    fn processes(&mut self) -> &[ProcessInfo] {
+        &self.processes
+    }
+
+    fn load_average(&mut self) -> (f64, f64, f64) {
+        self.load_average
+    }
+
+    fn disks(&mut self) -> &DiskSnapshot {
+        self.disk_snapshot = DiskSnapshot::new(self.disks.clone());
+        &self.disk_snapshot
+    }
+
+    fn network_rates(&mut self, interface: &str) -> Option<(f64, f64)> {
+        self.network_rates.get(interface).copied()
+    }
+
+    fn network_totals(&mut self, interface: &str) -> Option<[u64; 2]> {
+        self.network_totals.get(interface).copied()
+    }
+
+    fn network_interfaces(&mut self) -> Vec<NetworkInterface> {
+        let mut interfaces: Vec<_> = self
+            .network_rates
+            .keys()
+            .map(|name| NetworkInterface {
+                name: name.clone(),
+                loopback: self.loopback.contains(name),
+            })
+            .collect();
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        interfaces
+    }
+
+    fn interface_addresses(&mut self, interface: &str) -> Option<Vec<IpAddr>> {
+        self.addresses.get(interface).cloned()
+    }
+
+    fn batteries(&mut self) -> Vec<BatteryInfo> {
+        self.batteries
+            .iter()
+            .map(|(name, capacity, state)| BatteryInfo {
+                name: name.clone(),
+                capacity: *capacity,
+                state: *state,
+                end_threshold: self.end_thresholds.get(name).copied(),
+            })
+            .collect()
+    }
+
+    fn components(&mut self) -> &[ComponentInfo] {
+        &self.components
+    }
+
+    fn uptime(&mut self) -> u64 {
+        self.uptime
+    }
+
+    fn brightness(&mut self, device: Option<&str>) -> Option<(u64, u64)> {
+        self.backlights
+            .iter()
+            .find(|x| device.is_none_or(|device| x.0 == device))
+            .map(|x| (x.1, x.2))
+    }
+
+    fn keyboard_layout(&mut self) -> Result<String, String> {
+        self.keyboard_layout
+            .clone()
+            .ok_or_else(|| "unable to open the X display".into())
+    }
+
+    fn volume(&mut self, control: &str) -> Option<alsa::Volume> {
+        self.volumes.get(control).copied()
+    }
+
+    fn public_ip(&mut self, url: &str, _refresh_interval: Duration) -> Option<String> {
+        self.public_ips.get(url).cloned()
+    }
+
+    fn unread_mail(&mut self, maildir: &Path) -> Result<usize, String> {
+        self.maildirs.get(maildir).copied().ok_or_else(|| {
+            format!(
+                "{}: No such file or directory (os error 2)",
+                maildir.join("new").display()
+            )
+        })
+    }
+
+    fn failed_units(&mut self) -> Result<usize, String> {
+        self.failed_units
+            .ok_or_else(|| format!("{}: No such file or directory", dbus::SYSTEM_BUS_PATH))
+    }
+
+    fn bluetooth(&mut self) -> Result<bluetooth::Status, String> {
+        self.bluetooth.clone().ok_or_else(|| {
+            "org.freedesktop.DBus.Error.ServiceUnknown: The name org.bluez was not provided by \
+             any .service files"
+                .into()
+        })
+    }
+
+    fn mpd(&mut self, host: &str, port: u16) -> Result<mpd::Status, String> {
+        self.mpd
+            .clone()
+            .ok_or_else(|| format!("{host}:{port}: Connection refused (os error 111)"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_sampling_interval() {
+        let mut provider = SysinfoProvider::new();
+
+        provider.cpu_usage();
+        let first = provider.cpu_refreshed.unwrap();
+        provider.cpu_usage();
+        assert_eq!(Some(first), provider.cpu_refreshed);
+
+        std::thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL);
+        provider.cpu_usage();
+        assert!(provider.cpu_refreshed.unwrap() > first);
+    }
+
+    #[test]
+    fn cpu_refresh_shared() {
+        let mut provider = SysinfoProvider::new();
+
+        provider.cpu_usage();
+        let first = provider.cpu_refreshed.unwrap();
+        let cores = provider.cpu_frequencies().len();
+        assert_eq!(provider.system.cpus().len(), cores);
+        assert_eq!(Some(first), provider.cpu_refreshed);
+        assert!(provider.cpu_frequencies_wanted);
+    }
+
+    #[test]
+    fn network_rates_once_per_tick() {
+        let mut provider = SysinfoProvider::new();
+
+        assert_eq!(Some((0.0, 0.0)), provider.network_rates("lo"));
+        let first = provider.networks_sampled.unwrap();
+        assert_eq!(None, provider.network_rates("no-such-interface"));
+        assert!(provider.network_totals("lo").is_some());
+        assert_eq!(None, provider.network_totals("no-such-interface"));
+        assert_eq!(Some(first), provider.networks_sampled);
+
+        provider.begin_tick();
+        provider.network_rates("lo");
+        assert!(provider.networks_sampled.unwrap() > first);
+    }
+
+    #[test]
+    fn power_supplies() {
+        let root =
+            std::env::temp_dir().join(format!("stringbar-power-supply-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let supply = |name: &str, files: &[(&str, &str)]| {
+            let dir = root.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            for (file, content) in files {
+                fs::write(dir.join(file), format!("{content}\n")).unwrap();
+            }
+        };
+
+        supply("AC", &[("type", "Mains"), ("online", "1")]);
+        supply(
+            "BAT1",
+            &[("type", "Battery"), ("capacity", "100"), ("status", "Full")],
+        );
+        supply(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("capacity", "85"),
+                ("status", "Charging"),
+            ],
+        );
+        // Some drivers only report energy levels.
+        supply("BAT2", &[("type", "Battery"), ("status", "Discharging")]);
+
+        let batteries: Vec<_> = read_batteries(&root)
+            .into_iter()
+            .map(|x| (x.name, x.capacity, x.state))
+            .collect();
+        assert_eq!(
+            vec![
+                ("BAT0".to_string(), 85, ChargeState::Charging),
+                ("BAT1".to_string(), 100, ChargeState::Idle),
+            ],
+            batteries
+        );
+
+        assert!(read_batteries(&root.join("missing")).is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn end_thresholds() {
+        let root =
+            std::env::temp_dir().join(format!("stringbar-end-threshold-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let supply = |name: &str, files: &[(&str, &str)]| {
+            let dir = root.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("type"), "Battery\n").unwrap();
+            fs::write(dir.join("capacity"), "80\n").unwrap();
+            for (file, content) in files {
+                fs::write(dir.join(file), format!("{content}\n")).unwrap();
+            }
+        };
+
+        supply("BAT0", &[("charge_control_end_threshold", "80")]);
+        supply("BAT1", &[("charge_stop_threshold", "60")]);
+        supply("BAT2", &[]);
+        supply("BAT3", &[("charge_control_end_threshold", "100")]);
+        supply("BAT4", &[("charge_control_end_threshold", "on")]);
+
+        let thresholds = |cache: &mut EndThresholds, now| {
+            let mut batteries = read_batteries(&root);
+            cache.apply(&root, &mut batteries, now);
+            batteries
+                .into_iter()
+                .map(|x| x.end_threshold)
+                .collect::<Vec<_>>()
+        };
+        let mut cache = EndThresholds::default();
+        let start = Instant::now();
+        assert_eq!(
+            vec![Some(80), Some(60), None, None, None],
+            thresholds(&mut cache, start)
+        );
+
+        // Changes only show up once the cached thresholds are old enough.
+        fs::write(root.join("BAT0/charge_control_end_threshold"), "90\n").unwrap();
+        fs::write(root.join("BAT2/charge_control_end_threshold"), "75\n").unwrap();
+        assert_eq!(
+            vec![Some(80), Some(60), None, None, None],
+            thresholds(&mut cache, start + Duration::from_secs(1))
+        );
+        assert_eq!(
+            vec![Some(90), Some(60), Some(75), None, None],
+            thresholds(&mut cache, start + END_THRESHOLD_CACHE_DURATION)
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn backlights() {
+        let root = std::env::temp_dir().join(format!("stringbar-backlight-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let backlight = |name: &str, brightness: &str, max: &str| {
+            let dir = root.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("brightness"), format!("{brightness}\n")).unwrap();
+            fs::write(dir.join("max_brightness"), format!("{max}\n")).unwrap();
+        };
+
+        backlight("intel_backlight", "7500", "19200");
+        backlight("acpi_video0", "5", "0");
+        backlight("ddcci0", "40", "100");
+
+        assert_eq!(Some((40, 100)), read_backlight(&root, None));
+        assert_eq!(
+            Some((7500, 19200)),
+            read_backlight(&root, Some("intel_backlight"))
+        );
+        assert_eq!(None, read_backlight(&root, Some("acpi_video0")));
+        assert_eq!(None, read_backlight(&root, Some("missing")));
+
+        // Unplugging an external monitor removes its backlight.
+        fs::remove_dir_all(root.join("ddcci0")).unwrap();
+        assert_eq!(Some((7500, 19200)), read_backlight(&root, None));
+        assert_eq!(None, read_backlight(&root, Some("ddcci0")));
+
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(None, read_backlight(&root, None));
+    }
+}
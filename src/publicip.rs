@@ -0,0 +1,245 @@
+//! The machine's public address for [`Module::PublicIp`](crate::config::Module::PublicIp).
+//!
+//! stringbar has no HTTP client, so curl fetches it on a background thread. Updates only read
+//! the last fetched address and start a new fetch when it's due, an offline machine waiting on
+//! DNS never delays the bar.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    process::Command,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{spawn, warn_rate_limited};
+
+pub const DEFAULT_URL: &str = "https://icanhazip.com";
+/// Shortest accepted refresh interval, public services rate limit clients polling faster.
+pub const MIN_REFRESH_INTERVAL_S: u64 = 60;
+/// Failed fetches are retried sooner than the refresh interval, so a laptop coming online
+/// shows its address within a minute.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const FAILURE_WARNING_PERIOD: Duration = Duration::from_secs(3600);
+
+type Fetch = dyn Fn(&str) -> Result<String, String> + Send + Sync;
+
+#[derive(Default)]
+struct State {
+    address: Option<String>,
+    /// When the last finished fetch started, and whether it succeeded.
+    fetched: Option<(Instant, bool)>,
+    in_flight: bool,
+}
+
+pub struct PublicIp {
+    /// By URL.
+    states: HashMap<String, Arc<Mutex<State>>>,
+    fetch: Arc<Fetch>,
+}
+
+impl PublicIp {
+    pub fn new() -> Self {
+        Self::with_fetch(curl)
+    }
+
+    fn with_fetch(fetch: impl Fn(&str) -> Result<String, String> + Send + Sync + 'static) -> Self {
+        Self {
+            states: HashMap::new(),
+            fetch: Arc::new(fetch),
+        }
+    }
+
+    /// The last address fetched from `url`, `None` before the first fetch succeeds. Starts a
+    /// fetch in the background if the last one is older than `refresh_interval`, never blocks.
+    pub fn address(
+        &mut self,
+        url: &str,
+        refresh_interval: Duration,
+        now: Instant,
+    ) -> Option<String> {
+        let state = self.states.entry(url.into()).or_default();
+        let mut locked = state.lock().unwrap();
+
+        let due = match locked.fetched {
+            None => true,
+            Some((started, true)) => now.saturating_duration_since(started) >= refresh_interval,
+            Some((started, false)) => {
+                now.saturating_duration_since(started) >= refresh_interval.min(RETRY_INTERVAL)
+            }
+        };
+        if due && !locked.in_flight {
+            locked.in_flight = true;
+            let (state, fetch, url) = (state.clone(), self.fetch.clone(), url.to_string());
+            thread::spawn(move || {
+                let result = fetch(&url);
+                let mut state = state.lock().unwrap();
+                // Measured from the start, so slow fetches don't stretch the interval.
+                state.fetched = Some((now, result.is_ok()));
+                state.in_flight = false;
+                match result {
+                    // A failed refresh keeps showing the previous address.
+                    Ok(address) => state.address = Some(address),
+                    Err(e) => warn_rate_limited!(
+                        format!("public-ip:{url}"),
+                        FAILURE_WARNING_PERIOD,
+                        "Unable to fetch the public IP from {url}: {e}"
+                    ),
+                }
+            });
+        }
+
+        locked.address.clone()
+    }
+}
+
+/// Fetches `url` with curl and checks the response is an address.
+fn curl(url: &str) -> Result<String, String> {
+    let output = spawn::run(
+        Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--max-time"])
+            .arg(FETCH_TIMEOUT.as_secs().to_string())
+            .arg(url),
+        FETCH_TIMEOUT + Duration::from_secs(1),
+    )
+    .map_err(|e| format!("unable to run curl: {e}"))?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().into());
+    }
+    parse_address(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The address in a plain text response, rejecting anything else, e.g. a captive portal's
+/// login page.
+fn parse_address(response: &str) -> Result<String, String> {
+    let response = response.trim();
+    response
+        .parse::<IpAddr>()
+        .map(|x| x.to_string())
+        .map_err(|_| {
+            format!(
+                "unexpected response {:?}",
+                response.chars().take(40).collect::<String>()
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    const URL: &str = "https://example.com/ip";
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    impl PublicIp {
+        /// Waits for background fetches to finish.
+        fn settle(&self) {
+            let start = Instant::now();
+            while self.states.values().any(|x| x.lock().unwrap().in_flight) {
+                assert!(start.elapsed() < Duration::from_secs(5));
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    /// Fetches that answer from `responses` in order, counting how often they run.
+    fn scripted(
+        responses: Vec<Result<&'static str, &'static str>>,
+    ) -> (PublicIp, mpsc::Receiver<()>) {
+        let (tx, rx) = mpsc::channel();
+        let responses = Mutex::new(responses.into_iter());
+        let public_ip = PublicIp::with_fetch(move |_| {
+            tx.send(()).unwrap();
+            match responses.lock().unwrap().next() {
+                Some(Ok(x)) => Ok(x.into()),
+                Some(Err(e)) => Err(e.into()),
+                None => Err("no more responses".into()),
+            }
+        });
+        (public_ip, rx)
+    }
+
+    #[test]
+    fn caching() {
+        let (mut public_ip, fetches) = scripted(vec![
+            Ok("203.0.113.7"),
+            Err("Could not resolve host"),
+            Ok("203.0.113.8"),
+        ]);
+        let start = Instant::now();
+
+        assert_eq!(None, public_ip.address(URL, HOUR, start));
+        public_ip.settle();
+        assert_eq!(
+            Some("203.0.113.7".into()),
+            public_ip.address(URL, HOUR, start)
+        );
+        assert_eq!(
+            Some("203.0.113.7".into()),
+            public_ip.address(URL, HOUR, start + HOUR / 2)
+        );
+        assert_eq!(1, fetches.try_iter().count());
+
+        // The refresh fails, the stale address stays.
+        assert_eq!(
+            Some("203.0.113.7".into()),
+            public_ip.address(URL, HOUR, start + HOUR)
+        );
+        public_ip.settle();
+        assert_eq!(
+            Some("203.0.113.7".into()),
+            public_ip.address(URL, HOUR, start + HOUR + RETRY_INTERVAL / 2)
+        );
+        assert_eq!(1, fetches.try_iter().count());
+
+        // Retried sooner than the refresh interval.
+        public_ip.address(URL, HOUR, start + HOUR + RETRY_INTERVAL);
+        public_ip.settle();
+        assert_eq!(1, fetches.try_iter().count());
+        assert_eq!(
+            Some("203.0.113.8".into()),
+            public_ip.address(URL, HOUR, start + HOUR + RETRY_INTERVAL)
+        );
+    }
+
+    #[test]
+    fn never_blocks() {
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        let mut public_ip = PublicIp::with_fetch(move |_| {
+            released.lock().unwrap().recv().unwrap();
+            Ok("2001:db8::1".into())
+        });
+        let start = Instant::now();
+
+        // Updates while the fetch hangs neither wait nor start another one.
+        for second in 0..100 {
+            let now = start + Duration::from_secs(second);
+            assert_eq!(None, public_ip.address(URL, Duration::from_secs(60), now));
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        release.send(()).unwrap();
+        public_ip.settle();
+        assert_eq!(
+            Some("2001:db8::1".into()),
+            public_ip.address(URL, Duration::from_secs(60), start)
+        );
+    }
+
+    #[test]
+    fn responses() {
+        assert_eq!(Ok("203.0.113.7".into()), parse_address("203.0.113.7\n"));
+        assert_eq!(Ok("2001:db8::5".into()), parse_address(" 2001:0db8::5\r\n"));
+        assert_eq!(
+            Err("unexpected response \"<html><head><title>Hotel WiFi</title></h\"".into()),
+            parse_address("<html><head><title>Hotel WiFi</title></head></html>")
+        );
+        assert_eq!(Err("unexpected response \"\"".into()), parse_address(""));
+    }
+}
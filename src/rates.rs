@@ -0,0 +1,206 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::config::Module;
+
+/// Samples kept per window at most, so long windows at short update intervals stay bounded.
+/// Once full the oldest samples go first, shortening the window.
+const MAX_SAMPLES: usize = 1024;
+
+/// Recent samples of a rate, oldest first, for averages and peaks over the last few seconds.
+#[derive(Default)]
+pub struct Window {
+    samples: VecDeque<(Instant, f64)>,
+}
+
+impl Window {
+    /// Records `rate` and returns the rate to show, the average over `average_over` if set, and
+    /// the peak over `peak_hold` if set.
+    pub fn sample(
+        &mut self,
+        now: Instant,
+        rate: f64,
+        average_over: Option<Duration>,
+        peak_hold: Option<Duration>,
+    ) -> (f64, Option<f64>) {
+        self.push(now, rate, average_over.max(peak_hold).unwrap_or_default());
+        let shown = average_over
+            .and_then(|x| self.average(now, x))
+            .unwrap_or(rate);
+
+        (shown, peak_hold.and_then(|x| self.peak(now, x)))
+    }
+
+    /// Records `value` taken at `now` and forgets samples older than `keep`.
+    fn push(&mut self, now: Instant, value: f64, keep: Duration) {
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((now, value));
+        while self
+            .samples
+            .front()
+            .is_some_and(|(time, _)| now.duration_since(*time) >= keep)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Mean of the samples taken within `span` before `now`.
+    fn average(&self, now: Instant, span: Duration) -> Option<f64> {
+        let (count, sum) = self
+            .recent(now, span)
+            .fold((0, 0.0), |(count, sum), x| (count + 1, sum + x));
+        (count > 0).then(|| sum / count as f64)
+    }
+
+    /// Largest sample taken within `span` before `now`.
+    fn peak(&self, now: Instant, span: Duration) -> Option<f64> {
+        self.recent(now, span).reduce(f64::max)
+    }
+
+    fn recent(&self, now: Instant, span: Duration) -> impl Iterator<Item = f64> + '_ {
+        self.samples
+            .iter()
+            .filter(move |(time, _)| now.duration_since(*time) < span)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Bytes an interface received and transmitted since stringbar started or they were zeroed.
+#[derive(Default)]
+struct Totals {
+    /// The interface's own counters when they were last read.
+    counters: [u64; 2],
+    totals: [u64; 2],
+}
+
+/// Receive and transmit windows and session totals of rate sections.
+///
+/// Windows are keyed by module like [`Extremes`](crate::extremes::Extremes), so they survive
+/// sections being reordered on reload while changing a section's window sizes starts over.
+/// Totals are keyed by interface, so they survive any change to the section.
+#[derive(Default)]
+pub struct Rates {
+    windows: HashMap<Module, [Window; 2]>,
+    totals: HashMap<String, Totals>,
+}
+
+impl Rates {
+    pub fn windows(&mut self, module: &Module) -> &mut [Window; 2] {
+        self.windows.entry(module.clone()).or_default()
+    }
+
+    /// Forgets a module's samples, e.g. when its interface went away and its counters start
+    /// over.
+    pub fn reset(&mut self, module: &Module) {
+        self.windows.remove(module);
+    }
+
+    /// Adds what `interface` transferred since its `counters` were last read to its session
+    /// totals and returns them. The first read only sets the baseline. Counters going backwards
+    /// started over, e.g. because the interface was recreated, everything they count was
+    /// transferred since.
+    pub fn totals(&mut self, interface: &str, counters: [u64; 2]) -> [u64; 2] {
+        let Some(entry) = self.totals.get_mut(interface) else {
+            self.totals.insert(
+                interface.into(),
+                Totals {
+                    counters,
+                    totals: [0; 2],
+                },
+            );
+            return [0; 2];
+        };
+
+        for ((total, last), counter) in entry
+            .totals
+            .iter_mut()
+            .zip(&mut entry.counters)
+            .zip(counters)
+        {
+            *total = total.saturating_add(counter.checked_sub(*last).unwrap_or(counter));
+            *last = counter;
+        }
+        entry.totals
+    }
+
+    /// Zeroes the session totals of `interface`, counting on from its current counters.
+    pub fn reset_totals(&mut self, interface: &str) {
+        if let Some(entry) = self.totals.get_mut(interface) {
+            entry.totals = [0; 2];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let (span, keep) = (Duration::from_secs(3), Duration::from_secs(5));
+        let mut window = Window::default();
+        assert_eq!(None, window.average(at(0), span));
+        assert_eq!(None, window.peak(at(0), span));
+
+        // Average over 3s and peak over 5s, as samples come in each second.
+        let steps = [
+            (100.0, 100.0, 100.0),
+            (300.0, 200.0, 300.0),
+            (200.0, 200.0, 300.0),
+            (0.0, 500.0 / 3.0, 300.0),
+            (0.0, 200.0 / 3.0, 300.0),
+            (0.0, 0.0, 300.0),
+            (0.0, 0.0, 200.0),
+            (0.0, 0.0, 0.0),
+        ];
+        for (secs, (value, average, peak)) in steps.into_iter().enumerate() {
+            let now = at(secs as u64);
+            window.push(now, value, keep);
+            assert_eq!(Some(average), window.average(now, span), "{secs}s");
+            assert_eq!(Some(peak), window.peak(now, keep), "{secs}s");
+        }
+
+        // A gap longer than the window leaves only the new sample.
+        window.push(at(7), 400.0, keep);
+        window.push(at(60), 50.0, keep);
+        assert_eq!(Some(50.0), window.average(at(60), span));
+        assert_eq!(Some(50.0), window.peak(at(60), keep));
+    }
+
+    #[test]
+    fn totals() {
+        let mut rates = Rates::default();
+        assert_eq!([0, 0], rates.totals("eth0", [5000, 700]));
+        assert_eq!([1000, 300], rates.totals("eth0", [6000, 1000]));
+        assert_eq!([0, 0], rates.totals("wlan0", [10, 10]));
+
+        // The counters started over, e.g. after the interface was recreated.
+        assert_eq!([1200, 400], rates.totals("eth0", [200, 100]));
+        assert_eq!([1300, 400], rates.totals("eth0", [300, 100]));
+
+        rates.reset_totals("eth0");
+        assert_eq!([50, 10], rates.totals("eth0", [350, 110]));
+        assert_eq!([5, 5], rates.totals("wlan0", [15, 15]));
+    }
+
+    #[test]
+    fn bounded() {
+        let start = Instant::now();
+        let keep = Duration::from_secs(3600);
+        let mut window = Window::default();
+        for i in 0..MAX_SAMPLES + 10 {
+            window.push(start + Duration::from_millis(i as u64), i as f64, keep);
+        }
+
+        assert_eq!(MAX_SAMPLES, window.samples.len());
+        let now = start + Duration::from_millis(MAX_SAMPLES as u64 + 9);
+        assert_eq!(Some((MAX_SAMPLES + 9) as f64), window.peak(now, keep));
+        assert_eq!(Some(10.0), window.samples.front().map(|x| x.1));
+    }
+}
@@ -0,0 +1,3758 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    process,
+    time::{Duration, Instant},
+};
+
+use bittenhumans::{consts::UnitStyle, format_exact};
+use chrono::{DateTime, FixedOffset};
+use directories::BaseDirs;
+use tracing::error;
+
+use crate::{
+    alsa,
+    bar::Piece,
+    cgroup,
+    config::{
+        Config, Decoration, DiskFilter, FreqAggregate, MemoryBasis, Module, OutputBackend, Section,
+        SortKey, TempAggregate, UptimeFormat, UsageDisplay,
+    },
+    disks::DiskSnapshot,
+    duration, dwm,
+    expr::Expr,
+    extremes::Extremes,
+    format::{
+        format_byte_rate, format_byte_usage, format_bytes, format_frequency, format_number,
+        format_percent, UnitPolicy,
+    },
+    health, ifaddrs, maildir, mpd,
+    provider::{BatteryInfo, ChargeState, ComponentInfo, DiskInfo, ProcessInfo, SystemProvider},
+    rates::Rates,
+    sanitize, spawn,
+    template::{self, Spec},
+    text,
+    timer::{TimerDisplay, Timers},
+    unit::{Reading, Unit, Value},
+    urgency::Urgency,
+    validity::{Checked, Validity},
+    warn, warn_once, warn_rate_limited,
+};
+
+/// How often a section's out of range values are logged.
+const INVALID_VALUE_WARNING_PERIOD: Duration = Duration::from_secs(60);
+const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_millis(200);
+/// How often a command section's failures and stderr output are logged.
+const COMMAND_ERROR_PERIOD: Duration = Duration::from_secs(60);
+
+/// Everything a module may read while being rendered.
+pub struct Context<'a> {
+    /// Index of the section being rendered, used to key per-section log messages.
+    pub section: usize,
+    pub provider: &'a mut dyn SystemProvider,
+    pub timers: &'a Timers,
+    pub extremes: &'a mut Extremes,
+    pub validity: &'a mut Validity,
+    /// Recent samples of rate sections, for averages and peaks.
+    pub rates: &'a mut Rates,
+    /// Latest values of sections with an id, for [`Module::Derived`].
+    pub values: &'a mut HashMap<String, f64>,
+    /// Latest values of all sections by index, for the control socket's `metrics`.
+    pub readings: &'a mut HashMap<usize, Reading>,
+    /// Threshold state for urgency and hysteresis, and sections made urgent by hand.
+    pub urgency: &'a mut Urgency,
+    /// When the tick started, every section in a tick sees the same time.
+    pub now: Instant,
+    pub local: DateTime<FixedOffset>,
+}
+
+/// Renders a section, including its decoration and any output markup.
+pub fn render_section(section: &Section, config: &Config, ctx: &mut Context) -> Piece {
+    match &section.module {
+        Module::Separator { content, fg } => {
+            let status2d = config.output.dwm_markup().0;
+            Piece::Separator(dwm::wrap(content, 0, fg.as_deref(), None, status2d, false))
+        }
+        Module::Battery {
+            name,
+            hide_when_missing,
+            ..
+        } => match find_battery(name.as_deref(), ctx.provider.batteries()) {
+            None if *hide_when_missing => {
+                record_value(section, None, ctx);
+                Piece::Hidden
+            }
+            battery => text_piece(section, config, ctx, &Fetched::Battery(battery)),
+        },
+        Module::Maildir { path } => match unread_mail(path, ctx) {
+            Some(0) => {
+                record_value(section, Some(Value::new(0.0, Unit::Count)), ctx);
+                Piece::Hidden
+            }
+            count => text_piece(section, config, ctx, &Fetched::UnreadMail(count)),
+        },
+        Module::SystemdFailed => match failed_units(ctx) {
+            failed @ (Some(0) | None) => {
+                record_value(
+                    section,
+                    failed.map(|x| Value::new(x as f64, Unit::Count)),
+                    ctx,
+                );
+                Piece::Hidden
+            }
+            Some(_) => text_piece(section, config, ctx, &Fetched::Nothing),
+        },
+        Module::BackendHealth => match health::status() {
+            (None, failures) => {
+                record_value(section, Some(Value::new(failures as f64, Unit::Count)), ctx);
+                Piece::Hidden
+            }
+            _ => text_piece(section, config, ctx, &Fetched::Nothing),
+        },
+        Module::Mpd { host, port, .. } => match mpd_status(host, *port, ctx) {
+            Some(status) if status.state != mpd::State::Stop => {
+                text_piece(section, config, ctx, &Fetched::Nothing)
+            }
+            _ => Piece::Hidden,
+        },
+        _ => text_piece(section, config, ctx, &Fetched::Nothing),
+    }
+}
+
+/// What [`render_section`] already read to decide whether a section shows, handed on so it
+/// isn't read again.
+enum Fetched {
+    Nothing,
+    Battery(Option<BatteryInfo>),
+    UnreadMail(Option<usize>),
+}
+
+fn text_piece(section: &Section, config: &Config, ctx: &mut Context, fetched: &Fetched) -> Piece {
+    let (status2d, statuscmd) = config.output.dwm_markup();
+    let Some((text, compact, decoration, urgent)) = render_text(section, config, ctx, fetched)
+    else {
+        return Piece::Hidden;
+    };
+    let urgent = urgent || ctx.urgency.is_forced(ctx.section, section);
+    let finish = |text: String| {
+        // I3bar marks the block instead.
+        let text = match (urgent, &config.output) {
+            (true, OutputBackend::I3bar { .. }) | (false, _) => text,
+            (true, _) => format!("!{text}"),
+        };
+        dwm::wrap(
+            &text,
+            ctx.section + 1,
+            decoration.fg.as_deref(),
+            decoration.bg.as_deref(),
+            status2d,
+            statuscmd,
+        )
+    };
+
+    Piece::Text(
+        finish(text),
+        decoration.fg.clone(),
+        urgent,
+        compact.map(finish),
+    )
+}
+
+/// The section's text, the same with its `compact_template`, the decoration it was rendered
+/// with and whether a threshold makes it urgent, `None` while its `hide_when` matches.
+fn render_text<'a>(
+    section: &'a Section,
+    config: &Config,
+    ctx: &mut Context,
+    fetched: &Fetched,
+) -> Option<(String, Option<String>, &'a Decoration, bool)> {
+    match expand_entries(section, config, ctx) {
+        Some((entries, separator)) => {
+            if entries.is_empty() {
+                return Some((
+                    decorate(
+                        section,
+                        &section.decoration,
+                        config,
+                        "N/A",
+                        &[("{name}", "")],
+                    ),
+                    None,
+                    &section.decoration,
+                    false,
+                ));
+            }
+
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(name, module_out)| {
+                    decorate(
+                        section,
+                        &section.decoration,
+                        config,
+                        module_out,
+                        &[("{name}", name)],
+                    )
+                })
+                .collect();
+
+            Some((
+                entries.join(separator.unwrap_or(&config.separator)),
+                None,
+                &section.decoration,
+                false,
+            ))
+        }
+        None => {
+            let (module_out, value) = render_module(section, config, ctx, fetched);
+            let (module_out, value) = match (section.valid_range(), value) {
+                (Some(range), Some(Value { amount, unit })) => match ctx.validity.check(
+                    &section.module,
+                    range,
+                    module_out,
+                    amount,
+                    &config.invalid_value_marker,
+                ) {
+                    Checked::Valid(output, amount) => (output, Some(Value::new(amount, unit))),
+                    Checked::Replaced(output, last_amount) => {
+                        warn_rate_limited!(
+                            format!("{}:range", ctx.section),
+                            INVALID_VALUE_WARNING_PERIOD,
+                            "Section {}: {} reported {amount}, outside of {}-{}",
+                            ctx.section + 1,
+                            section.module.name(),
+                            range.0,
+                            range.1
+                        );
+                        (output, last_amount.map(|x| Value::new(x, unit)))
+                    }
+                },
+                _ => (module_out, value),
+            };
+            record_value(section, value, ctx);
+            let value = value.map(|x| x.amount);
+            if section
+                .hide_when
+                .is_some_and(|x| x.hides(&module_out, value))
+            {
+                return None;
+            }
+            let range = match (section.track_extremes, value) {
+                (true, Some(value)) => ctx.extremes.record(&section.module, value),
+                _ => None,
+            };
+            let triggered = ctx
+                .urgency
+                .check(ctx.section, &section.thresholds, value, ctx.now);
+            let decoration = section.decoration(triggered.threshold);
+
+            let mut placeholders = Vec::new();
+            if let Some(range) = range {
+                placeholders.push(("{min}", format_value(range.min, section, config)));
+                placeholders.push(("{max}", format_value(range.max, section, config)));
+            }
+            if let Some(limit) = battery_limit(decoration, fetched) {
+                placeholders.push(("{limit}", limit.to_string()));
+            }
+            let placeholders: Vec<_> = placeholders
+                .iter()
+                .map(|(key, value)| (*key, value.as_str()))
+                .collect();
+            let text = decorate(section, decoration, config, &module_out, &placeholders);
+            let compact = compact_output(section, config, ctx, value)
+                .map(|x| decorate(section, decoration, config, &x, &placeholders));
+            Some((text, compact, decoration, triggered.urgent))
+        }
+    }
+}
+
+/// The module output with the section's `compact_template`, `None` without one and for
+/// sections other than timestamps while they have no value.
+fn compact_output(
+    section: &Section,
+    config: &Config,
+    ctx: &Context,
+    value: Option<f64>,
+) -> Option<String> {
+    let template = section.compact_template.as_ref()?;
+    if let Module::Timestamp { .. } = section.module {
+        return Some(ctx.local.format(template).to_string());
+    }
+
+    let value = value?;
+    let fill = |name: &str, spec: &Spec| {
+        (name == "value").then(|| match spec.precision {
+            Some(precision) => format_number(value, precision, config.trim_trailing_zeros),
+            None => format_value(value, section, config),
+        })
+    };
+    // Checked when the config was loaded.
+    Some(template::fill(template, fill).unwrap_or_else(|_| template.clone()))
+}
+
+/// Sanitises, escapes, scrubs, truncates and pads module output and wraps it in `decoration`,
+/// filling in the given placeholders in the decoration.
+fn decorate(
+    section: &Section,
+    decoration: &Decoration,
+    config: &Config,
+    module_out: &str,
+    placeholders: &[(&str, &str)],
+) -> String {
+    let fill = |template: &str| {
+        placeholders
+            .iter()
+            .fold(template.to_string(), |acc, (key, value)| {
+                acc.replace(key, value)
+            })
+    };
+
+    // Sanitised first, so nothing it leaves behind can undo the escaping.
+    let module_out = sanitize::sanitize(module_out, section.sanitize.as_ref());
+    let module_out = match &config.escape_separator {
+        Some(replacement) => text::escape_separator(&module_out, &config.separator, replacement),
+        None => module_out,
+    };
+    let (status2d, statuscmd) = config.output.dwm_markup();
+    let module_out = dwm::scrub(&module_out, status2d, statuscmd);
+    let module_out = match section.max_width {
+        Some(width) => text::truncate_width(&module_out, width),
+        None => module_out,
+    };
+    let module_out = match section.min_width {
+        Some(width) => text::pad(&module_out, width, section.align),
+        None => module_out,
+    };
+
+    let mut output = String::new();
+    if let Some(x) = &decoration.before {
+        output.push_str(&fill(x));
+    }
+
+    output.push_str(&module_out);
+
+    if let Some(x) = &decoration.after {
+        output.push_str(&fill(x));
+    }
+
+    output
+}
+
+/// Device names with their module output.
+type Entries = Vec<(String, String)>;
+
+/// The devices of a section expanding into one entry per device, by name with their module
+/// output and sorted by name, and the separator between their entries. `None` for other
+/// sections. Devices are listed anew every time, so ones appearing or going away are picked
+/// up on the next update.
+fn expand_entries<'a>(
+    section: &'a Section,
+    config: &Config,
+    ctx: &mut Context,
+) -> Option<(Entries, Option<&'a str>)> {
+    match &section.module {
+        Module::DiskUsageEach {
+            filter,
+            separator,
+            inodes,
+        } => {
+            let entries = expand_disks(filter, ctx.provider.disks())
+                .iter()
+                .map(|disk| {
+                    let module_out = disk_usage(disk, *inodes, section, config).0;
+                    (disk.name.clone(), module_out)
+                })
+                .collect();
+            Some((entries, separator.as_deref()))
+        }
+        Module::BatteryEach { separator } => {
+            let mut batteries = ctx.provider.batteries();
+            batteries.sort_by(|a, b| a.name.cmp(&b.name));
+            let entries = batteries
+                .iter()
+                .map(|battery| (battery.name.clone(), battery_charge(battery, None, false).0))
+                .collect();
+            Some((entries, separator.as_deref()))
+        }
+        Module::NetworkEach {
+            exclude_loopback,
+            separator,
+        } => {
+            let policy = unit_policy(section, config, 1);
+            let entries = ctx
+                .provider
+                .network_interfaces()
+                .into_iter()
+                .filter(|x| !(*exclude_loopback && x.loopback))
+                .filter_map(|interface| {
+                    let (received, transmitted) = ctx.provider.network_rates(&interface.name)?;
+                    let module_out = format!(
+                        "{} ↓ / {} ↑",
+                        format_byte_rate(received, &policy),
+                        format_byte_rate(transmitted, &policy)
+                    );
+                    Some((interface.name, module_out))
+                })
+                .collect();
+            Some((entries, separator.as_deref()))
+        }
+        _ => None,
+    }
+}
+
+/// A disk as shown by the disk modules.
+struct DiskEntry {
+    name: String,
+    used: u64,
+    total: u64,
+    inodes: Option<(u64, u64)>,
+    spared: bool,
+}
+
+impl DiskEntry {
+    fn new(name: String, disk: &DiskInfo) -> Self {
+        Self {
+            name,
+            used: disk.total_space.saturating_sub(disk.available_space),
+            total: disk.total_space,
+            inodes: disk.inodes,
+            spared: disk.spared,
+        }
+    }
+}
+
+/// Every disk matching `filter`, named by the last component of its device name and sorted
+/// by name. Disks mounted more than once are only listed once.
+fn expand_disks(filter: &DiskFilter, disks: &DiskSnapshot) -> Vec<DiskEntry> {
+    let mut expanded: Vec<DiskEntry> = Vec::new();
+    for disk in disks.devices().filter(|x| filter.matches(x)) {
+        let name = Path::new(&disk.name)
+            .file_name()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or(disk.name.clone());
+
+        if !expanded.iter().any(|x| x.name == name) {
+            expanded.push(DiskEntry::new(name, disk));
+        }
+    }
+    expanded.sort_by(|a, b| a.name.cmp(&b.name));
+
+    expanded
+}
+
+/// Space usage of a disk, followed by its inode usage with `inodes`, e.g.
+/// "1.00/4.00 GiB / inodes 91%". The inode part is left out for filesystems without an inode
+/// limit. The value is the larger of the two percentages.
+fn disk_usage(
+    disk: &DiskEntry,
+    inodes: bool,
+    section: &Section,
+    config: &Config,
+) -> (String, Option<Value>) {
+    let (output, space) = usage(disk.used, disk.total, section, config);
+    let inode_usage = match inodes {
+        true => disk.inodes,
+        false => None,
+    };
+
+    let (output, value) = match inode_usage {
+        Some((used, total)) => {
+            let percent = used as f64 / total as f64 * 100.0;
+            (
+                format!(
+                    "{output} / inodes {}",
+                    format_percent(
+                        percent,
+                        section.decimals.unwrap_or(0),
+                        config.trim_trailing_zeros
+                    )
+                ),
+                space.map(|x| Value::new(x.amount.max(percent), x.unit)),
+            )
+        }
+        None => (output, space),
+    };
+
+    (sleeping(output, disk.spared), value)
+}
+
+/// Marks usage of disks left asleep by `skip_when_standby`, which is from before they fell
+/// asleep.
+fn sleeping(output: String, spared: bool) -> String {
+    match spared {
+        true => format!("{output} (sleeping)"),
+        false => output,
+    }
+}
+
+/// Used and total memory on `basis`, `None` if it's the cgroup and there is none.
+fn memory_usage(basis: MemoryBasis, provider: &mut dyn SystemProvider) -> Option<(u64, u64)> {
+    let host = provider.memory();
+    let cgroup = provider.cgroup_memory();
+    match (basis, cgroup) {
+        (MemoryBasis::Host, _) => Some(host),
+        (MemoryBasis::Cgroup, cgroup) => cgroup.map(|x| (x.current, x.max.unwrap_or(host.1))),
+        (MemoryBasis::Auto, Some(cgroup)) => match cgroup.max {
+            Some(max) if host.1 == 0 || max < host.1 => Some((cgroup.current, max)),
+            _ => Some(host),
+        },
+        (MemoryBasis::Auto, None) => Some(host),
+    }
+}
+
+/// The battery called `name`, or the first one if no name is given.
+fn find_battery(name: Option<&str>, batteries: Vec<BatteryInfo>) -> Option<BatteryInfo> {
+    batteries
+        .into_iter()
+        .find(|x| name.is_none_or(|name| x.name == name))
+}
+
+/// A battery's charge and whether it's charging, e.g. "62% ↑", along with the charge shown.
+/// See [`Module::Battery`] for `limit_marker` and `relative_to_limit`.
+fn battery_charge(
+    battery: &BatteryInfo,
+    limit_marker: Option<&str>,
+    relative_to_limit: bool,
+) -> (String, u8) {
+    let limit = battery.end_threshold.unwrap_or(100);
+    let capacity = match relative_to_limit {
+        true => (battery.capacity as u32 * 100 / limit as u32).min(100) as u8,
+        false => battery.capacity,
+    };
+    let marker = match limit_marker {
+        Some(marker) if battery.end_threshold.is_some() && battery.capacity >= limit => marker,
+        _ => "",
+    };
+    let output = match battery.state {
+        ChargeState::Charging => format!("{capacity}%{marker} ↑"),
+        ChargeState::Discharging => format!("{capacity}%{marker} ↓"),
+        ChargeState::Idle => format!("{capacity}%{marker}"),
+    };
+
+    (output, capacity)
+}
+
+/// The charge threshold of a battery section whose decoration has a `{limit}` placeholder,
+/// 100 for batteries without one.
+fn battery_limit(decoration: &Decoration, fetched: &Fetched) -> Option<u8> {
+    let Fetched::Battery(Some(battery)) = fetched else {
+        return None;
+    };
+    if ![&decoration.before, &decoration.after]
+        .into_iter()
+        .flatten()
+        .any(|x| x.contains("{limit}"))
+    {
+        return None;
+    }
+
+    Some(battery.end_threshold.unwrap_or(100))
+}
+
+/// Renders a module's output along with its numeric value, if it has one.
+/// Usage modules report the used percentage.
+fn render_module(
+    section: &Section,
+    config: &Config,
+    ctx: &mut Context,
+    fetched: &Fetched,
+) -> (String, Option<Value>) {
+    match &section.module {
+        Module::Timestamp { template } => (ctx.local.format(template).to_string(), None),
+        Module::MemoryUsage { basis } => {
+            match memory_usage(basis.unwrap_or(config.memory_basis), ctx.provider) {
+                Some((used, total)) if total > 0 => usage(used, total, section, config),
+                Some(_) => {
+                    warn_once!(
+                        format!("{}:memory", ctx.section),
+                        "Section {}: total memory reads as 0",
+                        ctx.section + 1
+                    );
+                    ("N/A".into(), None)
+                }
+                None => {
+                    warn_once!(
+                        format!("{}:memory", ctx.section),
+                        "Section {}: no cgroup v2 memory limits in {}",
+                        ctx.section + 1,
+                        cgroup::CGROUP_PATH
+                    );
+                    ("N/A".into(), None)
+                }
+            }
+        }
+        Module::SwapUsage => {
+            let (used, total) = ctx.provider.swap();
+            usage(used, total, section, config)
+        }
+        Module::CpuUsage => {
+            let cpu = ctx.provider.cpu_usage() as f64;
+            (
+                format_value(cpu, section, config),
+                Some(Value::new(cpu, Unit::Percent)),
+            )
+        }
+        Module::CpuFrequency { aggregate } => {
+            let frequencies: Vec<f64> = ctx
+                .provider
+                .cpu_frequencies()
+                .into_iter()
+                .filter(|x| *x > 0)
+                .map(|x| x as f64)
+                .collect();
+            let mhz = match aggregate {
+                _ if frequencies.is_empty() => None,
+                FreqAggregate::Max => frequencies.iter().copied().reduce(f64::max),
+                FreqAggregate::Min => frequencies.iter().copied().reduce(f64::min),
+                FreqAggregate::Average => {
+                    Some(frequencies.iter().sum::<f64>() / frequencies.len() as f64)
+                }
+            };
+            match mhz {
+                Some(mhz) => (
+                    format_value(mhz, section, config),
+                    Some(Value::new(mhz, Unit::Megahertz)),
+                ),
+                None => {
+                    warn_once!(
+                        format!("{}:frequency", ctx.section),
+                        "Section {}: no CPU reports its frequency",
+                        ctx.section + 1
+                    );
+                    ("N/A".into(), None)
+                }
+            }
+        }
+        Module::ProcessCount => {
+            let count = ctx.provider.process_count();
+            (
+                format!("{count}"),
+                Some(Value::new(count as f64, Unit::Count)),
+            )
+        }
+        Module::TopProcess {
+            by,
+            max_len,
+            exclude_kernel_threads,
+        } => {
+            let total_memory = ctx.provider.memory().1;
+            let value = |process: &ProcessInfo| match by {
+                SortKey::Cpu => process.cpu_usage as f64,
+                SortKey::Memory => process.memory as f64 / total_memory as f64 * 100.0,
+            };
+            // Ties go to the first name alphabetically, so idle systems don't flicker.
+            let top = ctx
+                .provider
+                .processes()
+                .iter()
+                .filter(|x| !(*exclude_kernel_threads && x.kernel_thread))
+                .max_by(|a, b| value(a).total_cmp(&value(b)).then(b.name.cmp(&a.name)));
+
+            match top {
+                Some(process) if value(process).is_finite() => {
+                    let name = match max_len {
+                        Some(max_len) => text::truncate(&process.name, *max_len),
+                        None => process.name.clone(),
+                    };
+                    let value = value(process);
+                    (
+                        format!("{name} {}", format_value(value, section, config)),
+                        Some(Value::new(value, Unit::Percent)),
+                    )
+                }
+                _ => ("N/A".into(), None),
+            }
+        }
+        Module::LoadAverage => {
+            let (one, five, fifteen) = ctx.provider.load_average();
+            (
+                format!("{one:.2} {five:.2} {fifteen:.2}"),
+                Some(Value::new(one, Unit::Count)),
+            )
+        }
+        Module::DiskUsage { name, inodes } => {
+            if let Some(disk) = ctx
+                .provider
+                .disks()
+                .find(name)
+                .map(|x| DiskEntry::new(name.clone(), x))
+            {
+                disk_usage(&disk, *inodes, section, config)
+            } else {
+                warn_once!(
+                    format!("{}:disk:{name}", ctx.section),
+                    "Section {}: disk {name} not found",
+                    ctx.section + 1
+                );
+                ("N/A".into(), None)
+            }
+        }
+        Module::DiskUsageTotal { include_removables } => {
+            let mut total = 0;
+            let mut used = 0;
+            let mut spared = false;
+
+            for disk in ctx
+                .provider
+                .disks()
+                .devices()
+                .filter(|x| *include_removables || !x.is_removable)
+            {
+                total += disk.total_space;
+                used += disk.total_space.saturating_sub(disk.available_space);
+                spared |= disk.spared;
+            }
+
+            let (output, value) = usage(used, total, section, config);
+            (sleeping(output, spared), value)
+        }
+        Module::Timer { name, expired_text } => {
+            let display = match ctx.timers.get(name) {
+                Some(timer) => timer.display(ctx.now),
+                None => TimerDisplay::Elapsed(Duration::ZERO),
+            };
+
+            let output = match display {
+                TimerDisplay::Elapsed(x) | TimerDisplay::Remaining(x) => {
+                    duration::format_compact(x)
+                }
+                TimerDisplay::Expired => expired_text.clone().unwrap_or("0s".into()),
+            };
+
+            (output, None)
+        }
+        Module::Battery {
+            name,
+            limit_marker,
+            relative_to_limit,
+            ..
+        } => {
+            let battery = match fetched {
+                Fetched::Battery(x) => x.clone(),
+                _ => find_battery(name.as_deref(), ctx.provider.batteries()),
+            };
+            if let Some(battery) = battery {
+                let (output, capacity) =
+                    battery_charge(&battery, limit_marker.as_deref(), *relative_to_limit);
+                (output, Some(Value::new(capacity as f64, Unit::Percent)))
+            } else {
+                let name = name.as_deref().unwrap_or("any");
+                warn_once!(
+                    format!("{}:battery:{name}", ctx.section),
+                    "Section {}: battery {name} not found",
+                    ctx.section + 1
+                );
+                ("N/A".into(), None)
+            }
+        }
+        Module::Temperature {
+            component,
+            fahrenheit,
+            aggregate,
+            ..
+        } => {
+            if let Some((celsius, named)) =
+                aggregate_temperatures(ctx.provider.components(), component, aggregate)
+            {
+                let output = if named.is_empty() {
+                    format_temperature(celsius, *fahrenheit)
+                } else {
+                    let named: Vec<_> = named
+                        .iter()
+                        .map(|(name, celsius)| match celsius {
+                            Some(x) => format!("{name} {}", format_temperature(*x, *fahrenheit)),
+                            None => format!("{name} N/A"),
+                        })
+                        .collect();
+                    named.join(" ")
+                };
+                (output, Some(Value::new(celsius, Unit::Celsius)))
+            } else {
+                warn_once!(
+                    format!("{}:component:{component}", ctx.section),
+                    "Section {}: temperature sensor {component} not found",
+                    ctx.section + 1
+                );
+                ("N/A".into(), None)
+            }
+        }
+        Module::Uptime { format } => {
+            let uptime = Duration::from_secs(ctx.provider.uptime());
+            let output = match format {
+                UptimeFormat::Compact => duration::format_days(uptime),
+                UptimeFormat::HoursMinutes => duration::format_hours_minutes(uptime),
+                UptimeFormat::Seconds => uptime.as_secs().to_string(),
+            };
+
+            (
+                output,
+                Some(Value::new(uptime.as_secs_f64(), Unit::Seconds)),
+            )
+        }
+        Module::Command {
+            command,
+            args,
+            timeout_ms,
+            fallback,
+            join_lines,
+        } => {
+            let timeout = timeout_ms.map(|x| x.0).unwrap_or(DEFAULT_COMMAND_TIMEOUT);
+            let output = run_command(ctx.section, command, args, timeout, join_lines.as_deref())
+                .unwrap_or_else(|| fallback.clone().unwrap_or("ERR".into()));
+
+            (output, None)
+        }
+        Module::Brightness { device } => {
+            if let Some((brightness, max)) = ctx.provider.brightness(device.as_deref()) {
+                let percent = brightness as f64 / max as f64 * 100.0;
+                (
+                    format_value(percent, section, config),
+                    Some(Value::new(percent, Unit::Percent)),
+                )
+            } else {
+                let device = device.as_deref().unwrap_or("any");
+                warn_once!(
+                    format!("{}:backlight:{device}", ctx.section),
+                    "Section {}: backlight {device} not found",
+                    ctx.section + 1
+                );
+                ("N/A".into(), None)
+            }
+        }
+        Module::IpAddress {
+            interface,
+            family,
+            max_addresses,
+            include_link_local,
+            separator,
+            missing_text,
+        } => match ctx.provider.interface_addresses(interface) {
+            Some(addresses) => {
+                let shown: Vec<_> = addresses
+                    .iter()
+                    .filter(|x| family.matches(x))
+                    .filter(|x| *include_link_local || !ifaddrs::is_link_local(x))
+                    .take(*max_addresses)
+                    .map(ToString::to_string)
+                    .collect();
+                let output = match shown.is_empty() {
+                    true => missing_text.as_deref().unwrap_or("no addr").into(),
+                    false => shown.join(separator.as_deref().unwrap_or(" ")),
+                };
+                (output, None)
+            }
+            None => {
+                warn_once!(
+                    format!("{}:interface:{interface}", ctx.section),
+                    "Section {}: interface {interface} not found",
+                    ctx.section + 1
+                );
+                ("N/A".into(), None)
+            }
+        },
+        Module::PublicIp {
+            url,
+            refresh_interval_s,
+        } => (
+            ctx.provider
+                .public_ip(url, refresh_interval_s.0)
+                .unwrap_or("…".into()),
+            None,
+        ),
+        Module::Maildir { path } => {
+            let count = match fetched {
+                Fetched::UnreadMail(x) => *x,
+                _ => unread_mail(path, ctx),
+            };
+            match count {
+                Some(count) => (
+                    format!("✉ {count}"),
+                    Some(Value::new(count as f64, Unit::Count)),
+                ),
+                None => ("N/A".into(), None),
+            }
+        }
+        Module::Bluetooth { device_mac } => match ctx.provider.bluetooth() {
+            Ok(status) => {
+                let (output, connected) = status.format(device_mac.as_deref());
+                (output, Some(Value::new(connected as f64, Unit::Count)))
+            }
+            Err(e) => {
+                warn_once!(
+                    format!("{}:bluetooth", ctx.section),
+                    "Section {}: unable to ask BlueZ for Bluetooth devices: {e}",
+                    ctx.section + 1
+                );
+                ("N/A".into(), None)
+            }
+        },
+        Module::SystemdFailed => match failed_units(ctx) {
+            Some(count) => (
+                format!("✗ {count}"),
+                Some(Value::new(count as f64, Unit::Count)),
+            ),
+            None => ("N/A".into(), None),
+        },
+        Module::BackendHealth => {
+            let (indicator, failures) = health::status();
+            (
+                indicator.unwrap_or_default(),
+                Some(Value::new(failures as f64, Unit::Count)),
+            )
+        }
+        Module::Mpd {
+            host,
+            port,
+            format,
+            max_len,
+        } => match mpd_status(host, *port, ctx) {
+            Some(status) => (status.format(format, *max_len), None),
+            None => (String::new(), None),
+        },
+        Module::KeyboardLayout => match ctx.provider.keyboard_layout() {
+            Ok(layout) => (layout, None),
+            Err(e) => {
+                warn_once!(
+                    format!("{}:keyboard", ctx.section),
+                    "Section {}: unable to read the keyboard layout: {e}",
+                    ctx.section + 1
+                );
+                ("N/A".into(), None)
+            }
+        },
+        Module::Volume { control } => {
+            let control = control.as_deref().unwrap_or(alsa::DEFAULT_CONTROL);
+            if let Some(volume) = ctx.provider.volume(control) {
+                let output = match volume.muted {
+                    true => "muted".into(),
+                    false => format_percent(
+                        volume.percent,
+                        section.decimals.unwrap_or(0),
+                        config.trim_trailing_zeros,
+                    ),
+                };
+
+                (output, Some(Value::new(volume.percent, Unit::Percent)))
+            } else {
+                warn_once!(
+                    format!("{}:volume:{control}", ctx.section),
+                    "Section {}: no sound card with mixer control {control} found",
+                    ctx.section + 1
+                );
+                ("N/A".into(), None)
+            }
+        }
+        Module::Derived { expression, unit } => {
+            let value = Expr::parse(expression)
+                .ok()
+                .and_then(|x| x.eval(&|id| ctx.values.get(id).copied()));
+            match value {
+                Some(value) => (
+                    format_value(value, section, config),
+                    Some(Value::new(value, *unit)),
+                ),
+                None => ("N/A".into(), None),
+            }
+        }
+        Module::NetworkThroughput {
+            interface,
+            average_over_s,
+            peak_hold_s,
+            min_unit,
+            max_unit,
+            show_totals,
+            ..
+        } => {
+            if let Some((received, transmitted)) = ctx.provider.network_rates(interface) {
+                let policy = UnitPolicy {
+                    min_magnitude: min_unit.map(Into::into),
+                    max_magnitude: max_unit.map(Into::into),
+                    ..unit_policy(section, config, 1)
+                };
+                let mut rates = [(received, None), (transmitted, None)];
+                if average_over_s.is_some() || peak_hold_s.is_some() {
+                    let (average_over, peak_hold) =
+                        (average_over_s.map(|x| x.0), peak_hold_s.map(|x| x.0));
+                    let windows = ctx.rates.windows(&section.module);
+                    for (window, rate) in windows.iter_mut().zip(&mut rates) {
+                        *rate = window.sample(ctx.now, rate.0, average_over, peak_hold);
+                    }
+                }
+                // Both ways together, e.g. for thresholds on how busy the link is.
+                let rate = rates[0].0 + rates[1].0;
+                let mut rates = rates.map(|(rate, peak)| match peak {
+                    Some(peak) => format!(
+                        "{} ({})",
+                        format_byte_rate(rate, &policy),
+                        format_byte_rate(peak, &policy)
+                    ),
+                    None => format_byte_rate(rate, &policy),
+                });
+                if let (true, Some(counters)) =
+                    (show_totals, ctx.provider.network_totals(interface))
+                {
+                    // Fitted on their own, min_unit and max_unit are meant for the rates.
+                    let policy = unit_policy(section, config, 1);
+                    let totals = ctx.rates.totals(interface, counters);
+                    for (rate, total) in rates.iter_mut().zip(totals) {
+                        rate.push_str(&format!(" ({})", format_bytes(total, &policy)));
+                    }
+                }
+                let [received, transmitted] = rates;
+
+                (
+                    format!("{received} ↓ / {transmitted} ↑"),
+                    Some(Value::new(rate, Unit::BytesPerSecond)),
+                )
+            } else {
+                ctx.rates.reset(&section.module);
+                warn_once!(
+                    format!("{}:network:{interface}", ctx.section),
+                    "Section {}: network interface {interface} not found",
+                    ctx.section + 1
+                );
+                ("N/A".into(), None)
+            }
+        }
+        Module::DiskUsageEach { .. } | Module::BatteryEach { .. } | Module::NetworkEach { .. } => {
+            let (entries, separator) = expand_entries(section, config, ctx).unwrap_or_default();
+            let entries: Vec<_> = entries.into_iter().map(|(_, x)| x).collect();
+            (entries.join(separator.unwrap_or(&config.separator)), None)
+        }
+        Module::Separator { content, .. } => (content.clone(), None),
+    }
+}
+
+/// The command's stdout without the line break ending it, `None` if it couldn't run, timed
+/// out, failed or printed more than one line without `join_lines` to join them with. Failures
+/// and anything printed to stderr are logged.
+fn run_command(
+    section: usize,
+    command: &str,
+    args: &[String],
+    timeout: Duration,
+    join_lines: Option<&str>,
+) -> Option<String> {
+    let log = |message: String| {
+        if warn::should_emit(&format!("{section}:command"), Some(COMMAND_ERROR_PERIOD)) {
+            error!("Section {}: {command}: {message}", section + 1);
+        }
+    };
+
+    let output = match spawn::run(process::Command::new(command).args(args), timeout) {
+        Ok(x) => x,
+        Err(e) => {
+            log(e.to_string());
+            return None;
+        }
+    };
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = stderr.trim_end();
+    if !output.status.success() {
+        log(format!("{}, stderr: {stderr}", output.status));
+        return None;
+    }
+    if !stderr.is_empty() {
+        log(format!("stderr: {stderr}"));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = stdout
+        .strip_suffix('\n')
+        .map(|x| x.strip_suffix('\r').unwrap_or(x))
+        .unwrap_or(&stdout);
+    if !stdout.contains('\n') {
+        return Some(stdout.into());
+    }
+    match join_lines {
+        Some(separator) => Some(stdout.lines().collect::<Vec<_>>().join(separator)),
+        None => {
+            log(format!(
+                "printed {} lines, set join_lines to show more than one",
+                stdout.lines().count()
+            ));
+            None
+        }
+    }
+}
+
+fn usage(used: u64, total: u64, section: &Section, config: &Config) -> (String, Option<Value>) {
+    (
+        format_usage(used, total, section, config),
+        Some(Value::new(used_percent(used, total), Unit::Percent)),
+    )
+}
+
+/// 0 for a total of 0.
+fn used_percent(used: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        used as f64 / total as f64 * 100.0
+    }
+}
+
+/// Failed systemd units, `None` on systems without systemd, which is logged once.
+fn failed_units(ctx: &mut Context) -> Option<usize> {
+    ctx.provider
+        .failed_units()
+        .inspect_err(|e| {
+            warn_once!(
+                format!("{}:systemd", ctx.section),
+                "Section {}: unable to ask systemd for failed units, hiding it: {e}",
+                ctx.section + 1
+            )
+        })
+        .ok()
+}
+
+/// Unread mail in the Maildir at `path`, `None` if it can't be read, which is logged once.
+fn unread_mail(path: &str, ctx: &mut Context) -> Option<usize> {
+    let home = BaseDirs::new().map(|x| x.home_dir().to_path_buf());
+    ctx.provider
+        .unread_mail(&maildir::expand_home(path, home.as_deref()))
+        .inspect_err(|e| {
+            warn_once!(
+                format!("{}:maildir", ctx.section),
+                "Section {}: unable to read Maildir: {e}",
+                ctx.section + 1
+            )
+        })
+        .ok()
+}
+
+/// What MPD is playing, `None` while it's unreachable, which is logged once.
+fn mpd_status(host: &str, port: u16, ctx: &mut Context) -> Option<mpd::Status> {
+    ctx.provider
+        .mpd(host, port)
+        .inspect_err(|e| {
+            warn_once!(
+                format!("{}:mpd", ctx.section),
+                "Section {}: unable to reach MPD, hiding it: {e}",
+                ctx.section + 1
+            )
+        })
+        .ok()
+}
+
+/// Makes a section's value available to [`Module::Derived`] expressions, if it has an id, and
+/// to the control socket's `metrics`.
+fn record_value(section: &Section, value: Option<Value>, ctx: &mut Context) {
+    if let Some(id) = &section.id {
+        match value {
+            Some(value) => ctx.values.insert(id.clone(), value.amount),
+            None => ctx.values.remove(id),
+        };
+    }
+    match value {
+        Some(value) => ctx.readings.insert(
+            ctx.section,
+            Reading {
+                id: section.id.clone(),
+                module: section.module.name(),
+                value,
+            },
+        ),
+        None => ctx.readings.remove(&ctx.section),
+    };
+}
+
+/// Formats a module's numeric value, a count for [`Module::ProcessCount`], the 1 minute load
+/// for [`Module::LoadAverage`], degrees Celsius for [`Module::Temperature`], a plain number for
+/// [`Module::Derived`], MHz or GHz for [`Module::CpuFrequency`], an unclamped whole percentage for [`Module::TopProcess`], a duration for [`Module::Uptime`], a byte rate for
+/// [`Module::NetworkThroughput`] and a percentage otherwise.
+fn format_value(value: f64, section: &Section, config: &Config) -> String {
+    match section.module {
+        Module::ProcessCount => format!("{value}"),
+        Module::LoadAverage => format!("{value:.2}"),
+        Module::Derived { .. } => format_number(
+            value,
+            section.decimals.unwrap_or(2),
+            config.trim_trailing_zeros,
+        ),
+        Module::Temperature { fahrenheit, .. } => format_temperature(value, fahrenheit),
+        Module::Uptime { .. } => duration::format_days(Duration::from_secs_f64(value.max(0.0))),
+        Module::NetworkThroughput { .. } => {
+            format_byte_rate(value, &unit_policy(section, config, 1))
+        }
+        // Whole percents by default, so tiny changes between reads don't make the bar jitter.
+        Module::Brightness { .. } => format_percent(
+            value,
+            section.decimals.unwrap_or(0),
+            config.trim_trailing_zeros,
+        ),
+        Module::CpuFrequency { .. } => format_frequency(
+            value,
+            section.decimals.unwrap_or(2),
+            config.trim_trailing_zeros,
+        ),
+        // Not clamped, a process can keep several cores busy.
+        Module::TopProcess { .. } => format!(
+            "{}%",
+            format_number(
+                value,
+                section.decimals.unwrap_or(0),
+                config.trim_trailing_zeros
+            )
+        ),
+        _ => format_percent(
+            value,
+            section.decimals.unwrap_or(1),
+            config.trim_trailing_zeros,
+        ),
+    }
+}
+
+/// Each name of [`TempAggregate::Named`] with the temperature of its sensor.
+type NamedTemperatures<'a> = Vec<(&'a str, Option<f64>)>;
+
+/// The temperature of the `sensors` matching `component` as `aggregate` combines them, and for
+/// [`TempAggregate::Named`] each name with its sensor's temperature, if one matched. `None`
+/// if no sensor matched at all.
+fn aggregate_temperatures<'a>(
+    sensors: &[ComponentInfo],
+    component: &str,
+    aggregate: &'a TempAggregate,
+) -> Option<(f64, NamedTemperatures<'a>)> {
+    let component = component.to_lowercase();
+    let matching: Vec<_> = sensors
+        .iter()
+        .filter(|x| x.label.to_lowercase().contains(&component))
+        .collect();
+    let temperatures = matching.iter().map(|x| x.temperature as f64);
+
+    match aggregate {
+        TempAggregate::First => Some((temperatures.clone().next()?, Vec::new())),
+        TempAggregate::Max => Some((temperatures.reduce(f64::max)?, Vec::new())),
+        TempAggregate::Average => {
+            let sum: f64 = temperatures.sum();
+            (!matching.is_empty()).then(|| (sum / matching.len() as f64, Vec::new()))
+        }
+        TempAggregate::Named(names) => {
+            let named: Vec<_> = names
+                .iter()
+                .map(|name| {
+                    let lower = name.to_lowercase();
+                    let sensor = matching
+                        .iter()
+                        .find(|x| x.label.to_lowercase().contains(&lower));
+                    (name.as_str(), sensor.map(|x| x.temperature as f64))
+                })
+                .collect();
+            let hottest = named.iter().filter_map(|x| x.1).reduce(f64::max)?;
+            Some((hottest, named))
+        }
+    }
+}
+
+/// Formats a temperature given in degrees Celsius as whole degrees, e.g. "54°C".
+fn format_temperature(celsius: f64, fahrenheit: bool) -> String {
+    if fahrenheit {
+        format!("{:.0}°F", celsius * 9.0 / 5.0 + 32.0)
+    } else {
+        format!("{celsius:.0}°C")
+    }
+}
+
+/// Units for the section's byte values. Full units keep `precision` decimals, compact ones
+/// default to one unless the section sets `decimals`. Neither drops trailing zeros, so the
+/// width stays steady as values change.
+fn unit_policy(section: &Section, config: &Config, precision: usize) -> UnitPolicy {
+    let policy = UnitPolicy::new(config.decimal_data_units);
+    if section.compact_units {
+        UnitPolicy {
+            precision: section
+                .decimals
+                .unwrap_or(UnitStyle::Compact.default_precision()),
+            style: UnitStyle::Compact,
+            ..policy
+        }
+    } else {
+        UnitPolicy {
+            precision,
+            ..policy
+        }
+    }
+}
+
+fn format_usage(used: u64, total: u64, section: &Section, config: &Config) -> String {
+    if let Some(format) = &section.usage_format {
+        // A unit in the spec picks fitted units even with exact bytes displayed.
+        let bytes = |x: u64, spec: &Spec| match (section.usage_display, spec.unit) {
+            (UsageDisplay::ExactBytes, None) => {
+                format!("{} B", format_exact(x, &config.thousands_separator))
+            }
+            (_, unit) => {
+                let policy = unit_policy(section, config, 2);
+                let policy = UnitPolicy {
+                    system: unit.map_or(policy.system, |(system, _)| system),
+                    fixed_magnitude: unit.map(|(_, magnitude)| magnitude),
+                    precision: spec.precision.unwrap_or(policy.precision),
+                    ..policy
+                };
+                format_bytes(x, &policy)
+            }
+        };
+        let value = |name: &str, spec: &Spec| {
+            Some(match name {
+                "used" => bytes(used, spec),
+                "total" => bytes(total, spec),
+                "free" => bytes(total.saturating_sub(used), spec),
+                "percent" => format_percent(
+                    used_percent(used, total),
+                    spec.precision.or(section.decimals).unwrap_or(0),
+                    config.trim_trailing_zeros,
+                ),
+                "used_raw" => used.to_string(),
+                "total_raw" => total.to_string(),
+                _ => return None,
+            })
+        };
+        // Checked when the config was loaded.
+        return template::fill(format, value).unwrap_or_else(|_| format.clone());
+    }
+
+    match section.usage_display {
+        UsageDisplay::Fitted => format_byte_usage(used, total, &unit_policy(section, config, 2)),
+        UsageDisplay::ExactBytes => format!(
+            "{}/{} B",
+            format_exact(used, &config.thousands_separator),
+            format_exact(total, &config.thousands_separator)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bar, bluetooth,
+        clock::{Clock, FakeClock, SystemClock},
+        config::{
+            default_name_atoms, AddrFamily, Alignment, Decoration, HideCondition, SanitizeOverride,
+            Threshold, ThresholdDirection, UnitPrefix, TEMPERATURE_WARNING_COLOR,
+            USAGE_PLACEHOLDERS,
+        },
+        provider::FakeProvider,
+        timer::Timer,
+    };
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    /// What the render loop keeps for a section between ticks.
+    #[derive(Default)]
+    struct Tracked {
+        extremes: Extremes,
+        validity: Validity,
+        values: HashMap<String, f64>,
+        readings: HashMap<usize, Reading>,
+        urgency: Urgency,
+    }
+
+    fn render_tracked(
+        section: &Section,
+        config: &Config,
+        provider: &mut FakeProvider,
+        tracked: &mut Tracked,
+    ) -> Piece {
+        let mut ctx = Context {
+            section: 0,
+            provider,
+            timers: &Timers::new(),
+            extremes: &mut tracked.extremes,
+            validity: &mut tracked.validity,
+            rates: &mut Rates::default(),
+            values: &mut tracked.values,
+            readings: &mut tracked.readings,
+            urgency: &mut tracked.urgency,
+            now: Instant::now(),
+            local: SystemClock.now_local(),
+        };
+        render_section(section, config, &mut ctx)
+    }
+
+    /// Renders `section` like the bar would on its first tick.
+    fn render_with(section: &Section, config: &Config, provider: &mut FakeProvider) -> Piece {
+        render_tracked(section, config, provider, &mut Tracked::default())
+    }
+
+    /// The text of a rendered section, `None` while it's hidden.
+    fn text(piece: Piece) -> Option<String> {
+        match piece {
+            Piece::Text(x, ..) => Some(x),
+            Piece::Hidden => None,
+            Piece::Separator(_) => panic!("expected text"),
+        }
+    }
+
+    fn render_module(section: &Section, config: &Config, ctx: &mut Context) -> String {
+        super::render_module(section, config, ctx, &Fetched::Nothing).0
+    }
+
+    fn render_text<'a>(
+        section: &'a Section,
+        config: &Config,
+        ctx: &mut Context,
+    ) -> Option<(String, &'a Decoration)> {
+        super::render_text(section, config, ctx, &Fetched::Nothing)
+            .map(|(text, _, decoration, _)| (text, decoration))
+    }
+
+    fn render(module: Module, provider: &mut FakeProvider, decimal: bool) -> String {
+        let config = Config {
+            decimal_data_units: decimal,
+            ..Config::default()
+        };
+        let mut ctx = Context {
+            section: 0,
+            provider,
+            timers: &Timers::new(),
+            extremes: &mut Extremes::default(),
+            validity: &mut Validity::default(),
+            rates: &mut Rates::default(),
+            values: &mut HashMap::new(),
+            readings: &mut HashMap::new(),
+            urgency: &mut Urgency::default(),
+            now: Instant::now(),
+            local: SystemClock.now_local(),
+        };
+
+        render_module(&module.into(), &config, &mut ctx)
+    }
+
+    #[test]
+    fn memory_and_swap() {
+        let cases = [
+            ((8 * GIB, 16 * GIB), false, "8.00/16.00 GiB"),
+            ((8_000_000_000, 16_000_000_000), true, "8.00/16.00 GB"),
+            ((0, 0), false, "0.00/0.00 KiB"),
+            ((0, 0), true, "0.00/0.00 KB"),
+            ((512 * 1024, GIB), false, "0.00/1.00 GiB"),
+            ((1536, 4096), false, "1.50/4.00 KiB"),
+        ];
+
+        for ((used, total), decimal, expected) in cases {
+            let mut provider = FakeProvider::default();
+            provider.set_memory(used, total).set_swap(used, total);
+            // Machines without swap are common, no memory means it couldn't be read.
+            assert_eq!(
+                if total == 0 { "N/A" } else { expected },
+                render(Module::MemoryUsage { basis: None }, &mut provider, decimal)
+            );
+            assert_eq!(expected, render(Module::SwapUsage, &mut provider, decimal));
+        }
+    }
+
+    #[test]
+    fn memory_basis() {
+        let render = |basis, provider: &mut FakeProvider| {
+            let config = Config {
+                memory_basis: basis,
+                ..Config::default()
+            };
+            text(render_with(
+                &Module::MemoryUsage { basis: None }.into(),
+                &config,
+                provider,
+            ))
+            .unwrap()
+        };
+
+        // A host, the root cgroup has no limits.
+        let mut host = FakeProvider::default();
+        host.set_memory(8 * GIB, 16 * GIB);
+        assert_eq!("8.00/16.00 GiB", render(MemoryBasis::Auto, &mut host));
+        assert_eq!("8.00/16.00 GiB", render(MemoryBasis::Host, &mut host));
+        assert_eq!("N/A", render(MemoryBasis::Cgroup, &mut host));
+
+        // A container limited to 2 GiB.
+        let mut limited = FakeProvider::default();
+        limited
+            .set_memory(8 * GIB, 16 * GIB)
+            .set_cgroup_memory(GIB / 2, Some(2 * GIB));
+        assert_eq!("0.50/2.00 GiB", render(MemoryBasis::Auto, &mut limited));
+        assert_eq!("0.50/2.00 GiB", render(MemoryBasis::Cgroup, &mut limited));
+        assert_eq!("8.00/16.00 GiB", render(MemoryBasis::Host, &mut limited));
+
+        // An unlimited container, or a limit above what the host has.
+        for max in [None, Some(32 * GIB)] {
+            let mut unlimited = FakeProvider::default();
+            unlimited
+                .set_memory(8 * GIB, 16 * GIB)
+                .set_cgroup_memory(GIB, max);
+            assert_eq!("8.00/16.00 GiB", render(MemoryBasis::Auto, &mut unlimited));
+        }
+        let mut unlimited = FakeProvider::default();
+        unlimited
+            .set_memory(8 * GIB, 16 * GIB)
+            .set_cgroup_memory(GIB, None);
+        assert_eq!(
+            "1.00/16.00 GiB",
+            render(MemoryBasis::Cgroup, &mut unlimited)
+        );
+
+        // A jail where the host total reads as 0.
+        let mut jail = FakeProvider::default();
+        jail.set_cgroup_memory(GIB, Some(4 * GIB));
+        assert_eq!("1.00/4.00 GiB", render(MemoryBasis::Auto, &mut jail));
+        assert_eq!("N/A", render(MemoryBasis::Host, &mut jail));
+        jail.set_cgroup_memory(GIB, None);
+        assert_eq!("N/A", render(MemoryBasis::Auto, &mut jail));
+        assert_eq!("N/A", render(MemoryBasis::Cgroup, &mut jail));
+
+        // A section's own basis wins over the global one.
+        let config = Config {
+            memory_basis: MemoryBasis::Host,
+            ..Config::default()
+        };
+        let section = Module::MemoryUsage {
+            basis: Some(MemoryBasis::Cgroup),
+        }
+        .into();
+        assert_eq!(
+            "0.50/2.00 GiB",
+            text(render_with(&section, &config, &mut limited)).unwrap()
+        );
+    }
+
+    #[test]
+    fn exact_bytes() {
+        let mut provider = FakeProvider::default();
+        provider
+            .set_memory(12_884_901_888, 17_179_869_184)
+            .add_disk("/dev/sda", 1000, 1, false);
+        let config = Config {
+            thousands_separator: " ".into(),
+            ..Config::default()
+        };
+        let exact = |module: Module| Section {
+            usage_display: UsageDisplay::ExactBytes,
+            ..module.into()
+        };
+
+        assert_eq!(
+            "12 884 901 888/17 179 869 184 B",
+            text(render_with(
+                &exact(Module::MemoryUsage { basis: None }),
+                &config,
+                &mut provider
+            ))
+            .unwrap()
+        );
+        assert_eq!(
+            "999/1 000 B",
+            text(render_with(
+                &exact(Module::DiskUsage {
+                    name: "/dev/sda".into(),
+                    inodes: false,
+                }),
+                &config,
+                &mut provider
+            ))
+            .unwrap()
+        );
+        assert_eq!(
+            "0/0 B",
+            text(render_with(
+                &exact(Module::SwapUsage),
+                &config,
+                &mut provider
+            ))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn usage_format() {
+        let mut provider = FakeProvider::default();
+        provider
+            .set_memory(12 * GIB, 16 * GIB)
+            .set_swap(0, 0)
+            .add_disk("/dev/sda", 1000, 1, false);
+        let config = Config::default();
+        let formatted = |module: Module, format: &str| Section {
+            usage_format: Some(format.into()),
+            ..module.into()
+        };
+
+        let cases = [
+            (
+                formatted(Module::MemoryUsage { basis: None }, "{percent}"),
+                "75%",
+            ),
+            (
+                formatted(Module::MemoryUsage { basis: None }, "{free} free"),
+                "4.00 GiB free",
+            ),
+            (
+                formatted(Module::MemoryUsage { basis: None }, "{used} of {total}"),
+                "12.00 GiB of 16.00 GiB",
+            ),
+            (
+                formatted(
+                    Module::MemoryUsage { basis: None },
+                    "{used_raw}/{total_raw}",
+                ),
+                "12884901888/17179869184",
+            ),
+            (
+                Section {
+                    decimals: Some(1),
+                    ..formatted(Module::SwapUsage, "{percent} of {total}")
+                },
+                "0.0% of 0.00 KiB",
+            ),
+            (
+                Section {
+                    usage_display: UsageDisplay::ExactBytes,
+                    ..formatted(
+                        Module::DiskUsage {
+                            name: "/dev/sda".into(),
+                            inodes: false,
+                        },
+                        "{free} left",
+                    )
+                },
+                "1 B left",
+            ),
+        ];
+        for (section, expected) in cases {
+            assert_eq!(
+                expected,
+                text(render_with(&section, &config, &mut provider)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn usage_format_specs() {
+        let mut provider = FakeProvider::default();
+        provider
+            .set_memory(10_533_000_000, 16 * GIB)
+            .add_disk("/dev/sda", 1_000_000, 1000, false);
+        let config = Config::default();
+        let memory = |format: &str| Section {
+            decimals: Some(2),
+            usage_format: Some(format.into()),
+            ..Module::MemoryUsage { basis: None }.into()
+        };
+
+        let cases = [
+            (memory("{percent}"), "61.31%"),
+            (memory("{percent:0}"), "61%"),
+            (memory("{percent:.1}"), "61.3%"),
+            (memory("{used:.1}"), "9.8 GiB"),
+            (memory("{used:0}/{total:0}"), "10 GiB/16 GiB"),
+            (memory("{used:MiB}"), "10045.05 MiB"),
+            (memory("{used:GiB.1}"), "9.8 GiB"),
+            (memory("{used:GB.1} of {total:GB}"), "10.5 GB of 17.18 GB"),
+            (memory("{free:TiB.3}"), "0.006 TiB"),
+            (memory("[{percent:0>4}]"), "[ 61%]"),
+            (memory("[{percent:0<4}]"), "[61% ]"),
+            (memory("[{used:.1^11}]"), "[  9.8 GiB  ]"),
+            (memory("[{used_raw:>12}]"), "[ 10533000000]"),
+            (memory("[{percent:>2}]"), "[61.31%]"),
+            (
+                Section {
+                    compact_units: true,
+                    ..memory("{used:MiB.0}")
+                },
+                "10045M",
+            ),
+            (
+                Section {
+                    usage_display: UsageDisplay::ExactBytes,
+                    usage_format: Some("{used} ({used:KB.0})".into()),
+                    ..Section::from(Module::DiskUsage {
+                        name: "/dev/sda".into(),
+                        inodes: false,
+                    })
+                },
+                "999\u{2009}000 B (999 KB)",
+            ),
+        ];
+        for (section, expected) in cases {
+            assert_eq!(
+                expected,
+                text(render_with(&section, &config, &mut provider)).unwrap()
+            );
+        }
+
+        // Every placeholder the config accepts has a value.
+        for (name, _) in USAGE_PLACEHOLDERS {
+            let format = format!("{{{name}}}");
+            assert_ne!(
+                format,
+                text(render_with(&memory(&format), &config, &mut provider)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn compact_units() {
+        let mut provider = FakeProvider::default();
+        provider
+            .set_memory(10_533_000_000, 16 * 1024 * 1024 * 1024)
+            .set_network_rates("eth0", 1.2 * 1024.0 * 1024.0, 340.0 * 1024.0);
+        let compact = |module: Module, decimals| Section {
+            compact_units: true,
+            decimals,
+            ..module.into()
+        };
+        let network = Module::NetworkThroughput {
+            interface: "eth0".into(),
+            average_over_s: None,
+            peak_hold_s: None,
+            min_unit: None,
+            max_unit: None,
+            show_totals: false,
+            reset_signal: None,
+        };
+        // Trailing zeros are kept even when the config trims them elsewhere.
+        let config = Config {
+            trim_trailing_zeros: true,
+            ..Config::default()
+        };
+
+        let cases = [
+            (
+                compact(Module::MemoryUsage { basis: None }, None),
+                &config,
+                "9.8/16.0G",
+            ),
+            (
+                compact(Module::MemoryUsage { basis: None }, Some(2)),
+                &config,
+                "9.81/16.00G",
+            ),
+            (
+                compact(Module::MemoryUsage { basis: None }, Some(0)),
+                &config,
+                "10/16G",
+            ),
+            (
+                compact(network.clone(), None),
+                &config,
+                "1.2M/s ↓ / 340.0K/s ↑",
+            ),
+            (
+                compact(network, Some(0)),
+                &Config {
+                    decimal_data_units: true,
+                    ..Config::default()
+                },
+                "1M/s ↓ / 348K/s ↑",
+            ),
+            // Without compact units, decimals only apply to percentages.
+            (
+                Section {
+                    decimals: Some(0),
+                    ..Module::MemoryUsage { basis: None }.into()
+                },
+                &config,
+                "9.81/16.00 GiB",
+            ),
+        ];
+        for (section, config, expected) in cases {
+            assert_eq!(
+                expected,
+                text(render_with(&section, config, &mut provider)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn cpu_frequency() {
+        let frequency = |aggregate| Module::CpuFrequency { aggregate };
+        let mut provider = FakeProvider::default();
+        assert_eq!(
+            "N/A",
+            render(frequency(FreqAggregate::Max), &mut provider, false)
+        );
+
+        // Cores without a frequency are left out.
+        provider.set_cpu_frequencies(&[3795, 800, 0, 1201]);
+        let cases = [
+            (FreqAggregate::Max, "3.80 GHz"),
+            (FreqAggregate::Min, "800 MHz"),
+            (FreqAggregate::Average, "1.93 GHz"),
+        ];
+        for (aggregate, expected) in cases {
+            assert_eq!(expected, render(frequency(aggregate), &mut provider, false));
+        }
+    }
+
+    #[test]
+    fn cpu_and_processes() {
+        let cases = [
+            (0.0, "0.0%"),
+            (7.4321, "7.4%"),
+            (100.0, "100.0%"),
+            (400.0, "100.0%"),
+        ];
+        for (usage, expected) in cases {
+            let mut provider = FakeProvider::default();
+            provider.set_cpu_usage(usage);
+            assert_eq!(expected, render(Module::CpuUsage, &mut provider, false));
+        }
+
+        let mut provider = FakeProvider::default();
+        provider.set_cpu_usage(7.4321);
+        let config = Config {
+            trim_trailing_zeros: true,
+            ..Config::default()
+        };
+        let section = |decimals| Section {
+            decimals,
+            ..Module::CpuUsage.into()
+        };
+        assert_eq!(
+            "7.43%",
+            text(render_with(&section(Some(2)), &config, &mut provider)).unwrap()
+        );
+        assert_eq!(
+            "7%",
+            text(render_with(&section(Some(0)), &config, &mut provider)).unwrap()
+        );
+
+        let mut provider = FakeProvider::default();
+        provider.set_cpu_usage(100.0);
+        assert_eq!(
+            "100%",
+            text(render_with(&section(None), &config, &mut provider)).unwrap()
+        );
+
+        let mut provider = FakeProvider::default();
+        assert_eq!("0", render(Module::ProcessCount, &mut provider, false));
+        provider.set_process_count(412);
+        assert_eq!("412", render(Module::ProcessCount, &mut provider, false));
+
+        provider.set_load_average(0.5, 1.234, 12.0);
+        assert_eq!(
+            "0.50 1.23 12.00",
+            render(Module::LoadAverage, &mut provider, false)
+        );
+    }
+
+    #[test]
+    fn disks() {
+        let mut provider = FakeProvider::default();
+        provider
+            .add_disk("/dev/sda", 500_000_000_000, 200_000_000_000, false)
+            .mounted_at("/")
+            // A second mount of the same filesystem, e.g. a btrfs subvolume.
+            .add_disk("/dev/sda", 500_000_000_000, 200_000_000_000, false)
+            .mounted_at("/home")
+            .add_disk("/dev/sdb", 0, 0, false)
+            .add_disk("/dev/sdc", 64 * GIB, 32 * GIB, true);
+
+        let disk = |name: &str| Module::DiskUsage {
+            name: name.into(),
+            inodes: false,
+        };
+        let total = |include_removables| Module::DiskUsageTotal { include_removables };
+
+        let cases = [
+            (disk("/dev/sda"), true, "300.00/500.00 GB"),
+            (disk("/dev/sda"), false, "279.40/465.66 GiB"),
+            (disk("/"), false, "279.40/465.66 GiB"),
+            (disk("/home"), false, "279.40/465.66 GiB"),
+            (disk("/dev/sdb"), false, "0.00/0.00 KiB"),
+            (disk("/dev/sdz"), false, "N/A"),
+            (total(false), false, "279.40/465.66 GiB"),
+            (total(true), false, "311.40/529.66 GiB"),
+        ];
+        for (module, decimal, expected) in cases {
+            assert_eq!(expected, render(module, &mut provider, decimal));
+        }
+
+        let mut empty = FakeProvider::default();
+        assert_eq!("0.00/0.00 KiB", render(total(true), &mut empty, false));
+
+        // Left asleep by skip_when_standby, with the usage from before.
+        provider.disks[3].spared = true;
+        assert_eq!(
+            "32.00/64.00 GiB (sleeping)",
+            render(disk("/dev/sdc"), &mut provider, false)
+        );
+        assert_eq!(
+            "279.40/465.66 GiB",
+            render(total(false), &mut provider, false)
+        );
+        assert_eq!(
+            "311.40/529.66 GiB (sleeping)",
+            render(total(true), &mut provider, false)
+        );
+    }
+
+    #[test]
+    fn network_throughput() {
+        let mut provider = FakeProvider::default();
+        provider
+            .set_network_rates("eth0", 1.2 * 1024.0 * 1024.0, 340.0 * 1024.0)
+            .set_network_rates("wlan0", 0.0, 0.0);
+        let network = |interface: &str| Module::NetworkThroughput {
+            interface: interface.into(),
+            average_over_s: None,
+            peak_hold_s: None,
+            min_unit: None,
+            max_unit: None,
+            show_totals: false,
+            reset_signal: None,
+        };
+        let bounded = |min_unit, max_unit| Module::NetworkThroughput {
+            interface: "eth0".into(),
+            average_over_s: None,
+            peak_hold_s: None,
+            min_unit,
+            max_unit,
+            show_totals: false,
+            reset_signal: None,
+        };
+
+        let cases = [
+            (network("eth0"), false, "1.2 MiB/s ↓ / 340.0 KiB/s ↑"),
+            (network("eth0"), true, "1.3 MB/s ↓ / 348.2 KB/s ↑"),
+            (network("wlan0"), false, "0.0 KiB/s ↓ / 0.0 KiB/s ↑"),
+            (network("eth1"), false, "N/A"),
+            (
+                bounded(Some(UnitPrefix::Mega), None),
+                false,
+                "1.2 MiB/s ↓ / 0.3 MiB/s ↑",
+            ),
+            (
+                bounded(None, Some(UnitPrefix::Kilo)),
+                false,
+                "1228.8 KiB/s ↓ / 340.0 KiB/s ↑",
+            ),
+        ];
+        for (module, decimal, expected) in cases {
+            assert_eq!(expected, render(module, &mut provider, decimal));
+        }
+    }
+
+    #[test]
+    fn network_totals() {
+        let section = Section::from(Module::NetworkThroughput {
+            interface: "eth0".into(),
+            average_over_s: None,
+            peak_hold_s: None,
+            min_unit: Some(UnitPrefix::Mega),
+            max_unit: None,
+            show_totals: true,
+            reset_signal: None,
+        });
+        let config = Config::default();
+        let mut provider = FakeProvider::default();
+        provider.set_network_rates("eth0", 1.2 * 1024.0 * 1024.0, 512.0);
+        let mut tracked = Tracked::default();
+        let mut rates = Rates::default();
+        let mut render = |counters, rates: &mut Rates| {
+            provider.set_network_totals("eth0", counters);
+            let mut ctx = Context {
+                section: 0,
+                provider: &mut provider,
+                timers: &Timers::new(),
+                extremes: &mut tracked.extremes,
+                validity: &mut tracked.validity,
+                rates,
+                values: &mut tracked.values,
+                readings: &mut tracked.readings,
+                urgency: &mut tracked.urgency,
+                now: Instant::now(),
+                local: SystemClock.now_local(),
+            };
+            render_module(&section, &config, &mut ctx)
+        };
+
+        // Counted from the first read, each fitted on its own. The interface's counters
+        // starting over keep what was counted before.
+        let cases = [
+            (
+                [40 * GIB, GIB],
+                "1.2 MiB/s (0.0 KiB) ↓ / 0.0 MiB/s (0.0 KiB) ↑",
+            ),
+            (
+                [44 * GIB + 3 * GIB / 10, GIB + 1024],
+                "1.2 MiB/s (4.3 GiB) ↓ / 0.0 MiB/s (1.0 KiB) ↑",
+            ),
+            ([GIB, 1024], "1.2 MiB/s (5.3 GiB) ↓ / 0.0 MiB/s (2.0 KiB) ↑"),
+        ];
+        for (counters, expected) in cases {
+            assert_eq!(expected, render(counters, &mut rates));
+        }
+
+        rates.reset_totals("eth0");
+        assert_eq!(
+            "1.2 MiB/s (1.0 GiB) ↓ / 0.0 MiB/s (0.0 KiB) ↑",
+            render([2 * GIB, 1024], &mut rates)
+        );
+    }
+
+    #[test]
+    fn network_windows() {
+        let section = |average_over_s, peak_hold_s| {
+            Section::from(Module::NetworkThroughput {
+                interface: "eth0".into(),
+                average_over_s,
+                peak_hold_s,
+                min_unit: None,
+                max_unit: None,
+                show_totals: false,
+                reset_signal: None,
+            })
+        };
+        let (averaged, peaks, both) = (
+            section(Some(3.into()), None),
+            section(None, Some(5.into())),
+            section(Some(3.into()), Some(5.into())),
+        );
+        let config = Config::default();
+        let mut rates = Rates::default();
+        let start = Instant::now();
+        let mut provider = FakeProvider::default();
+        let mut render = |section: &Section, provider: &mut FakeProvider, secs| {
+            let mut ctx = Context {
+                section: 0,
+                provider,
+                timers: &Timers::new(),
+                extremes: &mut Extremes::default(),
+                validity: &mut Validity::default(),
+                rates: &mut rates,
+                values: &mut HashMap::new(),
+                readings: &mut HashMap::new(),
+                urgency: &mut Urgency::default(),
+                now: start + Duration::from_secs(secs),
+                local: SystemClock.now_local(),
+            };
+            render_module(section, &config, &mut ctx)
+        };
+
+        // One update per second, in KiB/s received while sending 10 KiB/s.
+        let steps = [
+            (100.0, "100.0", "100.0"),
+            (400.0, "250.0", "400.0"),
+            (100.0, "200.0", "400.0"),
+            (0.0, "166.7", "400.0"),
+            (0.0, "33.3", "400.0"),
+            (0.0, "0.0", "400.0"),
+            (0.0, "0.0", "100.0"),
+            (0.0, "0.0", "0.0"),
+        ];
+        for (secs, (received, average, peak)) in steps.into_iter().enumerate() {
+            let secs = secs as u64;
+            provider.set_network_rates("eth0", received * 1024.0, 10.0 * 1024.0);
+            assert_eq!(
+                format!("{average} KiB/s ↓ / 10.0 KiB/s ↑"),
+                render(&averaged, &mut provider, secs)
+            );
+            assert_eq!(
+                format!("{received:.1} KiB/s ({peak} KiB/s) ↓ / 10.0 KiB/s (10.0 KiB/s) ↑"),
+                render(&peaks, &mut provider, secs)
+            );
+            render(&both, &mut provider, secs);
+        }
+        assert_eq!(
+            "0.0 KiB/s (0.0 KiB/s) ↓ / 10.0 KiB/s (10.0 KiB/s) ↑",
+            render(&both, &mut provider, 7)
+        );
+
+        // A vanished interface starts over when it comes back.
+        provider.set_network_rates("eth0", 800.0 * 1024.0, 10.0 * 1024.0);
+        render(&peaks, &mut provider, 8);
+        let mut provider = FakeProvider::default();
+        assert_eq!("N/A", render(&peaks, &mut provider, 9));
+        provider.set_network_rates("eth0", 50.0 * 1024.0, 0.0);
+        assert_eq!(
+            "50.0 KiB/s (50.0 KiB/s) ↓ / 0.0 KiB/s (0.0 KiB/s) ↑",
+            render(&peaks, &mut provider, 10)
+        );
+    }
+
+    #[test]
+    fn battery() {
+        let battery = |name: Option<&str>, hide_when_missing| Section {
+            decoration: Decoration {
+                before: Some("bat ".into()),
+                ..Decoration::default()
+            },
+            ..Module::Battery {
+                name: name.map(Into::into),
+                hide_when_missing,
+                limit_marker: None,
+                relative_to_limit: false,
+            }
+            .into()
+        };
+        let render = |section: &Section, provider: &mut FakeProvider| {
+            text(render_with(section, &Config::default(), provider))
+        };
+
+        let mut desktop = FakeProvider::default();
+        assert_eq!(
+            Some("bat N/A".into()),
+            render(&battery(None, false), &mut desktop)
+        );
+        assert_eq!(None, render(&battery(None, true), &mut desktop));
+
+        let mut laptop = FakeProvider::default();
+        laptop
+            .add_battery("BAT0", 85, ChargeState::Charging)
+            .add_battery("BAT1", 40, ChargeState::Discharging)
+            .add_battery("BAT2", 100, ChargeState::Idle);
+        let cases = [
+            (None, "bat 85% ↑"),
+            (Some("BAT1"), "bat 40% ↓"),
+            (Some("BAT2"), "bat 100%"),
+        ];
+        for (name, expected) in cases {
+            for hide_when_missing in [false, true] {
+                assert_eq!(
+                    Some(expected.into()),
+                    render(&battery(name, hide_when_missing), &mut laptop)
+                );
+            }
+        }
+        assert_eq!(
+            Some("bat N/A".into()),
+            render(&battery(Some("BAT3"), false), &mut laptop)
+        );
+        assert_eq!(None, render(&battery(Some("BAT3"), true), &mut laptop));
+
+        // Batteries kept from charging fully.
+        let limited = |name: &str, relative_to_limit, after: &str| Section {
+            decoration: Decoration {
+                after: Some(after.into()),
+                ..Decoration::default()
+            },
+            ..Module::Battery {
+                name: Some(name.into()),
+                hide_when_missing: false,
+                limit_marker: Some("▕".into()),
+                relative_to_limit,
+            }
+            .into()
+        };
+        let mut thinkpad = FakeProvider::default();
+        thinkpad
+            .add_battery("BAT0", 80, ChargeState::Idle)
+            .add_battery("BAT1", 40, ChargeState::Charging)
+            .add_battery("BAT2", 100, ChargeState::Idle)
+            .set_end_threshold("BAT0", 80)
+            .set_end_threshold("BAT1", 80);
+        let cases = [
+            ("BAT0", false, "", "80%▕"),
+            ("BAT0", true, "", "100%▕"),
+            ("BAT1", false, "", "40% ↑"),
+            ("BAT1", true, " of {limit}%", "50% ↑ of 80%"),
+            ("BAT2", false, " of {limit}%", "100% of 100%"),
+            ("BAT2", true, "", "100%"),
+        ];
+        for (name, relative_to_limit, after, expected) in cases {
+            assert_eq!(
+                Some(expected.into()),
+                render(&limited(name, relative_to_limit, after), &mut thinkpad),
+                "{name} {relative_to_limit}"
+            );
+        }
+        // Placeholders stay as they are on other modules.
+        let cpu = Section {
+            decoration: Decoration {
+                after: Some(" {limit}".into()),
+                ..Decoration::default()
+            },
+            ..Module::CpuUsage.into()
+        };
+        assert_eq!(Some("0.0% {limit}".into()), render(&cpu, &mut thinkpad));
+    }
+
+    #[test]
+    fn hide_when() {
+        let section = |module: Module, hide_when| Section {
+            id: Some("x".into()),
+            decoration: Decoration {
+                before: Some("[".into()),
+                after: Some("]".into()),
+                ..Decoration::default()
+            },
+            hide_when,
+            ..module.into()
+        };
+        let swap = |hide_when| section(Module::SwapUsage, hide_when);
+        let empty = |hide_when| {
+            section(
+                Module::Timestamp {
+                    template: String::new(),
+                },
+                hide_when,
+            )
+        };
+        let mut tracked = Tracked::default();
+        let mut render = |section: &Section, provider: &mut FakeProvider| {
+            text(render_tracked(
+                section,
+                &Config::default(),
+                provider,
+                &mut tracked,
+            ))
+        };
+
+        let mut provider = FakeProvider::default();
+        provider.set_swap(0, 0);
+        assert_eq!(
+            Some("[0.00/0.00 KiB]".into()),
+            render(&swap(None), &mut provider)
+        );
+        assert_eq!(
+            None,
+            render(&swap(Some(HideCondition::Zero)), &mut provider)
+        );
+        assert_eq!(
+            Some("[0.00/0.00 KiB]".into()),
+            render(&swap(Some(HideCondition::Empty)), &mut provider)
+        );
+        provider.set_swap(GIB, 4 * GIB);
+        assert_eq!(
+            Some("[1.00/4.00 GiB]".into()),
+            render(&swap(Some(HideCondition::Zero)), &mut provider)
+        );
+
+        // Sections without a value are never zero.
+        assert_eq!(Some("[]".into()), render(&empty(None), &mut provider));
+        assert_eq!(
+            Some("[]".into()),
+            render(&empty(Some(HideCondition::Zero)), &mut provider)
+        );
+        assert_eq!(
+            None,
+            render(&empty(Some(HideCondition::Empty)), &mut provider)
+        );
+
+        // Hidden sections still provide their value.
+        provider.set_swap(0, GIB);
+        render(&swap(Some(HideCondition::Zero)), &mut provider);
+        assert_eq!(Some(&0.0), tracked.values.get("x"));
+    }
+
+    #[test]
+    fn mpd() {
+        let section = Section {
+            decoration: Decoration {
+                before: Some("♪ ".into()),
+                ..Decoration::default()
+            },
+            ..Module::Mpd {
+                host: mpd::DEFAULT_HOST.into(),
+                port: mpd::DEFAULT_PORT,
+                format: mpd::DEFAULT_FORMAT.into(),
+                max_len: Some(12),
+            }
+            .into()
+        };
+        let render =
+            |provider: &mut FakeProvider| text(render_with(&section, &Config::default(), provider));
+        let status = |state| mpd::Status {
+            state,
+            artist: Some("Boards of Canada".into()),
+            title: Some("Roygbiv (Remastered)".into()),
+            elapsed: None,
+            duration: None,
+        };
+
+        // Unreachable.
+        let mut provider = FakeProvider::default();
+        assert_eq!(None, render(&mut provider));
+
+        provider.set_mpd(status(mpd::State::Stop));
+        assert_eq!(None, render(&mut provider));
+        provider.set_mpd(status(mpd::State::Play));
+        assert_eq!(
+            Some("♪ Boards of Canada - Roygbiv (Re…".into()),
+            render(&mut provider)
+        );
+        provider.set_mpd(status(mpd::State::Pause));
+        assert!(render(&mut provider).is_some());
+    }
+
+    #[test]
+    fn maildir() {
+        let section = |path: &str| Section {
+            id: Some("mail".into()),
+            ..Module::Maildir { path: path.into() }.into()
+        };
+        let render = |section: &Section, provider: &mut FakeProvider| {
+            let mut tracked = Tracked::default();
+            let piece = render_tracked(section, &Config::default(), provider, &mut tracked);
+            (text(piece), tracked.values.get("mail").copied())
+        };
+
+        let (work, home) = (section("/mail/work"), section("/mail/home"));
+        let mut provider = FakeProvider::default();
+        provider
+            .set_unread_mail("/mail/work", 3)
+            .set_unread_mail("/mail/home", 0);
+        assert_eq!(
+            (Some("✉ 3".into()), Some(3.0)),
+            render(&work, &mut provider)
+        );
+        assert_eq!((None, Some(0.0)), render(&home, &mut provider));
+        assert_eq!(
+            (Some("N/A".into()), None),
+            render(&section("/mail/gone"), &mut provider)
+        );
+    }
+
+    #[test]
+    fn backend_health() {
+        let section = Section {
+            id: Some("output".into()),
+            ..Module::BackendHealth.into()
+        };
+        let render = || {
+            let mut tracked = Tracked::default();
+            let piece = render_tracked(
+                &section,
+                &Config::default(),
+                &mut FakeProvider::default(),
+                &mut tracked,
+            );
+            (text(piece), tracked.values.get("output").copied())
+        };
+
+        health::set_backend("File");
+        assert_eq!((None, Some(0.0)), render());
+        health::record(&Err("Unable to write".into()), false);
+        health::record(&Err("Unable to write".into()), false);
+        assert_eq!((Some("file✗".into()), Some(2.0)), render());
+        // Cleared by the next successful write.
+        health::record(&Ok(()), false);
+        assert_eq!((None, Some(0.0)), render());
+    }
+
+    #[test]
+    fn systemd_failed() {
+        let section = Section {
+            id: Some("failed".into()),
+            ..Module::SystemdFailed.into()
+        };
+        let render = |provider: &mut FakeProvider| {
+            let mut tracked = Tracked::default();
+            let piece = render_tracked(&section, &Config::default(), provider, &mut tracked);
+            (text(piece), tracked.values.get("failed").copied())
+        };
+
+        // Without systemd the section stays hidden and has no value.
+        let mut provider = FakeProvider::default();
+        assert_eq!((None, None), render(&mut provider));
+
+        provider.set_failed_units(0);
+        assert_eq!((None, Some(0.0)), render(&mut provider));
+        provider.set_failed_units(2);
+        assert_eq!((Some("✗ 2".into()), Some(2.0)), render(&mut provider));
+    }
+
+    #[test]
+    fn temperature() {
+        let mut provider = FakeProvider::default();
+        provider
+            .add_component("acpitz temp1", 27.8)
+            .add_component("coretemp Package id 0", 54.2)
+            .add_component("k10temp Tctl", 61.5);
+        let sensor = |component: &str, fahrenheit| Module::Temperature {
+            component: component.into(),
+            fahrenheit,
+            aggregate: TempAggregate::First,
+            warn_above: None,
+            clear_below: None,
+        };
+
+        let cases = [
+            (sensor("coretemp", false), "54°C"),
+            (sensor("CoreTemp", true), "130°F"),
+            (sensor("tctl", false), "62°C"),
+            (sensor("temp", false), "28°C"),
+            (sensor("nvme", false), "N/A"),
+        ];
+        for (module, expected) in cases {
+            assert_eq!(expected, render(module, &mut provider, false));
+        }
+
+        let named = Module::Temperature {
+            component: "coretemp".into(),
+            fahrenheit: false,
+            aggregate: TempAggregate::Named(vec!["Package".into(), "Core 9".into()]),
+            warn_above: None,
+            clear_below: None,
+        };
+        assert_eq!(
+            "Package 54°C Core 9 N/A",
+            render(named, &mut provider, false)
+        );
+    }
+
+    #[test]
+    fn temperature_aggregates() {
+        let sensor = |label: &str, temperature| ComponentInfo {
+            label: label.into(),
+            temperature,
+        };
+        let sensors = [
+            sensor("acpitz temp1", 27.0),
+            sensor("coretemp Core 0", 50.0),
+            sensor("coretemp Core 1", 70.0),
+            sensor("coretemp Core 2", 60.0),
+            sensor("nvme Composite", 40.0),
+        ];
+        let aggregate =
+            |aggregate| aggregate_temperatures(&sensors, "coretemp", &aggregate).map(|x| x.0);
+
+        assert_eq!(Some(50.0), aggregate(TempAggregate::First));
+        assert_eq!(Some(70.0), aggregate(TempAggregate::Max));
+        assert_eq!(Some(60.0), aggregate(TempAggregate::Average));
+
+        let named = TempAggregate::Named(vec!["core 2".into(), "Core 7".into(), "core 0".into()]);
+        assert_eq!(
+            Some((
+                60.0,
+                vec![
+                    ("core 2", Some(60.0)),
+                    ("Core 7", None),
+                    ("core 0", Some(50.0))
+                ]
+            )),
+            aggregate_temperatures(&sensors, "coretemp", &named)
+        );
+        // Names only pick among the sensors matching the component.
+        let other = TempAggregate::Named(vec!["Composite".into()]);
+        assert_eq!(None, aggregate_temperatures(&sensors, "coretemp", &other));
+
+        for aggregate in [
+            TempAggregate::First,
+            TempAggregate::Max,
+            TempAggregate::Average,
+        ] {
+            assert_eq!(
+                None,
+                aggregate_temperatures(&sensors, "k10temp", &aggregate)
+            );
+        }
+    }
+
+    #[test]
+    fn temperature_warning() {
+        let mut config = Config {
+            output: OutputBackend::Stdout {
+                reverse_order: false,
+            },
+            sections: vec![Section {
+                decoration: Decoration {
+                    before: Some("cpu ".into()),
+                    ..Decoration::default()
+                },
+                ..Module::Temperature {
+                    component: "coretemp".into(),
+                    fahrenheit: false,
+                    aggregate: TempAggregate::Max,
+                    warn_above: Some(80),
+                    clear_below: Some(70),
+                }
+                .into()
+            }],
+            ..Config::default()
+        };
+        config.validate().unwrap();
+        let section = &config.sections[0];
+        assert_eq!(
+            Some(TEMPERATURE_WARNING_COLOR),
+            section.thresholds[0].decoration.fg.as_deref()
+        );
+
+        let mut tracked = Tracked::default();
+        let mut render = |temperatures: [f32; 2]| {
+            let mut provider = FakeProvider::default();
+            provider
+                .add_component("coretemp Core 0", temperatures[0])
+                .add_component("coretemp Core 1", temperatures[1]);
+            match render_tracked(section, &config, &mut provider, &mut tracked) {
+                Piece::Text(text, fg, urgent, _) => (text, fg.is_some(), urgent),
+                _ => panic!("expected text"),
+            }
+        };
+
+        // Only clears once the hottest core is back at clear_below.
+        let cases = [
+            ([60.0, 79.0], ("cpu 79°C", false, false)),
+            ([60.0, 81.0], ("!cpu 81°C", true, true)),
+            ([75.0, 72.0], ("!cpu 75°C", true, true)),
+            ([70.0, 65.0], ("cpu 70°C", false, false)),
+            ([75.0, 65.0], ("cpu 75°C", false, false)),
+        ];
+        for (temperatures, (text, colored, urgent)) in cases {
+            assert_eq!(
+                (text.into(), colored, urgent),
+                render(temperatures),
+                "{temperatures:?}"
+            );
+        }
+
+        let mut invalid = Config {
+            sections: vec![Module::Temperature {
+                component: "coretemp".into(),
+                fahrenheit: false,
+                aggregate: TempAggregate::Named(Vec::new()),
+                warn_above: None,
+                clear_below: None,
+            }
+            .into()],
+            ..Config::default()
+        };
+        assert!(invalid.validate().is_err());
+        invalid.sections[0].module = Module::Temperature {
+            component: "coretemp".into(),
+            fahrenheit: false,
+            aggregate: TempAggregate::Max,
+            warn_above: Some(70),
+            clear_below: Some(80),
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn uptime() {
+        let mut provider = FakeProvider::default();
+        provider.set_uptime(3 * 86_400 + 4 * 3600 + 12 * 60 + 30);
+        let uptime = |format| Module::Uptime { format };
+
+        assert_eq!(
+            "3d 4h 12m",
+            render(uptime(UptimeFormat::Compact), &mut provider, false)
+        );
+        assert_eq!(
+            "76:12",
+            render(uptime(UptimeFormat::HoursMinutes), &mut provider, false)
+        );
+        assert_eq!(
+            "274350",
+            render(uptime(UptimeFormat::Seconds), &mut provider, false)
+        );
+    }
+
+    #[test]
+    fn units() {
+        let config = Config::default();
+        let mut provider = FakeProvider::default();
+        provider
+            .set_cpu_usage(12.5)
+            .set_uptime(90)
+            .set_network_rates("eth0", 1000.0, 200.0);
+        let mut tracked = Tracked::default();
+        let mut reading = |section: &Section| {
+            render_tracked(section, &config, &mut provider, &mut tracked);
+            tracked.readings.get(&0).cloned()
+        };
+
+        let cpu = Section {
+            id: Some("cpu".into()),
+            ..Module::CpuUsage.into()
+        };
+        assert_eq!(
+            Some(Reading {
+                id: Some("cpu".into()),
+                module: "CpuUsage",
+                value: Value::new(12.5, Unit::Percent),
+            }),
+            reading(&cpu)
+        );
+        let uptime = Module::Uptime {
+            format: UptimeFormat::Compact,
+        };
+        assert_eq!(
+            Some(Value::new(90.0, Unit::Seconds)),
+            reading(&uptime.into()).map(|x| x.value)
+        );
+        let network = Module::NetworkThroughput {
+            interface: "eth0".into(),
+            average_over_s: None,
+            peak_hold_s: None,
+            min_unit: None,
+            max_unit: None,
+            show_totals: false,
+            reset_signal: None,
+        };
+        assert_eq!(
+            Some(Value::new(1200.0, Unit::BytesPerSecond)),
+            reading(&network.into()).map(|x| x.value)
+        );
+        let derived = Module::Derived {
+            expression: "2 * 512".into(),
+            unit: Unit::Bytes,
+        };
+        assert_eq!(
+            Some(Value::new(1024.0, Unit::Bytes)),
+            reading(&derived.into()).map(|x| x.value)
+        );
+
+        // Sections without a value leave none behind.
+        assert_eq!(None, reading(&Module::KeyboardLayout.into()));
+    }
+
+    #[test]
+    fn brightness() {
+        let mut provider = FakeProvider::default();
+        provider
+            .add_backlight("intel_backlight", 7650, 19200)
+            .add_backlight("ddcci0", 1, 3);
+        let brightness = |device: Option<&str>| Module::Brightness {
+            device: device.map(Into::into),
+        };
+
+        assert_eq!("40%", render(brightness(None), &mut provider, false));
+        assert_eq!(
+            "33%",
+            render(brightness(Some("ddcci0")), &mut provider, false)
+        );
+        assert_eq!(
+            "N/A",
+            render(brightness(Some("acpi_video0")), &mut provider, false)
+        );
+
+        let config = Config {
+            trim_trailing_zeros: true,
+            ..Config::default()
+        };
+        let mut render = |device, decimals| {
+            let section = Section {
+                decimals,
+                ..brightness(device).into()
+            };
+            text(render_with(&section, &config, &mut provider)).unwrap()
+        };
+        assert_eq!("33.3%", render(Some("ddcci0"), Some(1)));
+        assert_eq!("39.84%", render(None, Some(2)));
+    }
+
+    #[test]
+    fn keyboard_layout() {
+        let mut provider = FakeProvider::default();
+        assert_eq!("N/A", render(Module::KeyboardLayout, &mut provider, false));
+
+        provider.set_keyboard_layout("de");
+        assert_eq!("de", render(Module::KeyboardLayout, &mut provider, false));
+    }
+
+    #[test]
+    fn bluetooth() {
+        let mut provider = FakeProvider::default();
+        let module = || Module::Bluetooth {
+            device_mac: Some("00:1A:7D:DA:71:13".into()),
+        };
+        assert_eq!("N/A", render(module(), &mut provider, false));
+
+        provider.set_bluetooth(bluetooth::Status {
+            powered: true,
+            devices: vec![bluetooth::Device {
+                address: "00:1A:7D:DA:71:13".into(),
+                connected: true,
+                battery: Some(80),
+            }],
+        });
+        assert_eq!("BT 1 80%", render(module(), &mut provider, false));
+    }
+
+    #[test]
+    fn top_process() {
+        let mut provider = FakeProvider::default();
+        let top = |by, max_len, exclude_kernel_threads| Module::TopProcess {
+            by,
+            max_len,
+            exclude_kernel_threads,
+        };
+        assert_eq!(
+            "N/A",
+            render(top(SortKey::Cpu, None, false), &mut provider, false)
+        );
+
+        provider
+            .set_memory(8 * GIB, 16 * GIB)
+            .add_process("firefox", 34.2, 4 * GIB, false)
+            .add_process("kworker/0:1", 80.0, 6 * GIB, true)
+            .add_process("rustc", 150.0, GIB / 2, false)
+            .add_process("cargo", 150.0, GIB, false);
+
+        let cases = [
+            // Ties go to the first name.
+            (top(SortKey::Cpu, None, false), "cargo 150%"),
+            (top(SortKey::Memory, None, false), "kworker/0:1 38%"),
+            (top(SortKey::Memory, Some(4), true), "fir… 25%"),
+            (top(SortKey::Memory, Some(7), true), "firefox 25%"),
+        ];
+        for (module, expected) in cases {
+            assert_eq!(expected, render(module, &mut provider, false));
+        }
+
+        provider.set_memory(0, 0);
+        assert_eq!(
+            "N/A",
+            render(top(SortKey::Memory, None, false), &mut provider, false)
+        );
+    }
+
+    #[test]
+    fn ip_address() {
+        let mut provider = FakeProvider::default();
+        provider
+            .add_address("wlan0", "fe80::a00:27ff:fe4e:66a1")
+            .add_address("wlan0", "192.168.1.23")
+            .add_address("wlan0", "10.0.0.5")
+            .add_address("wlan0", "2001:db8::5")
+            .add_address("wg0", "169.254.3.3")
+            .add_address("eth0", "");
+        let module =
+            |interface: &str, family, max_addresses, include_link_local| Module::IpAddress {
+                interface: interface.into(),
+                family,
+                max_addresses,
+                include_link_local,
+                separator: None,
+                missing_text: None,
+            };
+
+        let cases = [
+            (module("wlan0", AddrFamily::Ipv4, 1, false), "192.168.1.23"),
+            (module("wlan0", AddrFamily::Ipv6, 1, false), "2001:db8::5"),
+            (
+                module("wlan0", AddrFamily::Ipv6, 2, true),
+                "fe80::a00:27ff:fe4e:66a1 2001:db8::5",
+            ),
+            (
+                module("wlan0", AddrFamily::Any, 3, false),
+                "192.168.1.23 10.0.0.5 2001:db8::5",
+            ),
+            (module("wg0", AddrFamily::Any, 1, false), "no addr"),
+            (module("wg0", AddrFamily::Any, 1, true), "169.254.3.3"),
+            (module("eth0", AddrFamily::Any, 1, false), "no addr"),
+            (module("eth1", AddrFamily::Any, 1, false), "N/A"),
+        ];
+        for (module, expected) in cases {
+            assert_eq!(expected, render(module, &mut provider, false));
+        }
+
+        let custom = Module::IpAddress {
+            interface: "wlan0".into(),
+            family: AddrFamily::Ipv4,
+            max_addresses: 5,
+            include_link_local: false,
+            separator: Some(", ".into()),
+            missing_text: Some("offline".into()),
+        };
+        assert_eq!(
+            "192.168.1.23, 10.0.0.5",
+            render(custom.clone(), &mut provider, false)
+        );
+        provider.addresses.insert("wlan0".into(), Vec::new());
+        assert_eq!("offline", render(custom, &mut provider, false));
+    }
+
+    #[test]
+    fn public_ip() {
+        let mut provider = FakeProvider::default();
+        let module = || Module::PublicIp {
+            url: "https://icanhazip.com".into(),
+            refresh_interval_s: 3600.into(),
+        };
+        assert_eq!("…", render(module(), &mut provider, false));
+
+        provider.set_public_ip("https://icanhazip.com", "203.0.113.7");
+        assert_eq!("203.0.113.7", render(module(), &mut provider, false));
+    }
+
+    #[test]
+    fn volume() {
+        let mut provider = FakeProvider::default();
+        provider
+            .set_volume("Master", 45.0, false)
+            .set_volume("PCM", 45.0, true);
+        let volume = |control: Option<&str>| Module::Volume {
+            control: control.map(Into::into),
+        };
+
+        assert_eq!("45%", render(volume(None), &mut provider, false));
+        assert_eq!("muted", render(volume(Some("PCM")), &mut provider, false));
+        assert_eq!("N/A", render(volume(Some("Speaker")), &mut provider, false));
+    }
+
+    #[test]
+    fn derived() {
+        let mut provider = FakeProvider::default();
+        provider.set_memory(3 * GIB, 4 * GIB).set_swap(GIB, 4 * GIB);
+        let config = Config {
+            trim_trailing_zeros: true,
+            ..Config::default()
+        };
+        let mut values = HashMap::new();
+        let mut ctx = Context {
+            section: 0,
+            provider: &mut provider,
+            timers: &Timers::new(),
+            extremes: &mut Extremes::default(),
+            validity: &mut Validity::default(),
+            rates: &mut Rates::default(),
+            values: &mut values,
+            readings: &mut HashMap::new(),
+            urgency: &mut Urgency::default(),
+            now: Instant::now(),
+            local: SystemClock.now_local(),
+        };
+        let named = |id: &str, module: Module| Section {
+            id: Some(id.into()),
+            ..module.into()
+        };
+        let derived = |expression: &str| {
+            Section::from(Module::Derived {
+                expression: expression.into(),
+                unit: Unit::Count,
+            })
+        };
+
+        assert_eq!(
+            Some("N/A"),
+            render_text(&derived("mem.value"), &config, &mut ctx)
+                .map(|x| x.0)
+                .as_deref()
+        );
+        render_text(
+            &named("mem", Module::MemoryUsage { basis: None }),
+            &config,
+            &mut ctx,
+        );
+        render_text(&named("swap", Module::SwapUsage), &config, &mut ctx);
+        assert_eq!(
+            Some("100"),
+            render_text(&derived("mem.value + swap.value"), &config, &mut ctx)
+                .map(|x| x.0)
+                .as_deref()
+        );
+        assert_eq!(
+            Some("6.25"),
+            render_text(&derived("mem.value * swap.value / 300"), &config, &mut ctx)
+                .map(|x| x.0)
+                .as_deref()
+        );
+        assert_eq!(
+            Some("N/A"),
+            render_text(&derived("mem.value / (swap.value - 25)"), &config, &mut ctx)
+                .map(|x| x.0)
+                .as_deref()
+        );
+
+        // Values of sections that show N/A are forgotten.
+        render_text(
+            &named(
+                "mem",
+                Module::DiskUsage {
+                    name: "/missing".into(),
+                    inodes: false,
+                },
+            ),
+            &config,
+            &mut ctx,
+        );
+        assert_eq!(
+            Some("N/A"),
+            render_text(&derived("mem.value"), &config, &mut ctx)
+                .map(|x| x.0)
+                .as_deref()
+        );
+    }
+
+    #[test]
+    fn command() {
+        let sh = |script: &str, timeout_ms, fallback: Option<&str>| Module::Command {
+            command: "sh".into(),
+            args: vec!["-c".into(), script.into()],
+            timeout_ms,
+            fallback: fallback.map(Into::into),
+            join_lines: None,
+        };
+        let joined = |script: &str, separator: &str| Module::Command {
+            command: "sh".into(),
+            args: vec!["-c".into(), script.into()],
+            timeout_ms: None,
+            fallback: None,
+            join_lines: Some(separator.into()),
+        };
+        let mut provider = FakeProvider::default();
+
+        let cases = [
+            (sh("echo 23°C", None, None), "23°C"),
+            (sh("printf '  padded \\r\\n'", None, None), "  padded "),
+            (sh("printf 'no newline'", None, None), "no newline"),
+            (sh("printf 'first\\nsecond\\n'", None, None), "ERR"),
+            (sh("printf 'one\\n\\n'", None, Some("-")), "-"),
+            (
+                joined("printf 'first\\nsecond\\n'", " / "),
+                "first / second",
+            ),
+            (joined("printf 'a\\r\\nb'", ""), "ab"),
+            (joined("echo single", " / "), "single"),
+            (sh("true", None, None), ""),
+            (sh("echo warning >&2; echo ok", None, None), "ok"),
+            (sh("echo partial; exit 3", None, None), "ERR"),
+            (sh("exit 1", None, Some("offline")), "offline"),
+            (sh("sleep 5; echo late", Some(100.into()), None), "ERR"),
+        ];
+        for (module, expected) in cases {
+            assert_eq!(expected, render(module, &mut provider, false));
+        }
+
+        let missing = Module::Command {
+            command: "stringbar-definitely-not-installed".into(),
+            args: Vec::new(),
+            timeout_ms: None,
+            fallback: None,
+            join_lines: None,
+        };
+        assert_eq!("ERR", render(missing, &mut provider, false));
+    }
+
+    #[test]
+    fn inodes() {
+        let mut provider = FakeProvider::default();
+        provider
+            .add_disk("/dev/sda1", 4 * GIB, 3 * GIB, false)
+            .mounted_at("/")
+            .with_inodes(91, 100)
+            .add_disk("/dev/sdb1", 4 * GIB, GIB, false)
+            .mounted_at("/home")
+            .with_inodes(10, 1000)
+            // btrfs reports no inode limit
+            .add_disk("/dev/sdc1", 4 * GIB, 2 * GIB, false)
+            .mounted_at("/data");
+        let config = Config::default();
+        let mut ctx = Context {
+            section: 0,
+            provider: &mut provider,
+            timers: &Timers::new(),
+            extremes: &mut Extremes::default(),
+            validity: &mut Validity::default(),
+            rates: &mut Rates::default(),
+            values: &mut HashMap::new(),
+            readings: &mut HashMap::new(),
+            urgency: &mut Urgency::default(),
+            now: Instant::now(),
+            local: SystemClock.now_local(),
+        };
+        let disk = |name: &str, inodes| {
+            Section::from(Module::DiskUsage {
+                name: name.into(),
+                inodes,
+            })
+        };
+
+        let cases = [
+            (disk("/", false), "1.00/4.00 GiB", 25.0),
+            (disk("/", true), "1.00/4.00 GiB / inodes 91%", 91.0),
+            (disk("/home", true), "3.00/4.00 GiB / inodes 1%", 75.0),
+            (disk("/data", true), "2.00/4.00 GiB", 50.0),
+        ];
+        for (section, expected, value) in cases {
+            assert_eq!(
+                (expected.to_string(), Some(Value::new(value, Unit::Percent))),
+                super::render_module(&section, &config, &mut ctx, &Fetched::Nothing)
+            );
+        }
+
+        let each = Module::DiskUsageEach {
+            filter: DiskFilter::default(),
+            separator: Some(", ".into()),
+            inodes: true,
+        };
+        assert_eq!(
+            "1.00/4.00 GiB / inodes 91%, 3.00/4.00 GiB / inodes 1%, 2.00/4.00 GiB",
+            render_module(&each.into(), &config, &mut ctx)
+        );
+    }
+
+    #[test]
+    fn disk_expansion() {
+        let config = Config::default();
+        let section = Section {
+            decoration: Decoration {
+                before: Some("{name} ".into()),
+                after: None,
+                ..Decoration::default()
+            },
+            ..Module::DiskUsageEach {
+                filter: DiskFilter::default(),
+                separator: Some(" ".into()),
+                inodes: false,
+            }
+            .into()
+        };
+        let render =
+            |provider: &mut FakeProvider| text(render_with(&section, &config, provider)).unwrap();
+
+        let mut provider = FakeProvider::default();
+        assert_eq!(" N/A", render(&mut provider));
+
+        provider.add_disk("/dev/sdb", 4 * GIB, 3 * GIB, false);
+        assert_eq!("sdb 1.00/4.00 GiB", render(&mut provider));
+
+        // new disks are sorted in, the same disk mounted twice is listed once,
+        // and removable disks are skipped
+        provider
+            .add_disk("/dev/sda", 2 * GIB, GIB, false)
+            .add_disk("/dev/sdb", 4 * GIB, 3 * GIB, false)
+            .add_disk("/dev/sdc", 8 * GIB, 0, true);
+        assert_eq!("sda 1.00/2.00 GiB sdb 1.00/4.00 GiB", render(&mut provider));
+
+        provider.disks.retain(|x| x.name != "/dev/sda");
+        assert_eq!("sdb 1.00/4.00 GiB", render(&mut provider));
+    }
+
+    #[test]
+    fn battery_expansion() {
+        let config = Config::default();
+        let section = Section {
+            decoration: Decoration {
+                before: Some("{name} ".into()),
+                ..Decoration::default()
+            },
+            ..Module::BatteryEach {
+                separator: Some(" | ".into()),
+            }
+            .into()
+        };
+        let render =
+            |provider: &mut FakeProvider| text(render_with(&section, &config, provider)).unwrap();
+
+        let mut provider = FakeProvider::default();
+        assert_eq!(" N/A", render(&mut provider));
+
+        provider.add_battery("BAT1", 40, ChargeState::Idle);
+        assert_eq!("BAT1 40%", render(&mut provider));
+
+        // A battery plugged in later is sorted in.
+        provider.add_battery("BAT0", 62, ChargeState::Discharging);
+        assert_eq!("BAT0 62% ↓ | BAT1 40%", render(&mut provider));
+
+        provider.batteries.retain(|x| x.0 != "BAT1");
+        assert_eq!("BAT0 62% ↓", render(&mut provider));
+    }
+
+    #[test]
+    fn network_expansion() {
+        let config = Config::default();
+        let section = |exclude_loopback| Section {
+            decoration: Decoration {
+                before: Some("{name} ".into()),
+                ..Decoration::default()
+            },
+            ..Module::NetworkEach {
+                exclude_loopback,
+                separator: None,
+            }
+            .into()
+        };
+        let render = |section: &Section, provider: &mut FakeProvider| {
+            text(render_with(section, &config, provider)).unwrap()
+        };
+
+        let mut provider = FakeProvider::default();
+        assert_eq!(" N/A", render(&section(false), &mut provider));
+
+        provider
+            .set_network_rates("wlan0", 1536.0, 512.0)
+            .set_network_rates("lo", 0.0, 0.0);
+        provider.loopback.insert("lo".into());
+        assert_eq!(
+            "lo 0.0 KiB/s ↓ / 0.0 KiB/s ↑ | wlan0 1.5 KiB/s ↓ / 0.5 KiB/s ↑",
+            render(&section(false), &mut provider)
+        );
+        assert_eq!(
+            "wlan0 1.5 KiB/s ↓ / 0.5 KiB/s ↑",
+            render(&section(true), &mut provider)
+        );
+
+        // Interfaces coming and going, e.g. a VPN, are picked up on the next update.
+        provider.set_network_rates("tun0", 2048.0, 1024.0);
+        assert_eq!(
+            "tun0 2.0 KiB/s ↓ / 1.0 KiB/s ↑ | wlan0 1.5 KiB/s ↓ / 0.5 KiB/s ↑",
+            render(&section(true), &mut provider)
+        );
+        provider.network_rates.remove("wlan0");
+        assert_eq!(
+            "tun0 2.0 KiB/s ↓ / 1.0 KiB/s ↑",
+            render(&section(true), &mut provider)
+        );
+    }
+
+    #[test]
+    fn disk_filter() {
+        let mut provider = FakeProvider::default();
+        provider
+            .add_disk("/dev/nvme0n1p2", 4 * GIB, 3 * GIB, false)
+            .add_disk("/dev/sda1", 2 * GIB, GIB, false)
+            .add_disk("/dev/sdc1", 8 * GIB, 0, true);
+        let filter = |include_removables, name_contains: Option<&str>| DiskFilter {
+            include_removables,
+            name_contains: name_contains.map(Into::into),
+        };
+        let names = |filter: DiskFilter, provider: &mut FakeProvider| -> Vec<String> {
+            expand_disks(&filter, provider.disks())
+                .into_iter()
+                .map(|x| x.name)
+                .collect()
+        };
+
+        assert_eq!(
+            vec!["nvme0n1p2", "sda1", "sdc1"],
+            names(filter(true, None), &mut provider)
+        );
+        assert_eq!(
+            vec!["sda1", "sdc1"],
+            names(filter(true, Some("/dev/sd")), &mut provider)
+        );
+        assert_eq!(
+            vec!["sda1"],
+            names(filter(false, Some("/dev/sd")), &mut provider)
+        );
+    }
+
+    #[test]
+    fn extremes() {
+        let config = Config::default();
+        let section = Section {
+            decoration: Decoration {
+                before: None,
+                after: Some(" ({min}-{max})".into()),
+                ..Decoration::default()
+            },
+            track_extremes: true,
+            ..Module::CpuUsage.into()
+        };
+        let mut tracked = Tracked::default();
+        let render = |usage, tracked: &mut Tracked| {
+            let mut provider = FakeProvider::default();
+            provider.set_cpu_usage(usage);
+            text(render_tracked(&section, &config, &mut provider, tracked)).unwrap()
+        };
+
+        assert_eq!("20.0% (20.0%-20.0%)", render(20.0, &mut tracked));
+        assert_eq!("5.0% (5.0%-20.0%)", render(5.0, &mut tracked));
+        assert_eq!("12.0% (5.0%-20.0%)", render(12.0, &mut tracked));
+        tracked.extremes.reset();
+        assert_eq!("12.0% (12.0%-12.0%)", render(12.0, &mut tracked));
+
+        // untracked sections leave placeholders alone
+        let mut provider = FakeProvider::default();
+        provider.set_memory(GIB, 4 * GIB);
+        let untracked = Section {
+            decoration: Decoration {
+                before: None,
+                after: Some(" ({max})".into()),
+                ..Decoration::default()
+            },
+            ..Module::MemoryUsage { basis: None }.into()
+        };
+        assert!(matches!(
+            render_tracked(&untracked, &config, &mut provider, &mut tracked),
+            Piece::Text(x, ..) if x == "1.00/4.00 GiB ({max})"
+        ));
+        let tracking = Section {
+            track_extremes: true,
+            ..untracked
+        };
+        assert!(matches!(
+            render_tracked(&tracking, &config, &mut provider, &mut tracked),
+            Piece::Text(x, ..) if x == "1.00/4.00 GiB (25.0%)"
+        ));
+    }
+
+    #[test]
+    fn invalid_values() {
+        let config = Config {
+            invalid_value_marker: "*".into(),
+            ..Config::default()
+        };
+        let cpu = Section {
+            decoration: Decoration {
+                after: Some(" (max {max})".into()),
+                ..Decoration::default()
+            },
+            track_extremes: true,
+            ..Module::CpuUsage.into()
+        };
+        let mut tracked = Tracked::default();
+        let mut render = |section: &Section, usage| {
+            let mut provider = FakeProvider::default();
+            provider.set_cpu_usage(usage);
+            text(render_tracked(
+                section,
+                &config,
+                &mut provider,
+                &mut tracked,
+            ))
+            .unwrap()
+        };
+
+        // Spikes neither show up nor end up in the extremes, a pegged CPU does.
+        let sequence = [
+            (40.0, "40.0% (max 40.0%)"),
+            (400.0, "40.0%* (max 40.0%)"),
+            (65261.0, "40.0%* (max 40.0%)"),
+            (100.0, "100.0% (max 100.0%)"),
+            (20.0, "20.0% (max 100.0%)"),
+        ];
+        for (usage, expected) in sequence {
+            assert_eq!(expected, render(&cpu, usage));
+        }
+
+        let narrow = Section {
+            valid_range: Some((0.0, 50.0)),
+            ..cpu
+        };
+        assert_eq!("20.0%* (max 100.0%)", render(&narrow, 60.0));
+    }
+
+    #[test]
+    fn dwm_markup() {
+        let config = Config {
+            output: OutputBackend::Xsetroot {
+                status2d: true,
+                statuscmd: true,
+                post: None,
+                spawn: false,
+                reverse_order: false,
+                name_atoms: default_name_atoms(),
+                latin1_replacement: '?',
+                width_check: None,
+            },
+            ..Config::default()
+        };
+        let sections = [
+            Section {
+                decoration: Decoration {
+                    fg: Some("#ff0000".into()),
+                    ..Decoration::default()
+                },
+                ..Module::CpuUsage.into()
+            },
+            Module::Separator {
+                content: " · ".into(),
+                fg: Some("#888888".into()),
+            }
+            .into(),
+            Section {
+                decoration: Decoration {
+                    before: Some("^b#333333^".into()),
+                    after: Some(" procs".into()),
+                    fg: Some("#00ff00".into()),
+                    bg: Some("#000000".into()),
+                },
+                ..Module::Timestamp {
+                    template: "4^c#fff^\x022".into(),
+                }
+                .into()
+            },
+        ];
+
+        let mut provider = FakeProvider::default();
+        provider.set_cpu_usage(12.0);
+        let mut ctx = Context {
+            section: 0,
+            provider: &mut provider,
+            timers: &Timers::new(),
+            extremes: &mut Extremes::default(),
+            validity: &mut Validity::default(),
+            rates: &mut Rates::default(),
+            values: &mut HashMap::new(),
+            readings: &mut HashMap::new(),
+            urgency: &mut Urgency::default(),
+            now: Instant::now(),
+            local: SystemClock.now_local(),
+        };
+        let pieces: Vec<Piece> = sections
+            .iter()
+            .enumerate()
+            .map(|(index, section)| {
+                ctx.section = index;
+                render_section(section, &config, &mut ctx)
+            })
+            .collect();
+
+        // Module output can't forge markup, decorations can add their own.
+        assert_eq!(
+            "\x01^c#ff0000^12.0%^d^^c#888888^ · ^d^\x03^c#00ff00^^b#000000^^b#333333^4ˆc#fffˆ2 procs^d^",
+            bar::assemble(&pieces, &config.separator)
+        );
+    }
+
+    #[test]
+    fn min_width() {
+        let config = Config::default();
+        let section = |min_width, align| Section {
+            decoration: Decoration {
+                before: Some("cpu ".into()),
+                ..Decoration::default()
+            },
+            min_width,
+            align,
+            ..Module::CpuUsage.into()
+        };
+        let mut provider = FakeProvider::default();
+        let mut render = |section: &Section, usage| {
+            provider.set_cpu_usage(usage);
+            text(render_with(section, &config, &mut provider)).unwrap()
+        };
+
+        let right = section(Some(6), Alignment::Right);
+        assert_eq!("cpu   9.8%", render(&right, 9.8));
+        assert_eq!("cpu  10.2%", render(&right, 10.2));
+        assert_eq!("cpu 100.0%", render(&right, 100.0));
+        let left = section(Some(6), Alignment::Left);
+        assert_eq!("cpu 9.8%  ", render(&left, 9.8));
+        // Longer output isn't cut.
+        let narrow = section(Some(2), Alignment::Left);
+        assert_eq!("cpu 10.2%", render(&narrow, 10.2));
+        assert_eq!("cpu 9.8%", render(&section(None, Alignment::Right), 9.8));
+    }
+
+    #[test]
+    fn max_width() {
+        let config = Config::default();
+        let section = |max_width, min_width| Section {
+            decoration: Decoration {
+                before: Some("♪ ".into()),
+                after: Some(" ♪".into()),
+                ..Decoration::default()
+            },
+            min_width,
+            max_width,
+            ..Module::KeyboardLayout.into()
+        };
+        let mut provider = FakeProvider::default();
+        let mut render = |section: &Section, output: &str| {
+            provider.set_keyboard_layout(output);
+            text(render_with(section, &config, &mut provider)).unwrap()
+        };
+
+        let truncated = section(Some(8), None);
+        assert_eq!("♪ short ♪", render(&truncated, "short"));
+        assert_eq!("♪ Bohemia… ♪", render(&truncated, "Bohemian Rhapsody"));
+        assert_eq!("♪ 夜に駆… ♪", render(&truncated, "夜に駆ける"));
+        assert_eq!("♪ 🎸🎸🎸… ♪", render(&truncated, "🎸🎸🎸🎸🎸"));
+
+        // Fixed-width cells, padded where a wide character didn't fit.
+        let fixed = section(Some(6), Some(6));
+        for (output, expected) in [
+            ("ab", "♪ ab     ♪"),
+            ("abcdefgh", "♪ abcde… ♪"),
+            ("夜に駆ける", "♪ 夜に…  ♪"),
+            ("a🎸🎸🎸", "♪ a🎸🎸… ♪"),
+        ] {
+            let cell = render(&fixed, output);
+            assert_eq!(expected, cell);
+            assert_eq!(10, text::width(&cell), "{output}");
+        }
+    }
+
+    #[test]
+    fn compact_template() {
+        let config = Config::default();
+        let compact = |piece: Piece| match piece {
+            Piece::Text(_, _, _, compact) => compact,
+            _ => panic!("expected text"),
+        };
+        let mut provider = FakeProvider::default();
+        provider.set_memory(11 * GIB, 16 * GIB);
+
+        let memory = Section {
+            decoration: Decoration {
+                before: Some("mem ".into()),
+                ..Decoration::default()
+            },
+            compact_template: Some("{value:0}%".into()),
+            ..Module::MemoryUsage { basis: None }.into()
+        };
+        let piece = render_with(&memory, &config, &mut provider);
+        assert_eq!(Some("mem 69%".into()), compact(piece.clone()));
+        assert_eq!(Some("mem 11.00/16.00 GiB".into()), text(piece));
+        let default_format = Section {
+            compact_template: Some("{value}".into()),
+            ..Module::MemoryUsage { basis: None }.into()
+        };
+        assert_eq!(
+            Some("68.8%".into()),
+            compact(render_with(&default_format, &config, &mut provider))
+        );
+
+        let timestamp = Section {
+            compact_template: Some("%%".into()),
+            ..Module::Timestamp {
+                template: "%a %d %b %H:%M".into(),
+            }
+            .into()
+        };
+        assert_eq!(
+            Some("%".into()),
+            compact(render_with(&timestamp, &config, &mut provider))
+        );
+
+        // Without a value there's nothing to fill in, and without a template nothing to switch to.
+        provider.set_keyboard_layout("us");
+        let layout = Section {
+            compact_template: Some("{value}".into()),
+            ..Module::KeyboardLayout.into()
+        };
+        assert_eq!(None, compact(render_with(&layout, &config, &mut provider)));
+        let plain = Module::MemoryUsage { basis: None }.into();
+        assert_eq!(None, compact(render_with(&plain, &config, &mut provider)));
+    }
+
+    #[test]
+    fn thresholds() {
+        let config = Config {
+            output: OutputBackend::Xsetroot {
+                status2d: true,
+                statuscmd: false,
+                post: None,
+                spawn: false,
+                reverse_order: false,
+                name_atoms: default_name_atoms(),
+                latin1_replacement: '?',
+                width_check: None,
+            },
+            ..Config::default()
+        };
+        let threshold = |value, direction, before: &str, fg: &str| Threshold {
+            value,
+            direction,
+            decoration: Decoration {
+                before: Some(before.into()),
+                fg: Some(fg.into()),
+                ..Decoration::default()
+            },
+            urgent: false,
+            clear_at: None,
+        };
+        let section = |module: Module, thresholds| Section {
+            decoration: Decoration {
+                before: Some("- ".into()),
+                ..Decoration::default()
+            },
+            thresholds,
+            ..module.into()
+        };
+        let cpu = section(
+            Module::CpuUsage,
+            vec![
+                threshold(75.0, ThresholdDirection::Above, "! ", "#ffff00"),
+                threshold(90.0, ThresholdDirection::Above, "!! ", "#ff0000"),
+            ],
+        );
+        let battery = section(
+            Module::Battery {
+                name: None,
+                hide_when_missing: false,
+                limit_marker: None,
+                relative_to_limit: false,
+            },
+            vec![threshold(
+                15.0,
+                ThresholdDirection::Below,
+                "low ",
+                "#ff0000",
+            )],
+        );
+        let timestamp = section(
+            Module::Timestamp {
+                template: "12".into(),
+            },
+            vec![threshold(0.0, ThresholdDirection::Above, "! ", "#ff0000")],
+        );
+        let render = |section: &Section, provider: &mut FakeProvider| {
+            text(render_with(section, &config, provider)).unwrap()
+        };
+
+        let mut provider = FakeProvider::default();
+        let cases = [
+            (50.0, "- 50.0%"),
+            (75.0, "^c#ffff00^! 75.0%^d^"),
+            (95.0, "^c#ff0000^!! 95.0%^d^"),
+        ];
+        for (usage, expected) in cases {
+            provider.set_cpu_usage(usage);
+            assert_eq!(expected, render(&cpu, &mut provider));
+        }
+
+        provider.add_battery("BAT0", 10, ChargeState::Discharging);
+        assert_eq!("^c#ff0000^low 10% ↓^d^", render(&battery, &mut provider));
+        // Without a value there's nothing to compare.
+        assert_eq!("- N/A", render(&battery, &mut FakeProvider::default()));
+        assert_eq!("- 12", render(&timestamp, &mut provider));
+    }
+
+    #[test]
+    fn urgent() {
+        let section = Section {
+            id: Some("cpu".into()),
+            thresholds: vec![Threshold {
+                value: 90.0,
+                direction: ThresholdDirection::Above,
+                decoration: Decoration::default(),
+                urgent: true,
+                clear_at: Some(80.0),
+            }],
+            ..Module::CpuUsage.into()
+        };
+        let config = |output| Config {
+            output,
+            ..Config::default()
+        };
+        let stdout = config(OutputBackend::Stdout {
+            reverse_order: false,
+        });
+        let i3bar = config(OutputBackend::I3bar {
+            reverse_order: false,
+        });
+        let xsetroot = config(OutputBackend::Xsetroot {
+            status2d: true,
+            statuscmd: false,
+            post: None,
+            spawn: false,
+            reverse_order: false,
+            name_atoms: default_name_atoms(),
+            latin1_replacement: '?',
+            width_check: None,
+        });
+
+        let mut provider = FakeProvider::default();
+        let mut tracked = Tracked::default();
+        let mut render = |config: &Config, usage| {
+            provider.set_cpu_usage(usage);
+            match render_tracked(&section, config, &mut provider, &mut tracked) {
+                Piece::Text(text, _, urgent, _) => (text, urgent),
+                _ => panic!("expected text"),
+            }
+        };
+
+        assert_eq!(("50.0%".into(), false), render(&stdout, 50.0));
+        assert_eq!(("!95.0%".into(), true), render(&stdout, 95.0));
+        assert_eq!(("!85.0%".into(), true), render(&xsetroot, 85.0));
+        // I3bar marks the block instead.
+        assert_eq!(("85.0%".into(), true), render(&i3bar, 85.0));
+        assert_eq!(("80.0%".into(), false), render(&i3bar, 80.0));
+        assert_eq!(("85.0%".into(), false), render(&stdout, 85.0));
+
+        // Until turned off again, whatever the value.
+        tracked.urgency.force("cpu", true);
+        let mut provider = FakeProvider::default();
+        let piece = render_tracked(&section, &stdout, &mut provider, &mut tracked);
+        assert_eq!(Some("!0.0%".into()), text(piece));
+        tracked.urgency.force("cpu", false);
+        let piece = render_tracked(&section, &stdout, &mut provider, &mut tracked);
+        assert_eq!(Some("0.0%".into()), text(piece));
+    }
+
+    #[test]
+    fn timestamp_and_timer() {
+        let mut provider = FakeProvider::default();
+        let template = Module::Timestamp {
+            template: "%%".into(),
+        };
+        assert_eq!("%", render(template, &mut provider, false));
+
+        let now = Instant::now();
+        let mut timers = Timers::new();
+        let mut countdown = Timer::default();
+        countdown.start(
+            now - Duration::from_secs(120),
+            Some(Duration::from_secs(60)),
+        );
+        timers.insert("expired".into(), countdown);
+        let mut stopwatch = Timer::default();
+        stopwatch.start(now - Duration::from_secs(75), None);
+        timers.insert("running".into(), stopwatch);
+
+        let config = Config::default();
+        let mut ctx = Context {
+            section: 0,
+            provider: &mut provider,
+            timers: &timers,
+            extremes: &mut Extremes::default(),
+            validity: &mut Validity::default(),
+            rates: &mut Rates::default(),
+            values: &mut HashMap::new(),
+            readings: &mut HashMap::new(),
+            urgency: &mut Urgency::default(),
+            now,
+            local: SystemClock.now_local(),
+        };
+        let timer = |name: &str, expired_text: Option<&str>| Module::Timer {
+            name: name.into(),
+            expired_text: expired_text.map(Into::into),
+        };
+
+        let cases = [
+            (timer("missing", None), "0s"),
+            (timer("running", None), "1m 15s"),
+            (timer("expired", None), "0s"),
+            (timer("expired", Some("done")), "done"),
+        ];
+        for (module, expected) in cases {
+            assert_eq!(expected, render_module(&module.into(), &config, &mut ctx));
+        }
+    }
+
+    #[test]
+    fn clock_boundaries() {
+        let mut provider = FakeProvider::default();
+        let clock = FakeClock::new("2024-03-30T23:59:59.500+01:00");
+        let config = Config::default();
+        let section = Section::from(Module::Timestamp {
+            template: "%F %T %:z".into(),
+        });
+        let mut timers = Timers::new();
+        let mut timer = Timer::default();
+        timer.start(clock.monotonic(), Some(Duration::from_secs(2)));
+        timers.insert("tea".into(), timer);
+        let countdown = Section::from(Module::Timer {
+            name: "tea".into(),
+            expired_text: Some("done".into()),
+        });
+
+        let mut render = |clock: &FakeClock| {
+            let mut ctx = Context {
+                section: 0,
+                provider: &mut provider,
+                timers: &timers,
+                extremes: &mut Extremes::default(),
+                validity: &mut Validity::default(),
+                rates: &mut Rates::default(),
+                values: &mut HashMap::new(),
+                readings: &mut HashMap::new(),
+                urgency: &mut Urgency::default(),
+                now: clock.monotonic(),
+                local: clock.now_local(),
+            };
+            [
+                render_module(&section, &config, &mut ctx),
+                render_module(&countdown, &config, &mut ctx),
+            ]
+        };
+
+        assert_eq!(["2024-03-30 23:59:59 +01:00", "2s"], render(&clock));
+        // Midnight.
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(["2024-03-31 00:00:00 +01:00", "1s"], render(&clock));
+        // Timers follow the monotonic clock, so DST switches don't affect them.
+        clock.advance(Duration::from_millis(1499));
+        clock.set_offset(2 * 3600);
+        assert_eq!(["2024-03-31 01:00:01 +02:00", "0s"], render(&clock));
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(["2024-03-31 01:00:01 +02:00", "done"], render(&clock));
+    }
+
+    #[test]
+    fn decoration() {
+        let mut provider = FakeProvider::default();
+        provider.set_process_count(7);
+        let config = Config::default();
+
+        let cases = [
+            (None, None, "7"),
+            (Some("procs "), None, "procs 7"),
+            (None, Some(" procs"), "7 procs"),
+            (Some("["), Some("]"), "[7]"),
+            (Some(""), Some(""), "7"),
+        ];
+        for (before, after, expected) in cases {
+            let section = Section {
+                decoration: Decoration {
+                    before: before.map(Into::into),
+                    after: after.map(Into::into),
+                    ..Decoration::default()
+                },
+                ..Module::ProcessCount.into()
+            };
+
+            match render_with(&section, &config, &mut provider) {
+                Piece::Text(x, ..) => assert_eq!(expected, x),
+                _ => panic!("expected text"),
+            }
+        }
+
+        let separator = Module::Separator {
+            content: " · ".into(),
+            fg: None,
+        }
+        .into();
+        assert!(matches!(
+            render_with(&separator, &config, &mut provider),
+            Piece::Separator(x) if x == " · "
+        ));
+    }
+
+    #[test]
+    fn escaping() {
+        let mut provider = FakeProvider::default();
+        provider.set_keyboard_layout("us | de");
+        let section = Module::KeyboardLayout.into();
+        let mut config = Config::default();
+
+        // Opt-in, module output is left alone by default.
+        assert_eq!(
+            Some("us | de".into()),
+            text(render_with(&section, &config, &mut provider))
+        );
+        config.escape_separator = Some("¦".into());
+        assert_eq!(
+            Some("us¦de".into()),
+            text(render_with(&section, &config, &mut provider))
+        );
+    }
+
+    #[test]
+    fn sanitising() {
+        let mut provider = FakeProvider::default();
+        provider.set_keyboard_layout("\x1b[31mus | de\x1b[0m\nx");
+        let mut section: Section = Module::KeyboardLayout.into();
+        let mut config = Config {
+            escape_separator: Some("¦".into()),
+            ..Config::default()
+        };
+        let mut render = |section: &Section, config: &Config| {
+            text(render_with(section, config, &mut provider)).unwrap()
+        };
+
+        assert_eq!("[31mus¦de[0m x", render(&section, &config));
+
+        // Kept when asked for, but still escaped.
+        section.sanitize = Some(SanitizeOverride {
+            allow_control: true,
+            max_length: Some(8),
+            preserve_newlines: true,
+        });
+        assert_eq!("\x1b[31mus…", render(&section, &config));
+        section.sanitize.as_mut().unwrap().max_length = None;
+        assert_eq!("\x1b[31mus¦de\x1b[0m\nx", render(&section, &config));
+
+        // statuscmd reads control characters as markers, so they go either way, leaving only
+        // its own.
+        config.output = OutputBackend::Xsetroot {
+            status2d: false,
+            statuscmd: true,
+            post: None,
+            spawn: false,
+            reverse_order: false,
+            name_atoms: default_name_atoms(),
+            latin1_replacement: '?',
+            width_check: None,
+        };
+        assert_eq!("\x01[31mus¦de[0mx", render(&section, &config));
+        section.sanitize = None;
+        assert_eq!("\x01[31mus¦de[0m x", render(&section, &config));
+    }
+}
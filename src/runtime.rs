@@ -0,0 +1,128 @@
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{DateTime, Local};
+
+use crate::{
+    config::ConfigFileInfo, extremes::Extremes, rates::Rates, snapshot::SectionSnapshot,
+    timer::Timers, unit::Reading, urgency::Urgency, validity::Validity,
+};
+
+/// State that outlives config reloads, shared between the render loop and the control socket.
+///
+/// Whoever needs both locks takes the config's first: the render loop holds it for a whole
+/// tick and locks this one within, e.g. for `Timer` sections.
+#[derive(Default)]
+pub struct Runtime {
+    pub timers: Timers,
+    pub reloads: ReloadStats,
+    /// Set by the control socket's freeze command. Nothing is evaluated while frozen.
+    pub frozen: bool,
+    pub extremes: Extremes,
+    pub validity: Validity,
+    pub rates: Rates,
+    /// Latest values of sections with an id, see [`Context`](crate::render::Context).
+    pub values: HashMap<String, f64>,
+    /// Latest values of all sections by index, with their units.
+    pub readings: HashMap<usize, Reading>,
+    /// Every section as of the last tick, in config order.
+    pub snapshots: Vec<SectionSnapshot>,
+    pub urgency: Urgency,
+    /// Sections to update on the next tick even if their interval hasn't passed, e.g. because
+    /// a file they watch changed.
+    pub refresh: BTreeSet<usize>,
+}
+
+#[derive(Default)]
+pub struct ReloadStats {
+    pub successful: u64,
+    pub failed: u64,
+    pub last_success: Option<DateTime<Local>>,
+    pub file: Option<ConfigFileInfo>,
+    /// What the last successful reload changed, see [`diff`](crate::diff::diff).
+    pub last_changes: Vec<String>,
+}
+
+impl ReloadStats {
+    /// Records a successfully loaded config. `reload` is false for the initial load.
+    pub fn loaded(&mut self, file: ConfigFileInfo, reload: bool) {
+        if reload {
+            self.successful += 1;
+            self.last_success = Some(Local::now());
+        }
+        self.file = Some(file);
+    }
+
+    pub fn summary(&self) -> String {
+        let (modified, sha256) = match &self.file {
+            Some(file) => (
+                file.modified
+                    .map(|x| DateTime::<Local>::from(x).to_rfc3339())
+                    .unwrap_or("unknown".into()),
+                file.sha256.as_str(),
+            ),
+            None => ("unknown".into(), "unknown"),
+        };
+
+        format!(
+            "config_sha256={sha256} config_mtime={modified} reloads_ok={} reloads_failed={} last_reload={} last_changes={}",
+            self.successful,
+            self.failed,
+            self.last_success
+                .map(|x| x.to_rfc3339())
+                .unwrap_or("never".into()),
+            self.changes_summary(),
+        )
+    }
+
+    /// The last reload's changes separated by "; ", or "none".
+    pub fn changes_summary(&self) -> String {
+        if self.last_changes.is_empty() {
+            "none".into()
+        } else {
+            self.last_changes.join("; ")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_counters() {
+        let mut stats = ReloadStats::default();
+        assert!(stats
+            .summary()
+            .contains("reloads_ok=0 reloads_failed=0 last_reload=never"));
+
+        stats.loaded(
+            ConfigFileInfo {
+                modified: None,
+                sha256: "abc".into(),
+            },
+            false,
+        );
+        assert!(stats
+            .summary()
+            .starts_with("config_sha256=abc config_mtime=unknown"));
+        assert!(stats.last_success.is_none());
+
+        stats.failed += 1;
+        stats.loaded(
+            ConfigFileInfo {
+                modified: None,
+                sha256: "def".into(),
+            },
+            true,
+        );
+        assert!(stats.summary().contains("config_sha256=def"));
+        assert!(stats.summary().contains("reloads_ok=1 reloads_failed=1"));
+        assert!(stats.summary().ends_with(" last_changes=none"));
+
+        stats.last_changes = vec!["a 1→2".into(), "section 1 added (Timer)".into()];
+        assert!(stats
+            .summary()
+            .ends_with(" last_changes=a 1→2; section 1 added (Timer)"));
+        assert!(stats.last_success.is_some());
+    }
+}
@@ -0,0 +1,91 @@
+//! The first thing done to module output, before it's escaped for the output, so a command
+//! printing terminal escapes or a whole file can't garble the bar.
+
+use crate::{config::SanitizeOverride, text};
+
+/// Longer module output is cut, ending in "…", unless a section allows more.
+pub const DEFAULT_MAX_LENGTH: usize = 512;
+
+/// Removes control characters, turns tabs and each run of line breaks into a space and cuts
+/// `text` to [`DEFAULT_MAX_LENGTH`] characters, each unless `rules` say otherwise.
+pub fn sanitize(text: &str, rules: Option<&SanitizeOverride>) -> String {
+    let allow_control = rules.is_some_and(|x| x.allow_control);
+    let preserve_newlines = rules.is_some_and(|x| x.preserve_newlines);
+    let max_length = rules
+        .and_then(|x| x.max_length)
+        .unwrap_or(DEFAULT_MAX_LENGTH);
+
+    let mut output = String::with_capacity(text.len());
+    let mut in_break = false;
+    for c in text.chars() {
+        if matches!(c, '\n' | '\r') && !preserve_newlines {
+            if !in_break {
+                output.push(' ');
+            }
+            in_break = true;
+            continue;
+        }
+        let c = match c {
+            _ if allow_control || !c.is_ascii_control() || matches!(c, '\n' | '\r') => c,
+            '\t' => ' ',
+            _ => continue,
+        };
+        output.push(c);
+        in_break = false;
+    }
+
+    text::truncate(&output, max_length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        assert_eq!("23°C", sanitize("23°C", None));
+        assert_eq!("[31mred[0m", sanitize("\x1b[31mred\x1b[0m", None));
+        assert_eq!("a b", sanitize("a\tb", None));
+        assert_eq!("one two three", sanitize("one\ntwo\r\n\r\nthree", None));
+        assert_eq!("bell ", sanitize("bell\x07\n", None));
+        assert_eq!("del", sanitize("de\x7fl", None));
+
+        let long = "x".repeat(DEFAULT_MAX_LENGTH + 1);
+        let cut = sanitize(&long, None);
+        assert_eq!(DEFAULT_MAX_LENGTH, cut.chars().count());
+        assert!(cut.ends_with('…'));
+    }
+
+    #[test]
+    fn overrides() {
+        let rules = |allow_control, max_length, preserve_newlines| SanitizeOverride {
+            allow_control,
+            max_length,
+            preserve_newlines,
+        };
+
+        let control = rules(true, None, false);
+        assert_eq!(
+            "\x1b[31mred\x1b[0m",
+            sanitize("\x1b[31mred\x1b[0m", Some(&control))
+        );
+        // Line breaks are still collapsed unless asked to keep them as well.
+        assert_eq!("a b", sanitize("a\n\nb", Some(&control)));
+
+        let newlines = rules(false, None, true);
+        assert_eq!(
+            "[1mone\ntwo\r\n",
+            sanitize("\x1b[1mone\ntwo\r\n", Some(&newlines))
+        );
+
+        assert_eq!(
+            "abc…",
+            sanitize("abcdefgh", Some(&rules(false, Some(4), false)))
+        );
+        let long = "x".repeat(DEFAULT_MAX_LENGTH * 2);
+        assert_eq!(
+            long,
+            sanitize(&long, Some(&rules(false, Some(usize::MAX), false)))
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::{fmt, time::Duration};
+
+use chrono::{NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+/// A daily time range like "22:00-07:00". Ranges ending before they start wrap past midnight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+/// A comma separated list of [`TimeWindow`]s, e.g. "22:00-07:00, 12:30-13:00".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeWindows(pub Vec<TimeWindow>);
+
+impl TimeWindows {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        self.0.iter().any(|x| x.contains(time))
+    }
+
+    /// Time from `time` until the next window starts or ends.
+    pub fn until_next_boundary(&self, time: NaiveTime) -> Option<Duration> {
+        self.0
+            .iter()
+            .flat_map(|x| [x.start, x.end])
+            .map(|boundary| {
+                let mut delta = boundary.signed_duration_since(time);
+                if delta <= chrono::Duration::zero() {
+                    delta += chrono::Duration::days(1);
+                }
+                delta.to_std().unwrap_or_default()
+            })
+            .min()
+    }
+}
+
+impl TryFrom<String> for TimeWindows {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let parse_time = |x: &str| {
+            NaiveTime::parse_from_str(x.trim(), "%H:%M")
+                .map_err(|_| format!("invalid time \"{}\", expected HH:MM", x.trim()))
+        };
+
+        value
+            .split(',')
+            .map(|window| {
+                let (start, end) = window
+                    .split_once('-')
+                    .ok_or_else(|| format!("invalid time window \"{}\"", window.trim()))?;
+                Ok(TimeWindow {
+                    start: parse_time(start)?,
+                    end: parse_time(end)?,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl From<TimeWindows> for String {
+    fn from(value: TimeWindows) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for TimeWindows {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let windows: Vec<String> = self
+            .0
+            .iter()
+            .map(|x| {
+                format!(
+                    "{:02}:{:02}-{:02}:{:02}",
+                    x.start.hour(),
+                    x.start.minute(),
+                    x.end.hour(),
+                    x.end.minute()
+                )
+            })
+            .collect();
+        write!(f, "{}", windows.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn windows(x: &str) -> TimeWindows {
+        TimeWindows::try_from(x.to_string()).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            TimeWindows(vec![
+                TimeWindow {
+                    start: time(22, 0),
+                    end: time(7, 0)
+                },
+                TimeWindow {
+                    start: time(12, 30),
+                    end: time(13, 0)
+                }
+            ]),
+            windows("22:00-07:00, 12:30 - 13:00")
+        );
+        assert_eq!(
+            "22:00-07:00, 12:30-13:00",
+            windows("22:00-07:00,12:30-13:00").to_string()
+        );
+
+        assert!(TimeWindows::try_from("22:00".to_string()).is_err());
+        assert!(TimeWindows::try_from("25:00-07:00".to_string()).is_err());
+        assert!(TimeWindows::try_from("".to_string()).is_err());
+    }
+
+    #[test]
+    fn contains() {
+        let overnight = windows("22:00-07:00");
+        assert!(overnight.contains(time(22, 0)));
+        assert!(overnight.contains(time(3, 0)));
+        assert!(!overnight.contains(time(7, 0)));
+        assert!(!overnight.contains(time(12, 0)));
+
+        let lunch = windows("12:30-13:00");
+        assert!(lunch.contains(time(12, 45)));
+        assert!(!lunch.contains(time(13, 0)));
+        assert!(!lunch.contains(time(0, 0)));
+    }
+
+    #[test]
+    fn next_boundary() {
+        let quiet = windows("22:00-07:00, 12:30-13:00");
+        assert_eq!(
+            Some(Duration::from_secs(30 * 60)),
+            quiet.until_next_boundary(time(12, 0))
+        );
+        assert_eq!(
+            Some(Duration::from_secs(9 * 3600)),
+            quiet.until_next_boundary(time(22, 0))
+        );
+        assert_eq!(
+            Some(Duration::from_secs(3600)),
+            quiet.until_next_boundary(time(6, 0))
+        );
+        assert_eq!(None, TimeWindows(vec![]).until_next_boundary(time(6, 0)));
+    }
+}
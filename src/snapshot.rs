@@ -0,0 +1,320 @@
+//! The one JSON shape sections are handed out in, e.g. by the control socket's `sections`, so
+//! every consumer reads the same fields. Changing them means bumping [`SCHEMA_VERSION`];
+//! `stringbar schema` prints the JSON Schema to validate them against.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::{
+    bar::Piece,
+    config::Config,
+    text::json_string,
+    unit::{Reading, Unit, Value},
+    version,
+};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A section as last rendered.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SectionSnapshot {
+    /// The section's id, or its module's name without one.
+    pub name: String,
+    /// Counting from 0 in config order.
+    pub index: usize,
+    /// Empty while the section is hidden.
+    pub text: String,
+    /// The text with the section's `compact_template`, if it has one.
+    pub short_text: Option<String>,
+    pub value: Option<Value>,
+    /// When the section was last evaluated, `None` before the first time.
+    pub updated_at: Option<DateTime<FixedOffset>>,
+    /// Not evaluated for more than twice its interval, e.g. held back by `max_tick_budget_ms`.
+    pub stale: bool,
+    pub urgent: bool,
+}
+
+impl SectionSnapshot {
+    /// The snapshot as a single line JSON object, with `schema_version` first.
+    pub fn json(&self) -> String {
+        let optional = |x: Option<String>| x.unwrap_or("null".into());
+        let values = [
+            SCHEMA_VERSION.to_string(),
+            json_string(&self.name),
+            self.index.to_string(),
+            json_string(&self.text),
+            optional(self.short_text.as_deref().map(json_string)),
+            // JSON has no infinities.
+            optional(
+                self.value
+                    .map(|x| x.amount)
+                    .filter(|x| x.is_finite())
+                    .map(|x| x.to_string()),
+            ),
+            optional(self.value.map(|x| json_string(&format!("{:?}", x.unit)))),
+            optional(self.updated_at.map(|x| json_string(&x.to_rfc3339()))),
+            self.stale.to_string(),
+            self.urgent.to_string(),
+        ];
+
+        let fields: Vec<_> = fields()
+            .iter()
+            .zip(values)
+            .map(|((key, _), value)| format!("\"{key}\":{value}"))
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+/// The fields in the order [`SectionSnapshot::json`] writes them, with their JSON Schema.
+fn fields() -> [(&'static str, String); 10] {
+    let units: Vec<_> = version::variants::<Unit>()
+        .iter()
+        .map(|x| json_string(x))
+        .collect();
+
+    [
+        ("schema_version", format!("{{\"const\": {SCHEMA_VERSION}}}")),
+        (
+            "name",
+            r#"{"type": "string", "description": "The section's id, or its module's name without one"}"#.into(),
+        ),
+        (
+            "index",
+            r#"{"type": "integer", "minimum": 0, "description": "Counting from 0 in config order"}"#.into(),
+        ),
+        (
+            "text",
+            r#"{"type": "string", "description": "What the section shows, empty while it's hidden"}"#.into(),
+        ),
+        (
+            "short_text",
+            r#"{"type": ["string", "null"], "description": "The text with the section's compact_template"}"#.into(),
+        ),
+        (
+            "value",
+            r#"{"type": ["number", "null"], "description": "The value thresholds and expressions see"}"#.into(),
+        ),
+        (
+            "unit",
+            format!(
+                "{{\"enum\": [{}, null], \"description\": \"What the value measures\"}}",
+                units.join(", ")
+            ),
+        ),
+        (
+            "updated_at",
+            r#"{"type": ["string", "null"], "format": "date-time", "description": "When the section was last evaluated"}"#.into(),
+        ),
+        (
+            "stale",
+            r#"{"type": "boolean", "description": "Not evaluated for more than twice its interval"}"#.into(),
+        ),
+        ("urgent", r#"{"type": "boolean"}"#.into()),
+    ]
+}
+
+/// The JSON Schema of [`SectionSnapshot::json`].
+pub fn schema() -> String {
+    let fields = fields();
+    let properties: Vec<_> = fields
+        .iter()
+        .map(|(key, schema)| format!("    \"{key}\": {schema}"))
+        .collect();
+    let required: Vec<_> = fields.iter().map(|(key, _)| format!("\"{key}\"")).collect();
+
+    format!(
+        "{{\n  \"$schema\": \"https://json-schema.org/draft/2020-12/schema\",\n  \
+         \"title\": \"SectionSnapshot\",\n  \
+         \"type\": \"object\",\n  \
+         \"properties\": {{\n{}\n  }},\n  \
+         \"required\": [{}],\n  \
+         \"additionalProperties\": false\n}}",
+        properties.join(",\n"),
+        required.join(", ")
+    )
+}
+
+/// Snapshots of the sections of `config` as rendered into `pieces`. `evaluated` holds when
+/// each section was last evaluated by index, on the monotonic clock and the local one.
+pub fn take(
+    config: &Config,
+    pieces: &[Piece],
+    readings: &HashMap<usize, Reading>,
+    evaluated: &HashMap<usize, (Instant, DateTime<FixedOffset>)>,
+    now: Instant,
+) -> Vec<SectionSnapshot> {
+    let intervals = config.section_intervals();
+    config
+        .sections
+        .iter()
+        .zip(pieces)
+        .enumerate()
+        .map(|(index, (section, piece))| {
+            let (text, short_text, urgent) = match piece {
+                Piece::Text(text, _, urgent, compact) => (text.clone(), compact.clone(), *urgent),
+                Piece::Separator(text) => (text.clone(), None, false),
+                Piece::Hidden => (String::new(), None, false),
+            };
+            let interval = intervals[index].unwrap_or(config.update_interval_ms.0);
+            let evaluated = evaluated.get(&index);
+
+            SectionSnapshot {
+                name: section.id.clone().unwrap_or(section.module.name().into()),
+                index,
+                text,
+                short_text,
+                value: readings.get(&index).map(|x| x.value),
+                updated_at: evaluated.map(|x| x.1),
+                stale: evaluated.is_some_and(|(at, _)| {
+                    now.saturating_duration_since(*at) > interval.saturating_mul(2)
+                }),
+                urgent,
+            }
+        })
+        .collect()
+}
+
+/// How long ago `snapshot` was taken at `now`.
+pub fn age(snapshot: &SectionSnapshot, now: DateTime<FixedOffset>) -> Option<Duration> {
+    snapshot
+        .updated_at
+        .map(|x| (now - x).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{Module, Section},
+        duration::ConfigDuration,
+    };
+
+    #[test]
+    fn json() {
+        let snapshot = SectionSnapshot {
+            name: "mem".into(),
+            index: 1,
+            text: "mem \"9.8\" GiB".into(),
+            short_text: Some("62%".into()),
+            value: Some(Value::new(61.25, Unit::Percent)),
+            updated_at: DateTime::parse_from_rfc3339("2024-03-31T08:00:00+02:00").ok(),
+            stale: false,
+            urgent: true,
+        };
+        assert_eq!(
+            "{\"schema_version\":1,\"name\":\"mem\",\"index\":1,\"text\":\"mem \\\"9.8\\\" GiB\",\
+             \"short_text\":\"62%\",\"value\":61.25,\"unit\":\"Percent\",\
+             \"updated_at\":\"2024-03-31T08:00:00+02:00\",\"stale\":false,\"urgent\":true}",
+            snapshot.json()
+        );
+
+        let snapshot = SectionSnapshot {
+            name: "Timestamp".into(),
+            index: 0,
+            text: String::new(),
+            short_text: None,
+            value: None,
+            updated_at: None,
+            stale: true,
+            urgent: false,
+        };
+        assert_eq!(
+            "{\"schema_version\":1,\"name\":\"Timestamp\",\"index\":0,\"text\":\"\",\
+             \"short_text\":null,\"value\":null,\"unit\":null,\"updated_at\":null,\
+             \"stale\":true,\"urgent\":false}",
+            snapshot.json()
+        );
+    }
+
+    #[test]
+    fn schema_fixture() {
+        assert_eq!(
+            include_str!("../tests/fixtures/section-snapshot.schema.json").trim_end(),
+            schema(),
+            "The snapshot's shape changed. Bump SCHEMA_VERSION if that was intended, then \
+             update the fixture with `stringbar schema`."
+        );
+    }
+
+    #[test]
+    fn taking() {
+        let config = Config {
+            sections: vec![
+                Section {
+                    id: Some("cpu".into()),
+                    ..Module::CpuUsage.into()
+                },
+                Section {
+                    interval_ms: Some(ConfigDuration(Duration::from_secs(60))),
+                    ..Module::LoadAverage.into()
+                },
+                Module::LoadAverage.into(),
+            ],
+            ..Config::default()
+        };
+        let pieces = [
+            Piece::Text("cpu 12%".into(), None, true, Some("12%".into())),
+            Piece::Text("up 3h".into(), None, false, None),
+            Piece::Hidden,
+        ];
+        let readings = HashMap::from([(
+            0,
+            Reading {
+                id: Some("cpu".into()),
+                module: "CpuUsage",
+                value: Value::new(12.0, Unit::Percent),
+            },
+        )]);
+        let start = Instant::now();
+        let local = DateTime::parse_from_rfc3339("2024-03-31T08:00:00+02:00").unwrap();
+        let evaluated = HashMap::from([(0, (start, local)), (1, (start, local))]);
+        let now = start + config.update_interval_ms.0 * 3;
+
+        let snapshots = take(&config, &pieces, &readings, &evaluated, now);
+        assert_eq!(
+            vec![
+                SectionSnapshot {
+                    name: "cpu".into(),
+                    index: 0,
+                    text: "cpu 12%".into(),
+                    short_text: Some("12%".into()),
+                    value: Some(Value::new(12.0, Unit::Percent)),
+                    updated_at: Some(local),
+                    stale: true,
+                    urgent: true,
+                },
+                // Not due yet with an interval of its own.
+                SectionSnapshot {
+                    name: "LoadAverage".into(),
+                    index: 1,
+                    text: "up 3h".into(),
+                    short_text: None,
+                    value: None,
+                    updated_at: Some(local),
+                    stale: false,
+                    urgent: false,
+                },
+                SectionSnapshot {
+                    name: "LoadAverage".into(),
+                    index: 2,
+                    text: String::new(),
+                    short_text: None,
+                    value: None,
+                    updated_at: None,
+                    stale: false,
+                    urgent: false,
+                },
+            ],
+            snapshots
+        );
+        assert_eq!(
+            Some(Duration::from_secs(90)),
+            age(&snapshots[0], local + chrono::Duration::seconds(90))
+        );
+    }
+}
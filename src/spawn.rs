@@ -0,0 +1,235 @@
+use std::{
+    io::{self, Read},
+    os::unix::process::CommandExt,
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How long the pipes may take to close once what's left of the process group was killed.
+const KILL_GRACE: Duration = Duration::from_millis(100);
+
+/// Runs `command` to completion and collects its output. This is the only way stringbar starts
+/// child processes.
+///
+/// The child gets its own process group, so on timeout the whole tree is killed, including
+/// anything started by a shell wrapper. stdin is `/dev/null`. The child is always reaped before
+/// returning. File descriptors other than stdio aren't inherited, since std opens everything
+/// (including our sockets) with `O_CLOEXEC`.
+///
+/// Processes the child leaves running in the background, e.g. `sh -c 'daemon &'`, keep its
+/// output open. They're killed along with the group if they still have it open at the timeout.
+pub fn run(command: &mut Command, timeout: Duration) -> io::Result<Output> {
+    let mut child = command
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Drain both pipes while waiting, a child filling one of them would block forever otherwise.
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let timed_out = || {
+        io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("timed out after {}ms", timeout.as_millis()),
+        )
+    };
+    let status = match wait_until(&mut child, deadline)? {
+        Some(status) => status,
+        None => {
+            kill_group(&mut child)?;
+            return Err(timed_out());
+        }
+    };
+
+    let group = child.id() as libc::pid_t;
+    let collect = |pipe: Receiver<Vec<u8>>| {
+        match pipe.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(x) => Ok(x),
+            Err(RecvTimeoutError::Disconnected) => Ok(Vec::new()),
+            Err(RecvTimeoutError::Timeout) => {
+                signal_group(group)?;
+                match pipe.recv_timeout(KILL_GRACE) {
+                    Ok(x) => Ok(x),
+                    Err(RecvTimeoutError::Disconnected) => Ok(Vec::new()),
+                    // Something left the group, e.g. with setsid, and still has the pipe open.
+                    Err(RecvTimeoutError::Timeout) => Err(timed_out()),
+                }
+            }
+        }
+    };
+
+    Ok(Output {
+        status,
+        stdout: collect(stdout)?,
+        stderr: collect(stderr)?,
+    })
+}
+
+/// Reads `pipe` to the end on another thread, the contents arrive once it's closed.
+fn drain(pipe: Option<impl Read + Send + 'static>) -> Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        let _ = tx.send(buffer);
+    });
+    rx
+}
+
+fn wait_until(child: &mut Child, deadline: Instant) -> io::Result<Option<ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Kills the child's process group and reaps the child.
+fn kill_group(child: &mut Child) -> io::Result<()> {
+    signal_group(child.id() as libc::pid_t)?;
+    child.wait().map(|_| ())
+}
+
+/// Kills every process in `group`, which is the id of the child that leads it.
+fn signal_group(group: libc::pid_t) -> io::Result<()> {
+    // SAFETY: kill has no memory safety requirements. The group id can't have been reused:
+    // the child's pid isn't until it's reaped, and a reaped child's isn't while its group
+    // has members left.
+    if unsafe { libc::kill(-group, libc::SIGKILL) } == -1 {
+        let e = io::Error::last_os_error();
+        // The group is already gone if every member has exited.
+        if e.raw_os_error() != Some(libc::ESRCH) {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::{fd::AsRawFd, unix::net::UnixListener};
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("stringbar-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn output_and_stdin() {
+        let output = run(
+            Command::new("sh").args(["-c", "cat; echo out; echo err >&2; exit 3"]),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        assert_eq!(Some(3), output.status.code());
+        assert_eq!(b"out\n", output.stdout.as_slice());
+        assert_eq!(b"err\n", output.stderr.as_slice());
+    }
+
+    #[test]
+    fn timeout_kills_group() {
+        let dir = temp_dir("spawn-timeout");
+        let pid_file = dir.join("pid");
+
+        let started = Instant::now();
+        let result = run(
+            Command::new("sh")
+                .arg("-c")
+                .arg("echo $$ > \"$0\"; sleep 100; true")
+                .arg(&pid_file),
+            Duration::from_millis(200),
+        );
+        assert_eq!(io::ErrorKind::TimedOut, result.unwrap_err().kind());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // The shell led the group, its sleep must be gone too.
+        let group: libc::pid_t = std::fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        // SAFETY: signal 0 only checks for existence.
+        while unsafe { libc::kill(-group, 0) } == 0 {
+            assert!(Instant::now() < deadline, "process group {group} survived");
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn backgrounded_child() {
+        let dir = temp_dir("spawn-background");
+        let pid_file = dir.join("pid");
+
+        // The sleep keeps stdout open long after the shell exited.
+        let started = Instant::now();
+        let output = run(
+            Command::new("sh")
+                .arg("-c")
+                .arg("echo $$ > \"$0\"; echo out; sleep 100 &")
+                .arg(&pid_file),
+            Duration::from_millis(200),
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(b"out\n", output.stdout.as_slice());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let group: libc::pid_t = std::fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        // SAFETY: signal 0 only checks for existence.
+        while unsafe { libc::kill(-group, 0) } == 0 {
+            assert!(Instant::now() < deadline, "process group {group} survived");
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn sockets_not_inherited() {
+        let dir = temp_dir("spawn-fds");
+        let listener = UnixListener::bind(dir.join("socket")).unwrap();
+
+        let output = run(
+            Command::new("sh").args(["-c", "ls /proc/$$/fd"]),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        let fds: Vec<String> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(Into::into)
+            .collect();
+
+        assert!(fds.len() >= 3, "stdio missing: {fds:?}");
+        assert!(!fds.contains(&listener.as_raw_fd().to_string()));
+
+        drop(listener);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,47 @@
+//! Inode counts, which sysinfo doesn't expose.
+//!
+//! std has no statvfs and neither nix nor rustix is a dependency, so libc is called directly,
+//! as spawn.rs does for process groups. The one unsafe block below is the only one needed:
+//! the path is checked for NULs when converting it, and the result is only read after the
+//! call reported success.
+
+use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt, path::Path};
+
+/// Used and total inodes of the filesystem mounted at `path`. `None` if it can't be queried or
+/// has no fixed number of inodes, like btrfs.
+pub fn inodes(path: &Path) -> Option<(u64, u64)> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: `path` is a valid C string and `stat` is only read once statvfs succeeded,
+    // which means it was filled in.
+    let stat = unsafe {
+        (libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) == 0).then(|| stat.assume_init())
+    }?;
+
+    let (total, free) = (stat.f_files as u64, stat.f_ffree as u64);
+    if total == 0 {
+        return None;
+    }
+
+    Some((total.saturating_sub(free), total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_and_missing() {
+        // The root filesystem may be btrfs, but /proc always exists and never has inode limits.
+        assert_eq!(None, inodes(Path::new("/proc")));
+        assert_eq!(None, inodes(Path::new("/stringbar/definitely/not/here")));
+        assert_eq!(None, inodes(Path::new("/nul\0byte")));
+
+        for path in ["/", "/dev", &std::env::temp_dir().to_string_lossy()] {
+            if let Some((used, total)) = inodes(Path::new(path)) {
+                assert!(used <= total, "{path}");
+            }
+        }
+    }
+}
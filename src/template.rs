@@ -0,0 +1,339 @@
+//! `{name}` placeholders in formats from the config, optionally with a spec after the name
+//! for how the value is written, e.g. `{used:GiB.1}` or `{percent:0>4}`.
+
+use bittenhumans::consts::{Magnitude, System};
+
+use crate::{config::Alignment, text};
+
+/// The spec grammar, for error messages.
+const SPEC_GRAMMAR: &str = "expected {name:[unit][.precision][<, > or ^ and width]}, \
+    e.g. {used:GiB.1}, {percent:0} or {percent:.1>6}";
+
+/// What a placeholder stands for, deciding which parts of a spec it takes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    /// Byte counts, which take a unit, precision and width.
+    Bytes,
+    /// Numbers with decimals, which take a precision and width.
+    Decimal,
+    /// Anything else, which only takes a width.
+    Plain,
+}
+
+/// How to write one placeholder's value, parsed from what follows the name.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Spec {
+    /// Unit for byte values instead of the one fitting them, e.g. "GiB".
+    pub unit: Option<(System, Magnitude)>,
+    /// Decimals instead of the section's.
+    pub precision: Option<usize>,
+    /// Columns to pad the value to.
+    pub width: Option<(Alignment, usize)>,
+}
+
+impl Spec {
+    /// Parses e.g. "GiB.1" or ".1>6", with `placeholder` for errors.
+    fn parse(spec: &str, placeholder: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid placeholder {placeholder}, {SPEC_GRAMMAR}");
+        let digits = |x: &str| x.bytes().take_while(u8::is_ascii_digit).count();
+
+        let unit_len = spec.bytes().take_while(u8::is_ascii_alphabetic).count();
+        let unit = match &spec[..unit_len] {
+            "" => None,
+            unit => Some(parse_unit(unit).ok_or_else(|| {
+                format!("invalid placeholder {placeholder}, unknown unit {unit:?}, expected one of KiB to EiB or KB to EB")
+            })?),
+        };
+        let mut rest = &spec[unit_len..];
+
+        let dot = rest.starts_with('.');
+        rest = rest.strip_prefix('.').unwrap_or(rest);
+        let precision = match digits(rest) {
+            0 if dot => return Err(invalid()),
+            0 => None,
+            len => {
+                let precision = rest[..len].parse().map_err(|_| invalid())?;
+                rest = &rest[len..];
+                Some(precision)
+            }
+        };
+
+        let mut chars = rest.chars();
+        let align = match chars.next() {
+            None => None,
+            Some('<') => Some(Alignment::Left),
+            Some('>') => Some(Alignment::Right),
+            Some('^') => Some(Alignment::Center),
+            Some(_) => return Err(invalid()),
+        };
+        let width = match align {
+            Some(align) => {
+                let rest = chars.as_str();
+                if rest.is_empty() || digits(rest) != rest.len() {
+                    return Err(invalid());
+                }
+                Some((align, rest.parse().map_err(|_| invalid())?))
+            }
+            None => None,
+        };
+
+        if unit.is_none() && precision.is_none() && width.is_none() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            unit,
+            precision,
+            width,
+        })
+    }
+
+    /// Checks that a placeholder of `kind` takes everything this spec sets.
+    fn check(&self, kind: Kind, placeholder: &str) -> Result<(), String> {
+        if self.unit.is_some() && kind != Kind::Bytes {
+            return Err(format!(
+                "invalid placeholder {placeholder}, only byte values take a unit"
+            ));
+        }
+        if self.precision.is_some() && kind == Kind::Plain {
+            return Err(format!(
+                "invalid placeholder {placeholder}, only byte values and numbers with decimals take a precision"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// E.g. "GiB" or "MB".
+fn parse_unit(unit: &str) -> Option<(System, Magnitude)> {
+    let (prefix, system) = match unit.strip_suffix("iB") {
+        Some(prefix) => (prefix, System::Binary),
+        None => (unit.strip_suffix('B')?, System::Decimal),
+    };
+    let magnitude = match prefix {
+        "K" => Magnitude::Kilo,
+        "M" => Magnitude::Mega,
+        "G" => Magnitude::Giga,
+        "T" => Magnitude::Tera,
+        "P" => Magnitude::Peta,
+        "E" => Magnitude::Exa,
+        _ => return None,
+    };
+
+    Some((system, magnitude))
+}
+
+/// Replaces each `{name}` or `{name:spec}` in `template` with the value `value` gives for
+/// the name and spec, padded to the spec's width. Braces around anything that can't be a
+/// name, e.g. "{ }", are kept as they are, unknown names and invalid specs are an error.
+pub fn fill(
+    template: &str,
+    value: impl Fn(&str, &Spec) -> Option<String>,
+) -> Result<String, String> {
+    substitute(template, |name, spec, placeholder| {
+        value(name, spec).ok_or_else(|| format!("unknown placeholder {placeholder}"))
+    })
+}
+
+/// Checks `template` for placeholders other than `names`, and for specs their kind doesn't
+/// take.
+pub fn check(template: &str, names: &[(&str, Kind)]) -> Result<(), String> {
+    substitute(template, |name, spec, placeholder| {
+        match names.iter().find(|(x, _)| *x == name) {
+            Some((_, kind)) => spec.check(*kind, placeholder).map(|_| String::new()),
+            None => Err(format!("unknown placeholder {placeholder}")),
+        }
+    })
+    .map(|_| ())
+}
+
+/// Replaces placeholders with what `value` returns for their name, spec and full text.
+fn substitute(
+    template: &str,
+    mut value: impl FnMut(&str, &Spec, &str) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let placeholder = rest[1..].find('}').map(|end| &rest[..end + 2]);
+        let inner = placeholder.map(|x| &x[1..x.len() - 1]).unwrap_or_default();
+        let (name, spec) = match inner.split_once(':') {
+            Some((name, spec)) => (name, Some(spec)),
+            None => (inner, None),
+        };
+        match placeholder {
+            Some(placeholder) if is_name(name) => {
+                let spec = match spec {
+                    Some(spec) => Spec::parse(spec, placeholder)?,
+                    None => Spec::default(),
+                };
+                let value = value(name, &spec, placeholder)?;
+                match spec.width {
+                    Some((align, width)) => output.push_str(&text::pad(&value, width, align)),
+                    None => output.push_str(&value),
+                }
+                rest = &rest[placeholder.len()..];
+            }
+            _ => {
+                output.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|x| x.is_ascii_lowercase() || x.is_ascii_digit() || x == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup<'a>(values: &'a [(&str, &str)]) -> impl Fn(&str, &Spec) -> Option<String> + 'a {
+        |name, _| {
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn filling() {
+        let values = [("used", "9.80 GiB"), ("percent", "62%"), ("used_raw", "10")];
+        let cases = [
+            ("{percent}", "62%"),
+            ("{used} ({percent})", "9.80 GiB (62%)"),
+            ("{used_raw}{used_raw}", "1010"),
+            ("no placeholders", "no placeholders"),
+            ("{ } {} {Used} {", "{ } {} {Used} {"),
+            ("{{percent}}", "{62%}"),
+            ("{Used:x} {:1}", "{Used:x} {:1}"),
+            ("[{percent:>5}]", "[  62%]"),
+            ("[{percent:<5}]", "[62%  ]"),
+            ("[{percent:^6}]", "[ 62%  ]"),
+            ("[{used:>2}]", "[9.80 GiB]"),
+            ("", ""),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(
+                Ok(expected.into()),
+                fill(template, lookup(&values)),
+                "{template}"
+            );
+        }
+
+        assert_eq!(
+            Err("unknown placeholder {free}".into()),
+            fill("{used} {free}", lookup(&values))
+        );
+        assert_eq!(
+            Err("unknown placeholder {free:.1}".into()),
+            fill("{free:.1}", lookup(&values))
+        );
+    }
+
+    #[test]
+    fn specs() {
+        let parse = |spec| Spec::parse(spec, "{x}");
+        let spec = |unit, precision, width| Spec {
+            unit,
+            precision,
+            width,
+        };
+        let gib = Some((System::Binary, Magnitude::Giga));
+
+        let cases = [
+            ("0", spec(None, Some(0), None)),
+            (".1", spec(None, Some(1), None)),
+            ("GiB", spec(gib, None, None)),
+            ("GiB.1", spec(gib, Some(1), None)),
+            ("GiB1", spec(gib, Some(1), None)),
+            (
+                "KB",
+                spec(Some((System::Decimal, Magnitude::Kilo)), None, None),
+            ),
+            (
+                "EiB",
+                spec(Some((System::Binary, Magnitude::Exa)), None, None),
+            ),
+            (">4", spec(None, None, Some((Alignment::Right, 4)))),
+            ("^10", spec(None, None, Some((Alignment::Center, 10)))),
+            (
+                "MB.2<12",
+                spec(
+                    Some((System::Decimal, Magnitude::Mega)),
+                    Some(2),
+                    Some((Alignment::Left, 12)),
+                ),
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(Ok(expected), parse(input), "{input}");
+        }
+
+        for input in [
+            "", ".", "GiB.", ">", ">x", "1>4x", "1.2", "x", "Gib", "iB", "B", "ZiB",
+        ] {
+            assert!(parse(input).is_err(), "{input}");
+        }
+        assert_eq!(
+            Err("invalid placeholder {x}, expected {name:[unit][.precision][<, > or ^ and width]}, \
+                e.g. {used:GiB.1}, {percent:0} or {percent:.1>6}"
+                .into()),
+            parse("1>")
+        );
+    }
+
+    #[test]
+    fn checking() {
+        let names = [
+            ("used", Kind::Bytes),
+            ("total", Kind::Bytes),
+            ("percent", Kind::Decimal),
+            ("used_raw", Kind::Plain),
+        ];
+        for template in [
+            "{total} total",
+            "{used:GiB.1}/{total:GiB.1}",
+            "{percent:0>4}",
+            "{used_raw:>12}",
+        ] {
+            assert_eq!(Ok(()), check(template, &names), "{template}");
+        }
+
+        let cases = [
+            ("{totl} total", "unknown placeholder {totl}"),
+            (
+                "{used:GiB.x}",
+                "invalid placeholder {used:GiB.x}, expected {name:[unit][.precision][<, > or ^ and width]}, \
+                    e.g. {used:GiB.1}, {percent:0} or {percent:.1>6}",
+            ),
+            (
+                "{used:Gb}",
+                "invalid placeholder {used:Gb}, unknown unit \"Gb\", expected one of KiB to EiB or KB to EB",
+            ),
+            (
+                "{percent:GiB}",
+                "invalid placeholder {percent:GiB}, only byte values take a unit",
+            ),
+            (
+                "{used_raw:1}",
+                "invalid placeholder {used_raw:1}, only byte values and numbers with decimals take a precision",
+            ),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(Err(expected.into()), check(template, &names), "{template}");
+        }
+    }
+}
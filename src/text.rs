@@ -0,0 +1,282 @@
+use crate::config::Alignment;
+
+/// Returns true for characters that extend the preceding grapheme cluster
+/// (combining marks, variation selectors, zero width joiners, emoji modifiers).
+///
+/// This is an approximation covering the ranges that show up in practice,
+/// not a full implementation of UAX #29.
+pub fn is_grapheme_extend(c: char) -> bool {
+    matches!(c as u32,
+        0x0300..=0x036F
+        | 0x0483..=0x0489
+        | 0x0591..=0x05BD
+        | 0x0610..=0x061A
+        | 0x064B..=0x065F
+        | 0x0E31 | 0x0E34..=0x0E3A | 0x0E47..=0x0E4E
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200C..=0x200D
+        | 0x20D0..=0x20FF
+        | 0x302A..=0x302F
+        | 0x3099..=0x309A
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F
+        | 0x1F3FB..=0x1F3FF
+        | 0xE0020..=0xE007F
+        | 0xE0100..=0xE01EF
+    )
+}
+
+/// Splits `text` into grapheme clusters as far as [`is_grapheme_extend`] tells, keeping
+/// characters joined by zero width joiners together.
+pub fn graphemes(text: &str) -> Vec<&str> {
+    let mut clusters = Vec::new();
+    let mut start = 0;
+    let mut previous = None;
+    for (index, c) in text.char_indices() {
+        if index > 0 && !is_grapheme_extend(c) && previous != Some('\u{200D}') {
+            clusters.push(&text[start..index]);
+            start = index;
+        }
+        previous = Some(c);
+    }
+    if start < text.len() {
+        clusters.push(&text[start..]);
+    }
+
+    clusters
+}
+
+/// Replaces every occurrence of `separator` in `text` with `replacement`.
+///
+/// Occurrences whose last character is extended by the following character
+/// (e.g. a combining accent on the final `|`) are part of a larger grapheme and left alone.
+pub fn escape_separator(text: &str, separator: &str, replacement: &str) -> String {
+    if separator.is_empty() {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find(separator) {
+        let end = index + separator.len();
+        let splits_grapheme = rest[end..].chars().next().is_some_and(is_grapheme_extend);
+
+        out.push_str(&rest[..index]);
+        if splits_grapheme {
+            out.push_str(&rest[index..end]);
+        } else {
+            out.push_str(replacement);
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Shortens `text` to at most `max_chars` characters, ending in "…" if anything was cut.
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        None => text.to_string(),
+        Some(_) if max_chars == 0 => String::new(),
+        Some(_) => {
+            let end = text
+                .char_indices()
+                .nth(max_chars - 1)
+                .map_or(text.len(), |(index, _)| index);
+            format!("{}…", &text[..end])
+        }
+    }
+}
+
+/// Returns true for characters taking up two columns: CJK, Hangul, fullwidth forms and the
+/// emoji blocks. Like [`is_grapheme_extend`] this covers what shows up in practice rather
+/// than all of UAX #11.
+pub fn is_wide(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x115F
+        | 0x231A..=0x231B
+        | 0x23E9..=0x23EC
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F680..=0x1F6FF
+        | 0x1F900..=0x1F9FF
+        | 0x1FA70..=0x1FAFF
+        | 0x20000..=0x3FFFD
+    )
+}
+
+/// Columns `text` takes up in a monospace font. A grapheme cluster is two columns wide if it
+/// starts with a wide character or asks for emoji presentation, e.g. "☀\u{FE0F}".
+pub fn width(text: &str) -> usize {
+    graphemes(text)
+        .iter()
+        .map(|x| match x.starts_with(is_wide) || x.contains('\u{FE0F}') {
+            true => 2,
+            false => 1,
+        })
+        .sum()
+}
+
+/// Shortens `text` to at most `max_width` columns, see [`width`], ending in "…" if anything
+/// was cut. Cuts between grapheme clusters, so a wide one that doesn't fit leaves a column
+/// unused.
+pub fn truncate_width(text: &str, max_width: usize) -> String {
+    if width(text) <= max_width {
+        return text.to_string();
+    }
+    if max_width == 0 {
+        return String::new();
+    }
+
+    let mut output = String::new();
+    let mut remaining = max_width - 1;
+    for cluster in graphemes(text) {
+        let cluster_width = width(cluster);
+        if cluster_width > remaining {
+            break;
+        }
+        remaining -= cluster_width;
+        output.push_str(cluster);
+    }
+    output.push('…');
+
+    output
+}
+
+/// Pads `text` with spaces to at least `min_width` columns, see [`width`].
+pub fn pad(text: &str, min_width: usize, align: Alignment) -> String {
+    let missing = min_width.saturating_sub(width(text));
+    let (left, right) = match align {
+        Alignment::Left => (0, missing),
+        Alignment::Right => (missing, 0),
+        Alignment::Center => (missing / 2, missing - missing / 2),
+    };
+
+    format!("{}{text}{}", " ".repeat(left), " ".repeat(right))
+}
+
+/// `value` as a JSON string literal.
+pub fn json_string(value: &str) -> String {
+    let mut output = String::from('"');
+    for x in value.chars() {
+        match x {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            x if x.is_control() => output.push_str(&format!("\\u{:04x}", x as u32)),
+            x => output.push(x),
+        }
+    }
+    output.push('"');
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json() {
+        assert_eq!("\"a\\\"b\\\\c\\u000a\"", json_string("a\"b\\c\n"));
+    }
+
+    #[test]
+    fn escape_multi_character_separator() {
+        assert_eq!(
+            "vim ¦ main.rs ¦ stringbar",
+            escape_separator("vim | main.rs | stringbar", " | ", " ¦ ")
+        );
+        assert_eq!("a|b", escape_separator("a|b", " | ", " ¦ "));
+        assert_eq!("¦¦", escape_separator(" |  | ", " | ", "¦"));
+    }
+
+    #[test]
+    fn escape_multi_byte_separator() {
+        assert_eq!("日本|語", escape_separator("日本║語", "║", "|"));
+        assert_eq!("a - b - c", escape_separator("a · b · c", " · ", " - "));
+        assert_eq!("🦀🦀", escape_separator("🦀 · 🦀", " · ", ""));
+    }
+
+    #[test]
+    fn escape_keeps_graphemes_intact() {
+        assert_eq!("a |\u{301} b", escape_separator("a |\u{301} b", " |", "¦"));
+        assert_eq!("x¦ y", escape_separator("x | y", " |", "¦"));
+    }
+
+    #[test]
+    fn escape_empty_separator() {
+        assert_eq!("unchanged", escape_separator("unchanged", "", "x"));
+    }
+
+    #[test]
+    fn grapheme_clusters() {
+        assert_eq!(vec!["a", "b"], graphemes("ab"));
+        assert_eq!(vec!["e\u{301}", "x"], graphemes("e\u{301}x"));
+        assert_eq!(
+            vec!["👩\u{200D}💻", "👍\u{1F3FD}"],
+            graphemes("👩\u{200D}💻👍\u{1F3FD}")
+        );
+        assert!(graphemes("").is_empty());
+    }
+
+    #[test]
+    fn widths() {
+        assert_eq!(0, width(""));
+        assert_eq!(5, width("19.0%"));
+        assert_eq!(3, width("e\u{301}↑x"));
+        assert_eq!(4, width("日本"));
+        assert_eq!(4, width("🦀a "));
+        assert_eq!(2, width("👩\u{200D}💻"));
+        assert_eq!(2, width("☀\u{FE0F}"));
+    }
+
+    #[test]
+    fn width_truncation() {
+        assert_eq!("firefox", truncate_width("firefox", 7));
+        assert_eq!("firef…", truncate_width("firefox", 6));
+        assert_eq!("…", truncate_width("firefox", 1));
+        assert_eq!("", truncate_width("firefox", 0));
+        assert_eq!("日本語", truncate_width("日本語", 6));
+        assert_eq!("日本…", truncate_width("日本語です", 6));
+        // The next character is two columns wide and only one is left.
+        assert_eq!("日…", truncate_width("日本語です", 4));
+        assert_eq!("a🦀…", truncate_width("a🦀🦀", 4));
+        assert_eq!(
+            "e\u{301}👩\u{200D}💻…",
+            truncate_width("e\u{301}👩\u{200D}💻xyz", 4)
+        );
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!("9.8%  ", pad("9.8%", 6, Alignment::Left));
+        assert_eq!("  9.8%", pad("9.8%", 6, Alignment::Right));
+        assert_eq!(" 9.8% ", pad("9.8%", 6, Alignment::Center));
+        assert_eq!(" 9.8%  ", pad("9.8%", 7, Alignment::Center));
+        assert_eq!("日本  ", pad("日本", 6, Alignment::Left));
+        assert_eq!("  🦀", pad("🦀", 4, Alignment::Right));
+        // Longer text is left alone.
+        assert_eq!("10.20%", pad("10.20%", 4, Alignment::Right));
+    }
+
+    #[test]
+    fn truncation() {
+        assert_eq!("firefox", truncate("firefox", 7));
+        assert_eq!("firef…", truncate("firefox", 6));
+        assert_eq!("…", truncate("firefox", 1));
+        assert_eq!("", truncate("firefox", 0));
+        assert_eq!("", truncate("", 0));
+        assert_eq!("日本…", truncate("日本語のプロセス", 3));
+    }
+}
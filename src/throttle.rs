@@ -0,0 +1,150 @@
+//! Limits how often the bar text is handed to the window manager. Every root window name
+//! change makes dwm redraw its bar, which flickers when it happens many times per second.
+
+use std::time::{Duration, Instant};
+
+/// Coalesces emissions that come too soon after the previous one. The latest text is held back
+/// until the minimum spacing has passed, so the final state is always shown.
+#[derive(Default)]
+pub struct Throttle {
+    spacing: Option<Duration>,
+    last: Option<Instant>,
+    pending: Option<String>,
+}
+
+impl Throttle {
+    /// `None` lets every emission through.
+    pub fn new(max_per_sec: Option<u32>) -> Self {
+        let mut throttle = Self::default();
+        throttle.set_rate(max_per_sec);
+        throttle
+    }
+
+    pub fn set_rate(&mut self, max_per_sec: Option<u32>) {
+        self.spacing = max_per_sec
+            .filter(|x| *x > 0)
+            .map(|x| Duration::from_secs(1) / x);
+    }
+
+    /// Returns `text` if it can be emitted right away. Otherwise it replaces any text that's
+    /// already waiting and is returned by [`due`](Self::due) later.
+    pub fn offer(&mut self, text: String, now: Instant) -> Option<String> {
+        match (self.spacing, self.last) {
+            (Some(spacing), Some(last)) if now.saturating_duration_since(last) < spacing => {
+                self.pending = Some(text);
+                None
+            }
+            _ => {
+                self.pending = None;
+                self.last = Some(now);
+                Some(text)
+            }
+        }
+    }
+
+    /// When the waiting text may be emitted, `None` if nothing is waiting.
+    pub fn deadline(&self) -> Option<Instant> {
+        match (&self.pending, self.spacing, self.last) {
+            (Some(_), Some(spacing), Some(last)) => Some(last + spacing),
+            _ => None,
+        }
+    }
+
+    /// The waiting text, once its deadline has passed.
+    pub fn due(&mut self, now: Instant) -> Option<String> {
+        if self.deadline()? > now {
+            return None;
+        }
+
+        self.last = Some(now);
+        self.pending.take()
+    }
+
+    /// The waiting text regardless of its deadline, for shutting down.
+    pub fn flush(&mut self) -> Option<String> {
+        self.pending.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn unlimited() {
+        let mut throttle = Throttle::new(None);
+        let start = Instant::now();
+        for i in 0..50 {
+            assert_eq!(Some(i.to_string()), throttle.offer(i.to_string(), start));
+        }
+        assert_eq!(None, throttle.deadline());
+        assert_eq!(None, throttle.flush());
+    }
+
+    #[test]
+    fn burst() {
+        let mut throttle = Throttle::new(Some(10));
+        let start = Instant::now();
+        let mut emitted = Vec::new();
+
+        // 50 updates 1ms apart, with the main loop checking for due text in between.
+        for i in 0..50 {
+            let now = start + i * MS;
+            emitted.extend(throttle.due(now));
+            emitted.extend(throttle.offer(i.to_string(), now));
+        }
+        assert_eq!(vec!["0"], emitted);
+        assert_eq!(Some(start + 100 * MS), throttle.deadline());
+        assert_eq!(None, throttle.due(start + 99 * MS));
+
+        assert_eq!(Some("49".into()), throttle.due(start + 100 * MS));
+        assert_eq!(None, throttle.deadline());
+        assert_eq!(None, throttle.due(start + 200 * MS));
+    }
+
+    #[test]
+    fn steady_updates() {
+        let mut throttle = Throttle::new(Some(10));
+        let start = Instant::now();
+        let mut emitted = Vec::new();
+
+        // 50 updates 20ms apart.
+        for i in 0..50 {
+            let now = start + i * 20 * MS;
+            emitted.extend(throttle.due(now));
+            emitted.extend(throttle.offer(i.to_string(), now));
+        }
+        emitted.extend(throttle.due(start + 1000 * MS));
+
+        // Once every 100ms, ending with the latest text.
+        assert_eq!(11, emitted.len());
+        assert_eq!(Some("49"), emitted.last().map(String::as_str));
+        // Each deadline emits the newest text offered before it.
+        assert_eq!(Some("4".into()), emitted.get(1).cloned());
+    }
+
+    #[test]
+    fn flush() {
+        let mut throttle = Throttle::new(Some(1));
+        let start = Instant::now();
+        assert_eq!(Some("a".into()), throttle.offer("a".into(), start));
+        assert_eq!(None, throttle.offer("b".into(), start + MS));
+        assert_eq!(None, throttle.offer("c".into(), start + 2 * MS));
+
+        assert_eq!(Some("c".into()), throttle.flush());
+        assert_eq!(None, throttle.flush());
+        assert_eq!(None, throttle.deadline());
+    }
+
+    #[test]
+    fn slower_than_the_limit() {
+        let mut throttle = Throttle::new(Some(2));
+        let start = Instant::now();
+        for i in 0..5 {
+            let now = start + i * 500 * MS;
+            assert_eq!(Some(i.to_string()), throttle.offer(i.to_string(), now));
+        }
+    }
+}
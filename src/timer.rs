@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// A stopwatch, or a countdown if started with a duration.
+#[derive(Default)]
+pub struct Timer {
+    accumulated: Duration,
+    running_since: Option<Instant>,
+    countdown: Option<Duration>,
+}
+
+pub enum TimerDisplay {
+    Elapsed(Duration),
+    Remaining(Duration),
+    Expired,
+}
+
+impl Timer {
+    /// Starts or resumes the timer. Passing a duration turns it into a fresh countdown.
+    pub fn start(&mut self, now: Instant, countdown: Option<Duration>) {
+        if countdown.is_some() {
+            self.accumulated = Duration::ZERO;
+            self.countdown = countdown;
+            self.running_since = Some(now);
+        } else if self.running_since.is_none() {
+            self.running_since = Some(now);
+        }
+    }
+
+    pub fn stop(&mut self, now: Instant) {
+        if let Some(since) = self.running_since.take() {
+            self.accumulated += now.saturating_duration_since(since);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running_since.is_some()
+    }
+
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        self.accumulated
+            + self
+                .running_since
+                .map(|since| now.saturating_duration_since(since))
+                .unwrap_or_default()
+    }
+
+    pub fn display(&self, now: Instant) -> TimerDisplay {
+        match self.countdown {
+            Some(countdown) => match countdown.checked_sub(self.elapsed(now)) {
+                Some(remaining) if !remaining.is_zero() => TimerDisplay::Remaining(remaining),
+                _ => TimerDisplay::Expired,
+            },
+            None => TimerDisplay::Elapsed(self.elapsed(now)),
+        }
+    }
+}
+
+pub type Timers = HashMap<String, Timer>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stopwatch() {
+        let start = Instant::now();
+        let mut timer = Timer::default();
+        timer.start(start, None);
+        assert!(timer.is_running());
+        assert_eq!(
+            Duration::from_secs(10),
+            timer.elapsed(start + Duration::from_secs(10))
+        );
+
+        timer.stop(start + Duration::from_secs(10));
+        assert_eq!(
+            Duration::from_secs(10),
+            timer.elapsed(start + Duration::from_secs(60))
+        );
+
+        timer.start(start + Duration::from_secs(60), None);
+        // starting a running stopwatch again must not restart it
+        timer.start(start + Duration::from_secs(65), None);
+        assert_eq!(
+            Duration::from_secs(15),
+            timer.elapsed(start + Duration::from_secs(65))
+        );
+
+        timer.reset();
+        assert!(!timer.is_running());
+        assert_eq!(
+            Duration::ZERO,
+            timer.elapsed(start + Duration::from_secs(70))
+        );
+    }
+
+    #[test]
+    fn countdown() {
+        let start = Instant::now();
+        let mut timer = Timer::default();
+        timer.start(start, Some(Duration::from_secs(60)));
+
+        assert!(matches!(
+            timer.display(start + Duration::from_secs(15)),
+            TimerDisplay::Remaining(x) if x == Duration::from_secs(45)
+        ));
+        assert!(matches!(
+            timer.display(start + Duration::from_secs(60)),
+            TimerDisplay::Expired
+        ));
+
+        timer.stop(start + Duration::from_secs(30));
+        timer.start(start + Duration::from_secs(100), None);
+        assert!(matches!(
+            timer.display(start + Duration::from_secs(110)),
+            TimerDisplay::Remaining(x) if x == Duration::from_secs(20)
+        ));
+    }
+}
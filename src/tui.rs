@@ -0,0 +1,308 @@
+//! `stringbar tui`: runs the bar as usual, but shows it in the terminal instead of publishing
+//! it, above a table of what each section last did. Meant for editing the config in another
+//! window and watching reloads take effect.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{self, Read, Write},
+    mem::MaybeUninit,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::{config::Alignment, duration, runtime::Runtime, snapshot, text, unit::Value};
+
+/// Columns assumed if the terminal doesn't say.
+const DEFAULT_WIDTH: usize = 80;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Key {
+    Quit,
+    /// Evaluates every section right away, including those with intervals of their own.
+    Refresh,
+    ToggleTiming,
+}
+
+impl Key {
+    fn parse(byte: u8) -> Option<Self> {
+        match byte {
+            b'q' => Some(Self::Quit),
+            b'r' => Some(Self::Refresh),
+            b't' => Some(Self::ToggleTiming),
+            _ => None,
+        }
+    }
+}
+
+/// A line of the section table.
+#[derive(PartialEq, Debug)]
+struct Row {
+    name: String,
+    value: String,
+    took: Option<Duration>,
+    age: Option<Duration>,
+    stale: bool,
+}
+
+pub struct Tui {
+    /// The terminal settings to restore on exit.
+    saved: libc::termios,
+    keys: Receiver<Key>,
+    timing: bool,
+    /// How long each section took to evaluate the last time, by index.
+    took: HashMap<usize, Duration>,
+}
+
+impl Tui {
+    /// Switches the terminal to an alternate screen that takes keys without waiting for Enter
+    /// or echoing them, and reads keys on a thread of their own, sending to `wake` for each.
+    /// Ctrl+C still ends stringbar.
+    pub fn start(wake: Sender<()>) -> io::Result<Self> {
+        let mut saved = MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: `saved` is only read once tcgetattr has filled it in.
+        let saved = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, saved.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            saved.assume_init()
+        };
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        // SAFETY: `raw` is a copy of the terminal's own settings.
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "\x1b[?1049h\x1b[?25l")?;
+        stdout.flush()?;
+
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                let Some(key) = byte.ok().and_then(Key::parse) else {
+                    continue;
+                };
+                if sender.send(key).is_err() {
+                    return;
+                }
+                let _ = wake.send(());
+            }
+        });
+
+        Ok(Self {
+            saved,
+            keys,
+            timing: true,
+            took: HashMap::new(),
+        })
+    }
+
+    /// Keys pressed since the last call.
+    pub fn keys(&self) -> Vec<Key> {
+        self.keys.try_iter().collect()
+    }
+
+    pub fn toggle_timing(&mut self) {
+        self.timing = !self.timing;
+    }
+
+    pub fn evaluated(&mut self, index: usize, took: Duration) {
+        self.took.insert(index, took);
+    }
+
+    /// Forgets the timings, whose indices belong to the old section list after a reload.
+    pub fn reset(&mut self) {
+        self.took.clear();
+    }
+
+    /// Redraws the screen with `line` on top and the sections' snapshots below it.
+    pub fn draw(&self, line: &str, runtime: &Runtime, now: DateTime<FixedOffset>) {
+        let rows: Vec<Row> = runtime
+            .snapshots
+            .iter()
+            .map(|snapshot| Row {
+                name: format!("{} {}", snapshot.index + 1, snapshot.name),
+                value: snapshot.value.map_or("-".into(), format_value),
+                took: self.took.get(&snapshot.index).copied(),
+                age: snapshot::age(snapshot, now),
+                stale: snapshot.stale,
+            })
+            .collect();
+        let reloads = &runtime.reloads;
+        let mut footer = "q quit, r refresh, t timing".to_string();
+        if reloads.failed > 0 {
+            let _ = write!(footer, ". {} reloads failed, see the log", reloads.failed);
+        }
+        if let Some(at) = reloads.last_success {
+            let _ = write!(
+                footer,
+                ". Reloaded at {}, changed: {}",
+                at.format("%H:%M:%S"),
+                reloads.changes_summary()
+            );
+        }
+
+        let mut stdout = io::stdout().lock();
+        let _ = write!(
+            stdout,
+            "\x1b[H\x1b[2J{}",
+            screen(line, &rows, &footer, self.timing, terminal_width())
+        );
+        let _ = stdout.flush();
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout().lock();
+        let _ = write!(stdout, "\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+        // SAFETY: `saved` came from tcgetattr.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+    }
+}
+
+/// `value` rounded to two decimals with its unit, e.g. "62.5 %".
+fn format_value(value: Value) -> String {
+    let amount = (value.amount * 100.0).round() / 100.0;
+    match value.unit.symbol() {
+        "" => amount.to_string(),
+        symbol => format!("{amount} {symbol}"),
+    }
+}
+
+fn terminal_width() -> usize {
+    let mut size = MaybeUninit::<libc::winsize>::uninit();
+    // SAFETY: `size` is only read once the ioctl has filled it in.
+    unsafe {
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) == 0 {
+            let columns = size.assume_init().ws_col;
+            if columns > 0 {
+                return columns.into();
+            }
+        }
+    }
+
+    DEFAULT_WIDTH
+}
+
+/// The screen's lines: `line`, the table of `rows` with the timing columns if `timing`, and
+/// `footer`, each cut to `width` columns.
+fn screen(line: &str, rows: &[Row], footer: &str, timing: bool, width: usize) -> String {
+    let mut table = vec![vec![
+        "section".to_string(),
+        "value".into(),
+        "took".into(),
+        "age".into(),
+    ]];
+    for row in rows {
+        table.push(vec![
+            row.name.clone(),
+            row.value.clone(),
+            row.took
+                .map_or("-".into(), |x| format!("{:.1}ms", x.as_secs_f64() * 1000.0)),
+            match row.age {
+                Some(age) if row.stale => format!("{} stale", duration::format_compact(age)),
+                Some(age) => duration::format_compact(age),
+                None => "-".into(),
+            },
+        ]);
+    }
+    let columns = if timing { 4 } else { 2 };
+    let widths: Vec<usize> = (0..columns)
+        .map(|x| {
+            table
+                .iter()
+                .map(|row| text::width(&row[x]))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut lines = vec![line.to_string(), String::new()];
+    for row in &table {
+        let cells: Vec<_> = (0..columns)
+            .map(|x| text::pad(&row[x], widths[x], Alignment::Left))
+            .collect();
+        lines.push(cells.join("  ").trim_end().to_string());
+    }
+    lines.push(String::new());
+    lines.push(footer.into());
+
+    lines
+        .iter()
+        .map(|x| text::truncate_width(x, width))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unit::Unit;
+
+    #[test]
+    fn layout() {
+        let rows = [
+            Row {
+                name: "1 cpu".into(),
+                value: format_value(Value::new(12.345, Unit::Percent)),
+                took: Some(Duration::from_micros(1250)),
+                age: Some(Duration::from_secs(1)),
+                stale: false,
+            },
+            Row {
+                name: "2 Timestamp".into(),
+                value: "-".into(),
+                took: Some(Duration::from_micros(40)),
+                age: Some(Duration::from_secs(75)),
+                stale: true,
+            },
+            Row {
+                name: "3 PublicIp".into(),
+                value: "-".into(),
+                took: None,
+                age: None,
+                stale: false,
+            },
+        ];
+
+        assert_eq!(
+            "cpu 12% | 14:02\n\
+             \n\
+             section      value    took   age\n\
+             1 cpu        12.35 %  1.2ms  1s\n\
+             2 Timestamp  -        0.0ms  1m 15s stale\n\
+             3 PublicIp   -        -      -\n\
+             \n\
+             q quit",
+            screen("cpu 12% | 14:02", &rows, "q quit", true, 80)
+        );
+        assert_eq!(
+            "cpu 12% | 14:02 | 3…\n\
+             \n\
+             section      value\n\
+             1 cpu        12.35 %\n\
+             2 Timestamp  -\n\
+             3 PublicIp   -\n\
+             \n\
+             q quit",
+            screen("cpu 12% | 14:02 | 3 GiB", &rows, "q quit", false, 20)
+        );
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(Some(Key::Quit), Key::parse(b'q'));
+        assert_eq!(Some(Key::Refresh), Key::parse(b'r'));
+        assert_eq!(Some(Key::ToggleTiming), Key::parse(b't'));
+        assert_eq!(None, Key::parse(b'Q'));
+        assert_eq!("3", format_value(Value::new(3.0, Unit::Count)));
+    }
+}
@@ -0,0 +1,172 @@
+//! What section values measure, so they can be passed on with their unit rather than as bare
+//! numbers, e.g. to the control socket's `metrics`.
+
+use std::{collections::HashMap, fmt::Write};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Unit {
+    Bytes,
+    /// 0 to 100, or beyond for e.g. a process keeping several cores busy.
+    Percent,
+    /// A plain number, e.g. of processes or unread mails.
+    #[default]
+    Count,
+    Seconds,
+    Celsius,
+    BytesPerSecond,
+    Megahertz,
+}
+
+impl Unit {
+    /// The Prometheus metric for values of this unit, named after the base unit they're
+    /// converted to by [`Value::metric`].
+    pub fn metric(self) -> &'static str {
+        match self {
+            Self::Bytes => "stringbar_section_bytes",
+            Self::Percent => "stringbar_section_ratio",
+            Self::Count => "stringbar_section_value",
+            Self::Seconds => "stringbar_section_seconds",
+            Self::Celsius => "stringbar_section_celsius",
+            Self::BytesPerSecond => "stringbar_section_bytes_per_second",
+            Self::Megahertz => "stringbar_section_hertz",
+        }
+    }
+
+    /// What amounts of this unit are written with, nothing for plain numbers.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Bytes => "B",
+            Self::Percent => "%",
+            Self::Count => "",
+            Self::Seconds => "s",
+            Self::Celsius => "°C",
+            Self::BytesPerSecond => "B/s",
+            Self::Megahertz => "MHz",
+        }
+    }
+}
+
+/// A section's value, which can't be given without saying what it measures.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Value {
+    pub amount: f64,
+    pub unit: Unit,
+}
+
+impl Value {
+    pub fn new(amount: f64, unit: Unit) -> Self {
+        Self { amount, unit }
+    }
+
+    /// The amount in the base unit of [`Unit::metric`], e.g. percentages as a ratio from 0 to
+    /// 1 as Prometheus has it.
+    pub fn metric(self) -> f64 {
+        match self.unit {
+            Unit::Percent => self.amount / 100.0,
+            Unit::Megahertz => self.amount * 1_000_000.0,
+            _ => self.amount,
+        }
+    }
+}
+
+/// The latest value of a section and what to label it with.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Reading {
+    pub id: Option<String>,
+    pub module: &'static str,
+    pub value: Value,
+}
+
+/// `readings` by section index in the Prometheus text format, a gauge per unit with a sample
+/// for every section of that unit, labelled with its number from 1, module and id if it has
+/// one.
+pub fn metrics(readings: &HashMap<usize, Reading>) -> String {
+    let mut indices: Vec<_> = readings.keys().copied().collect();
+    indices.sort_unstable();
+    let mut metrics: Vec<&str> = indices
+        .iter()
+        .map(|x| readings[x].value.unit.metric())
+        .collect();
+    metrics.sort_unstable();
+    metrics.dedup();
+
+    let mut lines = Vec::new();
+    for metric in metrics {
+        lines.push(format!("# TYPE {metric} gauge"));
+        for index in &indices {
+            let reading = &readings[index];
+            if reading.value.unit.metric() != metric {
+                continue;
+            }
+            let mut labels = format!("section=\"{}\",module=\"{}\"", index + 1, reading.module);
+            if let Some(id) = &reading.id {
+                let _ = write!(labels, ",id=\"{}\"", escape_label(id));
+            }
+            lines.push(format!("{metric}{{{labels}}} {}", reading.value.metric()));
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Escapes a label value for the Prometheus text format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let cases = [
+            (Unit::Bytes, 1024.0, "stringbar_section_bytes", 1024.0),
+            (Unit::Percent, 62.5, "stringbar_section_ratio", 0.625),
+            (Unit::Percent, 250.0, "stringbar_section_ratio", 2.5),
+            (Unit::Count, 3.0, "stringbar_section_value", 3.0),
+            (Unit::Seconds, 90.0, "stringbar_section_seconds", 90.0),
+            (Unit::Celsius, 54.0, "stringbar_section_celsius", 54.0),
+            (
+                Unit::BytesPerSecond,
+                1200.0,
+                "stringbar_section_bytes_per_second",
+                1200.0,
+            ),
+            (Unit::Megahertz, 2400.0, "stringbar_section_hertz", 2.4e9),
+        ];
+        for (unit, amount, metric, converted) in cases {
+            assert_eq!(metric, unit.metric());
+            assert_eq!(converted, Value::new(amount, unit).metric(), "{unit:?}");
+        }
+    }
+
+    #[test]
+    fn exposition() {
+        assert_eq!("", metrics(&HashMap::new()));
+
+        let reading = |id: Option<&str>, module, amount, unit| Reading {
+            id: id.map(Into::into),
+            module,
+            value: Value::new(amount, unit),
+        };
+        let readings = HashMap::from([
+            (3, reading(Some("mem"), "MemoryUsage", 50.0, Unit::Percent)),
+            (0, reading(None, "CpuUsage", 12.5, Unit::Percent)),
+            (1, reading(Some("a\"b"), "Temperature", 54.0, Unit::Celsius)),
+        ]);
+        assert_eq!(
+            "# TYPE stringbar_section_celsius gauge\n\
+             stringbar_section_celsius{section=\"2\",module=\"Temperature\",id=\"a\\\"b\"} 54\n\
+             # TYPE stringbar_section_ratio gauge\n\
+             stringbar_section_ratio{section=\"1\",module=\"CpuUsage\"} 0.125\n\
+             stringbar_section_ratio{section=\"4\",module=\"MemoryUsage\",id=\"mem\"} 0.5",
+            metrics(&readings)
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+use crate::config::{Section, Threshold};
+
+/// Which thresholds of each section are triggered, and the sections made urgent through the
+/// control socket.
+pub struct Urgency {
+    /// When stringbar started, for `startup_grace_secs`.
+    started: Instant,
+    grace: Duration,
+    /// Triggered thresholds by section index. Only needed for thresholds with `clear_at`, whose
+    /// state depends on the previous values, but kept for all so the indices line up.
+    triggered: HashMap<usize, Vec<bool>>,
+    /// Section ids or numbers from 1, as given to `urgent <section> on`.
+    forced: BTreeSet<String>,
+}
+
+impl Default for Urgency {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            grace: Duration::ZERO,
+            triggered: HashMap::new(),
+            forced: BTreeSet::new(),
+        }
+    }
+}
+
+/// The outcome of [`Urgency::check`] for one section.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Triggered {
+    /// The last triggered threshold, whose decoration the section uses.
+    pub threshold: Option<usize>,
+    pub urgent: bool,
+}
+
+impl Urgency {
+    /// Works out which of `thresholds` section `index` triggers with `value` at `now`.
+    /// Thresholds with `clear_at` stay triggered until the value gets back past it, sections
+    /// without a value trigger none. Urgency is held back during the startup grace period.
+    pub fn check(
+        &mut self,
+        index: usize,
+        thresholds: &[Threshold],
+        value: Option<f64>,
+        now: Instant,
+    ) -> Triggered {
+        let previous = self.triggered.remove(&index).unwrap_or_default();
+        let Some(value) = value else {
+            return Triggered {
+                threshold: None,
+                urgent: false,
+            };
+        };
+
+        let triggered: Vec<bool> = thresholds
+            .iter()
+            .enumerate()
+            .map(|(i, threshold)| match previous.get(i) {
+                Some(true) => threshold.stays_triggered_by(value),
+                _ => threshold.triggered_by(value),
+            })
+            .collect();
+        let mut result = Triggered {
+            threshold: triggered.iter().rposition(|x| *x),
+            urgent: thresholds
+                .iter()
+                .zip(&triggered)
+                .any(|(x, y)| x.urgent && *y),
+        };
+        self.triggered.insert(index, triggered);
+
+        if let (true, Some(remaining)) = (result.urgent, self.grace_remaining(now)) {
+            debug!(
+                "Section {} would be urgent, holding back for another {:.1}s of startup_grace_secs",
+                index + 1,
+                remaining.as_secs_f64()
+            );
+            result.urgent = false;
+        }
+
+        result
+    }
+
+    /// Sets `startup_grace_secs`, still counted from when stringbar started.
+    pub fn set_grace(&mut self, grace: Duration) {
+        self.grace = grace;
+    }
+
+    /// What's left of the startup grace period at `now`, `None` once it's over.
+    pub fn grace_remaining(&self, now: Instant) -> Option<Duration> {
+        (self.started + self.grace)
+            .checked_duration_since(now)
+            .filter(|x| !x.is_zero())
+    }
+
+    /// Makes a section urgent until cleared again, whatever its thresholds say. `section` is
+    /// its id or its number counting from 1.
+    pub fn force(&mut self, section: &str, urgent: bool) {
+        if urgent {
+            self.forced.insert(section.into());
+        } else {
+            self.forced.remove(section);
+        }
+    }
+
+    pub fn is_forced(&self, index: usize, section: &Section) -> bool {
+        section.id.as_ref().is_some_and(|x| self.forced.contains(x))
+            || self.forced.contains(&(index + 1).to_string())
+    }
+
+    /// Forgets the threshold state, whose indices belong to the old section list after a reload.
+    pub fn reset_thresholds(&mut self) {
+        self.triggered.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::{Clock, FakeClock},
+        config::{Decoration, Module, ThresholdDirection},
+    };
+
+    fn threshold(value: f64, direction: ThresholdDirection, clear_at: Option<f64>) -> Threshold {
+        Threshold {
+            value,
+            direction,
+            decoration: Decoration::default(),
+            urgent: true,
+            clear_at,
+        }
+    }
+
+    #[test]
+    fn auto_clear() {
+        let mut urgency = Urgency::default();
+        let thresholds = [
+            Threshold {
+                urgent: false,
+                ..threshold(60.0, ThresholdDirection::Above, None)
+            },
+            threshold(80.0, ThresholdDirection::Above, Some(70.0)),
+        ];
+        let now = Instant::now();
+        let mut check = |value| urgency.check(0, &thresholds, value, now);
+
+        let cases = [
+            (Some(50.0), None, false),
+            (Some(65.0), Some(0), false),
+            (Some(80.0), Some(1), true),
+            // Held by clear_at until the value drops to it.
+            (Some(75.0), Some(1), true),
+            (Some(70.5), Some(1), true),
+            (Some(70.0), Some(0), false),
+            (Some(75.0), Some(0), false),
+            (Some(90.0), Some(1), true),
+            // Without a value nothing is triggered, and the next one starts over.
+            (None, None, false),
+            (Some(75.0), Some(0), false),
+        ];
+        for (value, threshold, urgent) in cases {
+            assert_eq!(Triggered { threshold, urgent }, check(value), "{value:?}");
+        }
+
+        // Falling values work the same, e.g. battery charge.
+        let thresholds = [threshold(10.0, ThresholdDirection::Below, Some(15.0))];
+        let urgent: Vec<_> = [20.0, 10.0, 12.0, 15.0, 12.0]
+            .into_iter()
+            .map(|x| urgency.check(1, &thresholds, Some(x), now).urgent)
+            .collect();
+        assert_eq!(vec![false, true, true, false, false], urgent);
+    }
+
+    #[test]
+    fn startup_grace() {
+        let clock = FakeClock::new("2024-03-31T08:00:00+02:00");
+        let mut urgency = Urgency {
+            started: clock.monotonic(),
+            ..Urgency::default()
+        };
+        urgency.set_grace(Duration::from_secs(30));
+        let thresholds = [threshold(80.0, ThresholdDirection::Above, None)];
+        let mut check = |value| urgency.check(0, &thresholds, Some(value), clock.monotonic());
+
+        // Thresholds are still evaluated, only urgency waits.
+        assert_eq!(
+            Triggered {
+                threshold: Some(0),
+                urgent: false
+            },
+            check(95.0)
+        );
+        clock.advance(Duration::from_secs(30) - Duration::from_nanos(1));
+        assert!(!check(95.0).urgent);
+        clock.advance(Duration::from_nanos(1));
+        assert!(check(95.0).urgent);
+
+        // A reload lengthening it within the window keeps counting from the start.
+        let clock = FakeClock::new("2024-03-31T08:00:00+02:00");
+        let mut urgency = Urgency {
+            started: clock.monotonic(),
+            ..Urgency::default()
+        };
+        urgency.set_grace(Duration::from_secs(30));
+        clock.advance(Duration::from_secs(18));
+        assert_eq!(
+            Some(Duration::from_secs(12)),
+            urgency.grace_remaining(clock.monotonic())
+        );
+        urgency.reset_thresholds();
+        urgency.set_grace(Duration::from_secs(20));
+        assert_eq!(
+            Some(Duration::from_secs(2)),
+            urgency.grace_remaining(clock.monotonic())
+        );
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(None, urgency.grace_remaining(clock.monotonic()));
+
+        // Without one, urgency is there from the start.
+        urgency.set_grace(Duration::ZERO);
+        assert!(
+            urgency
+                .check(0, &thresholds, Some(95.0), urgency.started)
+                .urgent
+        );
+    }
+
+    #[test]
+    fn forced() {
+        let mut urgency = Urgency::default();
+        let section = Section {
+            id: Some("cpu".into()),
+            ..Module::CpuUsage.into()
+        };
+
+        assert!(!urgency.is_forced(2, &section));
+        urgency.force("cpu", true);
+        assert!(urgency.is_forced(2, &section));
+        urgency.force("cpu", false);
+        urgency.force("3", true);
+        assert!(urgency.is_forced(2, &section));
+        assert!(!urgency.is_forced(1, &section));
+
+        // Survives the threshold state being reset on reload.
+        urgency.reset_thresholds();
+        assert!(urgency.is_forced(2, &section));
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use crate::config::Module;
+
+/// Last in-range output of each module, shown in place of values that can't be right.
+///
+/// Keyed by module like [`Extremes`](crate::extremes::Extremes), so identical modules share
+/// their last valid value.
+#[derive(Default)]
+pub struct Validity(HashMap<Module, (String, f64)>);
+
+pub enum Checked {
+    Valid(String, f64),
+    /// The value was out of range. Holds the last valid output with the marker appended and its
+    /// value, or the rejected output with the marker if there was no valid one yet.
+    Replaced(String, Option<f64>),
+}
+
+impl Validity {
+    /// Passes `output` through if `value` lies within `range`, bounds included, and remembers it.
+    pub fn check(
+        &mut self,
+        module: &Module,
+        range: (f64, f64),
+        output: String,
+        value: f64,
+        marker: &str,
+    ) -> Checked {
+        if value >= range.0 && value <= range.1 {
+            self.0.insert(module.clone(), (output.clone(), value));
+            return Checked::Valid(output, value);
+        }
+
+        match self.0.get(module) {
+            Some((last, last_value)) => {
+                Checked::Replaced(format!("{last}{marker}"), Some(*last_value))
+            }
+            None => Checked::Replaced(format!("{output}{marker}"), None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spikes() {
+        let mut validity = Validity::default();
+        let cpu = Module::CpuUsage;
+        let mut check = |value: f64| match validity.check(
+            &cpu,
+            (0.0, 100.0),
+            format!("{value}%"),
+            value,
+            "?",
+        ) {
+            Checked::Valid(x, _) => x,
+            Checked::Replaced(x, _) => format!("replaced {x}"),
+        };
+
+        // Nothing valid to fall back to yet.
+        assert_eq!("replaced 400%?", check(400.0));
+
+        let sequence = [
+            (12.0, "12%"),
+            (400.0, "replaced 12%?"),
+            (f64::NAN, "replaced 12%?"),
+            (-1.0, "replaced 12%?"),
+            (100.0, "100%"),
+            (100.5, "replaced 100%?"),
+            (0.0, "0%"),
+        ];
+        for (value, expected) in sequence {
+            assert_eq!(expected, check(value), "{value}");
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! `stringbar --version`, which with `--json` also lists the modules and output backends this
+//! build supports, for bug reports and scripts.
+
+use serde::{
+    de::{self, value, Visitor},
+    forward_to_deserialize_any, Deserialize, Deserializer,
+};
+
+use crate::{
+    config::{Module, OutputBackend},
+    text::json_string,
+};
+
+const USAGE: &str = "usage: stringbar --version [--json]";
+
+/// Short hash of the commit this was built from, empty outside of a git checkout.
+const COMMIT: &str = env!("STRINGBAR_COMMIT");
+/// Enabled cargo features, comma separated.
+const FEATURES: &str = env!("STRINGBAR_FEATURES");
+
+/// Handles `stringbar --version <args>`. Returns a message for the user on failure.
+pub fn run(args: &[String]) -> Result<(), String> {
+    match args {
+        [] => println!("{}", text()),
+        [x] if x == "--json" => println!("{}", json()),
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+/// e.g. "stringbar 0.2.0 (d328f3791f20)".
+fn text() -> String {
+    match COMMIT {
+        "" => format!("stringbar {}", env!("CARGO_PKG_VERSION")),
+        commit => format!("stringbar {} ({commit})", env!("CARGO_PKG_VERSION")),
+    }
+}
+
+fn json() -> String {
+    let list = |items: &[&str]| {
+        let items: Vec<_> = items.iter().map(|x| json_string(x)).collect();
+        format!("[{}]", items.join(","))
+    };
+    let features: Vec<_> = FEATURES.split(',').filter(|x| !x.is_empty()).collect();
+    let commit = match COMMIT {
+        "" => "null".into(),
+        commit => json_string(commit),
+    };
+
+    format!(
+        "{{\"version\":{},\"commit\":{commit},\"features\":{},\"modules\":{},\"output_backends\":{}}}",
+        json_string(env!("CARGO_PKG_VERSION")),
+        list(&features),
+        list(variants::<Module>()),
+        list(variants::<OutputBackend>()),
+    )
+}
+
+/// Names of the variants of enum `T` as the config spells them, straight from its
+/// `Deserialize` implementation so variants left out of a build are left out here too.
+pub fn variants<T: for<'de> Deserialize<'de>>() -> &'static [&'static str] {
+    let mut names: &'static [&'static str] = &[];
+    let _ = T::deserialize(VariantProbe(&mut names));
+    names
+}
+
+/// A deserializer that only records the variant names it's asked for and gives up.
+struct VariantProbe<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for VariantProbe<'_> {
+    type Error = value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not an enum"))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        variants: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = variants;
+        Err(de::Error::custom("only listing variants"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        AddrFamily, DiskFilter, FreqAggregate, SortKey, TempAggregate, UptimeFormat,
+    };
+    use crate::unit::Unit;
+
+    #[test]
+    fn inventory() {
+        // One of every variant, so adding one without it showing up in the inventory fails.
+        let modules = [
+            Module::Timestamp {
+                template: "%H:%M".into(),
+            },
+            Module::MemoryUsage { basis: None },
+            Module::SwapUsage,
+            Module::CpuUsage,
+            Module::CpuFrequency {
+                aggregate: FreqAggregate::Average,
+            },
+            Module::ProcessCount,
+            Module::TopProcess {
+                by: SortKey::Cpu,
+                max_len: None,
+                exclude_kernel_threads: false,
+            },
+            Module::LoadAverage,
+            Module::DiskUsage {
+                name: "/".into(),
+                inodes: false,
+            },
+            Module::DiskUsageTotal {
+                include_removables: false,
+            },
+            Module::DiskUsageEach {
+                filter: DiskFilter::default(),
+                separator: None,
+                inodes: false,
+            },
+            Module::Battery {
+                name: None,
+                hide_when_missing: false,
+                limit_marker: None,
+                relative_to_limit: false,
+            },
+            Module::BatteryEach { separator: None },
+            Module::NetworkEach {
+                exclude_loopback: false,
+                separator: None,
+            },
+            Module::Temperature {
+                component: "coretemp".into(),
+                fahrenheit: false,
+                aggregate: TempAggregate::First,
+                warn_above: None,
+                clear_below: None,
+            },
+            Module::Uptime {
+                format: UptimeFormat::Compact,
+            },
+            Module::Brightness { device: None },
+            Module::KeyboardLayout,
+            Module::Volume { control: None },
+            Module::Command {
+                command: "date".into(),
+                args: Vec::new(),
+                timeout_ms: None,
+                fallback: None,
+                join_lines: None,
+            },
+            Module::Derived {
+                expression: "1".into(),
+                unit: Unit::Count,
+            },
+            Module::PublicIp {
+                url: String::new(),
+                refresh_interval_s: 3600.into(),
+            },
+            Module::IpAddress {
+                interface: "eth0".into(),
+                family: AddrFamily::Any,
+                max_addresses: 1,
+                include_link_local: false,
+                separator: None,
+                missing_text: None,
+            },
+            Module::Timer {
+                name: "a".into(),
+                expired_text: None,
+            },
+            Module::Mpd {
+                host: String::new(),
+                port: 6600,
+                format: String::new(),
+                max_len: None,
+            },
+            Module::Maildir {
+                path: String::new(),
+            },
+            Module::Bluetooth { device_mac: None },
+            Module::SystemdFailed,
+            Module::BackendHealth,
+            Module::NetworkThroughput {
+                interface: "eth0".into(),
+                average_over_s: None,
+                peak_hold_s: None,
+                min_unit: None,
+                max_unit: None,
+                show_totals: false,
+                reset_signal: None,
+            },
+            Module::Separator {
+                content: " ".into(),
+                fg: None,
+            },
+        ];
+        let inventory = variants::<Module>();
+        for module in &modules {
+            assert!(inventory.contains(&module.name()), "{}", module.name());
+        }
+        assert_eq!(modules.len(), inventory.len());
+
+        assert_eq!(
+            &["Xsetroot", "I3bar", "Stdout", "File", "None", "Auto"],
+            variants::<OutputBackend>()
+        );
+    }
+
+    #[test]
+    fn json_output() {
+        let json = json();
+        assert!(json.starts_with(&format!("{{\"version\":\"{}\",", env!("CARGO_PKG_VERSION"))));
+        assert!(json.contains(",\"modules\":[\"Timestamp\",\"MemoryUsage\","));
+        assert!(json.ends_with(
+            ",\"output_backends\":[\"Xsetroot\",\"I3bar\",\"Stdout\",\"File\",\"None\",\"Auto\"]}"
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+//! Deduplication for log messages that would otherwise repeat on every update.
+//!
+//! Use [`warn_once!`](crate::warn_once) and [`warn_rate_limited!`](crate::warn_rate_limited)
+//! with a key that includes the section's identity, so that two sections hitting
+//! the same problem each get their own message.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+static LIMITER: LazyLock<Mutex<RateLimiter>> = LazyLock::new(Default::default);
+
+#[derive(Default)]
+pub struct RateLimiter {
+    last_emitted: HashMap<String, Instant>,
+}
+
+impl RateLimiter {
+    /// Returns whether a message for `key` may be emitted at `now`, and records it if so.
+    /// A `period` of `None` allows only one message per key until [`reset`](Self::reset).
+    pub fn should_emit(&mut self, key: &str, period: Option<Duration>, now: Instant) -> bool {
+        let allowed = match (self.last_emitted.get(key), period) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(last), Some(period)) => now.saturating_duration_since(*last) >= period,
+        };
+
+        if allowed {
+            self.last_emitted.insert(key.to_string(), now);
+        }
+
+        allowed
+    }
+
+    pub fn reset(&mut self) {
+        self.last_emitted.clear();
+    }
+}
+
+pub fn should_emit(key: &str, period: Option<Duration>) -> bool {
+    LIMITER
+        .lock()
+        .unwrap()
+        .should_emit(key, period, Instant::now())
+}
+
+/// Forgets all emitted keys, so that problems still present after a config reload warn again.
+pub fn reset() {
+    LIMITER.lock().unwrap().reset();
+}
+
+/// Logs a warning once per key until [`reset`](crate::warn::reset) is called.
+/// Section warnings that keep coming up are also reported by [`notify`](crate::notify).
+#[macro_export]
+macro_rules! warn_once {
+    ($key:expr, $($arg:tt)+) => {
+        {
+            let key = $key;
+            if $crate::warn::should_emit(&key, None) {
+                tracing::warn!($($arg)+);
+            }
+            $crate::notify::section_warning(&key, || format!($($arg)+));
+        }
+    };
+}
+
+/// Logs a warning at most once per `period` for each key.
+/// Section warnings that keep coming up are also reported by [`notify`](crate::notify).
+#[macro_export]
+macro_rules! warn_rate_limited {
+    ($key:expr, $period:expr, $($arg:tt)+) => {
+        {
+            let key = $key;
+            if $crate::warn::should_emit(&key, Some($period)) {
+                tracing::warn!($($arg)+);
+            }
+            $crate::notify::section_warning(&key, || format!($($arg)+));
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn once() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::default();
+
+        assert!(limiter.should_emit("0:disk", None, start));
+        assert!(!limiter.should_emit("0:disk", None, start + Duration::from_secs(3600)));
+        assert!(limiter.should_emit("1:disk", None, start));
+
+        limiter.reset();
+        assert!(limiter.should_emit("0:disk", None, start + Duration::from_secs(3601)));
+    }
+
+    #[test]
+    fn rate_limited() {
+        let start = Instant::now();
+        let period = Some(Duration::from_secs(60));
+        let mut limiter = RateLimiter::default();
+
+        assert!(limiter.should_emit("key", period, start));
+        assert!(!limiter.should_emit("key", period, start + Duration::from_secs(1)));
+        assert!(!limiter.should_emit("key", period, start + Duration::from_secs(59)));
+        assert!(limiter.should_emit("key", period, start + Duration::from_secs(60)));
+        assert!(!limiter.should_emit("key", period, start + Duration::from_secs(119)));
+        assert!(limiter.should_emit("other", period, start + Duration::from_secs(119)));
+    }
+}
@@ -0,0 +1,673 @@
+//! Config file watching that copes with symlinks, e.g. a config directory
+//! linked into a dotfiles repository, with polling for filesystems where notifications
+//! don't work. Notifications also cover the paths sections with `watch` read.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::{error, info};
+
+use crate::sha256;
+
+/// Used when notifications can't be set up and no polling interval was configured.
+pub const FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How long the config has to stay unchanged before it's reloaded, so that a burst of writes,
+/// e.g. format-on-save right after saving, only reloads the final file.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// What a change is to, see [`spawn`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Trigger {
+    Config,
+    /// A path the section at this index reads.
+    Section(usize),
+}
+
+/// The directories to watch for a config file and the paths within them that count as the config,
+/// along with what the sections with `watch` read.
+#[derive(Debug, PartialEq)]
+struct Targets {
+    dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+    /// Symlinked directories on the way to the config, e.g. `~/.config/stringbar`. Their
+    /// parents are in `dirs`, so retargeting one counts as a config change.
+    links: Vec<PathBuf>,
+    sections: Vec<SectionTarget>,
+}
+
+/// A path a section reads. Directories are watched themselves and any change within them
+/// counts, files through the directory they're in like the config.
+#[derive(Debug, PartialEq)]
+struct SectionTarget {
+    index: usize,
+    dir: PathBuf,
+    file: Option<PathBuf>,
+}
+
+impl SectionTarget {
+    fn new(index: usize, path: PathBuf) -> Option<Self> {
+        if path.is_dir() {
+            return Some(Self {
+                index,
+                dir: path,
+                file: None,
+            });
+        }
+
+        Some(Self {
+            index,
+            dir: path.parent()?.to_path_buf(),
+            file: Some(path),
+        })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        match &self.file {
+            Some(file) => file == path,
+            None => path == self.dir || path.parent() == Some(&self.dir),
+        }
+    }
+}
+
+impl Targets {
+    /// Watches the directory the config path is in as well as the one its symlinks resolve to,
+    /// and the directories symlinked directories on the way are in. Watching directories
+    /// rather than the file keeps working when editors or `git checkout` replace the file
+    /// instead of writing to it. `sections` are the paths sections read, by section index.
+    fn resolve(config_path: &Path, sections: Vec<(usize, PathBuf)>) -> Self {
+        let mut targets = Self {
+            dirs: Vec::new(),
+            files: Vec::new(),
+            links: Vec::new(),
+            sections: sections
+                .into_iter()
+                .filter_map(|(index, path)| SectionTarget::new(index, path))
+                .collect(),
+        };
+
+        let mut add = |file: PathBuf| {
+            if let Some(dir) = file.parent() {
+                if !targets.dirs.iter().any(|x| x == dir) {
+                    targets.dirs.push(dir.to_path_buf());
+                }
+            }
+            if !targets.files.contains(&file) {
+                targets.files.push(file);
+            }
+        };
+
+        add(config_path.to_path_buf());
+        if let Ok(canonical) = config_path.canonicalize() {
+            add(canonical);
+        }
+
+        for link in config_path.ancestors().skip(1) {
+            if !link.symlink_metadata().is_ok_and(|x| x.is_symlink()) {
+                continue;
+            }
+            if let Some(dir) = link.parent() {
+                if !targets.dirs.iter().any(|x| x == dir) {
+                    targets.dirs.push(dir.to_path_buf());
+                }
+            }
+            targets.links.push(link.to_path_buf());
+        }
+
+        targets
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        self.files.iter().any(|x| x == path)
+            || self.links.iter().any(|x| x == path)
+            || path
+                .canonicalize()
+                .is_ok_and(|path| self.files.contains(&path))
+    }
+
+    /// What a change of `kind` to `path` triggers.
+    fn triggers(&self, kind: &EventKind, path: &Path) -> Vec<Trigger> {
+        let mut triggers = Vec::new();
+        // A deletion is usually followed by a create when a file is replaced,
+        // and reloading in between would only fail.
+        if matches!(kind, EventKind::Create(_) | EventKind::Modify(_)) && self.matches(path) {
+            triggers.push(Trigger::Config);
+        }
+        // Sections read whatever is there, a deletion included, e.g. mail moved out of `new`.
+        if matches!(
+            kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            triggers.extend(
+                self.sections
+                    .iter()
+                    .filter(|x| x.matches(path))
+                    .map(|x| Trigger::Section(x.index)),
+            );
+        }
+
+        triggers
+    }
+
+    /// Every directory to watch, the config's first.
+    fn all_dirs(&self) -> Vec<&PathBuf> {
+        let mut dirs: Vec<_> = self.dirs.iter().collect();
+        for section in &self.sections {
+            if !dirs.contains(&&section.dir) {
+                dirs.push(&section.dir);
+            }
+        }
+        dirs
+    }
+}
+
+/// Calls `on_change` from a background thread with [`Trigger::Config`] whenever the config
+/// file at `config_path` is modified, replaced or retargeted through a symlink, and with
+/// [`Trigger::Section`] whenever a path from `sections` changes. `sections` is asked again
+/// after every config change, so it can follow the reloaded config.
+///
+/// Paths of sections that can't be watched, e.g. because they don't exist yet, are logged
+/// and tried again after the next config change.
+pub fn spawn(
+    config_path: PathBuf,
+    sections: impl Fn() -> Vec<(usize, PathBuf)> + Send + 'static,
+    on_change: impl Fn(Trigger) + Send + 'static,
+) -> notify::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = RecommendedWatcher::new(tx, notify::Config::default())?;
+
+    let mut targets = Targets::resolve(&config_path, sections());
+    for dir in &targets.dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+    watch_sections(&mut watcher, &targets);
+
+    thread::spawn(move || {
+        let relevant =
+            |result: notify::Result<Event>, targets: &Targets, fired: &mut BTreeSet<Trigger>| {
+                let event = match result {
+                    Ok(x) => x,
+                    Err(e) => {
+                        error!("Config watcher error: {e}");
+                        return false;
+                    }
+                };
+
+                let before = fired.len();
+                for path in &event.paths {
+                    fired.extend(targets.triggers(&event.kind, path));
+                }
+                fired.len() > before
+            };
+
+        loop {
+            let Ok(result) = rx.recv() else { return };
+            let mut fired = BTreeSet::new();
+            if !relevant(result, &targets, &mut fired)
+                || !settle(&rx, DEBOUNCE, |result| {
+                    relevant(result, &targets, &mut fired)
+                })
+            {
+                continue;
+            }
+
+            if !fired.contains(&Trigger::Config) {
+                for trigger in fired {
+                    on_change(trigger);
+                }
+                continue;
+            }
+
+            // A reload updates every section anyway.
+            on_change(Trigger::Config);
+
+            let new_targets = Targets::resolve(&config_path, sections());
+            // A retargeted directory symlink keeps its path, so all of them are watched anew
+            // rather than only the ones that differ.
+            let retargeted = new_targets.dirs != targets.dirs;
+            if retargeted {
+                info!("Config symlink target changed, updating watches.");
+            }
+            let new_dirs = new_targets.all_dirs();
+            for dir in targets
+                .all_dirs()
+                .into_iter()
+                .filter(|x| retargeted || !new_dirs.contains(x))
+            {
+                let _ = watcher.unwatch(dir);
+            }
+            let old_dirs = targets.all_dirs();
+            for dir in new_targets
+                .dirs
+                .iter()
+                .filter(|x| retargeted || !old_dirs.contains(x))
+            {
+                if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    error!("Unable to watch {}: {e}", dir.display());
+                }
+            }
+            // Again even if unchanged, in case they were removed and have come back since.
+            watch_sections(&mut watcher, &new_targets);
+            targets = new_targets;
+        }
+    });
+
+    Ok(())
+}
+
+/// Watches the directories of `targets`' sections, logging the ones that can't be watched.
+fn watch_sections(watcher: &mut RecommendedWatcher, targets: &Targets) {
+    for section in &targets.sections {
+        if targets.dirs.contains(&section.dir) {
+            continue;
+        }
+        if let Err(e) = watcher.watch(&section.dir, RecursiveMode::NonRecursive) {
+            error!(
+                "Section {}: unable to watch {}: {e}",
+                section.index + 1,
+                section.dir.display()
+            );
+        }
+    }
+}
+
+/// Consumes messages from `rx` until none that `relevant` accepts arrived for `quiet`.
+/// Returns false if the channel was closed.
+fn settle<T>(rx: &Receiver<T>, quiet: Duration, mut relevant: impl FnMut(T) -> bool) -> bool {
+    let mut deadline = Instant::now() + quiet;
+    loop {
+        match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(x) => {
+                if relevant(x) {
+                    deadline = Instant::now() + quiet;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => return true,
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+}
+
+/// What identifies a version of the config file. Any difference counts as a change.
+#[derive(PartialEq)]
+struct Fingerprint {
+    modified: Option<SystemTime>,
+    size: u64,
+    sha256: String,
+}
+
+impl Fingerprint {
+    /// Follows symlinks, so retargeting one counts as a change. `None` if the file can't be read.
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let contents = std::fs::read(path).ok()?;
+
+        Some(Self {
+            modified: metadata.modified().ok(),
+            size: metadata.len(),
+            sha256: sha256::hex_digest(&contents),
+        })
+    }
+}
+
+/// Calls `on_change` from a background thread whenever the config file at `config_path`
+/// differs from the last check, checking every `interval`.
+pub fn spawn_polling(
+    config_path: PathBuf,
+    interval: Duration,
+    on_change: impl Fn() + Send + 'static,
+) {
+    thread::spawn(move || {
+        let mut last = Fingerprint::read(&config_path);
+        loop {
+            thread::sleep(interval);
+
+            // Like with notifications, a missing file is waited out instead of reloaded.
+            let Some(mut current) = Fingerprint::read(&config_path) else {
+                continue;
+            };
+            if last.as_ref() == Some(&current) {
+                continue;
+            }
+
+            // Wait for the file to settle, as with notifications.
+            loop {
+                thread::sleep(DEBOUNCE);
+                match Fingerprint::read(&config_path) {
+                    Some(x) if x == current => break,
+                    Some(x) => current = x,
+                    None => {}
+                }
+            }
+            last = Some(current);
+            on_change();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::fs::symlink,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stringbar-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn watch(config_path: PathBuf) -> Receiver<()> {
+        let (tx, rx) = mpsc::channel();
+        spawn(config_path, Vec::new, move |_| {
+            let _ = tx.send(());
+        })
+        .unwrap();
+        rx
+    }
+
+    /// Waits for a change notification, then swallows the duplicates a single write may cause.
+    fn changed(rx: &Receiver<()>) -> bool {
+        let changed = rx.recv_timeout(Duration::from_secs(5)).is_ok();
+        while rx.recv_timeout(Duration::from_millis(200)).is_ok() {}
+        changed
+    }
+
+    fn unchanged(rx: &Receiver<()>) -> bool {
+        rx.recv_timeout(Duration::from_millis(500)).is_err()
+    }
+
+    #[test]
+    fn settles() {
+        let (tx, rx) = mpsc::channel();
+        let start = Instant::now();
+        thread::spawn(move || {
+            for event in ["relevant", "relevant", "irrelevant", "irrelevant"] {
+                tx.send(event).unwrap();
+                thread::sleep(Duration::from_millis(100));
+            }
+            thread::sleep(Duration::from_millis(500));
+        });
+
+        let mut seen = Vec::new();
+        assert!(settle(&rx, Duration::from_millis(250), |x| {
+            seen.push(x);
+            x == "relevant"
+        }));
+        // Quiet since the last relevant message at 100ms, the irrelevant ones don't count.
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(350), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(500), "{elapsed:?}");
+        assert_eq!(
+            vec!["relevant", "relevant", "irrelevant", "irrelevant"],
+            seen
+        );
+
+        // The sender is gone after its final sleep.
+        assert!(!settle(&rx, Duration::from_secs(5), |_| true));
+    }
+
+    #[test]
+    fn coalesces_rapid_writes() {
+        let root = temp_dir("watch-coalesce").canonicalize().unwrap();
+        let path = root.join("config.ron");
+        std::fs::write(&path, "(a: 0)").unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        {
+            let path = path.clone();
+            spawn(path.clone(), Vec::new, move |_| {
+                let _ = tx.send(std::fs::read_to_string(&path).unwrap());
+            })
+            .unwrap();
+        }
+
+        // A save, a half written file and the formatter's rewrite in quick succession.
+        for contents in ["(a: 1)", "(a:", "(a: 2)"] {
+            std::fs::write(&path, contents).unwrap();
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        assert_eq!(
+            Ok("(a: 2)".to_string()),
+            rx.recv_timeout(Duration::from_secs(5))
+        );
+        assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn polling() {
+        let root = temp_dir("watch-poll").canonicalize().unwrap();
+        let path = root.join("config.ron");
+        std::fs::write(&path, "(a: 1)").unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        spawn_polling(path.clone(), Duration::from_millis(50), move || {
+            let _ = tx.send(());
+        });
+        assert!(unchanged(&rx));
+
+        // Only the modification time changes.
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(changed(&rx));
+
+        // Same size, and the modification time is restored.
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::fs::write(&path, "(a: 2)").unwrap();
+        file.set_modified(modified).unwrap();
+        assert!(changed(&rx));
+
+        // Waiting out a missing file doesn't count as a change.
+        std::fs::remove_file(&path).unwrap();
+        assert!(unchanged(&rx));
+        std::fs::write(&path, "(a: 2)").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert!(unchanged(&rx));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn resolves_symlinked_directory() {
+        let root = temp_dir("watch-resolve");
+        std::fs::create_dir(root.join("dotfiles")).unwrap();
+        std::fs::write(root.join("dotfiles/config.ron"), "()").unwrap();
+        symlink(root.join("dotfiles"), root.join("stringbar")).unwrap();
+
+        let root = root.canonicalize().unwrap();
+        let targets = Targets::resolve(&root.join("stringbar/config.ron"), Vec::new());
+        assert_eq!(
+            vec![root.join("stringbar"), root.join("dotfiles"), root.clone()],
+            targets.dirs
+        );
+        assert!(targets.matches(&root.join("dotfiles/config.ron")));
+        assert!(targets.matches(&root.join("stringbar/config.ron")));
+        assert!(targets.matches(&root.join("stringbar")));
+        assert!(!targets.matches(&root.join("dotfiles")));
+        assert!(!targets.matches(&root.join("dotfiles/other.ron")));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn reloads_after_rename_over_symlink_target() {
+        let root = temp_dir("watch-rename").canonicalize().unwrap();
+        std::fs::create_dir(root.join("dotfiles")).unwrap();
+        std::fs::write(root.join("dotfiles/config.ron"), "()").unwrap();
+        symlink(root.join("dotfiles"), root.join("stringbar")).unwrap();
+
+        let rx = watch(root.join("stringbar/config.ron"));
+
+        // what git checkout and many editors do: write elsewhere, then rename over the target
+        std::fs::write(root.join("dotfiles/.config.ron.tmp"), "(a: 1)").unwrap();
+        std::fs::rename(
+            root.join("dotfiles/.config.ron.tmp"),
+            root.join("dotfiles/config.ron"),
+        )
+        .unwrap();
+        assert!(changed(&rx));
+
+        std::fs::remove_file(root.join("dotfiles/config.ron")).unwrap();
+        std::fs::write(root.join("dotfiles/config.ron"), "(a: 2)").unwrap();
+        assert!(changed(&rx));
+
+        std::fs::write(root.join("dotfiles/unrelated.ron"), "()").unwrap();
+        assert!(unchanged(&rx));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn follows_retargeted_file_symlink() {
+        let root = temp_dir("watch-retarget").canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("config")).unwrap();
+        std::fs::create_dir_all(root.join("a")).unwrap();
+        std::fs::create_dir_all(root.join("b")).unwrap();
+        std::fs::write(root.join("a/bar.ron"), "()").unwrap();
+        std::fs::write(root.join("b/bar.ron"), "()").unwrap();
+        symlink(root.join("a/bar.ron"), root.join("config/config.ron")).unwrap();
+
+        let rx = watch(root.join("config/config.ron"));
+
+        symlink(root.join("b/bar.ron"), root.join("config/new.ron")).unwrap();
+        std::fs::rename(root.join("config/new.ron"), root.join("config/config.ron")).unwrap();
+        assert!(changed(&rx));
+
+        // edits to the new target reload, edits to the old one don't
+        std::fs::write(root.join("b/bar.ron"), "(b: 1)").unwrap();
+        assert!(changed(&rx));
+        std::fs::write(root.join("a/bar.ron"), "(a: 1)").unwrap();
+        assert!(unchanged(&rx));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn follows_retargeted_directory_symlink() {
+        let root = temp_dir("watch-retarget-dir").canonicalize().unwrap();
+        for dir in ["config", "dotfiles/a", "dotfiles/b"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::write(root.join("dotfiles/a/config.ron"), "()").unwrap();
+        std::fs::write(root.join("dotfiles/b/config.ron"), "()").unwrap();
+        symlink(root.join("dotfiles/a"), root.join("config/stringbar")).unwrap();
+
+        let rx = watch(root.join("config/stringbar/config.ron"));
+
+        // what `ln -sfn dotfiles/b ~/.config/stringbar` amounts to
+        symlink(root.join("dotfiles/b"), root.join("config/new")).unwrap();
+        std::fs::rename(root.join("config/new"), root.join("config/stringbar")).unwrap();
+        assert!(changed(&rx));
+
+        std::fs::write(root.join("dotfiles/b/config.ron"), "(b: 1)").unwrap();
+        assert!(changed(&rx));
+        std::fs::write(root.join("dotfiles/a/config.ron"), "(a: 1)").unwrap();
+        assert!(unchanged(&rx));
+        std::fs::write(root.join("config/unrelated.ron"), "()").unwrap();
+        assert!(unchanged(&rx));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn section_targets() {
+        let root = temp_dir("watch-section-targets").canonicalize().unwrap();
+        std::fs::create_dir(root.join("new")).unwrap();
+        let targets = Targets::resolve(
+            &root.join("config.ron"),
+            vec![(0, root.join("new")), (2, root.join("status"))],
+        );
+        assert_eq!(vec![&root, &root.join("new")], targets.all_dirs());
+
+        let create = EventKind::Create(notify::event::CreateKind::File);
+        let remove = EventKind::Remove(notify::event::RemoveKind::File);
+        let cases = [
+            (&create, root.join("new/1.mail"), vec![Trigger::Section(0)]),
+            (&remove, root.join("new/1.mail"), vec![Trigger::Section(0)]),
+            (&create, root.join("status"), vec![Trigger::Section(2)]),
+            (&create, root.join("config.ron"), vec![Trigger::Config]),
+            (&remove, root.join("config.ron"), vec![]),
+            (&create, root.join("other"), vec![]),
+            (&create, root.join("new/tmp/1.mail"), vec![]),
+        ];
+        for (kind, path, expected) in cases {
+            assert_eq!(expected, targets.triggers(kind, &path), "{path:?}");
+        }
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn watches_section_paths_from_reloads() {
+        let root = temp_dir("watch-sections").canonicalize().unwrap();
+        let path = root.join("config.ron");
+        std::fs::write(&path, "()").unwrap();
+        std::fs::create_dir(root.join("mail")).unwrap();
+
+        // Stands in for the reloaded config's watched paths.
+        let watched = Arc::new(Mutex::new(Vec::new()));
+        let (tx, rx) = mpsc::channel();
+        {
+            let watched = watched.clone();
+            spawn(
+                path.clone(),
+                move || watched.lock().unwrap().clone(),
+                move |trigger| {
+                    let _ = tx.send(trigger);
+                },
+            )
+            .unwrap();
+        }
+        let next = || {
+            let trigger = rx.recv_timeout(Duration::from_secs(5)).ok();
+            while rx.recv_timeout(Duration::from_millis(200)).is_ok() {}
+            trigger
+        };
+
+        // Not watched before the config says so.
+        std::fs::write(root.join("mail/1"), "").unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+
+        *watched.lock().unwrap() = vec![(1, root.join("mail"))];
+        std::fs::write(&path, "(a: 1)").unwrap();
+        assert_eq!(Some(Trigger::Config), next());
+
+        std::fs::write(root.join("mail/2"), "").unwrap();
+        assert_eq!(Some(Trigger::Section(1)), next());
+        std::fs::remove_file(root.join("mail/1")).unwrap();
+        assert_eq!(Some(Trigger::Section(1)), next());
+
+        // Removing the watched directory doesn't stop config changes from coming through,
+        // and it's watched again once it's back and the config changed.
+        std::fs::remove_dir_all(root.join("mail")).unwrap();
+        next();
+        std::fs::write(&path, "(a: 2)").unwrap();
+        assert_eq!(Some(Trigger::Config), next());
+        std::fs::create_dir(root.join("mail")).unwrap();
+        std::fs::write(&path, "(a: 3)").unwrap();
+        assert_eq!(Some(Trigger::Config), next());
+        std::fs::write(root.join("mail/3"), "").unwrap();
+        assert_eq!(Some(Trigger::Section(1)), next());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,736 @@
+//! Just enough of the X11 protocol to set the root window's name, which dwm and similar window
+//! managers show as their status text, without spawning xsetroot for every update or linking
+//! libX11.
+
+use std::{
+    collections::HashMap,
+    env,
+    ffi::c_char,
+    fs,
+    io::{self, Read, Write},
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixStream},
+        },
+    },
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use directories::BaseDirs;
+
+use crate::config::NameAtom;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+const SOCKET_DIR: &str = "/tmp/.X11-unix";
+const AUTH_NAME: &str = "MIT-MAGIC-COOKIE-1";
+
+/// Xauthority address families.
+const FAMILY_LOCAL: u16 = 256;
+const FAMILY_WILD: u16 = 65535;
+
+const INTERN_ATOM: u8 = 16;
+const CHANGE_PROPERTY: u8 = 18;
+#[cfg(test)]
+const GET_PROPERTY: u8 = 20;
+const GET_INPUT_FOCUS: u8 = 43;
+
+const PROP_MODE_REPLACE: u8 = 0;
+/// Predefined atoms.
+const ATOM_STRING: u32 = 31;
+const ATOM_WM_NAME: u32 = 39;
+
+/// Length of a ChangeProperty request without its data, in units of four bytes.
+const CHANGE_PROPERTY_HEADER: usize = 6;
+
+/// A connection to the X server, kept open across updates.
+pub struct Connection {
+    stream: UnixStream,
+    root: u32,
+    /// Of the root window, in pixels.
+    width: u16,
+    /// Longest request the server accepts, in units of four bytes.
+    max_request_length: usize,
+    /// Of the last request sent, the server numbers them from 1.
+    sequence: u16,
+    /// Interned atoms by name, asked for once per connection.
+    atoms: HashMap<&'static str, u32>,
+}
+
+/// The properties [`Connection::set_root_name`] sets.
+pub struct Names {
+    pub atoms: Vec<NameAtom>,
+    /// Stands in for characters Latin-1 doesn't have in [`NameAtom::WmNameLatin1`].
+    pub latin1_replacement: char,
+}
+
+impl Connection {
+    /// Connects to the local display in `$DISPLAY`, authenticating with the cookie for it in
+    /// `$XAUTHORITY` or ~/.Xauthority if there is one.
+    pub fn open() -> Result<Self, String> {
+        let display = env::var("DISPLAY").map_err(|_| "DISPLAY is not set")?;
+        Self::connect(&display)
+    }
+
+    fn connect(display: &str) -> Result<Self, String> {
+        let (number, screen) = parse_display(display)?;
+
+        // libxcb tries the abstract socket first too, it's reachable from a private /tmp.
+        let path = Path::new(SOCKET_DIR).join(format!("X{number}"));
+        let stream = SocketAddr::from_abstract_name(path.as_os_str().as_bytes())
+            .and_then(|x| UnixStream::connect_addr(&x))
+            .or_else(|_| UnixStream::connect(&path))
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+
+        let cookie = xauthority_path()
+            .and_then(|x| fs::read(x).ok())
+            .and_then(|x| find_cookie(&x, &hostname(), number));
+        Self::setup(stream, cookie.as_deref(), screen)
+    }
+
+    fn setup(mut stream: UnixStream, cookie: Option<&[u8]>, screen: usize) -> Result<Self, String> {
+        stream
+            .set_read_timeout(Some(TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        let (name, data) = match cookie {
+            Some(cookie) => (AUTH_NAME.as_bytes(), cookie),
+            None => (&[][..], &[][..]),
+        };
+
+        // Little endian, protocol version 11.0.
+        let mut request = vec![b'l', 0];
+        for x in [11, 0, name.len() as u16, data.len() as u16, 0] {
+            request.extend(u16::to_le_bytes(x));
+        }
+        for x in [name, data] {
+            request.extend(x);
+            request.resize(request.len().next_multiple_of(4), 0);
+        }
+        send(&mut stream, &request)?;
+
+        let mut header = [0; 8];
+        receive(&mut stream, &mut header)?;
+        let mut body = vec![0; u16::from_le_bytes([header[6], header[7]]) as usize * 4];
+        receive(&mut stream, &mut body)?;
+        let reason = |x: &[u8]| {
+            String::from_utf8_lossy(x)
+                .trim_end_matches('\0')
+                .to_string()
+        };
+        match header[0] {
+            1 => {}
+            0 => {
+                let length = (header[1] as usize).min(body.len());
+                return Err(format!("connection refused: {}", reason(&body[..length])));
+            }
+            _ => return Err(format!("authentication required: {}", reason(&body))),
+        }
+        let (root, width, max_request_length) = parse_setup(&body, screen)?;
+
+        Ok(Self {
+            stream,
+            root,
+            width,
+            max_request_length,
+            sequence: 0,
+            atoms: HashMap::new(),
+        })
+    }
+
+    /// Sets the root window's `names.atoms` to `name`, all in one go. dwm reads `WM_NAME` as
+    /// is, so [`NameAtom::WmName`] is UTF-8 like `xsetroot -name` sets it.
+    pub fn set_root_name(&mut self, name: &str, names: &Names) -> Result<(), String> {
+        let mut properties = Vec::new();
+        for atom in &names.atoms {
+            properties.push(match atom {
+                NameAtom::WmName => (ATOM_WM_NAME, ATOM_STRING, name.as_bytes().to_vec()),
+                NameAtom::WmNameLatin1 => (
+                    ATOM_WM_NAME,
+                    ATOM_STRING,
+                    latin1(name, names.latin1_replacement),
+                ),
+                NameAtom::NetWmName => (
+                    self.atom("_NET_WM_NAME")?,
+                    self.atom("UTF8_STRING")?,
+                    name.as_bytes().to_vec(),
+                ),
+            });
+        }
+
+        let mut requests = Vec::new();
+        for (property, kind, value) in properties {
+            if CHANGE_PROPERTY_HEADER + value.len().div_ceil(4) > self.max_request_length {
+                return Err(format!(
+                    "{} bytes are too long for the X server",
+                    value.len()
+                ));
+            }
+            let mut body = Vec::new();
+            for x in [self.root, property, kind] {
+                body.extend(x.to_le_bytes());
+            }
+            // 8 bit format.
+            body.extend([8, 0, 0, 0]);
+            body.extend((value.len() as u32).to_le_bytes());
+            body.extend(value);
+            self.request(&mut requests, CHANGE_PROPERTY, PROP_MODE_REPLACE, &body);
+        }
+        // Property changes have no reply. Waiting for the reply to a request sent after them
+        // reports their errors, like XSync.
+        let sync = self.request(&mut requests, GET_INPUT_FOCUS, 0, &[]);
+        send(&mut self.stream, &requests)?;
+        self.reply(sync).map(|_| ())
+    }
+
+    /// Width of the screen in pixels.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// The root window's `property`, as its type and value in 8 bit format.
+    #[cfg(test)]
+    fn root_property(&mut self, property: u32) -> Result<(u32, Vec<u8>), String> {
+        let mut body = Vec::new();
+        // Any type, from the start, up to 64 KiB.
+        for x in [self.root, property, 0, 0, 0x4000] {
+            body.extend(x.to_le_bytes());
+        }
+        let mut request = Vec::new();
+        let sequence = self.request(&mut request, GET_PROPERTY, 0, &body);
+        send(&mut self.stream, &request)?;
+
+        let reply = self.reply(sequence)?;
+        let u32_at =
+            |i: usize| u32::from_le_bytes([reply[i], reply[i + 1], reply[i + 2], reply[i + 3]]);
+        let length = u32_at(16) as usize;
+        Ok((u32_at(8), reply[32..32 + length].to_vec()))
+    }
+
+    /// The atom for `name`, interned on first use.
+    fn atom(&mut self, name: &'static str) -> Result<u32, String> {
+        if let Some(atom) = self.atoms.get(name) {
+            return Ok(*atom);
+        }
+        let atom = self.intern_atom(name)?;
+        self.atoms.insert(name, atom);
+        Ok(atom)
+    }
+
+    fn intern_atom(&mut self, name: &str) -> Result<u32, String> {
+        let mut body = Vec::new();
+        body.extend((name.len() as u16).to_le_bytes());
+        body.extend([0, 0]);
+        body.extend(name.as_bytes());
+        let mut request = Vec::new();
+        // Created if missing.
+        let sequence = self.request(&mut request, INTERN_ATOM, 0, &body);
+        send(&mut self.stream, &request)?;
+
+        let reply = self.reply(sequence)?;
+        Ok(u32::from_le_bytes([
+            reply[8], reply[9], reply[10], reply[11],
+        ]))
+    }
+
+    /// Appends a request to `requests`, with `data` in its header's spare byte. Returns its
+    /// sequence number.
+    fn request(&mut self, requests: &mut Vec<u8>, opcode: u8, data: u8, body: &[u8]) -> u16 {
+        let length = 1 + body.len().div_ceil(4);
+        requests.extend([opcode, data]);
+        requests.extend((length as u16).to_le_bytes());
+        requests.extend(body);
+        requests.resize(requests.len().next_multiple_of(4), 0);
+
+        self.sequence = self.sequence.wrapping_add(1);
+        self.sequence
+    }
+
+    /// Reads up to the reply to request `sequence`, skipping events. Fails on errors for it
+    /// or for the requests without replies sent before it.
+    fn reply(&mut self, sequence: u16) -> Result<Vec<u8>, String> {
+        let mut error = None;
+        loop {
+            let mut message = vec![0; 32];
+            receive(&mut self.stream, &mut message)?;
+            let answers = u16::from_le_bytes([message[2], message[3]]);
+
+            match message[0] {
+                // The request failed, there's no reply coming for it.
+                0 if answers == sequence => return Err(error_name(message[1])),
+                0 => error = error.or(Some(message[1])),
+                1 => {
+                    let extra =
+                        u32::from_le_bytes([message[4], message[5], message[6], message[7]]);
+                    message.resize(32 + extra as usize * 4, 0);
+                    receive(&mut self.stream, &mut message[32..])?;
+                    if answers == sequence {
+                        return match error {
+                            Some(code) => Err(error_name(code)),
+                            None => Ok(message),
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// `text` in Latin-1, with `replacement` for the characters it doesn't have, or "?" if that's
+/// one of them too.
+fn latin1(text: &str, replacement: char) -> Vec<u8> {
+    let replacement = u8::try_from(replacement).unwrap_or(b'?');
+    text.chars()
+        .map(|x| u8::try_from(x).unwrap_or(replacement))
+        .collect()
+}
+
+fn send(stream: &mut UnixStream, data: &[u8]) -> Result<(), String> {
+    stream.write_all(data).map_err(|e| e.to_string())
+}
+
+fn receive(stream: &mut UnixStream, buffer: &mut [u8]) -> Result<(), String> {
+    stream.read_exact(buffer).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => "the X server closed the connection".into(),
+        _ => e.to_string(),
+    })
+}
+
+fn error_name(code: u8) -> String {
+    let name = match code {
+        2 => "BadValue",
+        3 => "BadWindow",
+        5 => "BadAtom",
+        11 => "BadAlloc",
+        16 => "BadLength",
+        _ => return format!("X error {code}"),
+    };
+    format!("X error {code} ({name})")
+}
+
+/// The root window of `screen`, its width in pixels and the longest request the server
+/// accepts from the connection setup reply, after its 8 byte header.
+fn parse_setup(body: &[u8], screen: usize) -> Result<(u32, u16, usize), String> {
+    let malformed = || "malformed connection setup reply".to_string();
+    let u16_at = |i: usize| {
+        body.get(i..i + 2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]) as usize)
+            .ok_or_else(malformed)
+    };
+
+    let vendor = u16_at(16)?;
+    let max_request_length = u16_at(18)?;
+    let screens = *body.get(20).ok_or_else(malformed)? as usize;
+    let formats = *body.get(21).ok_or_else(malformed)? as usize;
+    if screen >= screens {
+        return Err(format!("the display has no screen {screen}"));
+    }
+
+    let mut offset = 32 + vendor.next_multiple_of(4) + 8 * formats;
+    for _ in 0..screen {
+        let depths = *body.get(offset + 39).ok_or_else(malformed)?;
+        offset += 40;
+        for _ in 0..depths {
+            offset += 8 + 24 * u16_at(offset + 2)?;
+        }
+    }
+    let root = body.get(offset..offset + 4).ok_or_else(malformed)?;
+
+    Ok((
+        u32::from_le_bytes([root[0], root[1], root[2], root[3]]),
+        u16_at(offset + 20)? as u16,
+        max_request_length,
+    ))
+}
+
+/// The display number and screen of a local `$DISPLAY`, e.g. (0, 0) for ":0" or (1, 2) for
+/// "unix:1.2".
+fn parse_display(display: &str) -> Result<(u32, usize), String> {
+    let invalid = || format!("invalid DISPLAY {display:?}");
+    let (host, rest) = display.rsplit_once(':').ok_or_else(invalid)?;
+    if !matches!(host, "" | "unix") {
+        return Err(format!("DISPLAY {display:?} isn't a local display"));
+    }
+
+    let (number, screen) = rest.split_once('.').unwrap_or((rest, "0"));
+    match (number.parse(), screen.parse()) {
+        (Ok(number), Ok(screen)) => Ok((number, screen)),
+        _ => Err(invalid()),
+    }
+}
+
+fn xauthority_path() -> Option<PathBuf> {
+    match env::var_os("XAUTHORITY").filter(|x| !x.is_empty()) {
+        Some(path) => Some(path.into()),
+        None => BaseDirs::new().map(|x| x.home_dir().join(".Xauthority")),
+    }
+}
+
+fn hostname() -> Vec<u8> {
+    let mut name = [0 as c_char; 256];
+    // SAFETY: the buffer is valid for its length, one byte is left for the terminating NUL.
+    if unsafe { libc::gethostname(name.as_mut_ptr(), name.len() - 1) } != 0 {
+        return Vec::new();
+    }
+    name.iter()
+        .take_while(|x| **x != 0)
+        .map(|x| *x as u8)
+        .collect()
+}
+
+/// The MIT-MAGIC-COOKIE-1 for local display `number` in an Xauthority file, whose entries are
+/// a family and four length prefixed fields, all big endian: address, display number, name
+/// and data.
+fn find_cookie(mut entries: &[u8], hostname: &[u8], number: u32) -> Option<Vec<u8>> {
+    fn field<'a>(entries: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let length = u16::from_be_bytes([*entries.first()?, *entries.get(1)?]) as usize;
+        let field = entries.get(2..2 + length)?;
+        *entries = &entries[2 + length..];
+        Some(field)
+    }
+
+    let number = number.to_string();
+    while entries.len() >= 2 {
+        let family = u16::from_be_bytes([entries[0], entries[1]]);
+        entries = &entries[2..];
+        let address = field(&mut entries)?;
+        let display = field(&mut entries)?;
+        let name = field(&mut entries)?;
+        let data = field(&mut entries)?;
+
+        let host = family == FAMILY_WILD || (family == FAMILY_LOCAL && address == hostname);
+        let display = display.is_empty() || display == number.as_bytes();
+        if host && display && name == AUTH_NAME.as_bytes() {
+            return Some(data.to_vec());
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn displays() {
+        let cases = [
+            (":0", Ok((0, 0))),
+            (":1.2", Ok((1, 2))),
+            ("unix:3", Ok((3, 0))),
+            ("localhost:10.0", Err(())),
+            ("example.org:0", Err(())),
+            (":x", Err(())),
+            (":0.", Err(())),
+            ("", Err(())),
+        ];
+        for (display, expected) in cases {
+            assert_eq!(
+                expected,
+                parse_display(display).map_err(|_| ()),
+                "{display}"
+            );
+        }
+    }
+
+    #[test]
+    fn cookies() {
+        let entry = |family: u16, address: &str, number: &str, name: &str, data: &[u8]| {
+            let mut entry = family.to_be_bytes().to_vec();
+            for x in [address.as_bytes(), number.as_bytes(), name.as_bytes(), data] {
+                entry.extend((x.len() as u16).to_be_bytes());
+                entry.extend(x);
+            }
+            entry
+        };
+        let entries = [
+            entry(FAMILY_LOCAL, "other", "0", AUTH_NAME, b"other host"),
+            entry(FAMILY_LOCAL, "box", "1", AUTH_NAME, b"other display"),
+            entry(
+                FAMILY_LOCAL,
+                "box",
+                "0",
+                "XDM-AUTHORIZATION-1",
+                b"other name",
+            ),
+            entry(FAMILY_LOCAL, "box", "0", AUTH_NAME, b"cookie"),
+            entry(FAMILY_WILD, "", "", AUTH_NAME, b"wild"),
+        ]
+        .concat();
+
+        assert_eq!(Some(b"cookie".to_vec()), find_cookie(&entries, b"box", 0));
+        assert_eq!(
+            Some(b"other display".to_vec()),
+            find_cookie(&entries, b"box", 1)
+        );
+        assert_eq!(Some(b"wild".to_vec()), find_cookie(&entries, b"box", 2));
+        assert_eq!(Some(b"wild".to_vec()), find_cookie(&entries, b"laptop", 0));
+        assert_eq!(
+            None,
+            find_cookie(&entries[..entries.len() - 3], b"laptop", 0)
+        );
+        assert_eq!(None, find_cookie(&[], b"box", 0));
+    }
+
+    /// A successful setup reply with a screen for each of `roots`.
+    fn setup_reply(roots: &[u32], max_request_length: u16) -> Vec<u8> {
+        let mut body = vec![0; 32];
+        body[16..18].copy_from_slice(&3u16.to_le_bytes());
+        body[18..20].copy_from_slice(&max_request_length.to_le_bytes());
+        body[20] = roots.len() as u8;
+        body[21] = 1;
+        body.extend(b"Fak\0");
+        // A pixmap format.
+        body.extend([24, 32, 32, 0, 0, 0, 0, 0]);
+        for root in roots {
+            let mut screen = vec![0; 40];
+            screen[..4].copy_from_slice(&root.to_le_bytes());
+            screen[20..22].copy_from_slice(&(*root as u16 + 1000).to_le_bytes());
+            screen[39] = 1;
+            body.extend(screen);
+            // A depth with one visual.
+            body.extend([24, 0, 1, 0, 0, 0, 0, 0]);
+            body.extend([0; 24]);
+        }
+
+        let mut reply = vec![1, 0, 11, 0, 0, 0];
+        reply.extend(((body.len() / 4) as u16).to_le_bytes());
+        reply.extend(body);
+        reply
+    }
+
+    /// Answers the setup with `setup` and then requests until the client hangs up: atoms from
+    /// 300 up, BadAlloc for properties set to "fail", the properties set before and focus
+    /// replies after an event. Returns the requests after the setup.
+    fn fake_server(mut stream: UnixStream, setup: Vec<u8>) -> thread::JoinHandle<Vec<Vec<u8>>> {
+        thread::spawn(move || {
+            let mut header = [0; 12];
+            stream.read_exact(&mut header).unwrap();
+            assert_eq!([b'l', 0, 11, 0, 0, 0], header[..6]);
+            let length = |x: usize| u16::from_le_bytes([header[x], header[x + 1]]) as usize;
+            let mut auth = vec![0; length(6).next_multiple_of(4) + length(8).next_multiple_of(4)];
+            stream.read_exact(&mut auth).unwrap();
+            assert_eq!(AUTH_NAME.as_bytes(), &auth[..length(6)]);
+            assert_eq!(b"cookie", &auth[20..20 + length(8)]);
+            stream.write_all(&setup).unwrap();
+
+            let (mut requests, mut atoms) = (Vec::new(), 300u32);
+            let mut properties = HashMap::new();
+            loop {
+                let mut request = vec![0; 4];
+                if stream.read_exact(&mut request).is_err() {
+                    return requests;
+                }
+                let length = u16::from_le_bytes([request[2], request[3]]) as usize;
+                request.resize(length * 4, 0);
+                stream.read_exact(&mut request[4..]).unwrap();
+                requests.push(request.clone());
+                let u32_at = |i: usize| {
+                    u32::from_le_bytes([request[i], request[i + 1], request[i + 2], request[i + 3]])
+                };
+
+                let sequence = (requests.len() as u16).to_le_bytes();
+                let mut message = vec![0; 32];
+                message[2..4].copy_from_slice(&sequence);
+                match request[0] {
+                    INTERN_ATOM => {
+                        message[0] = 1;
+                        message[8..12].copy_from_slice(&atoms.to_le_bytes());
+                        atoms += 1;
+                    }
+                    CHANGE_PROPERTY if request.ends_with(b"fail") => message[1] = 11,
+                    CHANGE_PROPERTY => {
+                        let value = request[24..24 + u32_at(20) as usize].to_vec();
+                        properties.insert(u32_at(8), (u32_at(12), value));
+                        continue;
+                    }
+                    GET_PROPERTY => {
+                        let (kind, value) = properties.get(&u32_at(8)).cloned().unwrap_or_default();
+                        message[0] = 1;
+                        message[1] = 8;
+                        message[4..8]
+                            .copy_from_slice(&(value.len().div_ceil(4) as u32).to_le_bytes());
+                        message[8..12].copy_from_slice(&kind.to_le_bytes());
+                        message[16..20].copy_from_slice(&(value.len() as u32).to_le_bytes());
+                        message.extend(value);
+                        message.resize(message.len().next_multiple_of(4), 0);
+                    }
+                    GET_INPUT_FOCUS => {
+                        // PropertyNotify, skipped.
+                        stream.write_all(&[28; 32]).unwrap();
+                        message[0] = 1;
+                    }
+                    opcode => panic!("unexpected request {opcode}"),
+                }
+                stream.write_all(&message).unwrap();
+            }
+        })
+    }
+
+    fn names(atoms: &[NameAtom]) -> Names {
+        Names {
+            atoms: atoms.to_vec(),
+            latin1_replacement: '_',
+        }
+    }
+
+    const NAME: &str = "CPU 5% | café ♪ 夜に駆ける 🎵";
+
+    #[test]
+    fn root_name() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let server = fake_server(server, setup_reply(&[0x1e6, 0x2a0], 16));
+        let mut connection = Connection::setup(client, Some(b"cookie"), 1).unwrap();
+        assert_eq!(
+            (0x2a0, 1672, 16),
+            (
+                connection.root,
+                connection.width(),
+                connection.max_request_length
+            )
+        );
+
+        let both = names(&[NameAtom::WmName, NameAtom::NetWmName]);
+        assert_eq!(Ok(()), connection.set_root_name(NAME, &both));
+        assert_eq!(
+            Ok((ATOM_STRING, NAME.as_bytes().to_vec())),
+            connection.root_property(ATOM_WM_NAME)
+        );
+        assert_eq!(
+            Ok((301, NAME.as_bytes().to_vec())),
+            connection.root_property(300)
+        );
+
+        assert_eq!(
+            Err("X error 11 (BadAlloc)".into()),
+            connection.set_root_name("fail", &both)
+        );
+        assert_eq!(Ok(()), connection.set_root_name("", &both));
+        assert_eq!(
+            Err("41 bytes are too long for the X server".into()),
+            connection.set_root_name(&"x".repeat(41), &both)
+        );
+        assert_eq!(Ok(()), connection.set_root_name(&"x".repeat(40), &both));
+
+        let latin1 = names(&[NameAtom::WmNameLatin1]);
+        assert_eq!(Ok(()), connection.set_root_name(NAME, &latin1));
+        assert_eq!(
+            Ok((ATOM_STRING, b"CPU 5% | caf\xe9 _ _____ _".to_vec())),
+            connection.root_property(ATOM_WM_NAME)
+        );
+        // Only the bytes count, 40 characters of Latin-1 fit where 40 of UTF-8 wouldn't.
+        assert_eq!(Ok(()), connection.set_root_name(&"é".repeat(40), &latin1));
+        drop(connection);
+
+        // Atoms are only interned once, all properties go in one batch.
+        let requests = server.join().unwrap();
+        let opcodes: Vec<_> = requests.iter().map(|x| x[0]).collect();
+        assert_eq!(
+            vec![
+                INTERN_ATOM,
+                INTERN_ATOM,
+                CHANGE_PROPERTY,
+                CHANGE_PROPERTY,
+                GET_INPUT_FOCUS,
+                GET_PROPERTY,
+                GET_PROPERTY,
+                CHANGE_PROPERTY,
+                CHANGE_PROPERTY,
+                GET_INPUT_FOCUS,
+                CHANGE_PROPERTY,
+                CHANGE_PROPERTY,
+                GET_INPUT_FOCUS,
+                CHANGE_PROPERTY,
+                CHANGE_PROPERTY,
+                GET_INPUT_FOCUS,
+                CHANGE_PROPERTY,
+                GET_INPUT_FOCUS,
+                GET_PROPERTY,
+                CHANGE_PROPERTY,
+                GET_INPUT_FOCUS,
+            ],
+            opcodes
+        );
+        assert_eq!(b"_NET_WM_NAME", &requests[0][8..20]);
+        assert_eq!(b"UTF8_STRING", &requests[1][8..19]);
+        assert_eq!(PROP_MODE_REPLACE, requests[2][1]);
+    }
+
+    /// Against a real X server, skipped without Xvfb.
+    #[test]
+    fn xvfb() {
+        let display = format!(":{}", 100 + std::process::id() % 900);
+        let Ok(mut xvfb) = std::process::Command::new("Xvfb")
+            .args([&display, "-nolisten", "tcp"])
+            .stderr(std::process::Stdio::null())
+            .spawn()
+        else {
+            return;
+        };
+
+        let started = std::time::Instant::now();
+        let mut connection = loop {
+            match Connection::connect(&display) {
+                Ok(x) => break x,
+                Err(_) if started.elapsed() < Duration::from_secs(10) => {
+                    thread::sleep(Duration::from_millis(50))
+                }
+                Err(e) => panic!("{e}"),
+            }
+        };
+
+        let both = names(&[NameAtom::WmName, NameAtom::NetWmName]);
+        let result = connection.set_root_name(NAME, &both).and_then(|_| {
+            let net_wm_name = connection.atom("_NET_WM_NAME")?;
+            let utf8_string = connection.atom("UTF8_STRING")?;
+            Ok((
+                connection.root_property(ATOM_WM_NAME)?,
+                connection.root_property(net_wm_name)?,
+                utf8_string,
+            ))
+        });
+        let latin1 = connection
+            .set_root_name(NAME, &names(&[NameAtom::WmNameLatin1]))
+            .and_then(|_| connection.root_property(ATOM_WM_NAME));
+        xvfb.kill().unwrap();
+        xvfb.wait().unwrap();
+
+        let (wm_name, net_wm_name, utf8_string) = result.unwrap();
+        assert_eq!((ATOM_STRING, NAME.as_bytes().to_vec()), wm_name);
+        assert_eq!((utf8_string, NAME.as_bytes().to_vec()), net_wm_name);
+        assert_eq!(
+            Ok((ATOM_STRING, b"CPU 5% | caf\xe9 _ _____ _".to_vec())),
+            latin1
+        );
+    }
+
+    #[test]
+    fn setup_failures() {
+        let refused = |status: u8| {
+            let (client, server) = UnixStream::pair().unwrap();
+            let mut reply = vec![status, 9, 11, 0, 0, 0, 3, 0];
+            reply.extend(b"No auth!\0\0\0\0");
+            let server = fake_server(server, reply);
+            let result = Connection::setup(client, Some(b"cookie"), 0).map(|_| ());
+            server.join().unwrap();
+            result
+        };
+        assert_eq!(Err("connection refused: No auth!".into()), refused(0));
+        assert_eq!(Err("authentication required: No auth!".into()), refused(2));
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let server = fake_server(server, setup_reply(&[0x1e6], 0xffff));
+        assert_eq!(
+            Err("the display has no screen 1".into()),
+            Connection::setup(client, Some(b"cookie"), 1).map(|_| ())
+        );
+        server.join().unwrap();
+
+        assert!(parse_setup(&setup_reply(&[0x1e6], 0xffff)[8..50], 0).is_err());
+    }
+}
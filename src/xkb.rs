@@ -0,0 +1,383 @@
+//! Active keyboard layout from the X server.
+//!
+//! libX11 is loaded when first needed rather than linked, so stringbar still starts without it,
+//! e.g. under Wayland or with the none output.
+//!
+//! Xlib's default error handlers exit the process, so they're replaced with ones that only
+//! take note. A broken connection is dropped and reopened later.
+
+use std::{
+    ffi::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong, c_void, CStr},
+    ptr, slice,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+const XKB_USE_CORE_KBD: c_uint = 0x100;
+const ANY_PROPERTY_TYPE: c_ulong = 0;
+const SUCCESS: c_int = 0;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Set by [`on_error`] when the server rejects a request.
+static REQUEST_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// `XkbStateRec`, only `group` is used.
+#[repr(C)]
+#[derive(Default)]
+struct XkbState {
+    group: c_uchar,
+    locked_group: c_uchar,
+    base_group: u16,
+    latched_group: u16,
+    mods: c_uchar,
+    base_mods: c_uchar,
+    latched_mods: c_uchar,
+    locked_mods: c_uchar,
+    compat_state: c_uchar,
+    grab_mods: c_uchar,
+    compat_grab_mods: c_uchar,
+    lookup_mods: c_uchar,
+    compat_lookup_mods: c_uchar,
+    ptr_buttons: u16,
+}
+
+type Display = c_void;
+type ErrorHandler = unsafe extern "C" fn(*mut Display, *mut c_void) -> c_int;
+type IoErrorHandler = unsafe extern "C" fn(*mut Display) -> c_int;
+type IoErrorExitHandler = unsafe extern "C" fn(*mut Display, *mut c_void);
+
+/// The libX11 functions used here.
+struct Xlib {
+    library: *mut c_void,
+    open_display: unsafe extern "C" fn(*const c_char) -> *mut Display,
+    close_display: unsafe extern "C" fn(*mut Display) -> c_int,
+    default_root_window: unsafe extern "C" fn(*mut Display) -> c_ulong,
+    intern_atom: unsafe extern "C" fn(*mut Display, *const c_char, c_int) -> c_ulong,
+    #[allow(clippy::type_complexity)]
+    get_window_property: unsafe extern "C" fn(
+        *mut Display,
+        c_ulong,
+        c_ulong,
+        c_long,
+        c_long,
+        c_int,
+        c_ulong,
+        *mut c_ulong,
+        *mut c_int,
+        *mut c_ulong,
+        *mut c_ulong,
+        *mut *mut c_uchar,
+    ) -> c_int,
+    free: unsafe extern "C" fn(*mut c_void) -> c_int,
+    xkb_get_state: unsafe extern "C" fn(*mut Display, c_uint, *mut XkbState) -> c_int,
+    set_error_handler: unsafe extern "C" fn(Option<ErrorHandler>) -> Option<ErrorHandler>,
+    set_io_error_handler: unsafe extern "C" fn(Option<IoErrorHandler>) -> Option<IoErrorHandler>,
+    /// Only in libX11 1.7 and later, before that a lost connection always exits.
+    set_io_error_exit_handler:
+        Option<unsafe extern "C" fn(*mut Display, Option<IoErrorExitHandler>, *mut c_void)>,
+}
+
+impl Xlib {
+    fn load() -> Result<Self, String> {
+        // SAFETY: the name is a valid C string, libX11 has no unsafe initializers.
+        let library = unsafe { libc::dlopen(c"libX11.so.6".as_ptr(), libc::RTLD_LAZY) };
+        if library.is_null() {
+            return Err("libX11 is not installed".into());
+        }
+
+        /// Looks up `name`, which must have the signature of `T`.
+        unsafe fn symbol<T>(library: *mut c_void, name: &CStr) -> Result<T, String> {
+            let symbol = libc::dlsym(library, name.as_ptr());
+            if symbol.is_null() {
+                return Err(format!("libX11 has no {}", name.to_string_lossy()));
+            }
+            Ok(std::mem::transmute_copy(&symbol))
+        }
+
+        // SAFETY: the types match the declarations in Xlib.h and XKBlib.h.
+        unsafe {
+            Ok(Self {
+                library,
+                open_display: symbol(library, c"XOpenDisplay")?,
+                close_display: symbol(library, c"XCloseDisplay")?,
+                default_root_window: symbol(library, c"XDefaultRootWindow")?,
+                intern_atom: symbol(library, c"XInternAtom")?,
+                get_window_property: symbol(library, c"XGetWindowProperty")?,
+                free: symbol(library, c"XFree")?,
+                xkb_get_state: symbol(library, c"XkbGetState")?,
+                set_error_handler: symbol(library, c"XSetErrorHandler")?,
+                set_io_error_handler: symbol(library, c"XSetIOErrorHandler")?,
+                set_io_error_exit_handler: symbol(library, c"XSetIOErrorExitHandler").ok(),
+            })
+        }
+    }
+}
+
+/// Notes that a request failed instead of printing it and exiting.
+unsafe extern "C" fn on_error(_display: *mut Display, _event: *mut c_void) -> c_int {
+    REQUEST_FAILED.store(true, Ordering::Relaxed);
+    0
+}
+
+/// Called first when the connection breaks, the default prints a message.
+unsafe extern "C" fn on_io_error(_display: *mut Display) -> c_int {
+    0
+}
+
+/// Called last when the connection breaks, the default exits. Marks the [`Keyboard`] whose
+/// `broken` flag is `data` instead.
+unsafe extern "C" fn on_io_error_exit(_display: *mut Display, data: *mut c_void) {
+    (*data.cast::<AtomicBool>()).store(true, Ordering::Relaxed);
+}
+
+impl Drop for Xlib {
+    fn drop(&mut self) {
+        // SAFETY: nothing loaded from the library outlives `self`.
+        unsafe { libc::dlclose(self.library) };
+    }
+}
+
+/// An open connection to the X server, kept between updates.
+pub struct Keyboard {
+    display: *mut Display,
+    rules_names: c_ulong,
+    /// Set once the connection is lost, boxed so the exit handler can keep a pointer to it.
+    broken: Box<AtomicBool>,
+    // Dropped after the connection is closed.
+    xlib: Xlib,
+}
+
+impl Keyboard {
+    /// Connects to the display in `$DISPLAY`.
+    pub fn open() -> Result<Self, String> {
+        let xlib = Xlib::load()?;
+        let set_io_error_exit_handler = xlib
+            .set_io_error_exit_handler
+            .ok_or("libX11 is older than 1.7 and would exit when the X server goes away")?;
+
+        // The handlers are global, and set again every time since unloading the library
+        // resets them. SAFETY: they match the prototypes in Xlib.h and only touch atomics.
+        unsafe {
+            (xlib.set_error_handler)(Some(on_error));
+            (xlib.set_io_error_handler)(Some(on_io_error));
+        }
+
+        // SAFETY: a null name makes Xlib use $DISPLAY.
+        let display = unsafe { (xlib.open_display)(ptr::null()) };
+        if display.is_null() {
+            return Err("unable to open the X display".into());
+        }
+        let broken = Box::new(AtomicBool::new(false));
+        // SAFETY: `display` is open and the name is a valid C string. The handler gets
+        // `broken`, which is only freed after the connection is closed.
+        let rules_names = unsafe {
+            set_io_error_exit_handler(
+                display,
+                Some(on_io_error_exit),
+                ptr::from_ref(&*broken).cast_mut().cast(),
+            );
+            (xlib.intern_atom)(display, c"_XKB_RULES_NAMES".as_ptr(), 0)
+        };
+
+        Ok(Self {
+            display,
+            rules_names,
+            broken,
+            xlib,
+        })
+    }
+
+    /// Short name of the active layout group, e.g. "us".
+    pub fn layout(&mut self) -> Result<String, String> {
+        REQUEST_FAILED.store(false, Ordering::Relaxed);
+        let layout = self.read_layout();
+        if self.broken.load(Ordering::Relaxed) {
+            return Err("lost the connection to the X server".into());
+        }
+        if REQUEST_FAILED.load(Ordering::Relaxed) {
+            return Err("the X server rejected a request".into());
+        }
+        layout
+    }
+
+    fn read_layout(&self) -> Result<String, String> {
+        let mut state = XkbState::default();
+        // SAFETY: `display` is open and `state` is an `XkbStateRec`.
+        if unsafe { (self.xlib.xkb_get_state)(self.display, XKB_USE_CORE_KBD, &mut state) }
+            != SUCCESS
+        {
+            return Err("unable to query the XKB state".into());
+        }
+
+        let names = self.rules_names()?;
+        layout_name(&names, state.group as usize)
+            .map(Into::into)
+            .ok_or_else(|| format!("no layout for group {} in {names:?}", state.group))
+    }
+
+    /// The root window's `_XKB_RULES_NAMES`, as set by setxkbmap: rules, model, layouts,
+    /// variants and options, each terminated by a NUL.
+    fn rules_names(&self) -> Result<String, String> {
+        let (mut kind, mut format, mut items, mut remaining) = (0, 0, 0, 0);
+        let mut data: *mut c_uchar = ptr::null_mut();
+
+        // SAFETY: `display` is open and every out pointer is valid for the call. `data` is
+        // `items` bytes long for 8 bit properties and freed with XFree once copied.
+        unsafe {
+            let root = (self.xlib.default_root_window)(self.display);
+            if (self.xlib.get_window_property)(
+                self.display,
+                root,
+                self.rules_names,
+                0,
+                1024,
+                0,
+                ANY_PROPERTY_TYPE,
+                &mut kind,
+                &mut format,
+                &mut items,
+                &mut remaining,
+                &mut data,
+            ) != SUCCESS
+                || data.is_null()
+            {
+                return Err("the root window has no _XKB_RULES_NAMES".into());
+            }
+
+            let names = match format {
+                8 => Ok(
+                    String::from_utf8_lossy(slice::from_raw_parts(data, items as usize))
+                        .into_owned(),
+                ),
+                _ => Err(format!("_XKB_RULES_NAMES has format {format}")),
+            };
+            (self.xlib.free)(data.cast());
+
+            names
+        }
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        // SAFETY: `display` is open or broken, either way it's freed and not used afterwards.
+        unsafe { (self.xlib.close_display)(self.display) };
+    }
+}
+
+/// The active layout, with a [`Keyboard`] kept open across updates.
+pub struct Layout {
+    keyboard: Option<Keyboard>,
+    /// After a failure, no connection is attempted before this.
+    retry_at: Option<Instant>,
+    backoff: Duration,
+    last_error: String,
+}
+
+impl Layout {
+    pub fn new() -> Self {
+        Self {
+            keyboard: None,
+            retry_at: None,
+            backoff: MIN_BACKOFF,
+            last_error: String::new(),
+        }
+    }
+
+    /// Short name of the active layout group, e.g. "us". Connects if needed, waiting twice as
+    /// long after every failed attempt, up to a minute. Errors while waiting repeat the last
+    /// one.
+    pub fn read(&mut self, now: Instant) -> Result<String, String> {
+        if self.keyboard.is_none() {
+            if self.retry_at.is_some_and(|x| now < x) {
+                return Err(self.last_error.clone());
+            }
+            match Keyboard::open() {
+                Ok(keyboard) => self.keyboard = Some(keyboard),
+                Err(e) => return Err(self.failed(now, e)),
+            }
+        }
+
+        match self.keyboard.as_mut().unwrap().layout() {
+            Ok(layout) => {
+                self.retry_at = None;
+                self.backoff = MIN_BACKOFF;
+                Ok(layout)
+            }
+            Err(e) => Err(self.failed(now, e)),
+        }
+    }
+
+    /// Drops the connection and schedules the next attempt.
+    fn failed(&mut self, now: Instant, error: String) -> String {
+        self.keyboard = None;
+        self.retry_at = Some(now + self.backoff);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        self.last_error = error;
+        self.last_error.clone()
+    }
+}
+
+/// The layout of `group` in `_XKB_RULES_NAMES`, e.g. "de" for group 1 of
+/// "evdev\0pc105\0us,de\0,nodeadkeys\0grp:alt_shift_toggle\0".
+fn layout_name(rules_names: &str, group: usize) -> Option<&str> {
+    rules_names
+        .split('\0')
+        .nth(2)?
+        .split(',')
+        .nth(group)
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_names() {
+        let names = "evdev\0pc105\0us,de\0,nodeadkeys\0grp:alt_shift_toggle\0";
+        assert_eq!(Some("us"), layout_name(names, 0));
+        assert_eq!(Some("de"), layout_name(names, 1));
+        assert_eq!(None, layout_name(names, 2));
+
+        assert_eq!(Some("fr"), layout_name("base\0pc104\0fr\0\0\0", 0));
+        assert_eq!(None, layout_name("base\0pc104\0\0\0\0", 0));
+        assert_eq!(None, layout_name("base\0pc104", 0));
+        assert_eq!(None, layout_name("", 0));
+    }
+
+    #[test]
+    fn backoff() {
+        let mut layout = Layout::new();
+        let start = Instant::now();
+
+        // Attempts wait a second, then twice as long, repeating the last error meanwhile.
+        layout.failed(start, "unable to open the X display".into());
+        assert_eq!(
+            Err("unable to open the X display".into()),
+            layout.read(start + MIN_BACKOFF / 2)
+        );
+        layout.failed(start + MIN_BACKOFF, "lost the connection".into());
+        assert_eq!(
+            Err("lost the connection".into()),
+            layout.read(start + MIN_BACKOFF * 2)
+        );
+        assert_eq!(Some(start + MIN_BACKOFF * 3), layout.retry_at);
+        assert_eq!(MIN_BACKOFF * 4, layout.backoff);
+
+        for _ in 0..10 {
+            layout.failed(start, String::new());
+        }
+        assert_eq!(MAX_BACKOFF, layout.backoff);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn layout() {
+        // Size from <X11/XKBlib.h>.
+        assert_eq!(18, std::mem::size_of::<XkbState>());
+    }
+}
@@ -0,0 +1,56 @@
+//! Startup errors exit with the codes documented in `error.rs`, so supervisors can tell them
+//! apart.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("stringbar-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("config/stringbar")).unwrap();
+    std::fs::create_dir_all(dir.join("runtime")).unwrap();
+    dir
+}
+
+/// stringbar with its config and runtime directories in `dir`.
+fn stringbar(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_stringbar"));
+    command
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_RUNTIME_DIR", dir.join("runtime"));
+    command
+}
+
+#[test]
+fn bad_config() {
+    let dir = temp_dir("exit-config");
+    std::fs::write(dir.join("config/stringbar/config.ron"), "(sections: [").unwrap();
+
+    let status = stringbar(&dir)
+        .args(["--output", "stdout"])
+        .output()
+        .unwrap()
+        .status;
+    assert_eq!(Some(2), status.code());
+    let status = stringbar(&dir).arg("check").output().unwrap().status;
+    assert_eq!(Some(2), status.code());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn no_display() {
+    let dir = temp_dir("exit-display");
+
+    // Writes the default config first.
+    let status = stringbar(&dir)
+        .args(["--output", "xsetroot"])
+        .env_remove("DISPLAY")
+        .status()
+        .unwrap();
+    assert_eq!(Some(3), status.code());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}